@@ -0,0 +1,47 @@
+use std::{alloc::Layout, sync::Arc, thread};
+
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use skip_list2::arena::BlockArena;
+
+const ALLOCS_PER_THREAD: usize = 20_000;
+
+fn alloc_from_threads(arena: &Arc<BlockArena>, threads: usize) {
+    let layout = Layout::from_size_align(48, 8).unwrap();
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let arena = arena.clone();
+            thread::spawn(move || {
+                for _ in 0..ALLOCS_PER_THREAD {
+                    black_box(arena.alloc(layout));
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+fn bench_concurrent_alloc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_alloc");
+
+    for threads in [1, 4, 16] {
+        group.bench_with_input(BenchmarkId::new("shared", threads), &threads, |b, &t| {
+            b.iter(|| alloc_from_threads(&Arc::new(BlockArena::new()), t))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("thread_cache", threads),
+            &threads,
+            |b, &t| {
+                b.iter(|| {
+                    alloc_from_threads(&Arc::new(BlockArena::with_thread_cache(64 * 1024)), t)
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_concurrent_alloc);
+criterion_main!(benches);
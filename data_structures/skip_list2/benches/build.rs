@@ -0,0 +1,42 @@
+// Building a list from unsorted entries: one insert after the other, against `par_build`
+// sorting and creating the nodes on all cores. Needs the `rayon` feature.
+use criterion::{BatchSize, Criterion, black_box, criterion_group, criterion_main};
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+use skip_list2::{arena::BlockArena, comparator::DefaultComparator, skip_list::SkipList};
+
+const COUNT: u64 = 1_000_000;
+
+fn bench_build(c: &mut Criterion) {
+    let mut entries: Vec<_> = (0..COUNT).map(|i| (i, i)).collect();
+    entries.shuffle(&mut StdRng::seed_from_u64(0));
+
+    let mut group = c.benchmark_group("build_1m");
+    group.sample_size(10);
+    group.bench_function("insert", |b| {
+        b.iter_batched(
+            || entries.clone(),
+            |entries| {
+                let list = SkipList::new(DefaultComparator::default(), BlockArena::new());
+                for (key, value) in entries {
+                    list.insert(black_box(key), value);
+                }
+                list
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("par_build", |b| {
+        b.iter_batched(
+            || entries.clone(),
+            |entries| {
+                SkipList::par_build(entries, DefaultComparator::default(), BlockArena::new())
+                    .unwrap()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_build);
+criterion_main!(benches);
@@ -0,0 +1,286 @@
+// `SkipList` against `BTreeMap` and `crossbeam_skiplist::SkipMap`, with 8 byte integer and
+// 64 byte string keys and values. `BTreeMap` only shows up in the single-threaded groups.
+use std::{collections::BTreeMap, sync::Arc, thread};
+
+use criterion::{BatchSize, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use crossbeam_skiplist::SkipMap;
+use rand::{SeedableRng, rngs::StdRng};
+use skip_list2::{arena::BlockArena, comparator::DefaultComparator, skip_list::SkipList};
+
+// shared with the stress harness, which is behind the `stress` feature
+#[path = "../src/stress/workload.rs"]
+mod workload;
+
+use workload::{Key, share, shuffled, value_of};
+
+const COUNT: u64 = 100_000;
+const LOOKUPS: u64 = 1_000;
+const SCAN_LEN: usize = 100;
+const SEED: u64 = 0;
+
+type List<K> = SkipList<K, K, DefaultComparator<K>, BlockArena>;
+
+fn entries<K: Key>(indices: &[u64]) -> Vec<(K, K)> {
+    indices
+        .iter()
+        .map(|&i| (K::from_index(i), K::from_index(value_of(i))))
+        .collect()
+}
+
+fn bench_insert<K: Key>(c: &mut Criterion, name: &str, indices: &[u64]) {
+    let entries = entries::<K>(indices);
+
+    let mut group = c.benchmark_group(format!("{name}_{}", K::NAME));
+    group.throughput(Throughput::Elements(entries.len() as u64));
+
+    group.bench_function("skip_list2", |b| {
+        b.iter_batched(
+            || {
+                let list: List<K> = SkipList::new(DefaultComparator::default(), BlockArena::new());
+                (list, entries.clone())
+            },
+            |(list, entries)| {
+                for (key, value) in entries {
+                    list.insert(key, value);
+                }
+                list
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("btree_map", |b| {
+        b.iter_batched(
+            || (BTreeMap::new(), entries.clone()),
+            |(mut map, entries)| {
+                for (key, value) in entries {
+                    map.insert(key, value);
+                }
+                map
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("crossbeam_skip_map", |b| {
+        b.iter_batched(
+            || (SkipMap::new(), entries.clone()),
+            |(map, entries)| {
+                for (key, value) in entries {
+                    map.insert(key, value);
+                }
+                map
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+// the structures filled with keys `0..COUNT` and the keys to look up in them
+struct Filled<K: Key> {
+    list: Arc<List<K>>,
+    btree: BTreeMap<K, K>,
+    skip_map: SkipMap<K, K>,
+    probes: Vec<K>,
+}
+
+impl<K: Key> Filled<K> {
+    fn new() -> Self {
+        let list = SkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_rng(StdRng::seed_from_u64(SEED));
+        let mut btree = BTreeMap::new();
+        let skip_map = SkipMap::new();
+        for (key, value) in entries::<K>(&shuffled(COUNT, SEED)) {
+            list.insert(key.clone(), value.clone());
+            btree.insert(key.clone(), value.clone());
+            skip_map.insert(key, value);
+        }
+        let probes = shuffled(COUNT, SEED + 1)[..LOOKUPS as usize]
+            .iter()
+            .map(|&i| K::from_index(i))
+            .collect();
+        Self {
+            list: Arc::new(list),
+            btree,
+            skip_map,
+            probes,
+        }
+    }
+}
+
+fn bench_point_lookup<K: Key>(c: &mut Criterion, filled: &Filled<K>) {
+    let mut group = c.benchmark_group(format!("point_lookup_{}", K::NAME));
+    group.throughput(Throughput::Elements(LOOKUPS));
+
+    group.bench_function("skip_list2", |b| {
+        b.iter(|| {
+            for key in &filled.probes {
+                black_box(filled.list.get(black_box(key)));
+            }
+        })
+    });
+    group.bench_function("btree_map", |b| {
+        b.iter(|| {
+            for key in &filled.probes {
+                black_box(filled.btree.get(black_box(key)));
+            }
+        })
+    });
+    group.bench_function("crossbeam_skip_map", |b| {
+        b.iter(|| {
+            for key in &filled.probes {
+                black_box(filled.skip_map.get(black_box(key)));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+// seek to a key, then step over the next `SCAN_LEN` entries
+fn bench_short_range<K: Key>(c: &mut Criterion, filled: &Filled<K>) {
+    let mut group = c.benchmark_group(format!("short_range_{}", K::NAME));
+    group.throughput(Throughput::Elements(LOOKUPS * SCAN_LEN as u64));
+
+    group.bench_function("skip_list2", |b| {
+        let mut iter = filled.list.iter();
+        b.iter(|| {
+            for key in &filled.probes {
+                iter.seek(black_box(key));
+                for _ in 0..SCAN_LEN {
+                    if !iter.is_valid() {
+                        break;
+                    }
+                    black_box(iter.value());
+                    iter.next();
+                }
+            }
+        })
+    });
+    group.bench_function("btree_map", |b| {
+        b.iter(|| {
+            for key in &filled.probes {
+                for entry in filled.btree.range(black_box(key)..).take(SCAN_LEN) {
+                    black_box(entry);
+                }
+            }
+        })
+    });
+    group.bench_function("crossbeam_skip_map", |b| {
+        b.iter(|| {
+            for key in &filled.probes {
+                for entry in filled.skip_map.range(black_box(key)..).take(SCAN_LEN) {
+                    black_box(entry);
+                }
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_full_scan<K: Key>(c: &mut Criterion, filled: &Filled<K>) {
+    let mut group = c.benchmark_group(format!("full_scan_{}", K::NAME));
+    group.throughput(Throughput::Elements(COUNT));
+
+    group.bench_function("skip_list2", |b| {
+        let mut iter = filled.list.iter();
+        b.iter(|| {
+            iter.seek_to_first();
+            while iter.is_valid() {
+                black_box(iter.value());
+                iter.next();
+            }
+        })
+    });
+    group.bench_function("btree_map", |b| {
+        b.iter(|| {
+            for entry in &filled.btree {
+                black_box(entry);
+            }
+        })
+    });
+    group.bench_function("crossbeam_skip_map", |b| {
+        b.iter(|| {
+            for entry in filled.skip_map.iter() {
+                black_box(entry);
+            }
+        })
+    });
+
+    group.finish();
+}
+
+// `threads` writers inserting their interleaved shares of `0..COUNT`
+fn bench_mt_insert<K: Key>(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("mt_insert_{}", K::NAME));
+    group.throughput(Throughput::Elements(COUNT));
+
+    for threads in [2, 4, 8] {
+        let shares: Vec<_> = (0..threads)
+            .map(|t| entries::<K>(&share(COUNT, threads, t, SEED + t as u64)))
+            .collect();
+
+        group.bench_function(format!("skip_list2/{threads}_threads"), |b| {
+            b.iter_batched(
+                || {
+                    let list: List<K> =
+                        SkipList::new(DefaultComparator::default(), BlockArena::new());
+                    (list, shares.clone())
+                },
+                |(list, shares)| {
+                    thread::scope(|s| {
+                        for entries in shares {
+                            let list = &list;
+                            s.spawn(move || {
+                                for (key, value) in entries {
+                                    list.insert(key, value);
+                                }
+                            });
+                        }
+                    });
+                    list
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function(format!("crossbeam_skip_map/{threads}_threads"), |b| {
+            b.iter_batched(
+                || (SkipMap::new(), shares.clone()),
+                |(map, shares)| {
+                    thread::scope(|s| {
+                        for entries in shares {
+                            let map = &map;
+                            s.spawn(move || {
+                                for (key, value) in entries {
+                                    map.insert(key, value);
+                                }
+                            });
+                        }
+                    });
+                    map
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_compare<K: Key>(c: &mut Criterion) {
+    bench_insert::<K>(c, "seq_insert", &(0..COUNT).collect::<Vec<_>>());
+    bench_insert::<K>(c, "random_insert", &shuffled(COUNT, SEED));
+
+    let filled = Filled::<K>::new();
+    bench_point_lookup(c, &filled);
+    bench_short_range(c, &filled);
+    bench_full_scan(c, &filled);
+
+    bench_mt_insert::<K>(c);
+}
+
+criterion_group!(benches, bench_compare::<u64>, bench_compare::<[u8; 64]>);
+criterion_main!(benches);
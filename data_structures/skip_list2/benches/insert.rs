@@ -0,0 +1,381 @@
+use std::sync::Arc;
+
+use criterion::{BatchSize, Criterion, black_box, criterion_group, criterion_main};
+use rand::{RngCore, SeedableRng, rngs::StdRng, seq::SliceRandom};
+use skip_list2::{
+    arena::{BlockArena, BlockPool, MemAllocator},
+    comparator::DefaultComparator,
+    local::LocalSkipList,
+    sharded::ShardedSkipList,
+    skip_list::{SkipList, SkipListOptions, SplitMix64},
+};
+
+const COUNT: usize = 100_000;
+
+fn bench_insert_startup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_startup");
+
+    group.bench_function("block_arena_new", |b| {
+        b.iter_batched(
+            || SkipList::new(DefaultComparator::default(), BlockArena::new()),
+            |list| {
+                for i in 0..COUNT {
+                    list.insert(black_box(i), i);
+                }
+                list
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("block_arena_with_capacity", |b| {
+        b.iter_batched(
+            || {
+                SkipList::new(
+                    DefaultComparator::default(),
+                    BlockArena::with_capacity(COUNT * 64),
+                )
+            },
+            |list| {
+                for i in 0..COUNT {
+                    list.insert(black_box(i), i);
+                }
+                list
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("block_arena_uninit", |b| {
+        b.iter_batched(
+            || {
+                SkipList::new(
+                    DefaultComparator::default(),
+                    BlockArena::with_uninit_blocks(),
+                )
+            },
+            |list| {
+                for i in 0..COUNT {
+                    list.insert(black_box(i), i);
+                }
+                list
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+// a handful of entries keeps the list low, so the constant part of an insert dominates
+fn bench_small_list_insert(c: &mut Criterion) {
+    const SMALL_COUNT: usize = 64;
+
+    c.bench_function("small_list_insert", |b| {
+        b.iter_batched(
+            || {
+                SkipList::new(
+                    DefaultComparator::default(),
+                    BlockArena::with_capacity(64 * 1024),
+                )
+            },
+            |list| {
+                for i in 0..SMALL_COUNT {
+                    list.insert(black_box(i), i);
+                }
+                list
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+// heights through `rand::random`, as before the thread's own generator
+struct RandRandom;
+
+impl RngCore for RandRandom {
+    fn next_u32(&mut self) -> u32 {
+        rand::random()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand::random()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        rand::fill(dst)
+    }
+}
+
+// Where heights come from, on lists small enough that the draw is a visible part of an
+// insert. `with_rng` puts its generator behind a lock, which `split_mix` pays with a cheap
+// draw: `rand_random` against it is the cost of `rand::random` itself, and `thread_local`,
+// the default, against it the cost of the lock.
+fn bench_height_rng(c: &mut Criterion) {
+    const SMALL_COUNT: usize = 1_000;
+
+    let mut group = c.benchmark_group("height_rng");
+    for name in ["thread_local", "split_mix", "rand_random"] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    let list = SkipList::new(DefaultComparator::default(), BlockArena::new());
+                    match name {
+                        "split_mix" => list.with_rng(SplitMix64::default()),
+                        "rand_random" => list.with_rng(RandRandom),
+                        _ => list,
+                    }
+                },
+                |list| {
+                    for i in 0..SMALL_COUNT {
+                        list.insert(black_box(i), i);
+                    }
+                    list
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// one thread building a list, through CASes and atomic counters or with plain stores
+fn bench_local_insert(c: &mut Criterion) {
+    let mut shuffled: Vec<_> = (0..COUNT).collect();
+    shuffled.shuffle(&mut StdRng::seed_from_u64(0));
+
+    for (name, keys) in [("seq", (0..COUNT).collect()), ("random", shuffled)] {
+        let mut group = c.benchmark_group(format!("local_insert_{name}"));
+        group.bench_function("shared", |b| {
+            b.iter_batched(
+                || SkipList::new(DefaultComparator::default(), BlockArena::new()),
+                |list| {
+                    for &i in &keys {
+                        list.insert(black_box(i), i);
+                    }
+                    list
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("local", |b| {
+            b.iter_batched(
+                || LocalSkipList::new(DefaultComparator::default(), BlockArena::new()),
+                |list| {
+                    for &i in &keys {
+                        list.insert(black_box(i), i);
+                    }
+                    list
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+    }
+}
+
+// sorted input, such as a table read back: inserts against linking the nodes in order
+fn bench_sorted_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sorted_build");
+
+    group.bench_function("insert", |b| {
+        b.iter(|| {
+            let list = SkipList::new(DefaultComparator::default(), BlockArena::new());
+            for i in 0..COUNT {
+                list.insert(black_box(i), i);
+            }
+            list
+        })
+    });
+
+    group.bench_function("from_sorted_iter", |b| {
+        b.iter(|| {
+            SkipList::from_sorted_iter(
+                (0..COUNT).map(|i| (black_box(i), i)),
+                DefaultComparator::default(),
+                BlockArena::new(),
+            )
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_build_drop_cycles(c: &mut Criterion) {
+    const CYCLE_COUNT: usize = 10_000;
+
+    let mut group = c.benchmark_group("build_drop_cycles");
+
+    group.bench_function("fresh_arena", |b| {
+        b.iter(|| {
+            let list = SkipList::new(DefaultComparator::default(), BlockArena::new());
+            for i in 0..CYCLE_COUNT {
+                list.insert(black_box(i), i);
+            }
+        })
+    });
+
+    let pool = Arc::new(BlockPool::new(CYCLE_COUNT * 128));
+    group.bench_function("pooled_arena", |b| {
+        b.iter(|| {
+            let list = SkipList::new(
+                DefaultComparator::default(),
+                BlockArena::with_pool(pool.clone()),
+            );
+            for i in 0..CYCLE_COUNT {
+                list.insert(black_box(i), i);
+            }
+        })
+    });
+
+    group.finish();
+}
+
+// static dispatch against `Box<dyn MemAllocator>`: one virtual call per node
+fn bench_allocator_dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("allocator_dispatch");
+
+    group.bench_function("static", |b| {
+        b.iter_batched(
+            || SkipList::new(DefaultComparator::default(), BlockArena::new()),
+            |list| {
+                for i in 0..COUNT {
+                    list.insert(black_box(i), i);
+                }
+                list
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("boxed_dyn", |b| {
+        b.iter_batched(
+            || {
+                let a: Box<dyn MemAllocator + Send + Sync> = Box::new(BlockArena::new());
+                SkipList::new(DefaultComparator::default(), a)
+            },
+            |list| {
+                for i in 0..COUNT {
+                    list.insert(black_box(i), i);
+                }
+                list
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+// threads inserting interleaved keys, so they keep splicing next to each other
+fn bench_contended_insert(c: &mut Criterion) {
+    const PER_THREAD: usize = 20_000;
+
+    let mut group = c.benchmark_group("contended_insert");
+    for threads in [2, 8] {
+        group.bench_function(format!("{threads}_threads"), |b| {
+            b.iter_batched(
+                || SkipList::new(DefaultComparator::default(), BlockArena::new()),
+                |list| {
+                    std::thread::scope(|s| {
+                        for t in 0..threads {
+                            let list = &list;
+                            s.spawn(move || {
+                                for i in 0..PER_THREAD {
+                                    list.insert(black_box(i * threads + t), i);
+                                }
+                            });
+                        }
+                    });
+                    list
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// every thread splices into the same gaps, with and without backoff between lost CASes
+fn bench_contended_backoff(c: &mut Criterion) {
+    const THREADS: usize = 8;
+    const PER_THREAD: usize = 20_000;
+
+    let mut group = c.benchmark_group("contended_backoff");
+    for backoff_spin_limit in [0, 6] {
+        let options = SkipListOptions {
+            backoff_spin_limit,
+            ..Default::default()
+        };
+        group.bench_function(format!("spin_limit_{backoff_spin_limit}"), |b| {
+            b.iter_batched(
+                || SkipList::with_options(DefaultComparator::default(), BlockArena::new(), options),
+                |list| {
+                    std::thread::scope(|s| {
+                        for t in 0..THREADS {
+                            let list = &list;
+                            s.spawn(move || {
+                                for i in 0..PER_THREAD {
+                                    list.insert(black_box(i * THREADS + t), i);
+                                }
+                            });
+                        }
+                    });
+                    list
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_sharded_insert(c: &mut Criterion) {
+    const THREADS: usize = 8;
+    const PER_THREAD: usize = 20_000;
+
+    let mut group = c.benchmark_group("sharded_insert");
+    for shards in [1, 4, 16] {
+        group.bench_function(format!("{shards}_shards"), |b| {
+            b.iter_batched(
+                || {
+                    ShardedSkipList::with_hash(
+                        shards,
+                        DefaultComparator::default(),
+                        BlockArena::new,
+                    )
+                },
+                |list| {
+                    std::thread::scope(|s| {
+                        for t in 0..THREADS {
+                            let list = &list;
+                            s.spawn(move || {
+                                for i in 0..PER_THREAD {
+                                    list.insert(black_box(i * THREADS + t), i);
+                                }
+                            });
+                        }
+                    });
+                    list
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_insert_startup,
+    bench_small_list_insert,
+    bench_height_rng,
+    bench_local_insert,
+    bench_sorted_build,
+    bench_build_drop_cycles,
+    bench_allocator_dispatch,
+    bench_contended_insert,
+    bench_contended_backoff,
+    bench_sharded_insert
+);
+criterion_main!(benches);
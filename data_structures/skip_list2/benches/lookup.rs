@@ -0,0 +1,163 @@
+use std::{
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, Ordering::Relaxed},
+    },
+    thread,
+};
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use rand::{SeedableRng, rngs::StdRng};
+use skip_list2::{
+    arena::BlockArena,
+    comparator::DefaultComparator,
+    skip_list::{SkipList, SkipListIter},
+};
+
+// big enough that most of the list is out of cache; compare runs with and without
+// `--no-default-features` to see what the `prefetch` feature buys
+const COUNT: u64 = 12_000_000;
+const LOOKUPS: usize = 1_000;
+
+type List = SkipList<u64, u64, DefaultComparator<u64>, BlockArena>;
+
+// seeded, so every run searches the same towers
+static LIST: LazyLock<Arc<List>> = LazyLock::new(|| {
+    let list = SkipList::new(DefaultComparator::default(), BlockArena::new())
+        .with_rng(StdRng::seed_from_u64(0));
+    for i in 0..COUNT {
+        // spread keys so that neighbours in key order are not neighbours in memory
+        list.insert(scatter(i), i);
+    }
+    Arc::new(list)
+});
+
+fn scatter(i: u64) -> u64 {
+    i.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+fn bench_point_lookup(c: &mut Criterion) {
+    let list = &*LIST;
+    let keys: Vec<_> = (0..LOOKUPS as u64)
+        .map(|i| scatter(i.wrapping_mul(7_919) % COUNT))
+        .collect();
+
+    c.bench_function("point_lookup_12m", |b| {
+        let mut iter = list.iter();
+        b.iter(|| {
+            for key in &keys {
+                iter.seek(black_box(key));
+                black_box(iter.value());
+            }
+        })
+    });
+}
+
+// the same lookups after freezing the list, binary search instead of a tower walk
+fn bench_frozen_lookup(c: &mut Criterion) {
+    let frozen = LIST.clone().freeze();
+    let keys: Vec<_> = (0..LOOKUPS as u64)
+        .map(|i| scatter(i.wrapping_mul(7_919) % COUNT))
+        .collect();
+
+    c.bench_function("point_lookup_12m_frozen", |b| {
+        b.iter(|| {
+            for key in &keys {
+                black_box(frozen.get(black_box(key)));
+            }
+        })
+    });
+}
+
+// readers seek while writers keep inserting; the writers dirty the height counter and the
+// arena's bump pointer, so this shows whether those lines still bounce into the readers
+fn bench_read_while_write(c: &mut Criterion) {
+    const PRELOAD: u64 = 100_000;
+
+    let mut group = c.benchmark_group("read_while_write");
+    for writers in [0, 1, 4] {
+        let list = Arc::new(SkipList::new(
+            DefaultComparator::default(),
+            BlockArena::new(),
+        ));
+        for i in 0..PRELOAD {
+            list.insert(scatter(i), i);
+        }
+        let keys: Vec<_> = (0..LOOKUPS as u64)
+            .map(|i| scatter(i.wrapping_mul(7_919) % PRELOAD))
+            .collect();
+        let stop = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            for w in 0..writers {
+                let (list, stop) = (&list, &stop);
+                s.spawn(move || {
+                    let mut i = PRELOAD + w;
+                    while !stop.load(Relaxed) {
+                        list.insert(scatter(i), i);
+                        i += writers;
+                    }
+                });
+            }
+
+            group.bench_function(format!("{writers}_writers"), |b| {
+                let mut iter = list.iter();
+                b.iter(|| {
+                    for key in &keys {
+                        iter.seek(black_box(key));
+                        black_box(iter.value());
+                    }
+                })
+            });
+            stop.store(true, Relaxed);
+        });
+    }
+    group.finish();
+}
+
+// time-ordered seeks with a little jitter, from the head every time versus from the finger
+// left by the previous seek
+fn bench_jittered_seek(c: &mut Criterion) {
+    const ENTRIES: u64 = 1_000_000;
+    const SEEKS: u64 = 10_000;
+
+    let list = Arc::new(
+        SkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_rng(StdRng::seed_from_u64(0)),
+    );
+    for i in 0..ENTRIES {
+        list.insert(i * 4, i);
+    }
+    let start = ENTRIES / 2 * 4;
+    let trace: Vec<_> = (0..SEEKS).map(|i| start + i * 4 + (i * 7) % 13).collect();
+
+    let mut group = c.benchmark_group("jittered_seek");
+    group.bench_function("head", |b| {
+        let mut iter = list.iter();
+        b.iter(|| {
+            for key in &trace {
+                iter.seek(black_box(key));
+                black_box(iter.value());
+            }
+        })
+    });
+    group.bench_function("finger", |b| {
+        let mut iter = SkipListIter::with_finger(list.clone());
+        b.iter(|| {
+            for key in &trace {
+                iter.seek(black_box(key));
+                black_box(iter.value());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_point_lookup,
+    bench_frozen_lookup,
+    bench_read_while_write,
+    bench_jittered_seek
+);
+criterion_main!(benches);
@@ -0,0 +1,87 @@
+/*
+ * Drives the C interface end to end. From the crate directory:
+ *
+ *   cargo rustc --release --features ffi --crate-type staticlib
+ *   cc -Wall -Wextra -Iinclude examples/ffi.c target/release/libskip_list2.a \
+ *       -lpthread -ldl -lm -o target/ffi && target/ffi
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "skip_list2.h"
+
+#define CHECK(call, expected)                                                      \
+    do {                                                                           \
+        int32_t status_ = (call);                                                  \
+        if (status_ != (expected)) {                                               \
+            fprintf(stderr, "%s:%d: %s returned %d, expected %d\n", __FILE__,      \
+                    __LINE__, #call, status_, (expected));                         \
+            return 1;                                                              \
+        }                                                                          \
+    } while (0)
+
+static int32_t insert(SkipListHandle *list, const char *key, const char *value) {
+    return skiplist_insert(list, (const uint8_t *)key, strlen(key), (const uint8_t *)value,
+                           strlen(value));
+}
+
+int main(void) {
+    SkipListHandle *list = NULL;
+    CHECK(skiplist_create(&list), SKIPLIST_OK);
+
+    CHECK(insert(list, "banana", "yellow"), SKIPLIST_OK);
+    CHECK(insert(list, "apple", "red"), SKIPLIST_OK);
+    CHECK(insert(list, "cherry", "dark red"), SKIPLIST_OK);
+    CHECK(insert(list, "apple", "green"), SKIPLIST_KEY_EXISTS);
+    CHECK(skiplist_insert(NULL, NULL, 0, NULL, 0), SKIPLIST_NULL_POINTER);
+
+    /* a buffer too small, then one of the length asked for */
+    uint8_t small[4];
+    size_t len = 0;
+    CHECK(skiplist_get(list, (const uint8_t *)"cherry", 6, small, sizeof small, &len),
+          SKIPLIST_BUFFER_TOO_SMALL);
+    uint8_t buf[64];
+    CHECK(len <= sizeof buf, 1);
+    CHECK(skiplist_get(list, (const uint8_t *)"cherry", 6, buf, len, &len), SKIPLIST_OK);
+    CHECK(len == 8 && memcmp(buf, "dark red", 8) == 0, 1);
+    CHECK(skiplist_get(list, (const uint8_t *)"durian", 6, buf, sizeof buf, &len),
+          SKIPLIST_NOT_FOUND);
+
+    size_t usage = 0;
+    CHECK(skiplist_mem_usage(list, &usage), SKIPLIST_OK);
+    printf("mem usage: %zu bytes\n", usage);
+
+    SkipListIterHandle *iter = NULL;
+    CHECK(skiplist_iter_create(list, &iter), SKIPLIST_OK);
+    /* the iterator keeps the entries alive */
+    skiplist_destroy(list);
+
+    const char *expected[] = {"apple", "banana", "cherry"};
+    size_t seen = 0;
+    for (int32_t status = skiplist_iter_seek_to_first(iter); status == SKIPLIST_OK;
+         status = skiplist_iter_next(iter)) {
+        const uint8_t *key, *value;
+        size_t key_len, value_len;
+        CHECK(skiplist_iter_key(iter, &key, &key_len), SKIPLIST_OK);
+        CHECK(skiplist_iter_value(iter, &value, &value_len), SKIPLIST_OK);
+        printf("%.*s = %.*s\n", (int)key_len, (const char *)key, (int)value_len,
+               (const char *)value);
+        CHECK(seen < 3 && key_len == strlen(expected[seen]) &&
+                  memcmp(key, expected[seen], key_len) == 0,
+              1);
+        seen++;
+    }
+    CHECK(seen == 3, 1);
+
+    CHECK(skiplist_iter_seek(iter, (const uint8_t *)"b", 1), SKIPLIST_OK);
+    const uint8_t *key;
+    CHECK(skiplist_iter_key(iter, &key, &len), SKIPLIST_OK);
+    CHECK(len == 6 && memcmp(key, "banana", 6) == 0, 1);
+    CHECK(skiplist_iter_seek(iter, (const uint8_t *)"z", 1), SKIPLIST_NOT_FOUND);
+    CHECK(skiplist_iter_key(iter, &key, &len), SKIPLIST_NOT_FOUND);
+    skiplist_iter_destroy(iter);
+
+    puts("ok");
+    return 0;
+}
@@ -0,0 +1,40 @@
+// Runs `skip_list2::stress::run` with knobs from the command line, e.g.
+//
+//     cargo run --release --features stress --example stress -- --writers 8 --seconds 30
+use std::{process, time::Duration};
+
+use skip_list2::stress::{StressConfig, run};
+
+const USAGE: &str = "usage: stress [--writers N] [--readers N] [--seconds N] [--seed N] \
+                     [--keys N] [--branching N] [--max-height N]";
+
+fn main() {
+    let mut config = StressConfig::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let Some(value) = args.next().and_then(|v| v.parse::<u64>().ok()) else {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        };
+        match flag.as_str() {
+            "--writers" => config.writers = value as usize,
+            "--readers" => config.readers = value as usize,
+            "--seconds" => config.duration = Duration::from_secs(value),
+            "--seed" => config.seed = value,
+            "--keys" => config.key_space = value,
+            "--branching" => config.options.branching = value as u32,
+            "--max-height" => config.options.max_height = value as usize,
+            _ => {
+                eprintln!("{USAGE}");
+                process::exit(2);
+            }
+        }
+    }
+
+    println!("{config:?}");
+    let report = run(config);
+    println!(
+        "ok: {} inserted, {} seeks, {} steps",
+        report.inserted, report.seeks, report.steps
+    );
+}
@@ -0,0 +1,63 @@
+//! Allocates a sequence of arbitrary layouts from a `BlockArena` and checks that every
+//! region is aligned as asked and overlaps no other. Each region is filled with a byte of
+//! its own and checked again at the end, which catches overlaps the address ranges alone
+//! would miss, e.g. a block handed out twice.
+#![no_main]
+
+use std::{alloc::Layout, collections::BTreeMap};
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use skip_list2::arena::{BlockArena, MemAllocator};
+
+#[derive(Arbitrary, Debug)]
+struct Request {
+    // sizes 1 to 64k, with most of them small
+    size: u16,
+    shift: u8,
+    // up to 4k
+    align_log2: u8,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    capacity: Option<u16>,
+    requests: Vec<Request>,
+}
+
+fuzz_target!(|input: Input| {
+    let arena = match input.capacity {
+        Some(kb) => BlockArena::with_capacity(kb as usize * 16),
+        None => BlockArena::new(),
+    };
+    // start address to (end address, fill byte, start pointer)
+    let mut regions = BTreeMap::new();
+
+    for (i, request) in input.requests.iter().enumerate().take(512) {
+        let size = (request.size as usize >> (request.shift % 16)).max(1);
+        let align = 1 << (request.align_log2 % 13);
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let ptr = unsafe { arena.allocate(layout) }.unwrap().as_ptr();
+        let start = ptr.addr();
+        assert_eq!(start % align, 0, "{layout:?}");
+
+        let end = start + size;
+        if let Some((_, &(prev_end, _, _))) = regions.range(..end).next_back() {
+            assert!(
+                prev_end <= start,
+                "{layout:?} overlaps the region before it"
+            );
+        }
+        if let Some((&next_start, _)) = regions.range(start..).next() {
+            assert!(end <= next_start, "{layout:?} overlaps the region after it");
+        }
+        let fill = i as u8;
+        unsafe { ptr.write_bytes(fill, size) };
+        regions.insert(start, (end, fill, ptr));
+    }
+
+    for (&start, &(end, fill, ptr)) in &regions {
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, end - start) };
+        assert!(bytes.iter().all(|&b| b == fill));
+    }
+});
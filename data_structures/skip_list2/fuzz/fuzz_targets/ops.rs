@@ -0,0 +1,107 @@
+//! Runs a sequence of inserts, lookups and cursor moves against a `SkipList` and a
+//! `BTreeMap`, and checks that both give the same answers after every step. Keys are at
+//! most three bytes from a four letter alphabet, so sequences keep hitting equal keys,
+//! neighbours and the ends of the list.
+#![no_main]
+
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    ops::Bound,
+    sync::Arc,
+};
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use skip_list2::{
+    arena::BlockArena,
+    comparator::BytewiseComparator,
+    skip_list::{NodeError, SkipList},
+};
+
+#[derive(Arbitrary, Debug)]
+struct Key([u8; 3], u8);
+
+impl Key {
+    fn bytes(&self) -> Vec<u8> {
+        let len = self.1 as usize % (self.0.len() + 1);
+        self.0[..len].iter().map(|b| b'a' + b % 4).collect()
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Insert(Key, u8),
+    Get(Key),
+    Seek(Key),
+    SeekForPrev(Key),
+    SeekToFirst,
+    SeekToLast,
+    Next,
+    Prev,
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let list = Arc::new(SkipList::new(BytewiseComparator, BlockArena::new()));
+    let mut model = BTreeMap::new();
+    let mut iter = list.iter();
+    // the key the model's cursor is on
+    let mut cursor: Option<Vec<u8>> = None;
+
+    for op in &ops {
+        match op {
+            Op::Insert(key, value) => {
+                let key = key.bytes();
+                let result = list.try_insert(key.clone(), *value);
+                match model.entry(key) {
+                    Entry::Occupied(_) => assert_eq!(result, Err(NodeError::KeyExists)),
+                    Entry::Vacant(entry) => {
+                        assert_eq!(result, Ok(()));
+                        entry.insert(*value);
+                    }
+                }
+            }
+            Op::Get(key) => {
+                let key = key.bytes();
+                assert_eq!(list.get(&key), model.get(&key));
+            }
+            Op::Seek(key) => {
+                let key = key.bytes();
+                iter.seek(&key);
+                cursor = model.range(key..).next().map(|(k, _)| k.clone());
+            }
+            Op::SeekForPrev(key) => {
+                let key = key.bytes();
+                iter.seek_for_prev(&key);
+                cursor = model.range(..=key).next_back().map(|(k, _)| k.clone());
+            }
+            Op::SeekToFirst => {
+                iter.seek_to_first();
+                cursor = model.keys().next().cloned();
+            }
+            Op::SeekToLast => {
+                iter.seek_to_last();
+                cursor = model.keys().next_back().cloned();
+            }
+            Op::Next | Op::Prev => {
+                let Some(cur) = cursor.take() else {
+                    // both refuse to move off the end
+                    continue;
+                };
+                let bounds = (Bound::Excluded(cur), Bound::Unbounded);
+                if let Op::Next = op {
+                    iter.next();
+                    cursor = model.range(bounds).next().map(|(k, _)| k.clone());
+                } else {
+                    iter.prev();
+                    let bounds = (Bound::Unbounded, bounds.0);
+                    cursor = model.range(bounds).next_back().map(|(k, _)| k.clone());
+                }
+            }
+        }
+        assert_eq!(iter.key(), cursor.as_ref(), "after {op:?}");
+        assert_eq!(iter.value(), cursor.as_ref().map(|k| &model[k]));
+    }
+
+    assert_eq!(list.len(), model.len());
+    assert_eq!(list.validate(), Ok(()));
+});
@@ -0,0 +1,194 @@
+/* The C interface of skip_list2, see src/ffi.rs. Generated by cbindgen; do not edit. */
+
+#ifndef SKIP_LIST2_H
+#define SKIP_LIST2_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define SKIPLIST_OK 0
+
+/**
+ * The key is not in the list, or the iterator is not on an entry.
+ */
+#define SKIPLIST_NOT_FOUND 1
+
+/**
+ * The value is longer than the buffer; the length out pointer holds its length.
+ */
+#define SKIPLIST_BUFFER_TOO_SMALL 2
+
+#define SKIPLIST_KEY_EXISTS 3
+
+/**
+ * The node could not be allocated.
+ */
+#define SKIPLIST_ALLOC_FAILED 4
+
+/**
+ * The write buffer is full, see `SkipList::with_write_buffer_size`.
+ */
+#define SKIPLIST_WRITE_STALL 5
+
+/**
+ * A handle or an out pointer is null, or a non-empty buffer is.
+ */
+#define SKIPLIST_NULL_POINTER 6
+
+/**
+ * The call panicked, e.g. on a key or value of 4 GiB or more. The list is left usable.
+ */
+#define SKIPLIST_PANIC 7
+
+/**
+ * A list, from `skiplist_create`.
+ */
+typedef struct SkipListHandle SkipListHandle;
+
+/**
+ * A cursor over a list, from `skiplist_iter_create`. It keeps the list's nodes alive, so
+ * it may outlive the list handle it came from.
+ */
+typedef struct SkipListIterHandle SkipListIterHandle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an empty list into `*out`.
+ *
+ * # Safety
+ *
+ * `out` is null or valid for a write.
+ */
+int32_t skiplist_create(SkipListHandle **out);
+
+/**
+ * Frees a list and, once no iterator is left on them, its nodes. Null is ignored.
+ *
+ * # Safety
+ *
+ * `list` is null or from `skiplist_create`, and not used again.
+ */
+void skiplist_destroy(SkipListHandle *list);
+
+/**
+ * Copies the key and the value into a new entry.
+ *
+ * # Safety
+ *
+ * `list` is null or a live list handle, and `key` and `value` are null or valid for reads
+ * of their lengths.
+ */
+int32_t skiplist_insert(const SkipListHandle *list,
+                        const uint8_t *key,
+                        size_t key_len,
+                        const uint8_t *value,
+                        size_t value_len);
+
+/**
+ * Copies the value of `key` into `buf` and its length into `*value_len`. When the value
+ * is longer than `buf_len`, nothing is copied, `*value_len` is still set and the call
+ * returns `SKIPLIST_BUFFER_TOO_SMALL`, so that it can be retried with a buffer that fits.
+ *
+ * # Safety
+ *
+ * `list` is null or a live list handle, `key` is null or valid for reads of `key_len`
+ * bytes, `buf` is null or valid for writes of `buf_len` bytes, and `value_len` is null
+ * or valid for a write.
+ */
+int32_t skiplist_get(const SkipListHandle *list,
+                     const uint8_t *key,
+                     size_t key_len,
+                     uint8_t *buf,
+                     size_t buf_len,
+                     size_t *value_len);
+
+/**
+ * The bytes the list has allocated, into `*out`.
+ *
+ * # Safety
+ *
+ * `list` is null or a live list handle, and `out` is null or valid for a write.
+ */
+int32_t skiplist_mem_usage(const SkipListHandle *list, size_t *out);
+
+/**
+ * Creates an iterator over `list` into `*out`, not on any entry until it seeks.
+ *
+ * # Safety
+ *
+ * `list` is null or a live list handle, and `out` is null or valid for a write.
+ */
+int32_t skiplist_iter_create(const SkipListHandle *list, SkipListIterHandle **out);
+
+/**
+ * Frees an iterator. Null is ignored.
+ *
+ * # Safety
+ *
+ * `iter` is null or from `skiplist_iter_create`, and not used again.
+ */
+void skiplist_iter_destroy(SkipListIterHandle *iter);
+
+/**
+ * Moves to the first entry, returning `SKIPLIST_NOT_FOUND` when the list is empty.
+ *
+ * # Safety
+ *
+ * `iter` is null or a live iterator handle.
+ */
+int32_t skiplist_iter_seek_to_first(SkipListIterHandle *iter);
+
+/**
+ * Moves to the first entry at or after `key`, returning `SKIPLIST_NOT_FOUND` when there
+ * is none.
+ *
+ * # Safety
+ *
+ * `iter` is null or a live iterator handle, and `key` is null or valid for reads of
+ * `key_len` bytes.
+ */
+int32_t skiplist_iter_seek(SkipListIterHandle *iter, const uint8_t *key, size_t key_len);
+
+/**
+ * Moves to the next entry, returning `SKIPLIST_NOT_FOUND` when it moves past the last
+ * one or was not on an entry to begin with.
+ *
+ * # Safety
+ *
+ * `iter` is null or a live iterator handle.
+ */
+int32_t skiplist_iter_next(SkipListIterHandle *iter);
+
+/**
+ * Points `*key` and `*key_len` at the key of the entry the iterator is on. The bytes stay
+ * valid, and unchanged, until the iterator is destroyed.
+ *
+ * # Safety
+ *
+ * `iter` is null or a live iterator handle, and `key` and `key_len` are null or valid
+ * for a write.
+ */
+int32_t skiplist_iter_key(const SkipListIterHandle *iter, const uint8_t **key, size_t *key_len);
+
+/**
+ * Like `skiplist_iter_key`, for the value.
+ *
+ * # Safety
+ *
+ * As for `skiplist_iter_key`.
+ */
+int32_t skiplist_iter_value(const SkipListIterHandle *iter,
+                            const uint8_t **value,
+                            size_t *value_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SKIP_LIST2_H */
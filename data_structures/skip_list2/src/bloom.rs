@@ -0,0 +1,50 @@
+use std::sync::atomic::Ordering::Relaxed;
+
+use crate::sync::AtomicU64;
+
+// A bloom filter over a list's keys, see `SkipList::with_bloom`. Bits are only ever set,
+// so a key whose bits were set before its node was linked is never missed: the `Release`
+// CAS that links the node carries them along to whoever finds it.
+pub(crate) struct Bloom<K> {
+    bits: Box<[AtomicU64]>,
+    probes: u32,
+    hash: fn(&K) -> u64,
+}
+
+impl<K> Bloom<K> {
+    // `bits_per_key` for `expected` keys; more keys only raise the false positive rate
+    pub(crate) fn new(bits_per_key: usize, expected: usize, hash: fn(&K) -> u64) -> Self {
+        let words = (bits_per_key.saturating_mul(expected) / 64).max(1);
+        // ln 2 probes per bit per key is where the false positive rate bottoms out
+        let probes = (bits_per_key as f64 * std::f64::consts::LN_2).round() as u32;
+        Self {
+            bits: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            probes: probes.clamp(1, 30),
+            hash,
+        }
+    }
+
+    pub(crate) fn insert(&self, key: &K) {
+        for bit in self.bits_of(key) {
+            self.bits[bit / 64].fetch_or(1 << (bit % 64), Relaxed);
+        }
+    }
+
+    // false only for keys never inserted
+    pub(crate) fn may_contain(&self, key: &K) -> bool {
+        self.bits_of(key)
+            .all(|bit| self.bits[bit / 64].load(Relaxed) & 1 << (bit % 64) != 0)
+    }
+
+    // double hashing: the probes step through the bits by the upper half of the hash
+    fn bits_of(&self, key: &K) -> impl Iterator<Item = usize> {
+        let hash = (self.hash)(key);
+        let len = self.bits.len() as u64 * 64;
+        let (mut bit, step) = (hash & u32::MAX as u64, hash >> 32 | 1);
+        (0..self.probes).map(move |_| {
+            let at = bit % len;
+            bit = bit.wrapping_add(step);
+            at as usize
+        })
+    }
+}
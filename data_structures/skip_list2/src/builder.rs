@@ -0,0 +1,260 @@
+//! `SkipListBuilder`, for lists set up with more than a comparator and an allocator.
+
+use std::{fmt, marker::PhantomData, sync::Arc};
+
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::{
+    arena::{BlockArena, MemAllocator},
+    comparator::{Comparator, DefaultComparator},
+    skip_list::{DuplicatePolicy, MAX_HEIGHT, NodeError, SkipList, SkipListOptions},
+};
+
+/// Gathers a list's comparator, allocator and `SkipListOptions` one setter at a time, and
+/// checks them together in `build`. Left alone it builds what `SkipList::new` does with a
+/// `DefaultComparator` and a fresh `BlockArena`.
+pub struct SkipListBuilder<K, V, C = DefaultComparator<K>, A = BlockArena> {
+    comparator: C,
+    allocator: A,
+    options: SkipListOptions,
+    seed: Option<u64>,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> SkipListBuilder<K, V> {
+    pub fn new() -> Self {
+        SkipListBuilder {
+            comparator: DefaultComparator::default(),
+            allocator: BlockArena::new(),
+            options: SkipListOptions::default(),
+            seed: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for SkipListBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, C, A> SkipListBuilder<K, V, C, A> {
+    pub fn comparator<C2>(self, comparator: C2) -> SkipListBuilder<K, V, C2, A> {
+        SkipListBuilder {
+            comparator,
+            allocator: self.allocator,
+            options: self.options,
+            seed: self.seed,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn allocator<A2>(self, allocator: A2) -> SkipListBuilder<K, V, C, A2> {
+        SkipListBuilder {
+            comparator: self.comparator,
+            allocator,
+            options: self.options,
+            seed: self.seed,
+            _marker: PhantomData,
+        }
+    }
+
+    /// See `SkipListOptions::max_height`.
+    pub fn max_height(mut self, max_height: usize) -> Self {
+        self.options.max_height = max_height;
+        self
+    }
+
+    /// See `SkipListOptions::branching`.
+    pub fn branching(mut self, branching: u32) -> Self {
+        self.options.branching = branching;
+        self
+    }
+
+    /// See `SkipList::with_write_buffer_size`.
+    pub fn write_buffer_size(mut self, bytes: usize) -> Self {
+        self.options.write_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Draws the list's heights from its own generator seeded with `seed`, so that the
+    /// same inserts build the same towers; see `SkipList::with_rng`.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// See `SkipListOptions::duplicates`.
+    pub fn duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.options.duplicates = policy;
+        self
+    }
+}
+
+impl<K, V, C, A> SkipListBuilder<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn build(self) -> Result<SkipList<K, V, C, A>, BuildError> {
+        let options = self.options;
+        if !(1..=MAX_HEIGHT).contains(&options.max_height) {
+            return Err(BuildError::MaxHeight(options.max_height));
+        }
+        if options.branching < 2 {
+            return Err(BuildError::Branching(options.branching));
+        }
+        let list = SkipList::try_with_options(self.comparator, self.allocator, options)?;
+        Ok(match self.seed {
+            Some(seed) => list.with_rng(StdRng::seed_from_u64(seed)),
+            None => list,
+        })
+    }
+
+    pub fn build_arc(self) -> Result<Arc<SkipList<K, V, C, A>>, BuildError> {
+        self.build().map(Arc::new)
+    }
+}
+
+/// Why `SkipListBuilder::build` refused its settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// The max height is 0 or above what a tower can hold.
+    MaxHeight(usize),
+    /// The branching factor is below 2, which would never grow a tower.
+    Branching(u32),
+    /// The head could not be allocated.
+    Node(NodeError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::MaxHeight(height) => {
+                write!(f, "max height {height} is not between 1 and {MAX_HEIGHT}")
+            }
+            BuildError::Branching(branching) => {
+                write!(f, "branching factor {branching} is less than 2")
+            }
+            BuildError::Node(e) => write!(f, "failed to allocate the skip list head: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuildError::Node(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<NodeError> for BuildError {
+    fn from(e: NodeError) -> Self {
+        BuildError::Node(e)
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{BuildError, SkipListBuilder};
+    use crate::{
+        arena::{DefaultAllocator, FaultInjector},
+        comparator::Comparator,
+        skip_list::{DuplicatePolicy, NodeError, SkipListOptions},
+    };
+
+    struct Reverse;
+
+    impl Comparator for Reverse {
+        type Item = u32;
+
+        fn compare(&self, a: &u32, b: &u32) -> Ordering {
+            b.cmp(a)
+        }
+    }
+
+    #[test]
+    fn builds_what_it_was_told() {
+        let list = SkipListBuilder::<u32, u32>::new().build().unwrap();
+        assert_eq!(list.options(), SkipListOptions::default());
+        assert_eq!(
+            (list.options().max_height, list.options().branching),
+            (20, 4)
+        );
+
+        let list = SkipListBuilder::new()
+            .comparator(Reverse)
+            .allocator(DefaultAllocator::default())
+            .max_height(8)
+            .branching(2)
+            .write_buffer_size(1 << 20)
+            .duplicate_policy(DuplicatePolicy::KeepExisting)
+            .build_arc()
+            .unwrap();
+        let options = list.options();
+        assert_eq!((options.max_height, options.branching), (8, 2));
+        assert_eq!(options.write_buffer_size, Some(1 << 20));
+        for i in 0..100 {
+            list.insert(i, i);
+        }
+        // a duplicate is kept out without an error, and the first value stays
+        assert_eq!(list.try_insert(7, 0), Ok(()));
+        assert_eq!(list.get(&7), Some(&7));
+        assert_eq!(list.len(), 100);
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        assert_eq!(iter.key(), Some(&99));
+
+        // the same seed, the same towers, which the write buffer charges for
+        let towers = |seed| {
+            let list = SkipListBuilder::new()
+                .seed(seed)
+                .write_buffer_size(usize::MAX)
+                .build()
+                .unwrap();
+            for i in 0..1_000_u64 {
+                list.insert(i, ());
+            }
+            list.write_buffer_usage()
+        };
+        assert_eq!(towers(9), towers(9));
+        assert_ne!(towers(9), towers(10));
+    }
+
+    #[test]
+    fn refuses_bad_settings() {
+        let built = SkipListBuilder::<u32, u32>::new().max_height(0).build();
+        let err = built.err().unwrap();
+        assert_eq!(err, BuildError::MaxHeight(0));
+        assert_eq!(err.to_string(), "max height 0 is not between 1 and 32");
+        assert!(
+            SkipListBuilder::<u32, u32>::new()
+                .max_height(33)
+                .build()
+                .is_err()
+        );
+
+        let err = SkipListBuilder::<u32, u32>::new()
+            .branching(1)
+            .build()
+            .err();
+        assert_eq!(err, Some(BuildError::Branching(1)));
+        assert_eq!(
+            err.unwrap().to_string(),
+            "branching factor 1 is less than 2"
+        );
+
+        let failing = FaultInjector::new(DefaultAllocator::default());
+        failing.fail_after(0);
+        let err = SkipListBuilder::<u32, u32>::new()
+            .allocator(failing)
+            .build()
+            .err();
+        assert!(matches!(err, Some(BuildError::Node(NodeError::Alloc(_)))));
+    }
+}
@@ -0,0 +1,818 @@
+use std::{
+    cell::Cell,
+    cmp, mem,
+    ptr::NonNull,
+    slice,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering::Relaxed},
+    },
+};
+
+use rand::Rng;
+
+use crate::{
+    arena::{AllocError, MemAllocator},
+    comparator::Comparator,
+    skip_list::{InvariantViolation, NodeError, SkipList, SkipListIter, SkipListOptions},
+};
+
+/// A skip list of byte string keys and values that keeps both in the node, like LevelDB's
+/// memtable: an insert makes one allocation for the node, its tower, the key and the
+/// value, and copies the slices in. A `SkipList<Vec<u8>, Vec<u8>, _, _>` makes three and
+/// follows a pointer out of the node on every comparison.
+///
+/// `c` orders the keys as slices, e.g. `DefaultComparator<[u8]>`. Keys and values are
+/// shorter than 4 GiB each; longer ones panic. The methods are named and behave like
+/// `SkipList`'s. Values too long to want in the node can go elsewhere, see
+/// `with_blob_store`.
+pub struct BytesSkipList<C, A> {
+    list: Arc<SkipList<InlineEntry, (), ByKey<C>, A>>,
+}
+
+// Where a node's key and value sit: `key_len` bytes of key right behind its tower, then
+// `value_len` bytes of value, or for a value in the blob store a `BlobRef` and `BLOB` for
+// `value_len`. Also the stand-in for a key being searched for, see `probe`.
+struct InlineEntry {
+    ptr: NonNull<u8>,
+    key_len: u32,
+    value_len: u32,
+}
+
+// the bytes are never written after the insert that copied them in
+unsafe impl Send for InlineEntry {}
+unsafe impl Sync for InlineEntry {}
+
+impl InlineEntry {
+    // only ever compared against, so it borrows `key` for no longer than the search
+    fn probe(key: &[u8]) -> Self {
+        Self {
+            ptr: NonNull::from(key).cast(),
+            key_len: len_u32(key),
+            value_len: 0,
+        }
+    }
+
+    fn key(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.key_len as usize) }
+    }
+
+    fn value(&self) -> &[u8] {
+        if let Some(blob) = self.blob() {
+            // the store lives in the list's comparator, which outlives every entry
+            return unsafe { (*blob.store).get(blob.handle) };
+        }
+        unsafe {
+            let value = self.ptr.as_ptr().add(self.key_len as usize);
+            slice::from_raw_parts(value, self.value_len as usize)
+        }
+    }
+
+    fn blob(&self) -> Option<BlobRef> {
+        (self.value_len == BLOB).then(|| unsafe {
+            let blob = self.ptr.as_ptr().add(self.key_len as usize);
+            blob.cast::<BlobRef>().read_unaligned()
+        })
+    }
+
+    // the key and the value or `BlobRef` behind the tower
+    fn stored_len(&self) -> usize {
+        let value_len = match self.value_len {
+            BLOB => mem::size_of::<BlobRef>(),
+            len => len as usize,
+        };
+        self.key_len as usize + value_len
+    }
+
+    fn trailer(&self, _: &()) -> usize {
+        self.stored_len() + CHECKSUM_LEN
+    }
+
+    // Compares the checksum behind the key and value with theirs; only for entries in
+    // the list, a probe has none.
+    #[cfg(feature = "checksums")]
+    fn verify(&self) -> Result<(), ChecksumMismatch> {
+        let stored = unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.stored_len()) };
+        let expected = unsafe {
+            let checksum = self.ptr.as_ptr().add(stored.len());
+            u32::from_le_bytes(checksum.cast::<[u8; 4]>().read_unaligned())
+        };
+        let actual = crate::sst::crc32c(stored);
+        if actual == expected {
+            return Ok(());
+        }
+        Err(ChecksumMismatch {
+            key: self.key().to_vec(),
+            node: self.ptr.as_ptr().addr(),
+            position: None,
+            expected,
+            actual,
+        })
+    }
+
+    // the entry, or a panic naming it when its bytes no longer match their checksum
+    fn checked(&self) -> &Self {
+        #[cfg(feature = "checksums")]
+        if let Err(e) = self.verify() {
+            panic!("{e}");
+        }
+        self
+    }
+}
+
+// the bytes behind the key and value holding their CRC32C, with the `checksums` feature
+const CHECKSUM_LEN: usize = if cfg!(feature = "checksums") { 4 } else { 0 };
+
+// Writes the checksum of the `stored` bytes at `bytes` behind them, see `CHECKSUM_LEN`.
+//
+// # Safety
+//
+// `bytes` is the trailer of a node being made, `stored + CHECKSUM_LEN` bytes long and all
+// but the checksum written.
+unsafe fn seal(bytes: *mut u8, stored: usize) {
+    #[cfg(feature = "checksums")]
+    unsafe {
+        let checksum = crate::sst::crc32c(slice::from_raw_parts(bytes, stored));
+        bytes
+            .add(stored)
+            .cast::<[u8; 4]>()
+            .write_unaligned(checksum.to_le_bytes());
+    }
+    let _ = (bytes, stored);
+}
+
+/// An entry of a `BytesSkipList` whose bytes changed after it was inserted, as caught by
+/// the `checksums` feature: most likely a stray write into the arena.
+#[cfg(feature = "checksums")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// The key as it reads now, which may be the corrupted part.
+    pub key: Vec<u8>,
+    /// The address of the key's bytes in the arena.
+    pub node: usize,
+    /// The entry's index in key order, when found by `verify_checksums`.
+    pub position: Option<usize>,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+#[cfg(feature = "checksums")]
+impl std::fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "checksum mismatch in the entry of key {:x?}", self.key)?;
+        if let Some(position) = self.position {
+            write!(f, ", entry {position}")?;
+        }
+        write!(
+            f,
+            ", at {:#x}: stored {:#010x}, computed {:#010x}",
+            self.node, self.expected, self.actual
+        )
+    }
+}
+
+#[cfg(feature = "checksums")]
+impl std::error::Error for ChecksumMismatch {}
+
+// Entries drop with the list, or when an insert loses the race for its key, and a probe
+// never has a blob.
+impl Drop for InlineEntry {
+    fn drop(&mut self) {
+        if let Some(blob) = self.blob() {
+            unsafe { (*blob.store).release(blob.handle) };
+        }
+    }
+}
+
+// the `value_len` of an entry whose value is in the blob store
+const BLOB: u32 = u32::MAX;
+
+// What a node holds in place of a value that went to the blob store, unaligned behind the
+// key.
+#[derive(Clone, Copy)]
+struct BlobRef {
+    store: *const dyn BlobStore,
+    handle: u64,
+}
+
+fn len_u32(bytes: &[u8]) -> u32 {
+    // `BLOB` is not a length
+    u32::try_from(bytes.len())
+        .ok()
+        .filter(|&len| len != BLOB)
+        .expect("keys and values are shorter than 4 GiB")
+}
+
+/// Where a `BytesSkipList` keeps the values too long to inline, see `with_blob_store`.
+/// The list releases each handle it was given exactly once: when the list drops, or right
+/// away for an insert that fails.
+pub trait BlobStore: Send + Sync {
+    /// Copies `value` in and returns the handle to read it by.
+    fn put(&self, value: &[u8]) -> Result<u64, AllocError>;
+
+    /// The value under `handle`, whose bytes stay where they are until it is released.
+    fn get(&self, handle: u64) -> &[u8];
+
+    fn release(&self, handle: u64);
+
+    /// Bytes held for the values not yet released.
+    fn mem_usage(&self) -> usize;
+}
+
+/// The `BlobStore` of `with_blobs`, with every value in a heap allocation of its own.
+#[derive(Debug, Default)]
+pub struct HeapBlobStore {
+    slots: Mutex<BlobSlots>,
+    bytes: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct BlobSlots {
+    blobs: Vec<Option<Box<[u8]>>>,
+    // released slots, reused first
+    free: Vec<usize>,
+}
+
+impl BlobStore for HeapBlobStore {
+    fn put(&self, value: &[u8]) -> Result<u64, AllocError> {
+        let mut blob = Vec::new();
+        blob.try_reserve_exact(value.len())
+            .map_err(|_| AllocError)?;
+        blob.extend_from_slice(value);
+        let blob = Some(blob.into_boxed_slice());
+
+        let mut slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+        let handle = match slots.free.pop() {
+            Some(slot) => {
+                slots.blobs[slot] = blob;
+                slot
+            }
+            None => {
+                slots.blobs.push(blob);
+                slots.blobs.len() - 1
+            }
+        };
+        self.bytes.fetch_add(value.len(), Relaxed);
+        Ok(handle as u64)
+    }
+
+    fn get(&self, handle: u64) -> &[u8] {
+        let slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+        let blob = slots.blobs[handle as usize]
+            .as_deref()
+            .expect("a blob read after its release");
+        // the box, unlike the slot that holds it, stays put until released
+        unsafe { slice::from_raw_parts(blob.as_ptr(), blob.len()) }
+    }
+
+    fn release(&self, handle: u64) {
+        let mut slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+        let blob = slots.blobs[handle as usize]
+            .take()
+            .expect("a blob released twice");
+        slots.free.push(handle as usize);
+        self.bytes.fetch_sub(blob.len(), Relaxed);
+    }
+
+    fn mem_usage(&self) -> usize {
+        self.bytes.load(Relaxed)
+    }
+}
+
+struct ByKey<C> {
+    c: C,
+    blobs: Option<Blobs>,
+}
+
+struct Blobs {
+    threshold: usize,
+    store: Box<dyn BlobStore>,
+}
+
+impl<C> ByKey<C> {
+    fn new(c: C) -> Self {
+        Self { c, blobs: None }
+    }
+}
+
+impl<C: Comparator<Item = [u8]>> Comparator for ByKey<C> {
+    type Item = InlineEntry;
+
+    fn compare(&self, a: &InlineEntry, b: &InlineEntry) -> cmp::Ordering {
+        self.c.compare(a.key(), b.key())
+    }
+}
+
+impl<C, A> BytesSkipList<C, A>
+where
+    C: Comparator<Item = [u8]>,
+    A: MemAllocator,
+{
+    pub fn new(c: C, a: A) -> Self {
+        Self::from_list(SkipList::new(ByKey::new(c), a))
+    }
+
+    pub fn try_new(c: C, a: A) -> Result<Self, NodeError> {
+        SkipList::try_new(ByKey::new(c), a).map(Self::from_list)
+    }
+
+    pub fn with_options(c: C, a: A, options: SkipListOptions) -> Self {
+        Self::from_list(SkipList::with_options(ByKey::new(c), a, options))
+    }
+
+    pub fn try_with_options(c: C, a: A, options: SkipListOptions) -> Result<Self, NodeError> {
+        SkipList::try_with_options(ByKey::new(c), a, options).map(Self::from_list)
+    }
+
+    fn from_list(list: SkipList<InlineEntry, (), ByKey<C>, A>) -> Self {
+        Self {
+            list: Arc::new(list.with_trailer(InlineEntry::trailer)),
+        }
+    }
+
+    /// See `SkipList::with_write_buffer_size`; a node counts with its key and value bytes.
+    pub fn with_write_buffer_size(self, bytes: usize) -> Self {
+        let list = Arc::into_inner(self.list).expect("set the write buffer before iterating");
+        Self::from_list(list.with_write_buffer_size(bytes))
+    }
+
+    /// See `SkipList::with_rng`.
+    pub fn with_rng(self, rng: impl Rng + Send + 'static) -> Self {
+        let list = Arc::into_inner(self.list).expect("set the generator before iterating");
+        Self::from_list(list.with_rng(rng))
+    }
+
+    /// `with_blob_store` on a `HeapBlobStore`.
+    pub fn with_blobs(self, threshold: usize) -> Self {
+        self.with_blob_store(threshold, HeapBlobStore::default())
+    }
+
+    /// Puts values longer than `threshold` bytes in `store`, the node keeping a handle in
+    /// their place, so that a few huge values do not spread the keys over the arena for
+    /// scans that never read them. `get` and iterators read through to the store; shorter
+    /// values stay inline. The bytes in the store count towards `mem_usage`, and towards
+    /// the write buffer with their entries. Panics once the list has entries.
+    pub fn with_blob_store(self, threshold: usize, store: impl BlobStore + 'static) -> Self {
+        let mut list = Arc::into_inner(self.list).expect("set the blob store before iterating");
+        assert!(list.is_empty(), "set the blob store before inserting");
+        list.comparator_mut().blobs = Some(Blobs {
+            threshold,
+            store: Box::new(store),
+        });
+        Self::from_list(list)
+    }
+
+    pub fn options(&self) -> SkipListOptions {
+        self.list.options()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn write_buffer_usage(&self) -> usize {
+        self.list.write_buffer_usage()
+    }
+
+    /// With the `checksums` feature, panics when the entry's bytes do not match their
+    /// checksum; so do the iterator's `key` and `value`.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let (entry, _) = self.list.get_entry(&InlineEntry::probe(key))?;
+        Some(entry.checked().value())
+    }
+
+    pub(crate) fn comparator(&self) -> &C {
+        &self.list.comparator().c
+    }
+
+    // the first key at or after `key`, with its value
+    pub(crate) fn lower_bound(&self, key: &[u8]) -> Option<(&[u8], &[u8])> {
+        let (entry, _) = self.list.lower_bound(&InlineEntry::probe(key))?;
+        Some((entry.key(), entry.value()))
+    }
+
+    /// Panics when the key is already in the list, the node cannot be allocated or the
+    /// write buffer is full; see `try_insert`.
+    pub fn insert(&self, key: &[u8], value: &[u8]) {
+        if let Err(e) = self.try_insert(key, value) {
+            panic!("failed to insert into the skip list: {e}");
+        }
+    }
+
+    /// See `SkipList::try_insert`.
+    pub fn try_insert(&self, key: &[u8], value: &[u8]) -> Result<(), NodeError> {
+        let blobs = self.list.comparator().blobs.as_ref();
+        if let Some(blobs) = blobs.filter(|blobs| value.len() > blobs.threshold) {
+            return self.insert_blob(key, value, blobs);
+        }
+        let (key_len, value_len) = (len_u32(key), len_u32(value));
+        let stored = key.len() + value.len();
+        self.list
+            .try_insert_with(stored + CHECKSUM_LEN, 0, |bytes| unsafe {
+                bytes.copy_from_nonoverlapping(key.as_ptr(), key.len());
+                bytes
+                    .add(key.len())
+                    .copy_from_nonoverlapping(value.as_ptr(), value.len());
+                seal(bytes, stored);
+                let entry = InlineEntry {
+                    // the node's own allocation, never null
+                    ptr: NonNull::new_unchecked(bytes),
+                    key_len,
+                    value_len,
+                };
+                (entry, ())
+            })
+    }
+
+    fn insert_blob(&self, key: &[u8], value: &[u8], blobs: &Blobs) -> Result<(), NodeError> {
+        let key_len = len_u32(key);
+        let blob = BlobRef {
+            store: &*blobs.store,
+            handle: blobs.store.put(value)?,
+        };
+        let stored = key.len() + mem::size_of::<BlobRef>();
+        let made = Cell::new(false);
+        let inserted =
+            self.list
+                .try_insert_with(stored + CHECKSUM_LEN, value.len(), |bytes| unsafe {
+                    made.set(true);
+                    bytes.copy_from_nonoverlapping(key.as_ptr(), key.len());
+                    bytes.add(key.len()).cast::<BlobRef>().write_unaligned(blob);
+                    seal(bytes, stored);
+                    let entry = InlineEntry {
+                        ptr: NonNull::new_unchecked(bytes),
+                        key_len,
+                        value_len: BLOB,
+                    };
+                    (entry, ())
+                });
+        if inserted.is_err() && !made.get() {
+            // no entry was made to release it on drop
+            blobs.store.release(blob.handle);
+        }
+        inserted
+    }
+
+    /// Bytes allocated for nodes, and held by the blob store.
+    pub fn mem_usage(&self) -> usize {
+        self.list.mem_usage() + self.blob_usage()
+    }
+
+    /// See `SkipList::useful_mem_usage`; blobs count in full.
+    pub fn useful_mem_usage(&self) -> usize {
+        self.list.useful_mem_usage() + self.blob_usage()
+    }
+
+    fn blob_usage(&self) -> usize {
+        let blobs = self.list.comparator().blobs.as_ref();
+        blobs.map_or(0, |blobs| blobs.store.mem_usage())
+    }
+
+    pub fn iter(&self) -> BytesIter<C, A> {
+        BytesIter {
+            inner: self.list.iter(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.list.validate()
+    }
+
+    /// Checks every entry's bytes against their checksum, in key order, and returns the
+    /// first that do not match.
+    #[cfg(feature = "checksums")]
+    pub fn verify_checksums(&self) -> Result<(), ChecksumMismatch> {
+        for (position, (entry, _)) in self.list.entries().enumerate() {
+            entry.verify().map_err(|e| ChecksumMismatch {
+                position: Some(position),
+                ..e
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// The cursor of a `BytesSkipList`, with the same methods as `SkipListIter`.
+pub struct BytesIter<C, A> {
+    inner: SkipListIter<InlineEntry, (), ByKey<C>, A>,
+}
+
+impl<C, A> BytesIter<C, A>
+where
+    C: Comparator<Item = [u8]>,
+    A: MemAllocator,
+{
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    pub fn key(&self) -> Option<&[u8]> {
+        self.inner.key().map(|entry| entry.checked().key())
+    }
+
+    pub fn value(&self) -> Option<&[u8]> {
+        self.inner.key().map(|entry| entry.checked().value())
+    }
+
+    /// Checks the entry the iterator is on against its checksum, instead of `key` and
+    /// `value` panicking on it.
+    #[cfg(feature = "checksums")]
+    pub fn verify(&self) -> Result<(), ChecksumMismatch> {
+        self.inner.key().map_or(Ok(()), InlineEntry::verify)
+    }
+
+    pub fn next(&mut self) {
+        self.inner.next();
+    }
+
+    pub fn prev(&mut self) {
+        self.inner.prev();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last();
+    }
+
+    pub fn seek(&mut self, key: &[u8]) {
+        self.inner.seek(&InlineEntry::probe(key));
+    }
+
+    pub fn seek_for_prev(&mut self, key: &[u8]) {
+        self.inner.seek_for_prev(&InlineEntry::probe(key));
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{
+        cmp,
+        collections::HashSet,
+        sync::{Arc, Mutex},
+    };
+
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use crate::{
+        arena::{AllocError, BlockArena, DefaultAllocator},
+        comparator::{BytewiseComparator, Comparator, DefaultComparator},
+        skip_list::{NodeError, SkipList, WriteStall},
+    };
+
+    use super::{BlobStore, BytesSkipList, HeapBlobStore};
+
+    #[test]
+    fn bytes_entries() {
+        let list = BytesSkipList::new(DefaultComparator::<[u8]>::default(), BlockArena::new());
+        for i in (0..500_u32).rev() {
+            list.insert(format!("key{i:04}").as_bytes(), &i.to_le_bytes());
+        }
+        list.insert(b"", b"empty");
+        list.insert(b"long", &[7; 5000]);
+        assert_eq!(list.try_insert(b"key0042", b""), Err(NodeError::KeyExists));
+        assert_eq!(list.len(), 502);
+        assert_eq!(list.validate(), Ok(()));
+
+        assert_eq!(list.get(b"key0042"), Some(&42_u32.to_le_bytes()[..]));
+        assert_eq!(list.get(b""), Some(&b"empty"[..]));
+        assert_eq!(list.get(b"long"), Some(&[7; 5000][..]));
+        assert_eq!(list.get(b"key"), None);
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        assert_eq!(iter.key(), Some(&b""[..]));
+        iter.next();
+        for i in 0..500_u32 {
+            assert_eq!(iter.key(), Some(format!("key{i:04}").as_bytes()));
+            assert_eq!(iter.value(), Some(&i.to_le_bytes()[..]));
+            iter.next();
+        }
+        assert_eq!(iter.key(), Some(&b"long"[..]));
+        iter.seek(b"key0100x");
+        assert_eq!(iter.key(), Some(&b"key0101"[..]));
+        iter.seek_for_prev(b"key0100x");
+        assert_eq!(iter.key(), Some(&b"key0100"[..]));
+        iter.prev();
+        assert_eq!(iter.key(), Some(&b"key0099"[..]));
+    }
+
+    #[test]
+    fn bytes_custom_order() {
+        struct Reverse;
+
+        impl Comparator for Reverse {
+            type Item = [u8];
+
+            fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+                b.cmp(a)
+            }
+        }
+
+        let list = BytesSkipList::new(Reverse, BlockArena::new());
+        for key in [&b"b"[..], b"a", b"c", b"ab"] {
+            list.insert(key, key);
+        }
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        let mut keys = vec![];
+        while let Some(key) = iter.key() {
+            assert_eq!(iter.value(), Some(key));
+            keys.push(key.to_vec());
+            iter.next();
+        }
+        assert_eq!(keys, [&b"c"[..], b"b", b"ab", b"a"]);
+    }
+
+    #[test]
+    fn bytes_node_layouts() {
+        // a refused node goes back with the layout it was allocated with, which the
+        // default allocator checks
+        let list = BytesSkipList::new(
+            DefaultComparator::<[u8]>::default(),
+            DefaultAllocator::default(),
+        );
+        list.insert(b"key", b"value");
+        let usage = list.mem_usage();
+        assert_eq!(
+            list.try_insert(b"key", b"another value"),
+            Err(NodeError::KeyExists)
+        );
+        assert_eq!(list.mem_usage(), usage);
+
+        // the write buffer counts the bytes with the node
+        let list = BytesSkipList::new(DefaultComparator::<[u8]>::default(), BlockArena::new())
+            .with_write_buffer_size(4096);
+        list.insert(b"k", &[0; 4096]);
+        assert!(list.write_buffer_usage() > 4096);
+        assert_eq!(
+            list.try_insert(b"l", b""),
+            Err(NodeError::Stall(WriteStall::MemtableFull))
+        );
+    }
+
+    #[test]
+    #[cfg_attr(
+        feature = "checksums",
+        ignore = "the checksum makes every node 8 bytes bigger"
+    )]
+    fn bytes_take_less_memory() {
+        const COUNT: u64 = if cfg!(miri) { 1_000 } else { 1_000_000 };
+
+        // the same towers for both lists
+        let rng = || StdRng::seed_from_u64(0);
+        let inline = BytesSkipList::new(DefaultComparator::<[u8]>::default(), BlockArena::new())
+            .with_rng(rng());
+        let vecs = SkipList::new(BytewiseComparator, BlockArena::new()).with_rng(rng());
+        let mut heap = 0;
+        for i in 0..COUNT {
+            let (key, value) = ((i * 7_919 % COUNT).to_be_bytes(), i.to_le_bytes());
+            inline.insert(&key, &value);
+            let (key, value) = (key.to_vec(), value.to_vec());
+            // not even counting what malloc keeps per allocation
+            heap += key.capacity() + value.capacity();
+            vecs.insert(key, value);
+        }
+
+        // 8 byte keys and values: 56 bytes plus the tower against 88
+        let inline = inline.useful_mem_usage();
+        let boxed = vecs.useful_mem_usage() + heap;
+        assert!(inline * 10 < boxed * 7, "{inline} vs {boxed}");
+    }
+
+    #[test]
+    fn blob_values() {
+        // the handles not yet released, shared with the test
+        #[derive(Default)]
+        struct Tracked {
+            store: HeapBlobStore,
+            live: Arc<Mutex<HashSet<u64>>>,
+        }
+
+        impl BlobStore for Tracked {
+            fn put(&self, value: &[u8]) -> Result<u64, AllocError> {
+                let handle = self.store.put(value)?;
+                assert!(self.live.lock().unwrap().insert(handle));
+                Ok(handle)
+            }
+
+            fn get(&self, handle: u64) -> &[u8] {
+                self.store.get(handle)
+            }
+
+            fn release(&self, handle: u64) {
+                assert!(self.live.lock().unwrap().remove(&handle), "released twice");
+                self.store.release(handle);
+            }
+
+            fn mem_usage(&self) -> usize {
+                self.store.mem_usage()
+            }
+        }
+
+        let huge = if cfg!(miri) { 64 << 10 } else { 4 << 20 };
+        let store = Tracked::default();
+        let live = store.live.clone();
+        let list = BytesSkipList::new(DefaultComparator::<[u8]>::default(), BlockArena::new())
+            .with_blob_store(1024, store);
+        for i in 0..50_u32 {
+            let value = if i % 10 == 0 {
+                vec![i as u8; huge]
+            } else {
+                i.to_le_bytes().to_vec()
+            };
+            list.insert(format!("key{i:02}").as_bytes(), &value);
+        }
+        // up to the threshold stays inline
+        list.insert(b"limit", &[1; 1024]);
+        assert_eq!(live.lock().unwrap().len(), 5);
+        assert!(list.mem_usage() >= 5 * huge);
+        // the nodes take nowhere near one huge value
+        assert!(list.mem_usage() - 5 * huge < huge);
+
+        assert_eq!(list.get(b"key20"), Some(&vec![20; huge][..]));
+        assert_eq!(list.get(b"key21"), Some(&21_u32.to_le_bytes()[..]));
+        assert_eq!(list.get(b"limit"), Some(&[1; 1024][..]));
+        // a duplicate key gives its blob back
+        assert_eq!(
+            list.try_insert(b"key30", &vec![0; huge]),
+            Err(NodeError::KeyExists)
+        );
+        assert_eq!(live.lock().unwrap().len(), 5);
+
+        let mut iter = list.iter();
+        iter.seek(b"key10");
+        assert_eq!(iter.value(), Some(&vec![10; huge][..]));
+        iter.next();
+        assert_eq!(iter.value(), Some(&11_u32.to_le_bytes()[..]));
+        // the iterator keeps the blobs, and the last of the two to go releases them
+        drop(list);
+        iter.seek(b"key40");
+        assert_eq!(iter.value(), Some(&vec![40; huge][..]));
+        drop(iter);
+        assert!(live.lock().unwrap().is_empty());
+
+        // blobs count towards the write buffer, and one refused by it is released
+        let store = Tracked::default();
+        let live = store.live.clone();
+        let list = BytesSkipList::new(DefaultComparator::<[u8]>::default(), BlockArena::new())
+            .with_write_buffer_size(huge)
+            .with_blob_store(16, store);
+        list.insert(b"a", &vec![0; huge]);
+        assert!(list.write_buffer_usage() > huge);
+        assert_eq!(
+            list.try_insert(b"b", &[0; 64]),
+            Err(NodeError::Stall(WriteStall::MemtableFull))
+        );
+        assert_eq!(live.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "checksums")]
+    fn checksums_catch_stray_writes() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        use super::InlineEntry;
+
+        let list = BytesSkipList::new(DefaultComparator::<[u8]>::default(), BlockArena::new())
+            .with_blobs(64);
+        for i in 0..100_u32 {
+            list.insert(format!("key{i:03}").as_bytes(), &i.to_le_bytes());
+        }
+        list.insert(b"blob", &[1; 100]);
+        assert_eq!(list.verify_checksums(), Ok(()));
+
+        // what a stray write from elsewhere does: one byte of a value, bypassing the list
+        let (entry, _) = list.list.get_entry(&InlineEntry::probe(b"key042")).unwrap();
+        unsafe { *entry.ptr.as_ptr().add(b"key042".len()) ^= 0x10 };
+
+        let e = list.verify_checksums().unwrap_err();
+        assert_eq!(e.key, b"key042");
+        // "blob" sorts first
+        assert_eq!(e.position, Some(43));
+        assert_ne!(e.expected, e.actual);
+        assert!(list.get(b"key041").is_some());
+        let caught = panic::catch_unwind(AssertUnwindSafe(|| list.get(b"key042"))).unwrap_err();
+        let message = caught.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("checksum mismatch"), "{message}");
+
+        let mut iter = list.iter();
+        iter.seek(b"key042");
+        assert_eq!(iter.verify().unwrap_err().position, None);
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| iter.value())).is_err());
+        iter.next();
+        assert_eq!(iter.verify(), Ok(()));
+        assert_eq!(iter.value(), Some(&43_u32.to_le_bytes()[..]));
+
+        // and of a key, through the blob's descriptor no less
+        let (entry, _) = list.list.get_entry(&InlineEntry::probe(b"blob")).unwrap();
+        unsafe { *entry.ptr.as_ptr() = b'g' };
+        assert_eq!(list.verify_checksums().unwrap_err().key, b"glob");
+    }
+}
@@ -0,0 +1,192 @@
+//! Lists dumped column-wise, for handing to Arrow-style processing: the keys concatenated
+//! into one buffer and the values into another, each with an offsets array in which entry
+//! `i` spans `offsets[i]..offsets[i + 1]`.
+
+use crate::{arena::MemAllocator, comparator::Comparator, skip_list::SkipList};
+
+/// Keys and values a `ColumnarDump` can take, as the bytes it copies.
+pub trait ToBytes {
+    fn to_bytes(&self) -> &[u8];
+}
+
+impl ToBytes for [u8] {
+    fn to_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<const N: usize> ToBytes for [u8; N] {
+    fn to_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl ToBytes for Vec<u8> {
+    fn to_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl ToBytes for Box<[u8]> {
+    fn to_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl ToBytes for str {
+    fn to_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl ToBytes for String {
+    fn to_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl ToBytes for Box<str> {
+    fn to_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+/// No bytes, for the values of a list used as a set.
+impl ToBytes for () {
+    fn to_bytes(&self) -> &[u8] {
+        &[]
+    }
+}
+
+impl<T: ToBytes + ?Sized> ToBytes for &T {
+    fn to_bytes(&self) -> &[u8] {
+        (**self).to_bytes()
+    }
+}
+
+/// A list's entries in key order, from `SkipList::dump_columnar`. Each offsets array has
+/// one more element than there are entries and starts at 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnarDump {
+    pub keys: Vec<u8>,
+    pub key_offsets: Vec<u32>,
+    pub values: Vec<u8>,
+    pub value_offsets: Vec<u32>,
+}
+
+impl ColumnarDump {
+    pub fn len(&self) -> usize {
+        self.key_offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn key(&self, i: usize) -> &[u8] {
+        column(&self.keys, &self.key_offsets, i)
+    }
+
+    pub fn value(&self, i: usize) -> &[u8] {
+        column(&self.values, &self.value_offsets, i)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        (0..self.len()).map(|i| (self.key(i), self.value(i)))
+    }
+}
+
+fn column<'a>(bytes: &'a [u8], offsets: &[u32], i: usize) -> &'a [u8] {
+    &bytes[offsets[i] as usize..offsets[i + 1] as usize]
+}
+
+// appends `bytes` and the offset of their end
+fn push(column: &mut Vec<u8>, offsets: &mut Vec<u32>, bytes: &[u8]) {
+    column.extend_from_slice(bytes);
+    let end = u32::try_from(column.len()).expect("a column is shorter than 4 GiB");
+    offsets.push(end);
+}
+
+impl<K, V, C, A> SkipList<K, V, C, A>
+where
+    K: ToBytes,
+    V: ToBytes,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    /// Copies the entries into a `ColumnarDump`, with the four buffers sized by a first
+    /// walk over the list so that the copying walk does not grow them. Entries inserted
+    /// meanwhile are copied or not, and can make a buffer grow after all. Panics when the
+    /// keys or the values take 4 GiB or more.
+    pub fn dump_columnar(&self) -> ColumnarDump {
+        let (mut len, mut key_bytes, mut value_bytes) = (0, 0, 0);
+        for (key, value) in self.entries() {
+            len += 1;
+            key_bytes += key.to_bytes().len();
+            value_bytes += value.to_bytes().len();
+        }
+
+        let mut dump = ColumnarDump {
+            keys: Vec::with_capacity(key_bytes),
+            key_offsets: Vec::with_capacity(len + 1),
+            values: Vec::with_capacity(value_bytes),
+            value_offsets: Vec::with_capacity(len + 1),
+        };
+        dump.key_offsets.push(0);
+        dump.value_offsets.push(0);
+        for (key, value) in self.entries() {
+            push(&mut dump.keys, &mut dump.key_offsets, key.to_bytes());
+            push(&mut dump.values, &mut dump.value_offsets, value.to_bytes());
+        }
+        dump
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use crate::{
+        arena::BlockArena,
+        comparator::{BytewiseComparator, DefaultComparator},
+        skip_list::SkipList,
+    };
+
+    #[test]
+    fn dump_round_trip() {
+        let list = SkipList::new(BytewiseComparator, BlockArena::new());
+        for i in (0..1000_u32).rev() {
+            // some empty values, and keys of several lengths
+            let value = if i % 7 == 0 {
+                String::new()
+            } else {
+                format!("value {i}")
+            };
+            list.insert(i.to_string().into_bytes(), value);
+        }
+        let dump = list.dump_columnar();
+        assert_eq!(dump.len(), 1000);
+        assert_eq!(dump.key_offsets.len(), 1001);
+        assert_eq!(dump.key(0), b"0");
+        assert_eq!(dump.value(1), b"value 1");
+
+        let restored = SkipList::from_sorted_iter(
+            dump.iter()
+                .map(|(key, value)| (key.to_vec(), String::from_utf8(value.to_vec()).unwrap())),
+            BytewiseComparator,
+            BlockArena::new(),
+        );
+        assert_eq!(restored.len(), list.len());
+        assert!(list.entries().eq(restored.entries()));
+        assert_eq!(restored.dump_columnar(), dump);
+
+        let set = SkipList::new(DefaultComparator::<Box<str>>::default(), BlockArena::new());
+        let dump = set.dump_columnar();
+        assert!(dump.is_empty());
+        assert_eq!((dump.key_offsets, dump.value_offsets), (vec![0], vec![0]));
+        set.insert("a".into(), ());
+        set.insert("bc".into(), ());
+        let dump = set.dump_columnar();
+        assert_eq!(dump.keys, b"abc");
+        assert_eq!(dump.key_offsets, [0, 1, 3]);
+        assert_eq!(dump.value_offsets, [0, 0, 0]);
+    }
+}
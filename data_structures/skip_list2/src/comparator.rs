@@ -1,19 +1,42 @@
 use std::{cmp, marker::PhantomData};
 
 pub trait Comparator: Send + Sync {
-    type Item;
+    type Item: ?Sized;
 
     fn compare(&self, a: &Self::Item, b: &Self::Item) -> cmp::Ordering;
+
+    /// Names the order, for telling apart data sorted by different comparators; the type's
+    /// name by default. Comparators of one type that order differently should override it.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct DefaultComparator<T> {
+#[derive(Debug)]
+pub struct DefaultComparator<T: ?Sized> {
     _marker: PhantomData<T>,
 }
 
+// not derived, that would require `T: Default`, or `T: Clone` and so `T: Sized`
+impl<T: ?Sized> Default for DefaultComparator<T> {
+    fn default() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Clone for DefaultComparator<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for DefaultComparator<T> {}
+
 impl<T> Comparator for DefaultComparator<T>
 where
-    T: Send + Sync + Ord,
+    T: Send + Sync + Ord + ?Sized,
 {
     type Item = T;
 
@@ -21,3 +44,19 @@ where
         a.cmp(b)
     }
 }
+
+/// Orders byte string keys by their bytes, like LevelDB's comparator of the same name.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BytewiseComparator;
+
+impl Comparator for BytewiseComparator {
+    type Item = Vec<u8>;
+
+    fn compare(&self, a: &Self::Item, b: &Self::Item) -> cmp::Ordering {
+        a.as_slice().cmp(b.as_slice())
+    }
+
+    fn name(&self) -> &str {
+        "leveldb.BytewiseComparator"
+    }
+}
@@ -0,0 +1,421 @@
+//! LevelDB's memtable entry format, for lists whose entries have to be byte for byte what
+//! LevelDB would write:
+//!
+//! ```text
+//! varint32(internal_key_len) | user_key | u64 le(seq << 8 | type) | varint32(value_len) | value
+//! ```
+//!
+//! The internal key is the user key and the 8 byte tag. Stored as the keys of a
+//! `BytesSkipList` with empty values and ordered by `MemTableKeyComparator`, whole entries
+//! sit in the arena next to their nodes, as in LevelDB.
+
+use std::{cmp, fmt};
+
+use crate::comparator::Comparator;
+
+pub const MAX_VARINT32_LEN: usize = 5;
+pub const MAX_VARINT64_LEN: usize = 10;
+
+/// The largest sequence number; the tag keeps the low 8 bits for the type.
+pub const MAX_SEQUENCE: u64 = (1 << 56) - 1;
+
+const TAG_LEN: usize = 8;
+
+/// What an entry records for its key, with LevelDB's tag values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ValueType {
+    Deletion = 0,
+    Value = 1,
+}
+
+impl TryFrom<u8> for ValueType {
+    type Error = DecodeError;
+
+    fn try_from(tag: u8) -> Result<Self, DecodeError> {
+        match tag {
+            0 => Ok(ValueType::Deletion),
+            1 => Ok(ValueType::Value),
+            _ => Err(DecodeError::Corrupted),
+        }
+    }
+}
+
+/// Why bytes did not decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ends inside a varint or before a length says it should.
+    Truncated,
+    /// A varint does not fit its type.
+    Overflow,
+    /// The lengths add up, but not to an entry: an internal key shorter than its tag, an
+    /// unknown type, or bytes left over.
+    Corrupted,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => f.write_str("input ends too early"),
+            DecodeError::Overflow => f.write_str("varint overflows its type"),
+            DecodeError::Corrupted => f.write_str("corrupted entry"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub fn put_varint32(dst: &mut Vec<u8>, v: u32) {
+    put_varint64(dst, v as u64);
+}
+
+/// Seven bits per byte, least significant first, the top bit set on all but the last.
+pub fn put_varint64(dst: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        dst.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    dst.push(v as u8);
+}
+
+/// Bytes `put_varint64` writes for `v`.
+pub fn varint_len(v: u64) -> usize {
+    (64 - (v | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// The varint at the start of `src` and the bytes it took.
+pub fn get_varint32(src: &[u8]) -> Result<(u32, usize), DecodeError> {
+    let (v, len) = get_varint(src, MAX_VARINT32_LEN)?;
+    let v = u32::try_from(v).map_err(|_| DecodeError::Overflow)?;
+    Ok((v, len))
+}
+
+/// The varint at the start of `src` and the bytes it took.
+pub fn get_varint64(src: &[u8]) -> Result<(u64, usize), DecodeError> {
+    get_varint(src, MAX_VARINT64_LEN)
+}
+
+// up to `max_len` bytes; the last of those may only hold the bits left of 64
+fn get_varint(src: &[u8], max_len: usize) -> Result<(u64, usize), DecodeError> {
+    let mut v = 0_u64;
+    for (i, &byte) in src.iter().enumerate().take(max_len) {
+        let bits = (byte & 0x7f) as u64;
+        let shift = 7 * i as u32;
+        if bits << shift >> shift != bits {
+            return Err(DecodeError::Overflow);
+        }
+        v |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok((v, i + 1));
+        }
+    }
+    if src.len() < max_len {
+        Err(DecodeError::Truncated)
+    } else {
+        Err(DecodeError::Overflow)
+    }
+}
+
+// `len` bytes off the front of `src`, after a varint32 length
+pub(crate) fn get_length_prefixed(src: &[u8]) -> Result<(&[u8], &[u8]), DecodeError> {
+    let (len, prefix) = get_varint32(src)?;
+    let rest = &src[prefix..];
+    if rest.len() < len as usize {
+        return Err(DecodeError::Truncated);
+    }
+    Ok(rest.split_at(len as usize))
+}
+
+/// Appends the entry for `user_key` at `seq`. Panics when `seq` is above `MAX_SEQUENCE`,
+/// or the key or value is 4 GiB or longer.
+pub fn encode_entry(
+    dst: &mut Vec<u8>,
+    user_key: &[u8],
+    seq: u64,
+    value_type: ValueType,
+    value: &[u8],
+) {
+    encode_internal_key(dst, user_key, seq, value_type);
+    let value_len = u32::try_from(value.len()).expect("values are shorter than 4 GiB");
+    put_varint32(dst, value_len);
+    dst.extend_from_slice(value);
+}
+
+/// The length-prefixed internal key alone, LevelDB's `LookupKey`: ordered by
+/// `MemTableKeyComparator` right in front of every entry for `user_key` at or below `seq`,
+/// so a seek to it lands on the newest of them.
+pub fn encode_lookup_key(dst: &mut Vec<u8>, user_key: &[u8], seq: u64) {
+    // the type sorts descending too, and `Value` is the highest
+    encode_internal_key(dst, user_key, seq, ValueType::Value);
+}
+
+fn encode_internal_key(dst: &mut Vec<u8>, user_key: &[u8], seq: u64, value_type: ValueType) {
+    assert!(
+        seq <= MAX_SEQUENCE,
+        "sequence number {seq} takes more than 56 bits"
+    );
+    let internal_len =
+        u32::try_from(user_key.len() + TAG_LEN).expect("keys are shorter than 4 GiB");
+    dst.reserve(varint_len(internal_len as u64) + internal_len as usize);
+    put_varint32(dst, internal_len);
+    dst.extend_from_slice(user_key);
+    dst.extend_from_slice(&(seq << 8 | value_type as u64).to_le_bytes());
+}
+
+/// An entry in the format above, decoded without copying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemTableKey<'a> {
+    user_key: &'a [u8],
+    tag: u64,
+    value: &'a [u8],
+}
+
+impl<'a> MemTableKey<'a> {
+    /// Checks every length against `entry`, which must hold exactly one entry.
+    pub fn decode(entry: &'a [u8]) -> Result<Self, DecodeError> {
+        let (internal_key, rest) = get_length_prefixed(entry)?;
+        let (user_key, tag) = split_internal_key(internal_key)?;
+        ValueType::try_from(tag as u8)?;
+        let (value, rest) = get_length_prefixed(rest)?;
+        if !rest.is_empty() {
+            return Err(DecodeError::Corrupted);
+        }
+        Ok(Self {
+            user_key,
+            tag,
+            value,
+        })
+    }
+
+    pub fn user_key(&self) -> &'a [u8] {
+        self.user_key
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.tag >> 8
+    }
+
+    pub fn value_type(&self) -> ValueType {
+        // checked by `decode`
+        ValueType::try_from(self.tag as u8).unwrap()
+    }
+
+    pub fn value(&self) -> &'a [u8] {
+        self.value
+    }
+}
+
+// the user key and the tag behind it
+fn split_internal_key(internal_key: &[u8]) -> Result<(&[u8], u64), DecodeError> {
+    let user_len = internal_key
+        .len()
+        .checked_sub(TAG_LEN)
+        .ok_or(DecodeError::Corrupted)?;
+    let (user_key, tag) = internal_key.split_at(user_len);
+    Ok((user_key, u64::from_le_bytes(tag.try_into().unwrap())))
+}
+
+/// Orders encoded entries, and lookup keys, the way LevelDB's memtable does: by user key
+/// under `C`, then newest first by sequence number and type. Only the internal key is
+/// decoded; values are never looked at.
+///
+/// Panics on bytes that are not an entry or lookup key, which only get into a list by
+/// inserting them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemTableKeyComparator<C> {
+    user: C,
+}
+
+impl<C> MemTableKeyComparator<C> {
+    pub fn new(user: C) -> Self {
+        Self { user }
+    }
+}
+
+impl<C: Comparator<Item = [u8]>> Comparator for MemTableKeyComparator<C> {
+    type Item = [u8];
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+        let internal_key = |entry| {
+            get_length_prefixed(entry)
+                .and_then(|(internal_key, _)| split_internal_key(internal_key))
+                .expect("not a memtable entry")
+        };
+        let ((a_key, a_tag), (b_key, b_tag)) = (internal_key(a), internal_key(b));
+        self.user
+            .compare(a_key, b_key)
+            .then_with(|| b_tag.cmp(&a_tag))
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use crate::{arena::BlockArena, bytes::BytesSkipList, comparator::DefaultComparator};
+
+    use super::{
+        DecodeError, MAX_SEQUENCE, MemTableKey, MemTableKeyComparator, ValueType, encode_entry,
+        encode_lookup_key, get_varint32, get_varint64, put_varint32, put_varint64, varint_len,
+    };
+
+    #[test]
+    fn varint_round_trips() {
+        let mut values = vec![0, 1, 127, 128, 255, 300, 16_383, 16_384];
+        for shift in [21, 28, 31, 32, 35, 42, 49, 56, 63] {
+            values.extend([(1 << shift) - 1, 1 << shift, (1 << shift) + 1]);
+        }
+        values.extend([u32::MAX as u64, u64::MAX - 1, u64::MAX]);
+
+        for v in values {
+            let mut buf = vec![];
+            put_varint64(&mut buf, v);
+            assert_eq!(buf.len(), varint_len(v), "{v}");
+            buf.push(0xaa);
+            assert_eq!(get_varint64(&buf), Ok((v, buf.len() - 1)), "{v}");
+
+            match u32::try_from(v) {
+                Ok(v32) => {
+                    let mut buf32 = vec![];
+                    put_varint32(&mut buf32, v32);
+                    assert_eq!(buf32, buf[..buf.len() - 1]);
+                    assert_eq!(get_varint32(&buf), Ok((v32, buf32.len())));
+                }
+                Err(_) => assert_eq!(get_varint32(&buf), Err(DecodeError::Overflow), "{v}"),
+            }
+
+            // every proper prefix stops inside the varint
+            for end in 0..buf.len() - 1 {
+                assert_eq!(get_varint64(&buf[..end]), Err(DecodeError::Truncated));
+            }
+        }
+    }
+
+    #[test]
+    fn varint_overflow() {
+        // a sixth byte for a varint32, an eleventh for a varint64
+        assert_eq!(get_varint32(&[0x80; 6]), Err(DecodeError::Overflow));
+        assert_eq!(get_varint64(&[0x80; 11]), Err(DecodeError::Overflow));
+        // bits past 32 and 64 in the last byte
+        assert_eq!(
+            get_varint32(&[0xff, 0xff, 0xff, 0xff, 0x1f]),
+            Err(DecodeError::Overflow)
+        );
+        let mut too_big = [0xff; 10];
+        too_big[9] = 0x02;
+        assert_eq!(get_varint64(&too_big), Err(DecodeError::Overflow));
+        too_big[9] = 0x01;
+        assert_eq!(get_varint64(&too_big), Ok((u64::MAX, 10)));
+    }
+
+    #[test]
+    fn entry_round_trips() {
+        for (user_key, seq, value_type, value) in [
+            (&b""[..], 0, ValueType::Value, &b""[..]),
+            (b"key", 7, ValueType::Value, b"value"),
+            (b"key", MAX_SEQUENCE, ValueType::Deletion, b""),
+            (&[0xff; 200], 1 << 40, ValueType::Value, &[1; 300]),
+        ] {
+            let mut entry = vec![];
+            encode_entry(&mut entry, user_key, seq, value_type, value);
+            let decoded = MemTableKey::decode(&entry).unwrap();
+            assert_eq!(decoded.user_key(), user_key);
+            assert_eq!(decoded.seq(), seq);
+            assert_eq!(decoded.value_type(), value_type);
+            assert_eq!(decoded.value(), value);
+        }
+
+        // byte for byte what LevelDB writes
+        let mut entry = vec![];
+        encode_entry(&mut entry, b"k", 0x0102, ValueType::Value, b"v");
+        assert_eq!(entry, [9, b'k', 1, 2, 1, 0, 0, 0, 0, 0, 1, b'v']);
+    }
+
+    #[test]
+    fn corrupted_entries() {
+        let mut entry = vec![];
+        encode_entry(&mut entry, b"key", 1, ValueType::Value, b"value");
+
+        // cut anywhere
+        for end in 0..entry.len() {
+            assert_eq!(
+                MemTableKey::decode(&entry[..end]),
+                Err(DecodeError::Truncated),
+                "{end}"
+            );
+        }
+        // a key length past the end of the entry
+        let mut long = entry.clone();
+        long[0] = 100;
+        assert_eq!(MemTableKey::decode(&long), Err(DecodeError::Truncated));
+        // a value length past the end of the entry
+        let mut long = entry.clone();
+        long[12] = 6;
+        assert_eq!(MemTableKey::decode(&long), Err(DecodeError::Truncated));
+        // an internal key too short for its tag
+        assert_eq!(
+            MemTableKey::decode(&[3, 1, 2, 3, 0]),
+            Err(DecodeError::Corrupted)
+        );
+        // an unknown type
+        let mut unknown = entry.clone();
+        unknown[4] = 2;
+        assert_eq!(MemTableKey::decode(&unknown), Err(DecodeError::Corrupted));
+        // trailing bytes
+        entry.push(0);
+        assert_eq!(MemTableKey::decode(&entry), Err(DecodeError::Corrupted));
+        // an overlong length
+        assert_eq!(
+            MemTableKey::decode(&[0xff, 0xff, 0xff, 0xff, 0xff, 0x01]),
+            Err(DecodeError::Overflow)
+        );
+    }
+
+    #[test]
+    fn memtable_order() {
+        let list = BytesSkipList::new(
+            MemTableKeyComparator::new(DefaultComparator::<[u8]>::default()),
+            BlockArena::new(),
+        );
+        let writes = [
+            (&b"b"[..], 1, ValueType::Value, &b"b1"[..]),
+            (b"a", 2, ValueType::Value, b"a2"),
+            (b"b", 3, ValueType::Deletion, b""),
+            (b"b", 5, ValueType::Value, b"b5"),
+            (b"c", 4, ValueType::Value, b"c4"),
+        ];
+        for (user_key, seq, value_type, value) in writes {
+            let mut entry = vec![];
+            encode_entry(&mut entry, user_key, seq, value_type, value);
+            list.insert(&entry, b"");
+        }
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        let mut order = vec![];
+        while let Some(entry) = iter.key() {
+            let entry = MemTableKey::decode(entry).unwrap();
+            order.push((entry.user_key().to_vec(), entry.seq()));
+            iter.next();
+        }
+        let order: Vec<_> = order.iter().map(|(k, s)| (&k[..], *s)).collect();
+        assert_eq!(
+            order,
+            [(&b"a"[..], 2), (b"b", 5), (b"b", 3), (b"b", 1), (b"c", 4)]
+        );
+
+        // the newest entry for a key as of a sequence number
+        let newest = |user_key: &[u8], seq| {
+            let mut lookup = vec![];
+            encode_lookup_key(&mut lookup, user_key, seq);
+            let mut iter = list.iter();
+            iter.seek(&lookup);
+            let entry = MemTableKey::decode(iter.key()?).unwrap();
+            (entry.user_key() == user_key).then(|| (entry.value_type(), entry.value().to_vec()))
+        };
+        assert_eq!(newest(b"b", 10), Some((ValueType::Value, b"b5".to_vec())));
+        assert_eq!(newest(b"b", 4), Some((ValueType::Deletion, vec![])));
+        assert_eq!(newest(b"b", 2), Some((ValueType::Value, b"b1".to_vec())));
+        assert_eq!(newest(b"b", 0), None);
+        assert_eq!(newest(b"a", 1), None);
+        assert_eq!(newest(b"bb", 10), None);
+    }
+}
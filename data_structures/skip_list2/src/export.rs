@@ -0,0 +1,190 @@
+//! `MetricsSnapshot`s published through the `metrics` facade, to whatever recorder the
+//! process installed. Every list registers its series once, under a prefix of the
+//! caller's choosing and labels that tell it apart from the other lists:
+//!
+//! | series                           | kind    | from                   |
+//! |----------------------------------|---------|------------------------|
+//! | `{prefix}_inserts_total`         | counter | `inserts`              |
+//! | `{prefix}_gets_total`            | counter | `gets`                 |
+//! | `{prefix}_seeks_total`           | counter | `seeks`                |
+//! | `{prefix}_cas_retries_total`     | counter | `cas_retries`          |
+//! | `{prefix}_comparisons_total`     | counter | `comparisons`          |
+//! | `{prefix}_entries`               | gauge   | `entries`              |
+//! | `{prefix}_arena_reserved_bytes`  | gauge   | `arena_reserved_bytes` |
+//! | `{prefix}_arena_used_bytes`      | gauge   | `arena_used_bytes`     |
+
+use metrics::{Counter, Gauge, IntoLabels, Label, counter, gauge};
+
+use crate::{
+    arena::MemAllocator,
+    comparator::Comparator,
+    skip_list::{MetricsSnapshot, SkipList},
+};
+
+/// One list's series, registered by `register` and brought up to date by `record`. The
+/// list keeps the running totals itself, so the counters are set to them rather than
+/// incremented, and nothing is recorded between two calls to `record`: call it on a
+/// timer, or before every scrape.
+pub struct ListMetrics {
+    inserts: Counter,
+    gets: Counter,
+    seeks: Counter,
+    cas_retries: Counter,
+    comparisons: Counter,
+    entries: Gauge,
+    arena_reserved_bytes: Gauge,
+    arena_used_bytes: Gauge,
+}
+
+impl ListMetrics {
+    /// Registers the series with the recorder installed at this point; a recorder
+    /// installed later never sees them.
+    pub fn register(prefix: &str, labels: impl IntoLabels) -> Self {
+        let labels: Vec<Label> = labels.into_labels();
+        let counter = |name: &str| counter!(format!("{prefix}_{name}_total"), labels.clone());
+        let gauge = |name: &str| gauge!(format!("{prefix}_{name}"), labels.clone());
+        ListMetrics {
+            inserts: counter("inserts"),
+            gets: counter("gets"),
+            seeks: counter("seeks"),
+            cas_retries: counter("cas_retries"),
+            comparisons: counter("comparisons"),
+            entries: gauge("entries"),
+            arena_reserved_bytes: gauge("arena_reserved_bytes"),
+            arena_used_bytes: gauge("arena_used_bytes"),
+        }
+    }
+
+    pub fn record(&self, snapshot: &MetricsSnapshot) {
+        self.inserts.absolute(snapshot.inserts);
+        self.gets.absolute(snapshot.gets);
+        self.seeks.absolute(snapshot.seeks);
+        self.cas_retries.absolute(snapshot.cas_retries);
+        self.comparisons.absolute(snapshot.comparisons);
+        self.entries.set(snapshot.entries as f64);
+        self.arena_reserved_bytes
+            .set(snapshot.arena_reserved_bytes as f64);
+        self.arena_used_bytes.set(snapshot.arena_used_bytes as f64);
+    }
+
+    /// `record` of a fresh `SkipList::metrics_snapshot`.
+    pub fn record_list<K, V, C, A>(&self, list: &SkipList<K, V, C, A>)
+    where
+        C: Comparator<Item = K>,
+        A: MemAllocator,
+    {
+        self.record(&list.metrics_snapshot());
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicU64, Ordering::Relaxed},
+        },
+    };
+
+    use metrics::{
+        Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+
+    use super::ListMetrics;
+    use crate::skip_list::SkipList;
+
+    // every series as `name{label=value,...}`, counters and gauges alike in an `AtomicU64`
+    #[derive(Default)]
+    struct Recorded(Mutex<HashMap<String, Arc<AtomicU64>>>);
+
+    impl Recorded {
+        fn series(&self, key: &Key) -> Arc<AtomicU64> {
+            let labels: Vec<String> = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            self.0.lock().unwrap().entry(name).or_default().clone()
+        }
+
+        fn counter(&self, name: &str) -> u64 {
+            self.0.lock().unwrap()[name].load(Relaxed)
+        }
+
+        fn gauge(&self, name: &str) -> f64 {
+            f64::from_bits(self.counter(name))
+        }
+    }
+
+    impl Recorder for Recorded {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.series(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.series(key))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn lists_publish_under_their_labels() {
+        let recorded = Recorded::default();
+        let (a, b) = (
+            SkipList::<_, _>::default_arc(),
+            SkipList::<_, _>::default_arc(),
+        );
+        let (a_metrics, b_metrics) = metrics::with_local_recorder(&recorded, || {
+            (
+                ListMetrics::register("memtable", &[("list", "a")]),
+                ListMetrics::register("memtable", vec![metrics::Label::new("list", "b")]),
+            )
+        });
+        assert_eq!(recorded.0.lock().unwrap().len(), 16);
+
+        for i in 0..10 {
+            a.insert(i, i);
+        }
+        a.get(&3);
+        b.insert(0, 0);
+        b.iter().seek(&0);
+        a_metrics.record_list(&a);
+        b_metrics.record_list(&b);
+        assert_eq!(recorded.counter("memtable_inserts_total{list=a}"), 10);
+        assert_eq!(recorded.counter("memtable_inserts_total{list=b}"), 1);
+        assert_eq!(recorded.counter("memtable_gets_total{list=a}"), 1);
+        assert_eq!(recorded.counter("memtable_seeks_total{list=b}"), 1);
+        assert_eq!(recorded.counter("memtable_seeks_total{list=a}"), 0);
+        assert_eq!(recorded.counter("memtable_cas_retries_total{list=a}"), 0);
+        assert_eq!(
+            recorded.counter("memtable_comparisons_total{list=a}"),
+            a.metrics().comparisons as u64
+        );
+        assert_eq!(recorded.gauge("memtable_entries{list=a}"), 10.0);
+        assert_eq!(
+            recorded.gauge("memtable_arena_reserved_bytes{list=b}"),
+            b.mem_usage() as f64
+        );
+        assert_eq!(
+            recorded.gauge("memtable_arena_used_bytes{list=a}"),
+            a.useful_mem_usage() as f64
+        );
+
+        // the counters follow the list's totals, recorded twice or not
+        a.insert(10, 10);
+        a_metrics.record_list(&a);
+        a_metrics.record_list(&a);
+        assert_eq!(recorded.counter("memtable_inserts_total{list=a}"), 11);
+        assert_eq!(recorded.gauge("memtable_entries{list=a}"), 11.0);
+    }
+}
@@ -0,0 +1,432 @@
+//! A C interface to `BytesSkipList`, with the keys ordered bytewise and the nodes in a
+//! `BlockArena`. `include/skip_list2.h` declares it and is generated from this file with
+//! `cbindgen --config cbindgen.toml --output include/skip_list2.h`; `examples/ffi.c`
+//! walks through it.
+//!
+//! Every function returns one of the `SKIPLIST_*` status codes and hands results back
+//! through out pointers. A panic is caught at the boundary and reported as
+//! `SKIPLIST_PANIC`; it never unwinds into C. A list handle can be shared between threads,
+//! which may insert and read concurrently; an iterator handle is used by one thread at a
+//! time.
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{
+    arena::BlockArena,
+    bytes::{BytesIter, BytesSkipList},
+    comparator::DefaultComparator,
+    skip_list::NodeError,
+};
+
+pub const SKIPLIST_OK: i32 = 0;
+/// The key is not in the list, or the iterator is not on an entry.
+pub const SKIPLIST_NOT_FOUND: i32 = 1;
+/// The value is longer than the buffer; the length out pointer holds its length.
+pub const SKIPLIST_BUFFER_TOO_SMALL: i32 = 2;
+pub const SKIPLIST_KEY_EXISTS: i32 = 3;
+/// The node could not be allocated.
+pub const SKIPLIST_ALLOC_FAILED: i32 = 4;
+/// The write buffer is full, see `SkipList::with_write_buffer_size`.
+pub const SKIPLIST_WRITE_STALL: i32 = 5;
+/// A handle or an out pointer is null, or a non-empty buffer is.
+pub const SKIPLIST_NULL_POINTER: i32 = 6;
+/// The call panicked, e.g. on a key or value of 4 GiB or more. The list is left usable.
+pub const SKIPLIST_PANIC: i32 = 7;
+
+type List = BytesSkipList<DefaultComparator<[u8]>, BlockArena>;
+
+/// A list, from `skiplist_create`.
+pub struct SkipListHandle {
+    list: List,
+}
+
+/// A cursor over a list, from `skiplist_iter_create`. It keeps the list's nodes alive, so
+/// it may outlive the list handle it came from.
+pub struct SkipListIterHandle {
+    iter: BytesIter<DefaultComparator<[u8]>, BlockArena>,
+}
+
+fn guard(f: impl FnOnce() -> i32) -> i32 {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(SKIPLIST_PANIC)
+}
+
+// `len` bytes from `data`, which may be null when `len` is 0
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (data.is_null(), len) {
+        (true, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(unsafe { slice::from_raw_parts(data, len) }),
+    }
+}
+
+fn status(e: NodeError) -> i32 {
+    match e {
+        NodeError::KeyExists => SKIPLIST_KEY_EXISTS,
+        NodeError::Stall(_) => SKIPLIST_WRITE_STALL,
+        NodeError::Layout(_) | NodeError::Alloc(_) => SKIPLIST_ALLOC_FAILED,
+    }
+}
+
+/// Creates an empty list into `*out`.
+///
+/// # Safety
+///
+/// `out` is null or valid for a write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_create(out: *mut *mut SkipListHandle) -> i32 {
+    if out.is_null() {
+        return SKIPLIST_NULL_POINTER;
+    }
+    guard(|| {
+        let list = match List::try_new(DefaultComparator::default(), BlockArena::new()) {
+            Ok(list) => list,
+            Err(e) => return status(e),
+        };
+        unsafe { out.write(Box::into_raw(Box::new(SkipListHandle { list }))) };
+        SKIPLIST_OK
+    })
+}
+
+/// Frees a list and, once no iterator is left on them, its nodes. Null is ignored.
+///
+/// # Safety
+///
+/// `list` is null or from `skiplist_create`, and not used again.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_destroy(list: *mut SkipListHandle) {
+    if !list.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(list) })));
+    }
+}
+
+/// Copies the key and the value into a new entry.
+///
+/// # Safety
+///
+/// `list` is null or a live list handle, and `key` and `value` are null or valid for reads
+/// of their lengths.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_insert(
+    list: *const SkipListHandle,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> i32 {
+    let (Some(list), Some(key), Some(value)) =
+        (unsafe { (list.as_ref(), bytes(key, key_len), bytes(value, value_len)) })
+    else {
+        return SKIPLIST_NULL_POINTER;
+    };
+    guard(|| match list.list.try_insert(key, value) {
+        Ok(()) => SKIPLIST_OK,
+        Err(e) => status(e),
+    })
+}
+
+/// Copies the value of `key` into `buf` and its length into `*value_len`. When the value
+/// is longer than `buf_len`, nothing is copied, `*value_len` is still set and the call
+/// returns `SKIPLIST_BUFFER_TOO_SMALL`, so that it can be retried with a buffer that fits.
+///
+/// # Safety
+///
+/// `list` is null or a live list handle, `key` is null or valid for reads of `key_len`
+/// bytes, `buf` is null or valid for writes of `buf_len` bytes, and `value_len` is null
+/// or valid for a write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_get(
+    list: *const SkipListHandle,
+    key: *const u8,
+    key_len: usize,
+    buf: *mut u8,
+    buf_len: usize,
+    value_len: *mut usize,
+) -> i32 {
+    let (Some(list), Some(key)) = (unsafe { (list.as_ref(), bytes(key, key_len)) }) else {
+        return SKIPLIST_NULL_POINTER;
+    };
+    if value_len.is_null() || (buf.is_null() && buf_len > 0) {
+        return SKIPLIST_NULL_POINTER;
+    }
+    guard(|| {
+        let Some(value) = list.list.get(key) else {
+            return SKIPLIST_NOT_FOUND;
+        };
+        unsafe { value_len.write(value.len()) };
+        if value.len() > buf_len {
+            return SKIPLIST_BUFFER_TOO_SMALL;
+        }
+        unsafe { ptr::copy_nonoverlapping(value.as_ptr(), buf, value.len()) };
+        SKIPLIST_OK
+    })
+}
+
+/// The bytes the list has allocated, into `*out`.
+///
+/// # Safety
+///
+/// `list` is null or a live list handle, and `out` is null or valid for a write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_mem_usage(list: *const SkipListHandle, out: *mut usize) -> i32 {
+    let Some(list) = (unsafe { list.as_ref() }) else {
+        return SKIPLIST_NULL_POINTER;
+    };
+    if out.is_null() {
+        return SKIPLIST_NULL_POINTER;
+    }
+    guard(|| {
+        unsafe { out.write(list.list.mem_usage()) };
+        SKIPLIST_OK
+    })
+}
+
+/// Creates an iterator over `list` into `*out`, not on any entry until it seeks.
+///
+/// # Safety
+///
+/// `list` is null or a live list handle, and `out` is null or valid for a write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_iter_create(
+    list: *const SkipListHandle,
+    out: *mut *mut SkipListIterHandle,
+) -> i32 {
+    let Some(list) = (unsafe { list.as_ref() }) else {
+        return SKIPLIST_NULL_POINTER;
+    };
+    if out.is_null() {
+        return SKIPLIST_NULL_POINTER;
+    }
+    guard(|| {
+        let iter = Box::new(SkipListIterHandle {
+            iter: list.list.iter(),
+        });
+        unsafe { out.write(Box::into_raw(iter)) };
+        SKIPLIST_OK
+    })
+}
+
+/// Frees an iterator. Null is ignored.
+///
+/// # Safety
+///
+/// `iter` is null or from `skiplist_iter_create`, and not used again.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_iter_destroy(iter: *mut SkipListIterHandle) {
+    if !iter.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(iter) })));
+    }
+}
+
+/// Moves to the first entry, returning `SKIPLIST_NOT_FOUND` when the list is empty.
+///
+/// # Safety
+///
+/// `iter` is null or a live iterator handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_iter_seek_to_first(iter: *mut SkipListIterHandle) -> i32 {
+    let Some(iter) = (unsafe { iter.as_mut() }) else {
+        return SKIPLIST_NULL_POINTER;
+    };
+    guard(|| {
+        iter.iter.seek_to_first();
+        on_entry(&iter.iter)
+    })
+}
+
+/// Moves to the first entry at or after `key`, returning `SKIPLIST_NOT_FOUND` when there
+/// is none.
+///
+/// # Safety
+///
+/// `iter` is null or a live iterator handle, and `key` is null or valid for reads of
+/// `key_len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_iter_seek(
+    iter: *mut SkipListIterHandle,
+    key: *const u8,
+    key_len: usize,
+) -> i32 {
+    let (Some(iter), Some(key)) = (unsafe { (iter.as_mut(), bytes(key, key_len)) }) else {
+        return SKIPLIST_NULL_POINTER;
+    };
+    guard(|| {
+        iter.iter.seek(key);
+        on_entry(&iter.iter)
+    })
+}
+
+/// Moves to the next entry, returning `SKIPLIST_NOT_FOUND` when it moves past the last
+/// one or was not on an entry to begin with.
+///
+/// # Safety
+///
+/// `iter` is null or a live iterator handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_iter_next(iter: *mut SkipListIterHandle) -> i32 {
+    let Some(iter) = (unsafe { iter.as_mut() }) else {
+        return SKIPLIST_NULL_POINTER;
+    };
+    guard(|| {
+        if !iter.iter.is_valid() {
+            return SKIPLIST_NOT_FOUND;
+        }
+        iter.iter.next();
+        on_entry(&iter.iter)
+    })
+}
+
+/// Points `*key` and `*key_len` at the key of the entry the iterator is on. The bytes stay
+/// valid, and unchanged, until the iterator is destroyed.
+///
+/// # Safety
+///
+/// `iter` is null or a live iterator handle, and `key` and `key_len` are null or valid
+/// for a write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_iter_key(
+    iter: *const SkipListIterHandle,
+    key: *mut *const u8,
+    key_len: *mut usize,
+) -> i32 {
+    unsafe { borrow(iter, key, key_len, BytesIter::key) }
+}
+
+/// Like `skiplist_iter_key`, for the value.
+///
+/// # Safety
+///
+/// As for `skiplist_iter_key`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn skiplist_iter_value(
+    iter: *const SkipListIterHandle,
+    value: *mut *const u8,
+    value_len: *mut usize,
+) -> i32 {
+    unsafe { borrow(iter, value, value_len, BytesIter::value) }
+}
+
+type Field = fn(&BytesIter<DefaultComparator<[u8]>, BlockArena>) -> Option<&[u8]>;
+
+unsafe fn borrow(
+    iter: *const SkipListIterHandle,
+    data: *mut *const u8,
+    len: *mut usize,
+    field: Field,
+) -> i32 {
+    let Some(iter) = (unsafe { iter.as_ref() }) else {
+        return SKIPLIST_NULL_POINTER;
+    };
+    if data.is_null() || len.is_null() {
+        return SKIPLIST_NULL_POINTER;
+    }
+    guard(|| {
+        let Some(bytes) = field(&iter.iter) else {
+            return SKIPLIST_NOT_FOUND;
+        };
+        unsafe {
+            data.write(bytes.as_ptr());
+            len.write(bytes.len());
+        }
+        SKIPLIST_OK
+    })
+}
+
+fn on_entry(iter: &BytesIter<DefaultComparator<[u8]>, BlockArena>) -> i32 {
+    if iter.is_valid() {
+        SKIPLIST_OK
+    } else {
+        SKIPLIST_NOT_FOUND
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{ptr, slice};
+
+    use super::*;
+
+    unsafe fn key_of(iter: *const SkipListIterHandle) -> &'static [u8] {
+        let (mut key, mut len) = (ptr::null(), 0);
+        assert_eq!(
+            unsafe { skiplist_iter_key(iter, &mut key, &mut len) },
+            SKIPLIST_OK
+        );
+        unsafe { slice::from_raw_parts(key, len) }
+    }
+
+    #[test]
+    fn through_the_c_interface() {
+        unsafe {
+            let mut list = ptr::null_mut();
+            assert_eq!(skiplist_create(&mut list), SKIPLIST_OK);
+            for key in [&b"b"[..], b"a", b"c"] {
+                let status = skiplist_insert(list, key.as_ptr(), key.len(), b"value".as_ptr(), 5);
+                assert_eq!(status, SKIPLIST_OK);
+            }
+            assert_eq!(
+                skiplist_insert(list, b"a".as_ptr(), 1, ptr::null(), 0),
+                SKIPLIST_KEY_EXISTS
+            );
+            assert_eq!(
+                skiplist_insert(list, ptr::null(), 1, ptr::null(), 0),
+                SKIPLIST_NULL_POINTER
+            );
+            // the empty key, with no bytes behind it
+            assert_eq!(
+                skiplist_insert(list, ptr::null(), 0, ptr::null(), 0),
+                SKIPLIST_OK
+            );
+
+            let (mut buf, mut len) = ([0; 8], 0);
+            let get = |key: &[u8], buf: &mut [u8], len: &mut usize| {
+                skiplist_get(
+                    list,
+                    key.as_ptr(),
+                    key.len(),
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                    len,
+                )
+            };
+            assert_eq!(get(b"b", &mut buf, &mut len), SKIPLIST_OK);
+            assert_eq!(&buf[..len], b"value");
+            assert_eq!(
+                get(b"b", &mut buf[..2], &mut len),
+                SKIPLIST_BUFFER_TOO_SMALL
+            );
+            assert_eq!(len, 5);
+            assert_eq!(get(b"d", &mut buf, &mut len), SKIPLIST_NOT_FOUND);
+            assert_eq!(get(b"", &mut [], &mut len), SKIPLIST_OK);
+            assert_eq!(len, 0);
+
+            let mut usage = 0;
+            assert_eq!(skiplist_mem_usage(list, &mut usage), SKIPLIST_OK);
+            assert!(usage > 0);
+
+            let mut iter = ptr::null_mut();
+            assert_eq!(skiplist_iter_create(list, &mut iter), SKIPLIST_OK);
+            assert_eq!(skiplist_iter_next(iter), SKIPLIST_NOT_FOUND);
+            assert_eq!(skiplist_iter_seek(iter, b"aa".as_ptr(), 2), SKIPLIST_OK);
+            // the iterator keeps the nodes alive
+            skiplist_destroy(list);
+            assert_eq!(key_of(iter), b"b");
+            let (mut value, mut len) = (ptr::null(), 0);
+            assert_eq!(skiplist_iter_value(iter, &mut value, &mut len), SKIPLIST_OK);
+            assert_eq!(slice::from_raw_parts(value, len), b"value");
+            assert_eq!(skiplist_iter_next(iter), SKIPLIST_OK);
+            assert_eq!(key_of(iter), b"c");
+            assert_eq!(skiplist_iter_next(iter), SKIPLIST_NOT_FOUND);
+            assert_eq!(
+                skiplist_iter_key(iter, &mut value, &mut len),
+                SKIPLIST_NOT_FOUND
+            );
+            assert_eq!(skiplist_iter_seek_to_first(iter), SKIPLIST_OK);
+            assert_eq!(key_of(iter), b"");
+            skiplist_iter_destroy(iter);
+            skiplist_iter_destroy(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    cmp::Ordering::*,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+};
+
+use crate::{arena::MemAllocator, comparator::Comparator, skip_list::SkipList};
+
+/// A list that takes no more inserts, from `SkipList::freeze`. Its entries are gathered
+/// into one sorted array, so `get` is a binary search and iteration steps through the
+/// array instead of chasing towers. Keys and values stay where they are: the array points
+/// into the list, which it keeps alive together with its arena.
+///
+/// Inserts into the list after it was frozen are not seen.
+pub struct FrozenSkipList<K, V, C, A> {
+    list: Arc<SkipList<K, V, C, A>>,
+    entries: Box<[(*const K, *const V)]>,
+}
+
+// the pointers are shared references into `list`
+unsafe impl<K, V, C, A> Send for FrozenSkipList<K, V, C, A> where Arc<SkipList<K, V, C, A>>: Send {}
+unsafe impl<K, V, C, A> Sync for FrozenSkipList<K, V, C, A> where Arc<SkipList<K, V, C, A>>: Sync {}
+
+impl<K, V, C, A> FrozenSkipList<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub(crate) fn new(list: Arc<SkipList<K, V, C, A>>) -> Self {
+        let entries = list
+            .entries()
+            .map(|(key, value)| (key as *const K, value as *const V))
+            .collect();
+        Self { list, entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn entry(&self, index: usize) -> (&K, &V) {
+        let (key, value) = self.entries[index];
+        unsafe { (&*key, &*value) }
+    }
+
+    // index of the first entry not below `key`, or with `inclusive` of the first one above
+    fn position(&self, key: &K, inclusive: bool) -> usize {
+        let c = self.list.comparator();
+        self.entries
+            .partition_point(|&(k, _)| match c.compare(unsafe { &*k }, key) {
+                Less => true,
+                Equal => inclusive,
+                Greater => false,
+            })
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let index = self.position(key, false);
+        (index < self.len())
+            .then(|| self.entry(index))
+            .filter(|(k, _)| self.list.comparator().compare(k, key) == Equal)
+            .map(|(_, v)| v)
+    }
+
+    /// The entries within `range`, in key order.
+    pub fn range(&self, range: impl RangeBounds<K>) -> impl DoubleEndedIterator<Item = (&K, &V)> {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.position(key, false),
+            Bound::Excluded(key) => self.position(key, true),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.position(key, true),
+            Bound::Excluded(key) => self.position(key, false),
+            Bound::Unbounded => self.len(),
+        };
+        (start..end.max(start)).map(|index| self.entry(index))
+    }
+
+    pub fn iter(self: &Arc<Self>) -> FrozenIter<K, V, C, A> {
+        FrozenIter {
+            pos: self.len(),
+            list: self.clone(),
+        }
+    }
+}
+
+/// The cursor of a `FrozenSkipList`, with the same methods as `SkipListIter`.
+pub struct FrozenIter<K, V, C, A> {
+    list: Arc<FrozenSkipList<K, V, C, A>>,
+    // `list.len()` when not valid
+    pos: usize,
+}
+
+impl<K, V, C, A> FrozenIter<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn is_valid(&self) -> bool {
+        self.pos < self.list.len()
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        self.is_valid().then(|| self.list.entry(self.pos).0)
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.is_valid().then(|| self.list.entry(self.pos).1)
+    }
+
+    pub fn next(&mut self) {
+        assert!(self.is_valid());
+        self.pos += 1;
+    }
+
+    pub fn prev(&mut self) {
+        assert!(self.is_valid());
+        self.pos = self.pos.checked_sub(1).unwrap_or(self.list.len());
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.pos = 0;
+    }
+
+    pub fn seek_to_last(&mut self) {
+        // 0 on an empty list, which is not valid either
+        self.pos = self.list.len().saturating_sub(1);
+    }
+
+    pub fn seek(&mut self, key: &K) {
+        self.pos = self.list.position(key, false);
+    }
+
+    pub fn seek_for_prev(&mut self, key: &K) {
+        let after = self.list.position(key, true);
+        self.pos = after.checked_sub(1).unwrap_or(self.list.len());
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{collections::BTreeMap, ops::Bound, sync::Arc};
+
+    use crate::{arena::BlockArena, comparator::DefaultComparator, skip_list::SkipList};
+
+    #[test]
+    fn frozen_reads_like_the_list() {
+        let list = SkipList::<_, _>::default_arc();
+        for i in (0..500).rev() {
+            list.insert(i * 2, i);
+        }
+        let frozen = Arc::new(list.clone().freeze());
+        // later inserts do not show up
+        list.insert(1, 0);
+        drop(list);
+
+        assert_eq!(frozen.len(), 500);
+        for i in 0..500 {
+            assert_eq!(frozen.get(&(i * 2)), Some(&i));
+            assert_eq!(frozen.get(&(i * 2 + 1)), None);
+        }
+
+        let mut iter = frozen.iter();
+        assert!(!iter.is_valid());
+        iter.seek_to_first();
+        for i in 0..500 {
+            assert_eq!(iter.key(), Some(&(i * 2)));
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        iter.seek_to_last();
+        for i in (0..500).rev() {
+            assert_eq!(iter.value(), Some(&i));
+            iter.prev();
+        }
+        assert!(!iter.is_valid());
+
+        iter.seek(&501);
+        assert_eq!(iter.key(), Some(&502));
+        iter.seek(&998);
+        assert_eq!(iter.key(), Some(&998));
+        iter.seek(&999);
+        assert!(!iter.is_valid());
+
+        iter.seek_for_prev(&501);
+        assert_eq!(iter.key(), Some(&500));
+        iter.seek_for_prev(&500);
+        assert_eq!(iter.key(), Some(&500));
+        iter.seek_for_prev(&2000);
+        assert_eq!(iter.key(), Some(&998));
+        iter.seek_for_prev(&0);
+        assert_eq!(iter.key(), Some(&0));
+    }
+
+    #[test]
+    fn frozen_ranges() {
+        let list = SkipList::<_, _>::default_arc();
+        let mut expected = BTreeMap::new();
+        for i in 0..100 {
+            list.insert(i * 3, i);
+            expected.insert(i * 3, i);
+        }
+        let frozen = list.freeze();
+
+        let bounds = [
+            Bound::Included(30),
+            Bound::Excluded(30),
+            Bound::Included(31),
+            Bound::Excluded(31),
+            Bound::Unbounded,
+        ];
+        for start in bounds {
+            for end in bounds {
+                let range = (start, end);
+                let inverted = match range {
+                    (Bound::Excluded(s), Bound::Excluded(e)) => s >= e,
+                    (
+                        Bound::Included(s) | Bound::Excluded(s),
+                        Bound::Included(e) | Bound::Excluded(e),
+                    ) => s > e,
+                    _ => false,
+                };
+                if inverted {
+                    // a `BTreeMap` panics on these, the frozen list is just empty
+                    assert_eq!(frozen.range(range).count(), 0);
+                    continue;
+                }
+                let found: Vec<_> = frozen.range(range).collect();
+                let want: Vec<_> = expected.range(range).collect();
+                assert_eq!(found, want, "{range:?}");
+            }
+        }
+        assert_eq!(frozen.range(250..).next_back(), Some((&297, &99)));
+    }
+
+    #[test]
+    fn frozen_empty() {
+        let list = Arc::new(SkipList::<u32, u32, _, _>::new(
+            DefaultComparator::default(),
+            BlockArena::new(),
+        ));
+        let frozen = Arc::new(list.freeze());
+        assert!(frozen.is_empty());
+        assert_eq!(frozen.get(&0), None);
+        assert_eq!(frozen.range(..).count(), 0);
+        let mut iter = frozen.iter();
+        iter.seek_to_first();
+        assert!(!iter.is_valid());
+        iter.seek_to_last();
+        assert!(!iter.is_valid());
+    }
+}
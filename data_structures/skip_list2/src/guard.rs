@@ -0,0 +1,116 @@
+//! References to entries that carry what keeps the entry's node alive, so read APIs can
+//! hand them out without tying them to how the list was reached.
+
+use std::{fmt, marker::PhantomData, ops::Deref, ptr::NonNull, sync::Arc};
+
+// Anything at all, for holding on to a list of any type in a `Keep`.
+pub(crate) trait KeepAlive {}
+
+impl<T: ?Sized> KeepAlive for T {}
+
+// What keeps a node alive while a reference to it is out. Nodes are freed with their list,
+// or by `pop_first` and `drain` through its `&mut`, so for now that is a borrow of the
+// list, or a clone of its `Arc` for a reference that outlives the borrow. Once nodes are
+// reclaimed while the list is shared, an epoch guard goes here.
+struct Keep<'g> {
+    _list: Option<Arc<dyn KeepAlive + 'g>>,
+    _borrow: PhantomData<&'g ()>,
+}
+
+impl<'g> Keep<'g> {
+    fn borrowed() -> Self {
+        Keep {
+            _list: None,
+            _borrow: PhantomData,
+        }
+    }
+
+    fn list(list: Arc<dyn KeepAlive + 'g>) -> Self {
+        Keep {
+            _list: Some(list),
+            _borrow: PhantomData,
+        }
+    }
+}
+
+/// A key and its value, readable for as long as the `EntryRef` lives. Not `Send`: what
+/// keeps the entry alive may later be tied to the thread.
+pub struct EntryRef<'g, K, V> {
+    key: NonNull<K>,
+    value: NonNull<V>,
+    _keep: Keep<'g>,
+}
+
+impl<'g, K, V> EntryRef<'g, K, V> {
+    pub(crate) fn borrowed(key: &'g K, value: &'g V) -> Self {
+        EntryRef {
+            key: NonNull::from(key),
+            value: NonNull::from(value),
+            _keep: Keep::borrowed(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `key` and `value` are in a node of `list`.
+    pub(crate) unsafe fn kept(key: &K, value: &V, list: Arc<dyn KeepAlive + 'g>) -> Self {
+        EntryRef {
+            key: NonNull::from(key),
+            value: NonNull::from(value),
+            _keep: Keep::list(list),
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        unsafe { self.key.as_ref() }
+    }
+
+    pub fn value(&self) -> &V {
+        unsafe { self.value.as_ref() }
+    }
+
+    pub fn into_value(self) -> ValueGuard<'g, V> {
+        ValueGuard {
+            value: self.value,
+            _keep: self._keep,
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for EntryRef<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EntryRef")
+            .field(self.key())
+            .field(self.value())
+            .finish()
+    }
+}
+
+/// A value, which it derefs to, kept alive like an `EntryRef`'s.
+pub struct ValueGuard<'g, V> {
+    value: NonNull<V>,
+    _keep: Keep<'g>,
+}
+
+impl<'g, V> ValueGuard<'g, V> {
+    pub(crate) fn borrowed(value: &'g V) -> Self {
+        ValueGuard {
+            value: NonNull::from(value),
+            _keep: Keep::borrowed(),
+        }
+    }
+}
+
+impl<V> Deref for ValueGuard<'_, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for ValueGuard<'_, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        V::fmt(self, f)
+    }
+}
@@ -1,5 +1,36 @@
-#![feature(let_chains)]
+//! Lock-free skip lists. `map::SkipMap` is the ordered map to start from; `skip_list::SkipList`
+//! takes a comparator, an allocator and options of its own, and the other modules build on
+//! it.
 
 pub mod arena;
+mod bloom;
+pub mod builder;
+pub mod bytes;
+mod cache_padded;
+pub mod columnar;
 pub mod comparator;
+pub mod encoding;
+#[cfg(feature = "metrics")]
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod frozen;
+pub mod guard;
+pub mod local;
+pub mod map;
+pub mod memtable;
+pub mod mvcc;
+pub mod prefix;
+#[cfg(feature = "serde")]
+pub mod serialize;
+pub mod set;
+pub mod sharded;
 pub mod skip_list;
+pub mod sst;
+#[cfg(any(test, feature = "stress"))]
+pub mod stress;
+mod sync;
+pub mod timestamp;
+pub mod ttl;
+pub mod value;
+pub mod wal;
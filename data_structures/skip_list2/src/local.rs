@@ -0,0 +1,202 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use rand::Rng;
+
+use crate::{
+    arena::MemAllocator,
+    comparator::Comparator,
+    skip_list::{InvariantViolation, NodeError, SkipList, SkipListIter, SkipListOptions},
+};
+
+/// A `SkipList` for a single thread, e.g. to build a batch. Inserts link with plain
+/// stores instead of CASes and bump the counters without atomic read-modify-writes; the
+/// search, the nodes and the arena are the concurrent list's own. The methods are named
+/// and behave like `SkipList`'s.
+///
+/// It is neither `Send` nor `Sync`: an iterator holds on to the list, so sending the list
+/// away would let inserts race the iterator. `into_shared` turns it into a plain
+/// `SkipList` to hand to other threads.
+pub struct LocalSkipList<K, V, C, A> {
+    list: Arc<SkipList<K, V, C, A>>,
+    _local: PhantomData<*mut ()>,
+}
+
+impl<K, V, C, A> LocalSkipList<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn new(c: C, a: A) -> Self {
+        Self::from_list(SkipList::new(c, a))
+    }
+
+    pub fn try_new(c: C, a: A) -> Result<Self, NodeError> {
+        SkipList::try_new(c, a).map(Self::from_list)
+    }
+
+    pub fn with_options(c: C, a: A, options: SkipListOptions) -> Self {
+        Self::from_list(SkipList::with_options(c, a, options))
+    }
+
+    pub fn try_with_options(c: C, a: A, options: SkipListOptions) -> Result<Self, NodeError> {
+        SkipList::try_with_options(c, a, options).map(Self::from_list)
+    }
+
+    fn from_list(list: SkipList<K, V, C, A>) -> Self {
+        Self {
+            list: Arc::new(list),
+            _local: PhantomData,
+        }
+    }
+
+    /// See `SkipList::with_write_buffer_size`.
+    pub fn with_write_buffer_size(self, bytes: usize) -> Self {
+        let list = Arc::into_inner(self.list).expect("set the write buffer before iterating");
+        Self::from_list(list.with_write_buffer_size(bytes))
+    }
+
+    /// See `SkipList::with_rng`.
+    pub fn with_rng(self, rng: impl Rng + Send + 'static) -> Self {
+        let list = Arc::into_inner(self.list).expect("set the generator before iterating");
+        Self::from_list(list.with_rng(rng))
+    }
+
+    pub fn options(&self) -> SkipListOptions {
+        self.list.options()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn write_buffer_usage(&self) -> usize {
+        self.list.write_buffer_usage()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.list.get(key)
+    }
+
+    /// Panics when the key is already in the list, the node cannot be allocated or the
+    /// write buffer is full; see `try_insert`.
+    pub fn insert(&self, key: K, value: V) {
+        if let Err(e) = self.try_insert(key, value) {
+            panic!("failed to insert into the skip list: {e}");
+        }
+    }
+
+    /// See `SkipList::try_insert`.
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), NodeError> {
+        // `Self` is neither `Send` nor `Sync`, and iterators, which are not either, only
+        // read; so this thread is the only one with the list
+        unsafe { self.list.try_insert_local(key, value) }
+    }
+
+    pub fn mem_usage(&self) -> usize {
+        self.list.mem_usage()
+    }
+
+    pub fn iter(&self) -> SkipListIter<K, V, C, A> {
+        self.list.iter()
+    }
+
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.list.validate()
+    }
+
+    /// The list, from now on for any thread to insert into. Sending it to another thread
+    /// publishes what was inserted here.
+    pub fn into_shared(self) -> Arc<SkipList<K, V, C, A>> {
+        self.list
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::thread;
+
+    use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+    use crate::{
+        arena::BlockArena,
+        comparator::DefaultComparator,
+        skip_list::{NodeError, WriteStall},
+    };
+
+    use super::LocalSkipList;
+
+    #[test]
+    fn local_inserts() {
+        const COUNT: u64 = if cfg!(miri) { 500 } else { 10_000 };
+
+        let list = LocalSkipList::new(DefaultComparator::default(), BlockArena::new());
+        let mut keys: Vec<_> = (0..COUNT).collect();
+        keys.shuffle(&mut StdRng::seed_from_u64(0));
+        for &key in &keys {
+            list.insert(key, key * 2);
+        }
+        assert_eq!(list.try_insert(7, 0), Err(NodeError::KeyExists));
+        assert_eq!(list.len(), COUNT as usize);
+        assert_eq!(list.validate(), Ok(()));
+        for key in 0..COUNT {
+            assert_eq!(list.get(&key), Some(&(key * 2)));
+        }
+        assert_eq!(list.get(&COUNT), None);
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for key in 0..COUNT {
+            assert_eq!(iter.key(), Some(&key));
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        // every entry is in a snapshot taken now
+        let shared = list.into_shared();
+        let snapshot = shared.snapshot();
+        assert_eq!(snapshot.seq(), COUNT);
+        assert_eq!(snapshot.get(&(COUNT - 1)), Some(&(COUNT * 2 - 2)));
+    }
+
+    #[test]
+    fn local_then_shared() {
+        let list = LocalSkipList::new(DefaultComparator::default(), BlockArena::new());
+        for key in (0..1000).step_by(2) {
+            list.insert(key, key);
+        }
+        let list = list.into_shared();
+        thread::scope(|s| {
+            for t in 0..2 {
+                let list = &list;
+                s.spawn(move || {
+                    for key in (1..1000).step_by(2).skip(t).step_by(2) {
+                        list.insert(key, key);
+                    }
+                });
+            }
+        });
+        assert_eq!(list.len(), 1000);
+        assert_eq!(list.validate(), Ok(()));
+        assert_eq!(list.get(&999), Some(&999));
+    }
+
+    #[test]
+    fn local_write_buffer() {
+        let list = LocalSkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_write_buffer_size(1024);
+        let mut inserted = 0;
+        let stall = loop {
+            match list.try_insert(inserted, inserted) {
+                Ok(()) => inserted += 1,
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(stall, NodeError::Stall(WriteStall::MemtableFull));
+        assert_eq!(list.len(), inserted as usize);
+        assert!(list.write_buffer_usage() >= 1024);
+    }
+}
@@ -0,0 +1,162 @@
+//! `SkipMap`, the concurrent ordered map for `Ord` keys, with the comparator and the
+//! allocator picked and out of sight. Reach for `SkipList` itself to choose them.
+
+use std::{
+    fmt,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+};
+
+use crate::{
+    arena::BlockArena,
+    comparator::DefaultComparator,
+    guard::{EntryRef, ValueGuard},
+    skip_list::{NodeError, SkipList},
+};
+
+/// A map that any number of threads read and insert into at once, through clones that all
+/// share one `SkipList` ordered by `Ord` in a `BlockArena`. Entries are never replaced or
+/// removed: an insert of a key already in the map leaves it as it was. Handles are `Send`
+/// and `Sync` when `K` and `V` are.
+pub struct SkipMap<K, V>(Arc<SkipList<K, V, DefaultComparator<K>, BlockArena>>);
+
+impl<K: Ord + Send + Sync, V> SkipMap<K, V> {
+    pub fn new() -> Self {
+        SkipMap(SkipList::default_arc())
+    }
+
+    /// Whether the entry went in: false when the key was already in the map, and `value`
+    /// is dropped. Panics when the node cannot be allocated.
+    pub fn insert(&self, key: K, value: V) -> bool {
+        match self.0.try_insert(key, value) {
+            Ok(()) => true,
+            Err(NodeError::KeyExists) => false,
+            Err(e) => panic!("failed to insert into the skip map: {e}"),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<ValueGuard<'_, V>> {
+        self.0.get_guard(key)
+    }
+
+    pub fn get_key_value(&self, key: &K) -> Option<EntryRef<'_, K, V>> {
+        self.0.get_key_value(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Every entry in key order, including the ones inserted ahead of the walk while it
+    /// goes.
+    pub fn iter(&self) -> impl Iterator<Item = EntryRef<'_, K, V>> {
+        self.0.entry_refs_after(Bound::Unbounded)
+    }
+
+    /// `iter` over the entries within `range`.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = EntryRef<'_, K, V>> {
+        self.0
+            .entry_refs_after(range.start_bound())
+            .take_while(move |entry| match range.end_bound() {
+                Bound::Included(end) => entry.key() <= end,
+                Bound::Excluded(end) => entry.key() < end,
+                Bound::Unbounded => true,
+            })
+    }
+}
+
+impl<K: Ord + Send + Sync, V> Default for SkipMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Another handle on the same map.
+impl<K, V> Clone for SkipMap<K, V> {
+    fn clone(&self) -> Self {
+        SkipMap(self.0.clone())
+    }
+}
+
+impl<K: Ord + Send + Sync + fmt::Debug, V: fmt::Debug> fmt::Debug for SkipMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for entry in self.iter() {
+            map.entry(entry.key(), entry.value());
+        }
+        map.finish()
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{
+        ops::{Bound, RangeBounds},
+        thread,
+    };
+
+    use super::SkipMap;
+
+    #[test]
+    fn map_front_door() {
+        let map = SkipMap::new();
+        assert!(map.is_empty());
+        for i in [5, 1, 4, 2, 3] {
+            assert!(map.insert(i, i.to_string()));
+        }
+        assert!(!map.insert(3, "again".to_string()));
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get(&3).as_deref().map(String::as_str), Some("3"));
+        let entry = map.get_key_value(&4).unwrap();
+        assert_eq!((entry.key(), entry.value().as_str()), (&4, "4"));
+        assert_eq!(entry.into_value().len(), 1);
+        assert!(map.get(&6).is_none());
+        assert!(map.contains_key(&1));
+        assert!(!map.contains_key(&6));
+        assert!(map.iter().map(|entry| *entry.key()).eq(1..=5));
+        assert_eq!(
+            format!("{map:?}"),
+            r#"{1: "1", 2: "2", 3: "3", 4: "4", 5: "5"}"#
+        );
+
+        fn keys(map: &SkipMap<i32, String>, range: impl RangeBounds<i32>) -> Vec<i32> {
+            map.range(range).map(|entry| *entry.key()).collect()
+        }
+        assert_eq!(keys(&map, 2..4), [2, 3]);
+        assert_eq!(keys(&map, ..=2), [1, 2]);
+        assert_eq!(keys(&map, 4..), [4, 5]);
+        assert_eq!(keys(&map, ..), [1, 2, 3, 4, 5]);
+        assert_eq!(keys(&map, (Bound::Excluded(2), Bound::Excluded(5))), [3, 4]);
+        assert!(keys(&map, (Bound::Excluded(5), Bound::Unbounded)).is_empty());
+        assert!(keys(&map, 6..).is_empty());
+    }
+
+    #[test]
+    fn clones_share_the_map_across_threads() {
+        const THREADS: usize = 4;
+        const COUNT: usize = if cfg!(miri) { 50 } else { 10_000 };
+
+        fn shared<T: Send + Sync + Clone>() {}
+        shared::<SkipMap<String, Vec<u8>>>();
+
+        let map = SkipMap::default();
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let map = map.clone();
+                thread::spawn(move || (0..COUNT).filter(|i| map.insert(*i, t)).count())
+            })
+            .collect();
+        let inserted: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(inserted, COUNT);
+        assert_eq!(map.len(), COUNT);
+        assert!(map.iter().map(|entry| *entry.key()).eq(0..COUNT));
+    }
+}
@@ -0,0 +1,705 @@
+use std::{
+    cmp,
+    collections::VecDeque,
+    io,
+    sync::{
+        Arc, PoisonError, RwLock, RwLockReadGuard,
+        atomic::{AtomicU64, Ordering::Relaxed},
+    },
+};
+
+use crate::{
+    arena::BlockArena,
+    bytes::{BytesIter, BytesSkipList},
+    comparator::Comparator,
+    encoding::{
+        DecodeError, MemTableKey, MemTableKeyComparator, ValueType, encode_entry, encode_lookup_key,
+    },
+    skip_list::NodeError,
+    value::{GetResult, Value},
+    wal::LogReader,
+};
+
+/// A LevelDB memtable: every write is an entry in the format of `encoding`, put or
+/// tombstone, with its sequence number, kept whole in a `BytesSkipList` node on the
+/// table's own `BlockArena`. Reads see the newest version of a key at or below the
+/// sequence number they ask for.
+///
+/// For other key types or orders, build on `SkipList` directly.
+pub struct MemTable<C> {
+    list: BytesSkipList<MemTableKeyComparator<C>, BlockArena>,
+    // one past the highest sequence number written
+    next_seq: AtomicU64,
+}
+
+/// What `MemTable::ingest_log` did with a log.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// Records inserted.
+    pub applied: usize,
+    /// Records skipped for a failed checksum or bytes that do not decode.
+    pub corrupted: usize,
+    /// Records skipped because the table already held their key at their sequence
+    /// number, as when a log is replayed twice.
+    pub duplicates: usize,
+    /// Whether the log ended inside a record.
+    pub truncated: bool,
+}
+
+impl<C: Comparator<Item = [u8]>> MemTable<C> {
+    /// A table with keys ordered by `user`.
+    pub fn new(user: C) -> Self {
+        Self {
+            list: BytesSkipList::new(MemTableKeyComparator::new(user), BlockArena::new()),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// One past the highest sequence number written, so the next write's.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_seq.load(Relaxed)
+    }
+
+    /// Entries, counting every version and tombstone.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Panics when `seq` is above `encoding::MAX_SEQUENCE`, or like `BytesSkipList::insert`;
+    /// see `try_put`.
+    pub fn put(&self, seq: u64, key: &[u8], value: &[u8]) {
+        if let Err(e) = self.try_put(seq, key, value) {
+            panic!("failed to insert into the memtable: {e}");
+        }
+    }
+
+    /// Fails with `NodeError::KeyExists` when the table already holds `key` at `seq`.
+    pub fn try_put(&self, seq: u64, key: &[u8], value: &[u8]) -> Result<(), NodeError> {
+        self.add(seq, ValueType::Value, key, value)
+    }
+
+    /// Records a tombstone for `key` at `seq`. Panics like `put`.
+    pub fn delete(&self, seq: u64, key: &[u8]) {
+        if let Err(e) = self.try_delete(seq, key) {
+            panic!("failed to insert into the memtable: {e}");
+        }
+    }
+
+    pub fn try_delete(&self, seq: u64, key: &[u8]) -> Result<(), NodeError> {
+        self.add(seq, ValueType::Deletion, key, b"")
+    }
+
+    fn add(
+        &self,
+        seq: u64,
+        value_type: ValueType,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), NodeError> {
+        let mut entry = vec![];
+        encode_entry(&mut entry, key, seq, value_type, value);
+        self.list.try_insert(&entry, b"")?;
+        self.next_seq.fetch_max(seq + 1, Relaxed);
+        Ok(())
+    }
+
+    /// Replays a log written by `wal::LogWriter` into the table, through the same inserts
+    /// as `put` and `delete`. Records that fail their checksum are skipped and counted;
+    /// see `LogReader::read_record`.
+    ///
+    /// Fails on the first error from `reader`, or when a record cannot be inserted for
+    /// any reason but its key being there already.
+    pub fn ingest_log<R: io::Read>(&self, reader: R) -> io::Result<ReplayStats> {
+        let mut log = LogReader::new(reader);
+        let mut stats = ReplayStats::default();
+        while let Some(record) = log.read_record()? {
+            let record = match record {
+                Ok(record) => record,
+                Err(DecodeError::Truncated) => {
+                    stats.truncated = true;
+                    break;
+                }
+                Err(_) => {
+                    stats.corrupted += 1;
+                    continue;
+                }
+            };
+            match self.add(record.seq, record.value_type, record.key, record.value) {
+                Ok(()) => stats.applied += 1,
+                Err(NodeError::KeyExists) => stats.duplicates += 1,
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+        Ok(stats)
+    }
+
+    /// The newest version of `key` at or below `seq`.
+    pub fn get(&self, key: &[u8], seq: u64) -> GetResult<'_, [u8]> {
+        let mut lookup = vec![];
+        encode_lookup_key(&mut lookup, key, seq);
+        let Some((entry, _)) = self.list.lower_bound(&lookup) else {
+            return GetResult::NotFound;
+        };
+        let entry = decode(entry);
+        if entry.user_key() != key {
+            return GetResult::NotFound;
+        }
+        match entry.value_type() {
+            ValueType::Value => GetResult::Found(entry.value()),
+            ValueType::Deletion => GetResult::Deleted,
+        }
+    }
+
+    /// Every entry, by key and then newest first; a flush writes them all out.
+    pub fn iter(&self) -> MemTableIter<C> {
+        MemTableIter {
+            inner: self.list.iter(),
+        }
+    }
+
+    /// The bytes the table's arena has taken.
+    pub fn approximate_memory_usage(&self) -> usize {
+        self.list.mem_usage()
+    }
+
+    /// Whether the table has outgrown `budget` bytes and should be flushed.
+    pub fn should_flush(&self, budget: usize) -> bool {
+        self.approximate_memory_usage() >= budget
+    }
+}
+
+// nothing else gets into the list
+fn decode(entry: &[u8]) -> MemTableKey<'_> {
+    MemTableKey::decode(entry).expect("memtable entries are encoded by `MemTable::add`")
+}
+
+/// A cursor over a `MemTable`'s entries, which keeps the table's nodes alive.
+pub struct MemTableIter<C> {
+    inner: BytesIter<MemTableKeyComparator<C>, BlockArena>,
+}
+
+impl<C: Comparator<Item = [u8]>> MemTableIter<C> {
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    pub fn entry(&self) -> Option<MemTableKey<'_>> {
+        self.inner.key().map(decode)
+    }
+
+    // the entry as stored, for comparing against other tables'
+    fn raw(&self) -> Option<&[u8]> {
+        self.inner.key()
+    }
+
+    pub fn next(&mut self) {
+        self.inner.next();
+    }
+
+    pub fn prev(&mut self) {
+        self.inner.prev();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last();
+    }
+
+    /// To the newest entry for `key` at or below `seq`, or the first one after.
+    pub fn seek(&mut self, key: &[u8], seq: u64) {
+        let mut lookup = vec![];
+        encode_lookup_key(&mut lookup, key, seq);
+        self.inner.seek(&lookup);
+    }
+}
+
+/// The memtables of a store: the active one taking writes, and the immutable ones
+/// rotated out of it and waiting to be flushed, each known by the id `rotate` returned.
+/// Reads see all of them.
+///
+/// Readers get their own `Arc`s of the tables, and iterators keep the nodes they walk, so
+/// neither `rotate` nor `drop_flushed` waits for them.
+pub struct MemTableSet<C> {
+    user: C,
+    tables: RwLock<Tables<C>>,
+}
+
+struct Tables<C> {
+    active: Arc<MemTable<C>>,
+    active_id: u64,
+    // oldest first
+    immutable: VecDeque<(u64, Arc<MemTable<C>>)>,
+}
+
+impl<C: Comparator<Item = [u8]> + Clone> MemTableSet<C> {
+    pub fn new(user: C) -> Self {
+        Self {
+            tables: RwLock::new(Tables {
+                active: Arc::new(MemTable::new(user.clone())),
+                active_id: 0,
+                immutable: VecDeque::new(),
+            }),
+            user,
+        }
+    }
+
+    // writers only ever hold the read lock, and nothing panics while either is held
+    fn tables(&self) -> RwLockReadGuard<'_, Tables<C>> {
+        self.tables.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn active(&self) -> Arc<MemTable<C>> {
+        self.tables().active.clone()
+    }
+
+    /// The immutable tables with their ids, oldest first: the order to flush them in.
+    pub fn immutables(&self) -> Vec<(u64, Arc<MemTable<C>>)> {
+        self.tables().immutable.iter().cloned().collect()
+    }
+
+    /// Writes to the active table. Panics like `MemTable::put`.
+    pub fn put(&self, seq: u64, key: &[u8], value: &[u8]) {
+        // held through the insert, so a rotation never leaves a write behind in a table
+        // that is already being flushed
+        self.tables().active.put(seq, key, value);
+    }
+
+    /// Panics like `MemTable::delete`.
+    pub fn delete(&self, seq: u64, key: &[u8]) {
+        self.tables().active.delete(seq, key);
+    }
+
+    /// The newest version of `key` at or below `seq` in any table, active first, then the
+    /// immutable ones newest first. `None` when no table knows the key, and
+    /// `Some(Value::Delete)` when the newest version is a tombstone.
+    pub fn get(&self, key: &[u8], seq: u64) -> Option<Value<Vec<u8>>> {
+        let tables = self.tables();
+        let newest_first = [&tables.active]
+            .into_iter()
+            .chain(tables.immutable.iter().rev().map(|(_, table)| table));
+        for table in newest_first {
+            match table.get(key, seq) {
+                GetResult::Found(value) => return Some(Value::Put(value.to_vec())),
+                GetResult::Deleted => return Some(Value::Delete),
+                GetResult::NotFound => {}
+            }
+        }
+        None
+    }
+
+    /// Makes the active table immutable and puts a fresh one with its own arena in its
+    /// place, once the writes in flight are done. Returns the id of the table rotated out.
+    pub fn rotate(&self) -> u64 {
+        let fresh = Arc::new(MemTable::new(self.user.clone()));
+        let mut tables = self.tables.write().unwrap_or_else(PoisonError::into_inner);
+        let id = tables.active_id;
+        let old = std::mem::replace(&mut tables.active, fresh);
+        tables.immutable.push_back((id, old));
+        tables.active_id += 1;
+        id
+    }
+
+    /// Forgets the immutable table `id` once it is persisted. Its memory goes back when
+    /// the last reader lets go of it. Returns whether there was such a table.
+    pub fn drop_flushed(&self, id: u64) -> bool {
+        let mut tables = self.tables.write().unwrap_or_else(PoisonError::into_inner);
+        let Some(at) = tables.immutable.iter().position(|&(i, _)| i == id) else {
+            return false;
+        };
+        tables.immutable.remove(at);
+        true
+    }
+
+    /// The entries of every table as one cursor, in the order of a single table.
+    pub fn iter(&self) -> MergingIter<C> {
+        let tables = self.tables();
+        let children = [&tables.active]
+            .into_iter()
+            .chain(tables.immutable.iter().map(|(_, table)| table))
+            .map(|table| table.iter())
+            .collect();
+        MergingIter {
+            c: MemTableKeyComparator::new(self.user.clone()),
+            children,
+            current: None,
+        }
+    }
+
+    /// Memory taken by all the tables.
+    pub fn approximate_memory_usage(&self) -> usize {
+        let tables = self.tables();
+        tables.active.approximate_memory_usage()
+            + tables
+                .immutable
+                .iter()
+                .map(|(_, table)| table.approximate_memory_usage())
+                .sum::<usize>()
+    }
+}
+
+/// A forward cursor over several tables at once, from `MemTableSet::iter`: always at the
+/// smallest entry any of them is at.
+pub struct MergingIter<C> {
+    c: MemTableKeyComparator<C>,
+    children: Vec<MemTableIter<C>>,
+    // the child at the smallest entry
+    current: Option<usize>,
+}
+
+impl<C: Comparator<Item = [u8]>> MergingIter<C> {
+    pub fn is_valid(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn entry(&self) -> Option<MemTableKey<'_>> {
+        self.children[self.current?].entry()
+    }
+
+    pub fn next(&mut self) {
+        let current = self.current.expect("the iterator is not valid");
+        self.children[current].next();
+        self.find_smallest();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        for child in &mut self.children {
+            child.seek_to_first();
+        }
+        self.find_smallest();
+    }
+
+    /// To the newest entry for `key` at or below `seq` in any table, or the first one
+    /// after.
+    pub fn seek(&mut self, key: &[u8], seq: u64) {
+        for child in &mut self.children {
+            child.seek(key, seq);
+        }
+        self.find_smallest();
+    }
+
+    fn find_smallest(&mut self) {
+        let mut smallest: Option<(usize, &[u8])> = None;
+        for (i, child) in self.children.iter().enumerate() {
+            let Some(entry) = child.raw() else {
+                continue;
+            };
+            if smallest.is_none_or(|(_, min)| self.c.compare(entry, min) == cmp::Ordering::Less) {
+                smallest = Some((i, entry));
+            }
+        }
+        self.current = smallest.map(|(i, _)| i);
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        sync::{
+            Mutex,
+            atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+        },
+        thread,
+    };
+
+    use crate::{
+        comparator::DefaultComparator,
+        encoding::{MAX_SEQUENCE, ValueType},
+        skip_list::NodeError,
+        value::{GetResult, Value},
+        wal::LogWriter,
+    };
+
+    use super::{MemTable, MemTableSet, ReplayStats};
+
+    #[test]
+    fn versions_and_tombstones() {
+        let table = MemTable::new(DefaultComparator::<[u8]>::default());
+        assert!(table.is_empty());
+        table.put(1, b"apple", b"red");
+        table.put(2, b"banana", b"yellow");
+        table.delete(3, b"apple");
+        table.put(4, b"apple", b"green");
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.try_put(2, b"banana", b""), Err(NodeError::KeyExists));
+        // a put and a tombstone at the same sequence number are different entries
+        table.delete(2, b"banana");
+
+        assert_eq!(table.get(b"apple", 0), GetResult::NotFound);
+        assert_eq!(table.get(b"apple", 1), GetResult::Found(&b"red"[..]));
+        assert_eq!(table.get(b"apple", 2), GetResult::Found(&b"red"[..]));
+        assert_eq!(table.get(b"apple", 3), GetResult::Deleted);
+        assert_eq!(table.get(b"apple", 10), GetResult::Found(&b"green"[..]));
+        // the put sorts before the tombstone, both being at 2
+        assert_eq!(table.get(b"banana", 2), GetResult::Found(&b"yellow"[..]));
+        assert_eq!(table.get(b"app", 10), GetResult::NotFound);
+        assert_eq!(table.get(b"cherry", 10), GetResult::NotFound);
+
+        let mut iter = table.iter();
+        iter.seek_to_first();
+        let mut entries = vec![];
+        while let Some(entry) = iter.entry() {
+            entries.push((entry.user_key().to_vec(), entry.seq(), entry.value_type()));
+            iter.next();
+        }
+        let entries: Vec<_> = entries.iter().map(|(k, s, t)| (&k[..], *s, *t)).collect();
+        assert_eq!(
+            entries,
+            [
+                (&b"apple"[..], 4, ValueType::Value),
+                (b"apple", 3, ValueType::Deletion),
+                (b"apple", 1, ValueType::Value),
+                (b"banana", 2, ValueType::Value),
+                (b"banana", 2, ValueType::Deletion),
+            ]
+        );
+
+        iter.seek(b"apple", 2);
+        assert_eq!(iter.entry().map(|e| e.seq()), Some(1));
+        iter.seek(b"apricot", 10);
+        assert_eq!(iter.entry().map(|e| e.user_key()), Some(&b"banana"[..]));
+        iter.seek_to_last();
+        assert_eq!(
+            iter.entry().map(|e| e.value_type()),
+            Some(ValueType::Deletion)
+        );
+    }
+
+    #[test]
+    fn flush_budget() {
+        let table = MemTable::new(DefaultComparator::<[u8]>::default());
+        let budget = table.approximate_memory_usage() + 64 * 1024;
+        assert!(!table.should_flush(budget));
+        let mut seq = 0;
+        while !table.should_flush(budget) {
+            seq += 1;
+            table.put(seq, &seq.to_be_bytes(), &[0; 100]);
+        }
+        // each entry takes over 100 bytes
+        assert!(seq < 64 * 1024 / 100, "{seq}");
+        assert!(table.approximate_memory_usage() >= budget);
+    }
+
+    #[test]
+    fn rotation() {
+        let set = MemTableSet::new(DefaultComparator::<[u8]>::default());
+        set.put(1, b"a", b"a1");
+        set.put(2, b"b", b"b2");
+        assert_eq!(set.rotate(), 0);
+        set.delete(3, b"a");
+        set.put(4, b"c", b"c4");
+        assert_eq!(set.rotate(), 1);
+        set.put(5, b"b", b"b5");
+
+        assert_eq!(set.get(b"a", 10), Some(Value::Delete));
+        assert_eq!(set.get(b"a", 2), Some(Value::Put(b"a1".to_vec())));
+        assert_eq!(set.get(b"b", 10), Some(Value::Put(b"b5".to_vec())));
+        assert_eq!(set.get(b"b", 4), Some(Value::Put(b"b2".to_vec())));
+        assert_eq!(set.get(b"c", 3), None);
+        assert_eq!(set.get(b"d", 10), None);
+
+        let entries = |mut iter: super::MergingIter<_>| {
+            iter.seek_to_first();
+            let mut entries = vec![];
+            while let Some(entry) = iter.entry() {
+                entries.push((entry.user_key().to_vec(), entry.seq()));
+                iter.next();
+            }
+            entries
+        };
+        let all = [
+            (b"a".to_vec(), 3),
+            (b"a".to_vec(), 1),
+            (b"b".to_vec(), 5),
+            (b"b".to_vec(), 2),
+            (b"c".to_vec(), 4),
+        ];
+        assert_eq!(entries(set.iter()), all);
+        let mut iter = set.iter();
+        iter.seek(b"b", 4);
+        assert_eq!(iter.entry().map(|e| e.seq()), Some(2));
+
+        // the oldest table is flushed while a reader still walks it
+        let reader = set.iter();
+        assert_eq!(
+            set.immutables()
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert!(set.drop_flushed(0));
+        assert!(!set.drop_flushed(0));
+        assert!(!set.drop_flushed(2));
+        assert_eq!(entries(reader), all);
+        assert_eq!(set.get(b"a", 2), None);
+        assert_eq!(set.immutables().len(), 1);
+    }
+
+    #[test]
+    fn rotation_under_load() {
+        const WRITERS: usize = 2;
+        const PER_WRITER: u64 = if cfg!(miri) { 100 } else { 5_000 };
+
+        let set = MemTableSet::new(DefaultComparator::<[u8]>::default());
+        let seq = AtomicU64::new(0);
+        // what the flusher has written out: user key and sequence number
+        let flushed = Mutex::new(BTreeSet::new());
+        let done = AtomicBool::new(false);
+        let is_flushed = |key: &[u8], seq| flushed.lock().unwrap().contains(&(key.to_vec(), seq));
+
+        thread::scope(|s| {
+            let writers: Vec<_> = (0..WRITERS)
+                .map(|w| {
+                    let (set, seq) = (&set, &seq);
+                    s.spawn(move || {
+                        for i in 0..PER_WRITER {
+                            let key = format!("{w}-{i:05}").into_bytes();
+                            let seq = seq.fetch_add(1, Relaxed) + 1;
+                            set.put(seq, &key, &seq.to_le_bytes());
+                            // found, or persisted before its table went
+                            let value = Some(Value::Put(seq.to_le_bytes().to_vec()));
+                            assert!(set.get(&key, MAX_SEQUENCE) == value || is_flushed(&key, seq));
+                        }
+                    })
+                })
+                .collect();
+
+            // rotates and flushes the immutable tables, oldest first
+            s.spawn(|| {
+                while !done.load(Relaxed) {
+                    set.rotate();
+                    for (id, table) in set.immutables() {
+                        let mut iter = table.iter();
+                        iter.seek_to_first();
+                        let mut flushed = flushed.lock().unwrap();
+                        while let Some(entry) = iter.entry() {
+                            flushed.insert((entry.user_key().to_vec(), entry.seq()));
+                            iter.next();
+                        }
+                        drop(flushed);
+                        assert!(set.drop_flushed(id));
+                    }
+                    thread::yield_now();
+                }
+            });
+
+            // scans across rotations stay in order
+            s.spawn(|| {
+                while !done.load(Relaxed) {
+                    let mut iter = set.iter();
+                    iter.seek_to_first();
+                    let mut last: Option<(Vec<u8>, u64)> = None;
+                    while let Some(entry) = iter.entry() {
+                        let here = (entry.user_key().to_vec(), entry.seq());
+                        if let Some(last) = &last {
+                            assert!(last.0 < here.0 || (last.0 == here.0 && last.1 > here.1));
+                        }
+                        last = Some(here);
+                        iter.next();
+                    }
+                }
+            });
+
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            done.store(true, Relaxed);
+        });
+
+        // every write is in a table or flushed, and nowhere twice
+        let flushed = flushed.into_inner().unwrap();
+        let mut iter = set.iter();
+        iter.seek_to_first();
+        let mut left = 0;
+        while let Some(entry) = iter.entry() {
+            assert!(!flushed.contains(&(entry.user_key().to_vec(), entry.seq())));
+            left += 1;
+            iter.next();
+        }
+        assert_eq!(flushed.len() + left, WRITERS * PER_WRITER as usize);
+    }
+
+    #[test]
+    fn log_replay() {
+        let mut writer = LogWriter::new(vec![]);
+        let direct = MemTable::new(DefaultComparator::<[u8]>::default());
+        for seq in 1..=1_000_u64 {
+            let key = format!("key{:03}", seq % 300).into_bytes();
+            if seq % 7 == 0 {
+                writer.delete(seq, &key).unwrap();
+                direct.delete(seq, &key);
+            } else {
+                writer.put(seq, &key, &seq.to_le_bytes()).unwrap();
+                direct.put(seq, &key, &seq.to_le_bytes());
+            }
+        }
+        let log = writer.into_inner();
+
+        let replayed = MemTable::new(DefaultComparator::<[u8]>::default());
+        let stats = replayed.ingest_log(&log[..]).unwrap();
+        assert_eq!(
+            stats,
+            ReplayStats {
+                applied: 1_000,
+                ..ReplayStats::default()
+            }
+        );
+        assert_eq!(replayed.next_sequence(), 1_001);
+        assert_eq!(direct.next_sequence(), 1_001);
+
+        let (mut a, mut b) = (direct.iter(), replayed.iter());
+        a.seek_to_first();
+        b.seek_to_first();
+        while a.is_valid() {
+            assert_eq!(a.entry(), b.entry());
+            a.next();
+            b.next();
+        }
+        assert!(!b.is_valid());
+
+        // again, on top: every record is there already
+        let stats = replayed.ingest_log(&log[..]).unwrap();
+        assert_eq!((stats.applied, stats.duplicates), (0, 1_000));
+    }
+
+    #[test]
+    fn log_replay_skips_damage() {
+        let mut writer = LogWriter::new(vec![]);
+        for seq in [5, 9, 7] {
+            writer.put(seq, &seq.to_be_bytes(), b"value").unwrap();
+        }
+        let mut log = writer.into_inner();
+        let record_len = log.len() / 3;
+        // the highest sequence number goes, and the log is torn in the last record
+        log[2 * record_len - 1] ^= 1;
+        log.pop();
+
+        let table = MemTable::new(DefaultComparator::<[u8]>::default());
+        let stats = table.ingest_log(&log[..]).unwrap();
+        assert_eq!(
+            stats,
+            ReplayStats {
+                applied: 1,
+                corrupted: 1,
+                duplicates: 0,
+                truncated: true,
+            }
+        );
+        assert_eq!(table.next_sequence(), 6);
+        assert_eq!(
+            table.get(&5_u64.to_be_bytes(), 10),
+            GetResult::Found(&b"value"[..])
+        );
+        // writes after recovery carry on from there
+        table.put(table.next_sequence(), b"new", b"");
+        assert_eq!(table.next_sequence(), 7);
+    }
+}
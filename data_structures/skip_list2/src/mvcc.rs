@@ -0,0 +1,1373 @@
+use std::{
+    cmp,
+    collections::BTreeMap,
+    mem::ManuallyDrop,
+    ptr,
+    sync::{
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError,
+        atomic::{AtomicU64, Ordering::*},
+    },
+};
+
+use crate::{
+    arena::{DefaultAllocator, MemAllocator},
+    comparator::Comparator,
+    skip_list::{NodeError, SkipList, SkipListIter, SkipListOptions, SortedBuilder},
+    value::{GetResult, Value},
+};
+
+/// A user key and the sequence number of the write that made this version of it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InternalKey<K> {
+    user_key: K,
+    seq: u64,
+}
+
+impl<K> InternalKey<K> {
+    pub fn new(user_key: K, seq: u64) -> Self {
+        Self { user_key, seq }
+    }
+
+    pub fn user_key(&self) -> &K {
+        &self.user_key
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+/// Orders internal keys by user key under `C`, and the versions of one user key newest
+/// first, so that a seek to `(key, seq)` lands on the newest version at or below `seq`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InternalKeyComparator<C> {
+    user: C,
+}
+
+impl<C> InternalKeyComparator<C> {
+    pub fn new(user: C) -> Self {
+        Self { user }
+    }
+
+    pub fn user_comparator(&self) -> &C {
+        &self.user
+    }
+}
+
+impl<K, C: Comparator<Item = K>> InternalKeyComparator<C> {
+    /// Whether `a` and `b` are versions of one user key.
+    pub fn same_user_key(&self, a: &InternalKey<K>, b: &InternalKey<K>) -> bool {
+        self.user.compare(&a.user_key, &b.user_key) == cmp::Ordering::Equal
+    }
+}
+
+impl<K, C: Comparator<Item = K>> Comparator for InternalKeyComparator<C> {
+    type Item = InternalKey<K>;
+
+    fn compare(&self, a: &InternalKey<K>, b: &InternalKey<K>) -> cmp::Ordering {
+        self.user
+            .compare(&a.user_key, &b.user_key)
+            .then_with(|| b.seq.cmp(&a.seq))
+    }
+}
+
+// A stand-in for `InternalKey::new(user_key, seq)` without taking the key: a bitwise copy
+// that is only ever compared against and never dropped.
+fn probe<K>(user_key: &K, seq: u64) -> ManuallyDrop<InternalKey<K>> {
+    ManuallyDrop::new(InternalKey {
+        user_key: unsafe { ptr::read(user_key) },
+        seq,
+    })
+}
+
+/// Folds merge operands into values, see `MvccSkipList::merge`. Operands of one key are
+/// applied oldest first, each onto what the ones before made of the newest put under
+/// them, or onto `None` when there is none or a tombstone.
+pub trait MergeOperator<V>: Send + Sync {
+    fn merge(&self, existing: Option<&V>, operand: &V) -> V;
+}
+
+impl<V, F> MergeOperator<V> for F
+where
+    F: Fn(Option<&V>, &V) -> V + Send + Sync,
+{
+    fn merge(&self, existing: Option<&V>, operand: &V) -> V {
+        self(existing, operand)
+    }
+}
+
+/// One write of a user key in an `MvccSkipList`.
+#[derive(Debug)]
+pub enum Version<V> {
+    Put(V),
+    Delete,
+    Merge(Operand<V>),
+}
+
+/// A merge operand, and what it folds the versions below it into once read.
+#[derive(Debug)]
+pub struct Operand<V> {
+    operand: V,
+    // set by the first read; the versions below are fixed by then, but for `put_at`s of
+    // even older sequence numbers, which it does not see
+    folded: OnceLock<V>,
+}
+
+impl<V> Version<V> {
+    pub fn as_put(&self) -> Option<&V> {
+        match self {
+            Version::Put(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<V> Operand<V> {
+    pub fn operand(&self) -> &V {
+        &self.operand
+    }
+}
+
+type Inner<K, V, C, A> = SkipList<InternalKey<K>, Version<V>, InternalKeyComparator<C>, A>;
+
+// range tombstones, each keyed by its start and the write's sequence number to its end
+type Ranges<K, C> = SkipList<InternalKey<K>, K, InternalKeyComparator<C>, DefaultAllocator>;
+
+/// A deletion of the user keys in `start..end`, from `MvccSkipList::delete_range`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RangeTombstone<'a, K> {
+    pub start: &'a K,
+    pub end: &'a K,
+    pub seq: u64,
+}
+
+/// Which versions of a user key a flush keeps, see `MvccSkipList::with_retention`. Reads
+/// at sequence numbers below the versions kept find nothing of the key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Retention {
+    #[default]
+    KeepAll,
+    /// The newest `n` versions, and at least one.
+    KeepLastN(usize),
+    /// The versions above `seq`, and the newest one at or below it: what a read at `seq`
+    /// sees.
+    KeepSinceSeq(u64),
+}
+
+/// A skip list of versioned entries: every write of a user key, put or delete, is a new
+/// entry tagged with a sequence number, and `get_at` reads the key as it was at any
+/// sequence number. Sequence numbers come from the list (`put`, `delete`), counting up from
+/// 1, or from the caller (`put_at`, `delete_at`), e.g. to replay a log; the list's own
+/// count then continues above the highest one it was given.
+///
+/// A number is handed out before its write is linked, so a read at a number whose write
+/// has not returned yet may not find it.
+pub struct MvccSkipList<K, V, C, A> {
+    list: Arc<Inner<K, V, C, A>>,
+    last_seq: AtomicU64,
+    snapshots: Arc<SnapshotRegistry>,
+    merge: Option<Arc<dyn MergeOperator<V>>>,
+    // made by the first range deletion
+    ranges: Arc<OnceLock<Ranges<K, C>>>,
+    // held by writes to a list with a merge operator, see `with_merge_operator`
+    ordered_writes: Mutex<()>,
+    retention: Retention,
+}
+
+impl<K, V, C, A> MvccSkipList<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn new(c: C, a: A) -> Self {
+        Self::from_list(SkipList::new(InternalKeyComparator::new(c), a))
+    }
+
+    pub fn try_new(c: C, a: A) -> Result<Self, NodeError> {
+        SkipList::try_new(InternalKeyComparator::new(c), a).map(Self::from_list)
+    }
+
+    pub fn with_options(c: C, a: A, options: SkipListOptions) -> Self {
+        Self::from_list(SkipList::with_options(
+            InternalKeyComparator::new(c),
+            a,
+            options,
+        ))
+    }
+
+    pub fn try_with_options(c: C, a: A, options: SkipListOptions) -> Result<Self, NodeError> {
+        SkipList::try_with_options(InternalKeyComparator::new(c), a, options).map(Self::from_list)
+    }
+
+    fn from_list(list: Inner<K, V, C, A>) -> Self {
+        Self {
+            list: Arc::new(list),
+            last_seq: AtomicU64::new(0),
+            snapshots: Arc::default(),
+            merge: None,
+            ranges: Arc::default(),
+            ordered_writes: Mutex::new(()),
+            retention: Retention::KeepAll,
+        }
+    }
+
+    /// Lets `merge` write operands, which `operator` folds on reads. Writes then link in
+    /// sequence order, one at a time: a fold sees every version below its operand once it
+    /// can see the operand.
+    pub fn with_merge_operator(mut self, operator: impl MergeOperator<V> + 'static) -> Self {
+        self.merge = Some(Arc::new(operator));
+        self
+    }
+
+    /// Which versions of each user key `flush_iter` keeps; all of them by default.
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Versions in the list, tombstones included and range tombstones not.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// The highest sequence number handed out or given so far; 0 before the first write.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq.load(Acquire)
+    }
+
+    /// The newest version of `user_key` at or below `seq`, with the merge operands up to
+    /// there folded in.
+    pub fn get_at(&self, user_key: &K, seq: u64) -> GetResult<'_, V> {
+        let Some((found, version)) = self.list.lower_bound(&probe(user_key, seq)) else {
+            return GetResult::NotFound;
+        };
+        if !self
+            .list
+            .comparator()
+            .same_user_key(found, &probe(user_key, seq))
+        {
+            return GetResult::NotFound;
+        }
+        if covering(&self.ranges, user_key, seq).is_some_and(|range| range > found.seq) {
+            return GetResult::Deleted;
+        }
+        match resolve(
+            &self.list,
+            &self.ranges,
+            self.merge.as_deref(),
+            found,
+            version,
+        ) {
+            Some(value) => GetResult::Found(value),
+            None => GetResult::Deleted,
+        }
+    }
+
+    /// The newest version of `user_key`.
+    pub fn get(&self, user_key: &K) -> GetResult<'_, V> {
+        self.get_at(user_key, u64::MAX)
+    }
+
+    /// Pins the latest sequence number for reads until the handle is dropped. Every
+    /// outstanding handle counts towards `min_live_sequence`.
+    pub fn get_snapshot(&self) -> SnapshotHandle {
+        // registered under the lock, so `min_live_sequence` cannot miss a handle whose
+        // number it is already past
+        self.snapshots.register(|| self.last_seq())
+    }
+
+    /// The lowest sequence number an outstanding `SnapshotHandle` pins, `None` with no
+    /// handles. Versions a handle at this number no longer sees are unreachable: a future
+    /// compaction may drop them.
+    pub fn min_live_sequence(&self) -> Option<u64> {
+        self.snapshots.min()
+    }
+
+    /// `get_at` the snapshot's sequence number.
+    pub fn get_at_snapshot(&self, user_key: &K, snapshot: &SnapshotHandle) -> GetResult<'_, V> {
+        self.get_at(user_key, snapshot.seq())
+    }
+
+    /// The user keys as of the snapshot, each with the newest value at or below its
+    /// sequence number; deleted keys are left out. The snapshot's number is copied, so the
+    /// iterator does not keep it pinned.
+    pub fn iter_at(&self, snapshot: &SnapshotHandle) -> MvccIter<K, V, C, A> {
+        MvccIter {
+            list: self.list.clone(),
+            inner: self.list.iter(),
+            seq: snapshot.seq(),
+            merge: self.merge.clone(),
+            ranges: self.ranges.clone(),
+        }
+    }
+
+    /// Writes `value` under the next sequence number and returns it. Panics like
+    /// `SkipList::insert`.
+    pub fn put(&self, user_key: K, value: V) -> u64 {
+        self.write(user_key, Version::Put(value))
+    }
+
+    /// Writes a tombstone under the next sequence number and returns it.
+    pub fn delete(&self, user_key: K) -> u64 {
+        self.write(user_key, Version::Delete)
+    }
+
+    /// Writes `operand` under the next sequence number and returns it, for the merge
+    /// operator to fold into the value when it is read: concurrent merges of one key
+    /// never lose each other, as a read, modify and put could. Panics without a merge
+    /// operator, see `with_merge_operator`.
+    pub fn merge(&self, user_key: K, operand: V) -> u64 {
+        assert!(self.merge.is_some(), "merge without a merge operator");
+        let operand = Operand {
+            operand,
+            folded: OnceLock::new(),
+        };
+        self.write(user_key, Version::Merge(operand))
+    }
+
+    /// `merge` with the list to itself: folds `operand` in right away and writes the
+    /// result as a put.
+    pub fn merge_mut(&mut self, user_key: K, operand: V) -> u64 {
+        let merge = self.merge.clone().expect("merge without a merge operator");
+        let merged = merge.merge(self.get(&user_key).found(), &operand);
+        self.put(user_key, merged)
+    }
+
+    /// Deletes the user keys in `start..end` under the next sequence number and returns
+    /// it: their versions below it read as deleted, and writes above it are not affected.
+    /// It is one write however many keys the range holds, which reads pay for instead,
+    /// each looking through the range tombstones that start at or before its key.
+    pub fn delete_range(&self, start: K, end: K) -> u64
+    where
+        C: Clone,
+    {
+        let ranges = self.ranges();
+        self.write_with(|seq| ranges.insert(InternalKey::new(start, seq), end))
+    }
+
+    fn write(&self, user_key: K, value: Version<V>) -> u64 {
+        self.write_with(|seq| self.list.insert(InternalKey::new(user_key, seq), value))
+    }
+
+    fn write_with(&self, write: impl FnOnce(u64)) -> u64 {
+        let _ordered = self.merge.is_some().then(|| {
+            self.ordered_writes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        });
+        let seq = self.last_seq.fetch_add(1, AcqRel) + 1;
+        write(seq);
+        seq
+    }
+
+    fn ranges(&self) -> &Ranges<K, C>
+    where
+        C: Clone,
+    {
+        self.ranges.get_or_init(|| {
+            let user = self.list.comparator().user_comparator().clone();
+            SkipList::new(
+                InternalKeyComparator::new(user),
+                DefaultAllocator::default(),
+            )
+        })
+    }
+
+    /// Writes `value` under a sequence number of the caller's. Fails with
+    /// `NodeError::KeyExists` when the list already holds `user_key` at `seq`.
+    pub fn put_at(&self, user_key: K, seq: u64, value: V) -> Result<(), NodeError> {
+        self.write_at(user_key, seq, Version::Put(value))
+    }
+
+    /// `put_at` for a tombstone.
+    pub fn delete_at(&self, user_key: K, seq: u64) -> Result<(), NodeError> {
+        self.write_at(user_key, seq, Version::Delete)
+    }
+
+    /// `delete_range` under a sequence number of the caller's. Fails with
+    /// `NodeError::KeyExists` when a range tombstone from `start` at `seq` is already
+    /// there.
+    pub fn delete_range_at(&self, start: K, end: K, seq: u64) -> Result<(), NodeError>
+    where
+        C: Clone,
+    {
+        self.ranges()
+            .try_insert(InternalKey::new(start, seq), end)?;
+        self.last_seq.fetch_max(seq, AcqRel);
+        Ok(())
+    }
+
+    fn write_at(&self, user_key: K, seq: u64, value: Version<V>) -> Result<(), NodeError> {
+        self.list
+            .try_insert(InternalKey::new(user_key, seq), value)?;
+        self.last_seq.fetch_max(seq, AcqRel);
+        Ok(())
+    }
+
+    /// Every version, in key order and newest first within a key, tombstones and merge
+    /// operands included.
+    pub fn iter(&self) -> SkipListIter<InternalKey<K>, Version<V>, InternalKeyComparator<C>, A> {
+        self.list.iter()
+    }
+
+    /// What a flush writes out: every version like `iter` that the retention keeps, see
+    /// `with_retention`, except that a run of merge operands goes out as one put of what it
+    /// folds into, under the newest operand's sequence number. Versions under a range
+    /// tombstone stay, for the reads below it; the tombstones go out separately, from
+    /// `range_tombstones`.
+    pub fn flush_iter(&self) -> impl Iterator<Item = (&InternalKey<K>, Value<&V>)> + '_ {
+        let c = self.list.comparator();
+        let mut entries = self.list.entries().peekable();
+        let folded = std::iter::from_fn(move || {
+            let (key, version) = entries.next()?;
+            let Version::Merge(_) = version else {
+                return Some((key, as_value(version)));
+            };
+            let folded = resolve(
+                &self.list,
+                &self.ranges,
+                self.merge.as_deref(),
+                key,
+                version,
+            );
+            // the rest of the run; a put or tombstone under it stays for the reads below
+            while let Some((older, Version::Merge(_))) = entries.peek() {
+                if !c.same_user_key(older, key) {
+                    break;
+                }
+                entries.next();
+            }
+            let folded = folded.expect("a merge operand folds into a value");
+            Some((key, Value::Put(folded)))
+        });
+
+        // versions kept of the user key last seen, and whether one was at or below the
+        // retained sequence number
+        let (retention, mut last, mut kept, mut below) = (self.retention, None, 0, false);
+        folded.filter(move |&(key, _)| {
+            if !last.is_some_and(|last| c.same_user_key(last, key)) {
+                (kept, below) = (0, false);
+            }
+            last = Some(key);
+            kept += 1;
+            match retention {
+                Retention::KeepAll => true,
+                Retention::KeepLastN(n) => kept <= n.max(1),
+                Retention::KeepSinceSeq(seq) => {
+                    // the newest one at or below is what a read at `seq` finds
+                    key.seq() > seq || !std::mem::replace(&mut below, true)
+                }
+            }
+        })
+    }
+
+    /// Every version of `user_key`, newest first, each as a read at its sequence number
+    /// sees it: merge operands folded, tombstones as `Value::Delete`.
+    pub fn versions<'a>(
+        &'a self,
+        user_key: &K,
+    ) -> impl Iterator<Item = (u64, Value<&'a V>)> + use<'a, K, V, C, A> {
+        let newest = probe(user_key, u64::MAX);
+        let c = self.list.comparator();
+        let mut entries = self.list.entries_at(&newest).peekable();
+        // the rest are matched against the list's copy of the key, which outlives `user_key`
+        let first = entries.peek().map(|&(first, _)| first);
+        let first = first.filter(|first| c.same_user_key(first, &newest));
+        entries
+            .take_while(move |(key, _)| first.is_some_and(|first| c.same_user_key(key, first)))
+            .map(|(key, version)| {
+                let value = resolve(
+                    &self.list,
+                    &self.ranges,
+                    self.merge.as_deref(),
+                    key,
+                    version,
+                );
+                (key.seq(), value.map_or(Value::Delete, Value::Put))
+            })
+    }
+
+    /// Every range tombstone, by start and newest first from one start.
+    pub fn range_tombstones(&self) -> impl Iterator<Item = RangeTombstone<'_, K>> {
+        let ranges = self.ranges.get().into_iter().flat_map(Ranges::entries);
+        ranges.map(|(start, end)| RangeTombstone {
+            start: start.user_key(),
+            end,
+            seq: start.seq(),
+        })
+    }
+
+    pub fn mem_usage(&self) -> usize {
+        let ranges = self.ranges.get().map_or(0, Ranges::mem_usage);
+        self.list.mem_usage() + ranges
+    }
+
+    /// Rebuilds the list on `allocator` with only what reads at `min_live_seq` and above
+    /// can see, e.g. at `min_live_sequence`: the versions above it, and of each user key
+    /// the newest version at or below it, as a put of the value a read at `min_live_seq`
+    /// finds. Everything under it is dropped, and so is a tombstone there, which had
+    /// nothing left to delete: a read at `min_live_seq` then finds `NotFound` rather than
+    /// `Deleted`. Range tombstones at or below `min_live_seq` go likewise, once applied.
+    ///
+    /// The new list has the merge operator, the retention and the sequence numbers of this
+    /// one, and no snapshots: handles on this list do not pin anything in it.
+    #[allow(clippy::type_complexity)]
+    pub fn compact<A2>(
+        &self,
+        min_live_seq: u64,
+        allocator: A2,
+    ) -> Result<(MvccSkipList<K, V, C, A2>, CompactionStats), NodeError>
+    where
+        K: Clone,
+        V: Clone,
+        C: Clone,
+        A2: MemAllocator,
+    {
+        let c = self.list.comparator();
+        let mut builder = SortedBuilder::new(SkipList::try_with_options(
+            c.clone(),
+            allocator,
+            self.list.options(),
+        )?);
+        let (mut entries_in, mut entries_out) = (0, 0);
+        // the user key whose newest version at or below `min_live_seq` was seen
+        let mut settled = None;
+        for (key, version) in self.list.entries() {
+            entries_in += 1;
+            if settled.is_some_and(|settled| c.same_user_key(settled, key)) {
+                continue;
+            }
+            let version = if key.seq() > min_live_seq {
+                match version {
+                    Version::Put(value) => Version::Put(value.clone()),
+                    Version::Delete => Version::Delete,
+                    Version::Merge(operand) => Version::Merge(Operand {
+                        operand: operand.operand.clone(),
+                        folded: operand.folded.clone(),
+                    }),
+                }
+            } else {
+                settled = Some(key);
+                let deleted = covering(&self.ranges, key.user_key(), min_live_seq)
+                    .is_some_and(|range| range > key.seq());
+                let value = resolve(
+                    &self.list,
+                    &self.ranges,
+                    self.merge.as_deref(),
+                    key,
+                    version,
+                );
+                match value.filter(|_| !deleted) {
+                    Some(value) => Version::Put(value.clone()),
+                    None => continue,
+                }
+            };
+            builder.push(key.clone(), version)?;
+            entries_out += 1;
+        }
+
+        let ranges = OnceLock::new();
+        if let Some(old) = self.ranges.get() {
+            let mut kept = SortedBuilder::new(SkipList::try_new(
+                old.comparator().clone(),
+                DefaultAllocator::default(),
+            )?);
+            for (start, end) in old.entries() {
+                entries_in += 1;
+                if start.seq() > min_live_seq {
+                    kept.push(start.clone(), end.clone())?;
+                    entries_out += 1;
+                }
+            }
+            let kept = kept.finish();
+            if !kept.is_empty() {
+                let _ = ranges.set(kept);
+            }
+        }
+
+        let list = MvccSkipList {
+            list: Arc::new(builder.finish()),
+            last_seq: AtomicU64::new(self.last_seq()),
+            snapshots: Arc::default(),
+            merge: self.merge.clone(),
+            ranges: Arc::new(ranges),
+            ordered_writes: Mutex::new(()),
+            retention: self.retention,
+        };
+        let stats = CompactionStats {
+            entries_in,
+            entries_out,
+            bytes_reclaimed: self.mem_usage().saturating_sub(list.mem_usage()),
+        };
+        Ok((list, stats))
+    }
+}
+
+/// What `MvccSkipList::compact` did: versions and range tombstones read and written, and
+/// how much less memory the new list takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub entries_in: usize,
+    pub entries_out: usize,
+    pub bytes_reclaimed: usize,
+}
+
+// The sequence number of the newest range tombstone at or below `seq` over `user_key`.
+fn covering<K, C>(ranges: &OnceLock<Ranges<K, C>>, user_key: &K, seq: u64) -> Option<u64>
+where
+    C: Comparator<Item = K>,
+{
+    let ranges = ranges.get()?;
+    let user = ranges.comparator().user_comparator();
+    ranges
+        .entries()
+        .take_while(|(start, _)| user.compare(start.user_key(), user_key) != cmp::Ordering::Greater)
+        .filter(|(start, end)| {
+            start.seq() <= seq && user.compare(user_key, end) == cmp::Ordering::Less
+        })
+        .map(|(start, _)| start.seq())
+        .max()
+}
+
+fn as_value<V>(version: &Version<V>) -> Value<&V> {
+    match version {
+        Version::Put(value) => Value::Put(value),
+        Version::Delete | Version::Merge(_) => Value::Delete,
+    }
+}
+
+// The value as of `version` of `key`, `None` for a tombstone. A merge operand is folded
+// over the versions below it once, by the first read.
+fn resolve<'a, K, V, C, A>(
+    list: &'a Arc<Inner<K, V, C, A>>,
+    ranges: &OnceLock<Ranges<K, C>>,
+    merge: Option<&dyn MergeOperator<V>>,
+    key: &InternalKey<K>,
+    version: &'a Version<V>,
+) -> Option<&'a V>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    let operand = match version {
+        Version::Put(value) => return Some(value),
+        Version::Delete => return None,
+        Version::Merge(operand) => operand,
+    };
+    let merge = merge.expect("merge operands in a list without a merge operator");
+    Some(operand.folded.get_or_init(|| {
+        // the operands newest first, down to a put, a tombstone or an already folded one
+        let mut operands = vec![&operand.operand];
+        let mut base = None;
+        // older versions under a range tombstone below the operand are deleted
+        let range = covering(ranges, key.user_key(), key.seq().saturating_sub(1));
+        for (older, version) in list.entries_at(key).skip(1) {
+            let covered = range.is_some_and(|range| range > older.seq());
+            if covered || !list.comparator().same_user_key(older, key) {
+                break;
+            }
+            match version {
+                Version::Put(value) => base = Some(value),
+                Version::Delete => {}
+                Version::Merge(older) => match older.folded.get() {
+                    Some(folded) => base = Some(folded),
+                    None => {
+                        operands.push(&older.operand);
+                        continue;
+                    }
+                },
+            }
+            break;
+        }
+        let mut operands = operands.into_iter().rev();
+        let first = merge.merge(base, operands.next().unwrap());
+        operands.fold(first, |folded, operand| merge.merge(Some(&folded), operand))
+    }))
+}
+
+/// A sequence number pinned by `MvccSkipList::get_snapshot`, released on drop, also when
+/// unwinding out of a panic. Clones pin the number again.
+#[derive(Debug)]
+pub struct SnapshotHandle {
+    seq: u64,
+    registry: Arc<SnapshotRegistry>,
+}
+
+impl SnapshotHandle {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+impl Clone for SnapshotHandle {
+    fn clone(&self) -> Self {
+        self.registry.register(|| self.seq)
+    }
+}
+
+impl Drop for SnapshotHandle {
+    fn drop(&mut self) {
+        self.registry.release(self.seq);
+    }
+}
+
+// outstanding handles per pinned sequence number
+#[derive(Debug, Default)]
+struct SnapshotRegistry(Mutex<BTreeMap<u64, usize>>);
+
+impl SnapshotRegistry {
+    // nothing panics while holding the lock, but a handle dropped while unwinding must
+    // still get through
+    fn pinned(&self) -> MutexGuard<'_, BTreeMap<u64, usize>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn register(self: &Arc<Self>, seq: impl FnOnce() -> u64) -> SnapshotHandle {
+        let mut pinned = self.pinned();
+        let seq = seq();
+        *pinned.entry(seq).or_default() += 1;
+        SnapshotHandle {
+            seq,
+            registry: self.clone(),
+        }
+    }
+
+    fn release(&self, seq: u64) {
+        let mut pinned = self.pinned();
+        let count = pinned.get_mut(&seq).expect("released a snapshot twice");
+        *count -= 1;
+        if *count == 0 {
+            pinned.remove(&seq);
+        }
+    }
+
+    fn min(&self) -> Option<u64> {
+        self.pinned().keys().next().copied()
+    }
+}
+
+/// A forward cursor over the user keys of an `MvccSkipList` as of a sequence number, from
+/// `iter_at`: it steps over versions newer than the number, versions shadowed by a newer
+/// one and keys whose visible version is a tombstone.
+pub struct MvccIter<K, V, C, A> {
+    list: Arc<Inner<K, V, C, A>>,
+    inner: SkipListIter<InternalKey<K>, Version<V>, InternalKeyComparator<C>, A>,
+    seq: u64,
+    merge: Option<Arc<dyn MergeOperator<V>>>,
+    ranges: Arc<OnceLock<Ranges<K, C>>>,
+}
+
+impl<K, V, C, A> MvccIter<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        self.inner.key().map(InternalKey::user_key)
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        let (key, version) = (self.inner.key()?, self.inner.value()?);
+        resolve(
+            &self.list,
+            &self.ranges,
+            self.merge.as_deref(),
+            key,
+            version,
+        )
+    }
+
+    /// The sequence number of the version `value` comes from.
+    pub fn seq(&self) -> Option<u64> {
+        self.inner.key().map(InternalKey::seq)
+    }
+
+    pub fn next(&mut self) {
+        assert!(self.is_valid());
+        self.skip_user_key();
+        self.find_visible();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+        self.find_visible();
+    }
+
+    /// Moves to the first user key at or after `user_key`.
+    pub fn seek(&mut self, user_key: &K) {
+        self.inner.seek(&probe(user_key, self.seq));
+        self.find_visible();
+    }
+
+    // past every older version of the user key the cursor is on
+    fn skip_user_key(&mut self) {
+        let user = self.inner.key().unwrap() as *const InternalKey<K>;
+        let c = self.list.comparator();
+        self.inner.next();
+        // nodes stay put while the iterator holds the list
+        while let Some(key) = self.inner.key() {
+            if !c.same_user_key(key, unsafe { &*user }) {
+                break;
+            }
+            self.inner.next();
+        }
+    }
+
+    // onto the newest version at or below `seq` of this or a later user key that is not a
+    // tombstone or under one
+    fn find_visible(&mut self) {
+        while let (Some(key), Some(value)) = (self.inner.key(), self.inner.value()) {
+            let covered =
+                || covering(&self.ranges, key.user_key(), self.seq).is_some_and(|r| r > key.seq());
+            if key.seq() > self.seq {
+                self.inner.next();
+            } else if matches!(value, Version::Delete) || covered() {
+                self.skip_user_key();
+            } else {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{
+        panic::{self, AssertUnwindSafe},
+        thread,
+    };
+
+    use crate::{
+        arena::BlockArena, comparator::DefaultComparator, skip_list::NodeError, value::GetResult,
+    };
+
+    use super::{MvccSkipList, RangeTombstone, Retention};
+
+    fn sum(existing: Option<&u64>, operand: &u64) -> u64 {
+        existing.copied().unwrap_or(0) + operand
+    }
+
+    #[test]
+    fn versions_of_one_key() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new());
+        // versions of "a" interleaved with writes of other keys
+        assert_eq!(list.put("a".to_string(), 1), 1);
+        assert_eq!(list.put("b".to_string(), 10), 2);
+        assert_eq!(list.put("a".to_string(), 2), 3);
+        assert_eq!(list.delete("a".to_string()), 4);
+        assert_eq!(list.put("c".to_string(), 30), 5);
+        assert_eq!(list.put("a".to_string(), 3), 6);
+        assert_eq!(list.len(), 6);
+
+        let a = "a".to_string();
+        assert_eq!(list.get_at(&a, 0), GetResult::NotFound);
+        assert_eq!(list.get_at(&a, 1), GetResult::Found(&1));
+        assert_eq!(list.get_at(&a, 2), GetResult::Found(&1));
+        assert_eq!(list.get_at(&a, 3), GetResult::Found(&2));
+        assert_eq!(list.get_at(&a, 4), GetResult::Deleted);
+        assert_eq!(list.get_at(&a, 5), GetResult::Deleted);
+        assert_eq!(list.get_at(&a, 6), GetResult::Found(&3));
+        assert_eq!(list.get(&a), GetResult::Found(&3));
+
+        let b = "b".to_string();
+        assert_eq!(list.get_at(&b, 1), GetResult::NotFound);
+        assert_eq!(list.get_at(&b, 2), GetResult::Found(&10));
+        assert_eq!(list.get(&"bb".to_string()), GetResult::NotFound);
+        assert_eq!(list.get(&"0".to_string()), GetResult::NotFound);
+
+        // newest first within a key
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        let mut order = vec![];
+        while let Some(key) = iter.key() {
+            order.push((key.user_key().clone(), key.seq()));
+            iter.next();
+        }
+        let order: Vec<_> = order.iter().map(|(k, s)| (k.as_str(), *s)).collect();
+        assert_eq!(
+            order,
+            [("a", 6), ("a", 4), ("a", 3), ("a", 1), ("b", 2), ("c", 5)]
+        );
+    }
+
+    #[test]
+    fn caller_sequence_numbers() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new());
+        // replayed out of order
+        list.put_at(7, 20, "seven").unwrap();
+        list.put_at(7, 10, "old seven").unwrap();
+        list.delete_at(8, 15).unwrap();
+        assert_eq!(list.put_at(7, 10, "again"), Err(NodeError::KeyExists));
+        assert_eq!(list.last_seq(), 20);
+
+        assert_eq!(list.get_at(&7, 9), GetResult::NotFound);
+        assert_eq!(list.get_at(&7, 19), GetResult::Found(&"old seven"));
+        assert_eq!(list.get_at(&8, 15), GetResult::Deleted);
+        assert_eq!(list.get_at(&8, 14), GetResult::NotFound);
+
+        // the list's own numbers continue above the replayed ones
+        assert_eq!(list.put(7, "newest"), 21);
+        assert_eq!(list.get(&7), GetResult::Found(&"newest"));
+        assert_eq!(list.get_at(&7, 20), GetResult::Found(&"seven"));
+    }
+
+    #[test]
+    fn concurrent_versions() {
+        const PER_THREAD: u64 = if cfg!(miri) { 50 } else { 2_000 };
+
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new());
+        thread::scope(|s| {
+            for t in 0..4 {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        let seq = list.put(i % 8, (t, i));
+                        assert!(matches!(list.get_at(&(i % 8), seq), GetResult::Found(_)));
+                    }
+                });
+            }
+        });
+        assert_eq!(list.len() as u64, 4 * PER_THREAD);
+        assert_eq!(list.last_seq(), 4 * PER_THREAD);
+
+        // every version reads back at its own sequence number
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        while let (Some(key), Some(value)) = (iter.key(), iter.value()) {
+            assert_eq!(
+                list.get_at(key.user_key(), key.seq()).found(),
+                value.as_put()
+            );
+            iter.next();
+        }
+    }
+
+    #[test]
+    fn snapshot_handles() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new());
+        assert_eq!(list.min_live_sequence(), None);
+
+        list.put(1, "one");
+        let first = list.get_snapshot();
+        list.put(1, "uno");
+        list.put(2, "two");
+        let second = list.get_snapshot();
+        let second_again = second.clone();
+        list.delete(2);
+        let third = list.get_snapshot();
+        assert_eq!((first.seq(), second.seq(), third.seq()), (1, 3, 4));
+        assert_eq!(list.min_live_sequence(), Some(1));
+
+        assert_eq!(list.get_at_snapshot(&1, &first), GetResult::Found(&"one"));
+        assert_eq!(list.get_at_snapshot(&2, &first), GetResult::NotFound);
+        assert_eq!(list.get_at_snapshot(&1, &second), GetResult::Found(&"uno"));
+        assert_eq!(list.get_at_snapshot(&2, &third), GetResult::Deleted);
+
+        // released out of order
+        drop(second);
+        assert_eq!(list.min_live_sequence(), Some(1));
+        drop(first);
+        assert_eq!(list.min_live_sequence(), Some(3));
+        drop(second_again);
+        assert_eq!(list.min_live_sequence(), Some(4));
+
+        // a handler that panics still releases its snapshot
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _snapshot = list.get_snapshot();
+            list.put(3, "three");
+            panic!("request failed");
+        }));
+        assert!(result.is_err());
+        assert_eq!(list.min_live_sequence(), Some(4));
+        drop(third);
+        assert_eq!(list.min_live_sequence(), None);
+    }
+
+    #[test]
+    fn iter_at_snapshots() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new());
+        for i in 0..10 {
+            list.put(i, 0);
+        }
+        let before = list.get_snapshot();
+        for i in (0..10).step_by(2) {
+            list.put(i, 1);
+        }
+        for i in (0..10).step_by(3) {
+            list.delete(i);
+        }
+        let after = list.get_snapshot();
+        list.put(11, 2);
+
+        let scan = |snapshot| {
+            let mut iter = list.iter_at(snapshot);
+            iter.seek_to_first();
+            let mut seen = vec![];
+            while let (Some(&key), Some(&value)) = (iter.key(), iter.value()) {
+                seen.push((key, value));
+                iter.next();
+            }
+            seen
+        };
+        assert_eq!(scan(&before), (0..10).map(|i| (i, 0)).collect::<Vec<_>>());
+        assert_eq!(
+            scan(&after),
+            [(1, 0), (2, 1), (4, 1), (5, 0), (7, 0), (8, 1)]
+        );
+
+        let mut iter = list.iter_at(&after);
+        iter.seek(&5);
+        assert_eq!((iter.key(), iter.value()), (Some(&5), Some(&0)));
+        iter.seek(&6);
+        assert_eq!((iter.key(), iter.value()), (Some(&7), Some(&0)));
+        iter.seek(&9);
+        assert!(!iter.is_valid());
+        iter.seek(&0);
+        assert_eq!(iter.key(), Some(&1));
+        assert_eq!(iter.seq(), Some(2));
+    }
+
+    #[test]
+    fn merge_operands() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_merge_operator(sum);
+        list.merge(1, 5);
+        list.put(2, 100);
+        list.merge(2, 1);
+        list.merge(2, 2);
+        list.delete(2);
+        list.merge(2, 3);
+        let snapshot = list.get_snapshot();
+        list.merge(2, 4);
+
+        assert_eq!(list.get(&1), GetResult::Found(&5));
+        // over the put, over the tombstone, and at each operand in between
+        assert_eq!(list.get_at(&2, 3), GetResult::Found(&101));
+        assert_eq!(list.get_at(&2, 4), GetResult::Found(&103));
+        assert_eq!(list.get_at(&2, 5), GetResult::Deleted);
+        assert_eq!(list.get_at_snapshot(&2, &snapshot), GetResult::Found(&3));
+        assert_eq!(list.get(&2), GetResult::Found(&7));
+
+        let mut iter = list.iter_at(&snapshot);
+        iter.seek_to_first();
+        assert_eq!((iter.key(), iter.value()), (Some(&1), Some(&5)));
+        iter.next();
+        assert_eq!((iter.key(), iter.value()), (Some(&2), Some(&3)));
+
+        // what a flush writes: each run of operands as one put
+        let flushed: Vec<_> = list
+            .flush_iter()
+            .map(|(key, value)| {
+                (
+                    *key.user_key(),
+                    key.seq(),
+                    value.as_put().map(|&&value| value),
+                )
+            })
+            .collect();
+        assert_eq!(
+            flushed,
+            [
+                (1, 1, Some(5)),
+                (2, 7, Some(7)),
+                (2, 5, None),
+                (2, 4, Some(103)),
+                (2, 2, Some(100))
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_mut_folds_right_away() {
+        let mut list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_merge_operator(sum);
+        list.merge_mut(1, 2);
+        list.merge_mut(1, 3);
+        assert_eq!(list.get(&1), GetResult::Found(&5));
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        while let Some(version) = iter.value() {
+            assert!(version.as_put().is_some());
+            iter.next();
+        }
+    }
+
+    #[test]
+    #[should_panic = "merge without a merge operator"]
+    fn merge_needs_an_operator() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new());
+        list.merge(1, 1_u64);
+    }
+
+    #[test]
+    fn concurrent_merges_sum() {
+        const PER_THREAD: u64 = if cfg!(miri) { 20 } else { 1_000 };
+
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_merge_operator(sum);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        list.merge(i % 2, 1);
+                        // reads fold the operands so far while more come in
+                        assert!(matches!(list.get(&(i % 2)), GetResult::Found(_)));
+                    }
+                });
+            }
+        });
+        assert_eq!(list.get(&0), GetResult::Found(&(2 * PER_THREAD)));
+        assert_eq!(list.get(&1), GetResult::Found(&(2 * PER_THREAD)));
+    }
+
+    #[test]
+    fn range_tombstones() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_merge_operator(sum);
+        for i in 0..10 {
+            list.put(i, i);
+        }
+        let before = list.get_snapshot();
+        // overlapping, and ending exactly on a key
+        assert_eq!(list.delete_range(2, 5), 11);
+        assert_eq!(list.delete_range(4, 7), 12);
+        // newer than the ranges
+        list.put(3, 30);
+        list.merge(5, 1);
+        list.merge(6, 1);
+        list.put(6, 60);
+
+        let get = |key| list.get(&key).found().copied();
+        let visible = (0..10).map(get).collect::<Vec<_>>();
+        assert_eq!(
+            visible,
+            [
+                Some(0),
+                Some(1),
+                None,
+                Some(30),
+                None,
+                Some(1),
+                Some(60),
+                Some(7),
+                Some(8),
+                Some(9)
+            ]
+        );
+        assert_eq!(list.get(&4), GetResult::Deleted);
+        assert_eq!(list.get_at(&6, 11), GetResult::Found(&6));
+        // the end is not in the range
+        assert_eq!(list.get_at(&5, 11), GetResult::Found(&5));
+        assert_eq!(list.get_at(&2, 11), GetResult::Deleted);
+        assert_eq!(list.get_at(&2, 10), GetResult::Found(&2));
+
+        let scan = |snapshot| {
+            let mut iter = list.iter_at(snapshot);
+            iter.seek_to_first();
+            let mut seen = vec![];
+            while let (Some(&key), Some(&value)) = (iter.key(), iter.value()) {
+                seen.push((key, value));
+                iter.next();
+            }
+            seen
+        };
+        assert_eq!(scan(&before), (0..10).map(|i| (i, i)).collect::<Vec<_>>());
+        assert_eq!(
+            scan(&list.get_snapshot()),
+            [
+                (0, 0),
+                (1, 1),
+                (3, 30),
+                (5, 1),
+                (6, 60),
+                (7, 7),
+                (8, 8),
+                (9, 9)
+            ]
+        );
+
+        // flushed apart from the versions they cover, which stay
+        let tombstones: Vec<_> = list.range_tombstones().collect();
+        assert_eq!(
+            tombstones,
+            [
+                RangeTombstone {
+                    start: &2,
+                    end: &5,
+                    seq: 11
+                },
+                RangeTombstone {
+                    start: &4,
+                    end: &7,
+                    seq: 12
+                }
+            ]
+        );
+        assert_eq!(
+            list.flush_iter().filter(|(key, _)| key.seq() <= 10).count(),
+            10
+        );
+
+        assert_eq!(list.delete_range_at(4, 5, 12), Err(NodeError::KeyExists));
+        list.delete_range_at(0, 1, 20).unwrap();
+        assert_eq!(list.last_seq(), 20);
+        assert_eq!(list.get(&0), GetResult::Deleted);
+    }
+
+    #[test]
+    fn version_history() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_merge_operator(sum);
+        // what each key reads as at each write of it, newest last
+        let mut model = vec![vec![]; 4];
+        for i in 0..40_u64 {
+            let key = i * 7 % 4;
+            let newest = model[key as usize].last().and_then(|&(_, read)| read);
+            let (seq, read) = match i % 5 {
+                0 | 3 => (list.put(key, i), Some(i)),
+                1 => (list.merge(key, i), Some(sum(newest.as_ref(), &i))),
+                2 => (list.delete(key), None),
+                _ => (list.merge(key, 1), Some(sum(newest.as_ref(), &1))),
+            };
+            model[key as usize].push((seq, read));
+        }
+        assert!(list.versions(&4).next().is_none());
+        for (key, written) in model.iter().enumerate() {
+            let versions: Vec<_> = list
+                .versions(&(key as u64))
+                .map(|(seq, value)| (seq, value.as_put().map(|&&value| value)))
+                .collect();
+            let newest_first: Vec<_> = written.iter().rev().copied().collect();
+            assert_eq!(versions, newest_first, "{key}");
+        }
+        // the caller's key need not outlive the iterator
+        let list = MvccSkipList::new(DefaultComparator::<String>::default(), BlockArena::new());
+        list.put("a".to_string(), 1);
+        list.put("b".to_string(), 2);
+        list.put("a".to_string(), 3);
+        let versions = list.versions(&"a".to_string());
+        assert_eq!(versions.map(|(seq, _)| seq).collect::<Vec<_>>(), [3, 1]);
+    }
+
+    #[test]
+    fn flush_retention() {
+        let flushed = |retention| {
+            let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new())
+                .with_retention(retention);
+            for i in 0..12 {
+                // 0 and 1 in turn, then a key written once
+                list.put(i % 2, i);
+            }
+            list.delete(0);
+            list.put(2, 100);
+            let seqs: Vec<_> = list
+                .flush_iter()
+                .map(|(key, value)| (*key.user_key(), key.seq(), value.is_delete()))
+                .collect();
+            seqs
+        };
+        assert_eq!(flushed(Retention::KeepAll).len(), 14);
+        assert_eq!(
+            flushed(Retention::KeepLastN(2)),
+            [
+                (0, 13, true),
+                (0, 11, false),
+                (1, 12, false),
+                (1, 10, false),
+                (2, 14, false)
+            ]
+        );
+        assert_eq!(
+            flushed(Retention::KeepLastN(0)),
+            [(0, 13, true), (1, 12, false), (2, 14, false)]
+        );
+        // 9 is a version of 0 and 8 of 1
+        assert_eq!(
+            flushed(Retention::KeepSinceSeq(9)),
+            [
+                (0, 13, true),
+                (0, 11, false),
+                (0, 9, false),
+                (1, 12, false),
+                (1, 10, false),
+                (1, 8, false),
+                (2, 14, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn compaction_drops_what_no_read_sees() {
+        let list = MvccSkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_merge_operator(sum);
+        for round in 0..4 {
+            for key in 0..20_u64 {
+                match (key + round) % 5 {
+                    0 => list.delete(key),
+                    1 => list.merge(key, 1),
+                    _ => list.put(key, key * 100 + round),
+                };
+            }
+        }
+        list.delete_range(5, 8);
+        let min_live_seq = list.last_seq();
+        for key in (0..20).step_by(3) {
+            list.merge(key, 1000);
+        }
+        list.delete(19);
+        list.delete_range(10, 12);
+
+        let (compacted, stats) = list.compact(min_live_seq, BlockArena::new()).unwrap();
+        assert_eq!(stats.entries_in, list.len() + 2);
+        assert_eq!(stats.entries_out, compacted.len() + 1);
+        assert!(stats.bytes_reclaimed > 0);
+        assert_eq!(compacted.last_seq(), list.last_seq());
+        assert_eq!(compacted.range_tombstones().count(), 1);
+        for key in 0..20 {
+            for seq in min_live_seq..=list.last_seq() {
+                assert_eq!(
+                    compacted.get_at(&key, seq).found(),
+                    list.get_at(&key, seq).found(),
+                    "{key} at {seq}"
+                );
+            }
+            // old versions are gone, tombstones at the bottom too
+            let versions: Vec<_> = compacted.versions(&key).collect();
+            assert!(
+                versions
+                    .iter()
+                    .filter(|(seq, _)| *seq <= min_live_seq)
+                    .count()
+                    <= 1
+            );
+            assert!(
+                versions
+                    .last()
+                    .is_none_or(|(seq, value)| *seq > min_live_seq || !value.is_delete())
+            );
+        }
+        // 2 had a tombstone on top, and 5 to 7 read as deleted under the range
+        for key in [2, 5, 7] {
+            assert_eq!(compacted.versions(&key).count(), 0);
+        }
+        assert_eq!(compacted.get(&6).found(), Some(&1000));
+        assert_eq!(compacted.get(&19), GetResult::Deleted);
+
+        // nothing to drop above the first write
+        let (copy, stats) = list.compact(0, BlockArena::new()).unwrap();
+        assert_eq!(copy.len(), list.len());
+        assert_eq!(
+            (stats.entries_in, stats.entries_out),
+            (list.len() + 2, list.len() + 2)
+        );
+    }
+}
@@ -0,0 +1,502 @@
+//! Byte string keys stored as an interned prefix and a suffix. Keys like
+//! `/tenant/123/table/users/row/000042` repeat most of their bytes from key to key; a
+//! `PrefixSkipList` keeps each distinct prefix once, in a table of its own, and the node
+//! only the suffix and a pointer to the prefix:
+//!
+//! ```text
+//! node:   tower | suffix | value        prefix table:   len u32 le | prefix bytes
+//!            `-> prefix ------------------------------> ^
+//! ```
+//!
+//! Keys are ordered bytewise, as if whole: two keys with one prefix compare by suffix,
+//! others by prefix and suffix chained together, without copying either key.
+
+use std::{
+    cmp,
+    collections::HashMap,
+    ptr::{self, NonNull},
+    slice,
+    sync::{
+        Arc, PoisonError, RwLock,
+        atomic::{AtomicUsize, Ordering::Relaxed},
+    },
+};
+
+use rand::Rng;
+
+use crate::{
+    arena::MemAllocator,
+    comparator::Comparator,
+    skip_list::{NodeError, SkipList, SkipListIter},
+};
+
+// the prefix of keys that have none, or whose prefix did not fit in the table
+static NO_PREFIX: [u8; 4] = [0; 4];
+
+/// The prefix `PrefixSkipList` interns by default: the key up to and including its last
+/// `/`, or nothing without one.
+pub fn through_last_slash(key: &[u8]) -> usize {
+    key.iter().rposition(|&b| b == b'/').map_or(0, |i| i + 1)
+}
+
+// A key's prefix, a record in the table, and where its suffix and value sit: `suffix_len`
+// bytes right behind the node's tower, then `value_len` bytes of value. Also the stand-in
+// for a key being searched for, with the whole key as suffix, see `probe`.
+struct PrefixedEntry {
+    prefix: NonNull<u8>,
+    ptr: NonNull<u8>,
+    suffix_len: u32,
+    value_len: u32,
+}
+
+// the prefix records and the bytes behind the node are never written after the insert
+unsafe impl Send for PrefixedEntry {}
+unsafe impl Sync for PrefixedEntry {}
+
+impl PrefixedEntry {
+    // only ever compared against, so it borrows `key` for no longer than the search
+    fn probe(key: &[u8]) -> Self {
+        Self {
+            prefix: NonNull::from(&NO_PREFIX).cast(),
+            ptr: NonNull::from(key).cast(),
+            suffix_len: len_u32(key),
+            value_len: 0,
+        }
+    }
+
+    fn prefix(&self) -> &[u8] {
+        unsafe { record_bytes(self.prefix) }
+    }
+
+    fn suffix(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.suffix_len as usize) }
+    }
+
+    fn value(&self) -> &[u8] {
+        unsafe {
+            let value = self.ptr.as_ptr().add(self.suffix_len as usize);
+            slice::from_raw_parts(value, self.value_len as usize)
+        }
+    }
+
+    fn trailer(&self, _: &()) -> usize {
+        self.suffix_len as usize + self.value_len as usize
+    }
+}
+
+// the bytes of the prefix record at `record`
+//
+// # Safety
+//
+// `record` is `NO_PREFIX` or a record of a table that is still alive.
+unsafe fn record_bytes<'a>(record: NonNull<u8>) -> &'a [u8] {
+    unsafe {
+        let len = record.cast::<[u8; 4]>().read();
+        let bytes = record.as_ptr().add(4);
+        slice::from_raw_parts(bytes, u32::from_le_bytes(len) as usize)
+    }
+}
+
+fn len_u32(bytes: &[u8]) -> u32 {
+    u32::try_from(bytes.len()).expect("keys and values are shorter than 4 GiB")
+}
+
+// Lexicographic order of `a[0] ++ a[1]` against `b[0] ++ b[1]`, comparing slice against
+// slice up to the end of the shorter one and carrying on from there.
+fn compare_chained(mut a: [&[u8]; 2], mut b: [&[u8]; 2]) -> cmp::Ordering {
+    let (mut i, mut j) = (0, 0);
+    loop {
+        while i < 2 && a[i].is_empty() {
+            i += 1;
+        }
+        while j < 2 && b[j].is_empty() {
+            j += 1;
+        }
+        if i == 2 || j == 2 {
+            return (i != 2).cmp(&(j != 2));
+        }
+        let n = a[i].len().min(b[j].len());
+        match a[i][..n].cmp(&b[j][..n]) {
+            cmp::Ordering::Equal => (a[i], b[j]) = (&a[i][n..], &b[j][n..]),
+            unequal => return unequal,
+        }
+    }
+}
+
+// The order of the entries, and the prefix table they point into, which it owns so that
+// the records outlive every entry.
+struct PrefixOrder {
+    table: RwLock<Records>,
+    table_bytes: AtomicUsize,
+    max_prefixes: usize,
+    extract: fn(&[u8]) -> usize,
+}
+
+// the record of each prefix interned so far, from `Box::into_raw`
+type Records = HashMap<Box<[u8]>, NonNull<[u8]>>;
+
+// the records are written before they are published under the lock, and only read after
+unsafe impl Send for PrefixOrder {}
+unsafe impl Sync for PrefixOrder {}
+
+impl Drop for PrefixOrder {
+    fn drop(&mut self) {
+        let table = self.table.get_mut().unwrap_or_else(PoisonError::into_inner);
+        for (_, record) in table.drain() {
+            drop(unsafe { Box::from_raw(record.as_ptr()) });
+        }
+    }
+}
+
+impl PrefixOrder {
+    // the record for `prefix`, interned on first use while the table has room
+    fn intern(&self, prefix: &[u8]) -> NonNull<u8> {
+        let no_prefix = NonNull::from(&NO_PREFIX).cast();
+        if prefix.is_empty() {
+            return no_prefix;
+        }
+        let table = self.table.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(found) = table.get(prefix) {
+            return found.cast();
+        }
+        drop(table);
+
+        let mut table = self.table.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(found) = table.get(prefix) {
+            return found.cast();
+        }
+        if table.len() >= self.max_prefixes {
+            return no_prefix;
+        }
+        let mut bytes = Vec::with_capacity(4 + prefix.len());
+        bytes.extend_from_slice(&len_u32(prefix).to_le_bytes());
+        bytes.extend_from_slice(prefix);
+        let record = NonNull::from(Box::leak(bytes.into_boxed_slice()));
+        self.table_bytes.fetch_add(prefix.len() * 2 + 4, Relaxed);
+        table.insert(prefix.into(), record);
+        record.cast()
+    }
+}
+
+impl Comparator for PrefixOrder {
+    type Item = PrefixedEntry;
+
+    fn compare(&self, a: &PrefixedEntry, b: &PrefixedEntry) -> cmp::Ordering {
+        if ptr::eq(a.prefix.as_ptr(), b.prefix.as_ptr()) {
+            return a.suffix().cmp(b.suffix());
+        }
+        compare_chained([a.prefix(), a.suffix()], [b.prefix(), b.suffix()])
+    }
+}
+
+/// A skip list of byte string keys and values that interns the keys' prefixes, see the
+/// module docs. Keys are ordered bytewise. What counts as a key's prefix is up to
+/// `with_prefix_extractor`, through the last `/` by default, and the table takes the first
+/// `with_max_prefixes` distinct ones; keys whose prefix finds no room are kept whole.
+/// Otherwise it behaves like `BytesSkipList`, except that the iterator hands out keys in
+/// two parts, or copied whole into a buffer.
+pub struct PrefixSkipList<A> {
+    list: Arc<SkipList<PrefixedEntry, (), PrefixOrder, A>>,
+}
+
+impl<A: MemAllocator> PrefixSkipList<A> {
+    pub fn new(a: A) -> Self {
+        Self::try_new(a).expect("failed to allocate the skip list's head")
+    }
+
+    pub fn try_new(a: A) -> Result<Self, NodeError> {
+        let order = PrefixOrder {
+            table: RwLock::default(),
+            table_bytes: AtomicUsize::new(0),
+            max_prefixes: 1024,
+            extract: through_last_slash,
+        };
+        let list = SkipList::try_new(order, a)?;
+        Ok(Self {
+            list: Arc::new(list.with_trailer(PrefixedEntry::trailer)),
+        })
+    }
+
+    /// Interns the first `extract(key)` bytes of each key as its prefix. Panics once the
+    /// list has entries.
+    pub fn with_prefix_extractor(self, extract: fn(&[u8]) -> usize) -> Self {
+        self.configure(|order| order.extract = extract)
+    }
+
+    /// Caps the prefix table at `n` distinct prefixes, 1024 by default. Panics once the
+    /// list has entries.
+    pub fn with_max_prefixes(self, n: usize) -> Self {
+        self.configure(|order| order.max_prefixes = n)
+    }
+
+    /// See `SkipList::with_rng`.
+    pub fn with_rng(self, rng: impl Rng + Send + 'static) -> Self {
+        let list = Arc::into_inner(self.list).expect("set the generator before iterating");
+        Self {
+            list: Arc::new(list.with_rng(rng)),
+        }
+    }
+
+    fn configure(self, set: impl FnOnce(&mut PrefixOrder)) -> Self {
+        let mut list = Arc::into_inner(self.list).expect("configure the list before iterating");
+        assert!(list.is_empty(), "configure the list before inserting");
+        set(list.comparator_mut());
+        Self {
+            list: Arc::new(list),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Distinct prefixes in the table.
+    pub fn prefix_count(&self) -> usize {
+        let table = self.list.comparator().table.read();
+        table.unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let (entry, _) = self.list.get_entry(&PrefixedEntry::probe(key))?;
+        Some(entry.value())
+    }
+
+    /// Panics when the key is already in the list, the node cannot be allocated or the
+    /// write buffer is full; see `try_insert`.
+    pub fn insert(&self, key: &[u8], value: &[u8]) {
+        if let Err(e) = self.try_insert(key, value) {
+            panic!("failed to insert into the skip list: {e}");
+        }
+    }
+
+    /// See `SkipList::try_insert`. Interns the key's prefix first, so a failed insert may
+    /// still leave it in the table.
+    pub fn try_insert(&self, key: &[u8], value: &[u8]) -> Result<(), NodeError> {
+        let order = self.list.comparator();
+        let split = (order.extract)(key).min(key.len());
+        let prefix = order.intern(&key[..split]);
+        // a prefix that found no room stays with the suffix
+        let suffix = match unsafe { record_bytes(prefix) }.len() {
+            0 => key,
+            _ => &key[split..],
+        };
+        let (suffix_len, value_len) = (len_u32(suffix), len_u32(value));
+        self.list
+            .try_insert_with(suffix.len() + value.len(), 0, |bytes| unsafe {
+                bytes.copy_from_nonoverlapping(suffix.as_ptr(), suffix.len());
+                bytes
+                    .add(suffix.len())
+                    .copy_from_nonoverlapping(value.as_ptr(), value.len());
+                let entry = PrefixedEntry {
+                    prefix,
+                    // the node's own allocation, never null
+                    ptr: NonNull::new_unchecked(bytes),
+                    suffix_len,
+                    value_len,
+                };
+                (entry, ())
+            })
+    }
+
+    /// Bytes allocated for nodes, and taken by the prefix table.
+    pub fn mem_usage(&self) -> usize {
+        self.list.mem_usage() + self.table_usage()
+    }
+
+    /// See `SkipList::useful_mem_usage`; the prefix table counts in full.
+    pub fn useful_mem_usage(&self) -> usize {
+        self.list.useful_mem_usage() + self.table_usage()
+    }
+
+    fn table_usage(&self) -> usize {
+        self.list.comparator().table_bytes.load(Relaxed)
+    }
+
+    pub fn iter(&self) -> PrefixIter<A> {
+        PrefixIter {
+            inner: self.list.iter(),
+        }
+    }
+}
+
+/// The cursor of a `PrefixSkipList`, with the methods of `SkipListIter`.
+pub struct PrefixIter<A> {
+    inner: SkipListIter<PrefixedEntry, (), PrefixOrder, A>,
+}
+
+impl<A: MemAllocator> PrefixIter<A> {
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    /// The key as its interned prefix and the rest, which make up the key end to end.
+    pub fn key_parts(&self) -> Option<(&[u8], &[u8])> {
+        self.inner
+            .key()
+            .map(|entry| (entry.prefix(), entry.suffix()))
+    }
+
+    /// Copies the key whole into `buf`, replacing what was there, and returns it.
+    pub fn key_into<'b>(&self, buf: &'b mut Vec<u8>) -> Option<&'b [u8]> {
+        let (prefix, suffix) = self.key_parts()?;
+        buf.clear();
+        buf.extend_from_slice(prefix);
+        buf.extend_from_slice(suffix);
+        Some(buf)
+    }
+
+    pub fn value(&self) -> Option<&[u8]> {
+        self.inner.key().map(PrefixedEntry::value)
+    }
+
+    pub fn next(&mut self) {
+        self.inner.next();
+    }
+
+    pub fn prev(&mut self) {
+        self.inner.prev();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last();
+    }
+
+    pub fn seek(&mut self, key: &[u8]) {
+        self.inner.seek(&PrefixedEntry::probe(key));
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::cmp::Ordering;
+
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use crate::{arena::BlockArena, bytes::BytesSkipList, comparator::DefaultComparator};
+
+    use super::{PrefixSkipList, compare_chained};
+
+    #[test]
+    fn chained_order() {
+        let whole =
+            |a: [&[u8]; 2], b: [&[u8]; 2]| [a[0], a[1]].concat().cmp(&[b[0], b[1]].concat());
+        let cases: [[&[u8]; 2]; 8] = [
+            [b"", b""],
+            [b"a", b""],
+            [b"", b"a"],
+            [b"ab", b"c"],
+            [b"a", b"bc"],
+            [b"abc", b"d"],
+            [b"a/", b"b"],
+            [b"a", b"/c"],
+        ];
+        for a in cases {
+            for b in cases {
+                assert_eq!(compare_chained(a, b), whole(a, b), "{a:?} {b:?}");
+            }
+        }
+        assert_eq!(
+            compare_chained([b"ab", b"c"], [b"a", b"bc"]),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn prefixed_keys() {
+        const ROWS: u32 = if cfg!(miri) { 60 } else { 2_000 };
+
+        let key = |tenant: u32, table: &str, row: u32| {
+            format!("/tenant/{tenant}/table/{table}/rows/{row:06}").into_bytes()
+        };
+        // the same towers for both lists
+        let rng = || StdRng::seed_from_u64(0);
+        let list = PrefixSkipList::new(BlockArena::new()).with_rng(rng());
+        let whole = BytesSkipList::new(DefaultComparator::<[u8]>::default(), BlockArena::new())
+            .with_rng(rng());
+        let mut keys = vec![];
+        for row in (0..ROWS).rev() {
+            for tenant in [7, 123, 4096] {
+                for table in ["users", "orders", "order_items", "audit_log"] {
+                    let key = key(tenant, table, row);
+                    list.insert(&key, &row.to_le_bytes());
+                    whole.insert(&key, &row.to_le_bytes());
+                    keys.push(key);
+                }
+            }
+        }
+        // a key with no slash, and one whose prefix is itself a key's prefix
+        list.insert(b"flat", b"1");
+        list.insert(b"/tenant/7/", b"2");
+        keys.extend([b"flat".to_vec(), b"/tenant/7/".to_vec()]);
+        keys.sort();
+        assert_eq!(list.len(), keys.len());
+        assert_eq!(list.prefix_count(), 13);
+        assert_eq!(
+            list.get(&key(123, "orders", 5)),
+            Some(&5_u32.to_le_bytes()[..])
+        );
+        assert_eq!(list.get(b"/tenant/7/table/users/rows/"), None);
+        assert_eq!(list.get(b"flat"), Some(&b"1"[..]));
+        assert!(list.try_insert(&key(7, "users", 1), b"").is_err());
+
+        let (mut iter, mut buf) = (list.iter(), vec![]);
+        iter.seek_to_first();
+        for key in &keys {
+            assert_eq!(iter.key_into(&mut buf), Some(&key[..]));
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+        iter.seek(b"/tenant/123/table/orders/rows/000003x");
+        assert_eq!(
+            iter.key_parts(),
+            Some((&b"/tenant/123/table/orders/rows/"[..], &b"000004"[..]))
+        );
+        iter.prev();
+        assert_eq!(iter.key_into(&mut buf), Some(&key(123, "orders", 3)[..]));
+
+        let (compressed, full) = (list.useful_mem_usage(), whole.useful_mem_usage());
+        let saved = 1.0 - compressed as f64 / full as f64;
+        assert!(
+            saved > 0.25,
+            "{compressed} bytes against {full}, {:.0}% saved",
+            saved * 100.0
+        );
+    }
+
+    #[test]
+    fn prefix_table_fills_up() {
+        let list = PrefixSkipList::new(BlockArena::new())
+            .with_max_prefixes(2)
+            .with_prefix_extractor(|key| key.len().min(2));
+        for key in [&b"aa1"[..], b"bb1", b"cc1", b"aa2", b"cc2", b"c"] {
+            list.insert(key, key);
+        }
+        assert_eq!(list.prefix_count(), 2);
+        let mut iter = list.iter();
+        iter.seek(b"b");
+        let mut parts = vec![];
+        while let Some((prefix, suffix)) = iter.key_parts() {
+            assert_eq!(iter.value(), Some(&[prefix, suffix].concat()[..]));
+            parts.push((prefix.to_vec(), suffix.to_vec()));
+            iter.next();
+        }
+        // `cc` found the table full
+        assert_eq!(
+            parts,
+            [
+                (b"bb".to_vec(), b"1".to_vec()),
+                (b"".to_vec(), b"c".to_vec()),
+                (b"".to_vec(), b"cc1".to_vec()),
+                (b"".to_vec(), b"cc2".to_vec()),
+            ]
+        );
+    }
+}
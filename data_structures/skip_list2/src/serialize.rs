@@ -0,0 +1,297 @@
+//! A list serializes as the sequence of its `(key, value)` pairs in order, streamed off
+//! level 0. It deserializes through the sorted build of `SkipList::from_sorted_iter`, one
+//! node per pair as they are read, and refuses input whose keys are not ascending by the
+//! list's comparator.
+//!
+//! `SkipListSeed` brings the comparator and allocator to deserialize with; lists whose
+//! comparator and allocator are `Default` also implement `Deserialize`.
+//!
+//! `SkipListStats` and `MetricsSnapshot` serialize as structs of their fields, for export
+//! next to other metrics.
+
+use std::{cmp, fmt, marker::PhantomData};
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, DeserializeSeed, SeqAccess, Visitor},
+    ser::{SerializeSeq, SerializeStruct},
+};
+
+#[cfg(feature = "counters")]
+use crate::skip_list::MetricsSnapshot;
+use crate::{
+    arena::MemAllocator,
+    comparator::Comparator,
+    skip_list::{SkipList, SkipListOptions, SkipListStats, SortedBuilder},
+};
+
+/// The entries counted by `len` when serializing begins, so the length given upfront
+/// holds; entries inserted meanwhile may take the place of some of them.
+impl<K, V, C, A> Serialize for SkipList<K, V, C, A>
+where
+    K: Serialize,
+    V: Serialize,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = self.len();
+        let mut seq = serializer.serialize_seq(Some(len))?;
+        for entry in self.entries().take(len) {
+            seq.serialize_element(&entry)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, K, V, C, A> Deserialize<'de> for SkipList<K, V, C, A>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Comparator<Item = K> + Default,
+    A: MemAllocator + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        SkipListSeed::new(C::default(), A::default()).deserialize(deserializer)
+    }
+}
+
+/// Deserializes a `SkipList` ordered by `c` whose nodes come from `a`.
+pub struct SkipListSeed<K, V, C, A> {
+    c: C,
+    a: A,
+    options: SkipListOptions,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, C, A> SkipListSeed<K, V, C, A> {
+    pub fn new(c: C, a: A) -> Self {
+        Self::with_options(c, a, SkipListOptions::default())
+    }
+
+    pub fn with_options(c: C, a: A, options: SkipListOptions) -> Self {
+        Self {
+            c,
+            a,
+            options,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, K, V, C, A> DeserializeSeed<'de> for SkipListSeed<K, V, C, A>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    type Value = SkipList<K, V, C, A>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, K, V, C, A> Visitor<'de> for SkipListSeed<K, V, C, A>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    type Value = SkipList<K, V, C, A>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence of key-value pairs in ascending key order")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+        let list =
+            SkipList::try_with_options(self.c, self.a, self.options).map_err(de::Error::custom)?;
+        let mut builder = SortedBuilder::new(list);
+        while let Some((key, value)) = seq.next_element::<(K, V)>()? {
+            let ascending = builder
+                .last_key()
+                .is_none_or(|last| builder.comparator().compare(last, &key) == cmp::Ordering::Less);
+            if !ascending {
+                return Err(de::Error::custom("keys are not in ascending order"));
+            }
+            builder.push(key, value).map_err(de::Error::custom)?;
+        }
+        Ok(builder.finish())
+    }
+}
+
+impl Serialize for SkipListStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields = if cfg!(feature = "counters") { 9 } else { 7 };
+        let mut stats = serializer.serialize_struct("SkipListStats", fields)?;
+        stats.serialize_field("height", &self.height)?;
+        stats.serialize_field("len", &self.len)?;
+        stats.serialize_field("level_counts", &self.level_counts)?;
+        stats.serialize_field("mean_height", &self.mean_height)?;
+        stats.serialize_field("sampled_nodes", &self.sampled_nodes)?;
+        stats.serialize_field("mem_usage", &self.mem_usage)?;
+        stats.serialize_field("useful_mem_usage", &self.useful_mem_usage)?;
+        #[cfg(feature = "counters")]
+        {
+            stats.serialize_field("comparisons", &self.comparisons)?;
+            stats.serialize_field("cas_failures", &self.cas_failures)?;
+        }
+        stats.end()
+    }
+}
+
+#[cfg(feature = "counters")]
+impl Serialize for MetricsSnapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut snapshot = serializer.serialize_struct("MetricsSnapshot", 8)?;
+        snapshot.serialize_field("inserts", &self.inserts)?;
+        snapshot.serialize_field("gets", &self.gets)?;
+        snapshot.serialize_field("seeks", &self.seeks)?;
+        snapshot.serialize_field("cas_retries", &self.cas_retries)?;
+        snapshot.serialize_field("comparisons", &self.comparisons)?;
+        snapshot.serialize_field("entries", &self.entries)?;
+        snapshot.serialize_field("arena_reserved_bytes", &self.arena_reserved_bytes)?;
+        snapshot.serialize_field("arena_used_bytes", &self.arena_used_bytes)?;
+        snapshot.end()
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{cmp, sync::Arc};
+
+    use serde::de::DeserializeSeed;
+
+    use crate::{
+        arena::{BlockArena, DefaultAllocator, TrackingAllocator},
+        comparator::{Comparator, DefaultComparator},
+        skip_list::SkipList,
+    };
+
+    use super::SkipListSeed;
+
+    #[test]
+    fn json_round_trip() {
+        let list = SkipList::<_, _>::default();
+        for i in [3, 1, 2] {
+            list.insert(i, format!("v{i}"));
+        }
+        let json = serde_json::to_string(&list).unwrap();
+        assert_eq!(json, r#"[[1,"v1"],[2,"v2"],[3,"v3"]]"#);
+
+        let restored: SkipList<i32, String, DefaultComparator<i32>, BlockArena> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored.validate(), Ok(()));
+        assert_eq!(restored.get(&2).map(String::as_str), Some("v2"));
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+
+        // and many more, over every level
+        let list = SkipList::<_, _>::default();
+        for i in (0..10_000_u32).rev() {
+            list.insert(i * 3, i);
+        }
+        let json = serde_json::to_vec(&list).unwrap();
+        let restored = Arc::new(
+            SkipListSeed::new(DefaultComparator::default(), BlockArena::new())
+                .deserialize(&mut serde_json::Deserializer::from_slice(&json))
+                .unwrap(),
+        );
+        assert_eq!(restored.validate(), Ok(()));
+        let mut iter = restored.iter();
+        iter.seek(&100);
+        assert_eq!((iter.key(), iter.value()), (Some(&102), Some(&34)));
+    }
+
+    #[test]
+    fn seed_brings_comparator_and_allocator() {
+        struct Reverse;
+
+        impl Comparator for Reverse {
+            type Item = u32;
+
+            fn compare(&self, a: &u32, b: &u32) -> cmp::Ordering {
+                b.cmp(a)
+            }
+        }
+
+        let tracker = TrackingAllocator::new(DefaultAllocator::default());
+        let list = SkipListSeed::<u32, u32, _, _>::new(Reverse, &tracker)
+            .deserialize(&mut serde_json::Deserializer::from_str(
+                "[[3,0],[2,0],[1,0]]",
+            ))
+            .unwrap();
+        assert_eq!(list.validate(), Ok(()));
+        // the head and three nodes
+        tracker.assert_no_leaks_except(4);
+
+        // ascending by `Reverse` is descending
+        let e = SkipListSeed::<u32, u32, _, _>::new(Reverse, BlockArena::new())
+            .deserialize(&mut serde_json::Deserializer::from_str("[[1,0],[2,0]]"))
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("not in ascending order"), "{e}");
+    }
+
+    #[test]
+    fn bad_input_is_refused() {
+        type List = SkipList<u32, u32, DefaultComparator<u32>, BlockArena>;
+        for json in ["[[1,0],[1,0]]", "[[2,0],[1,0]]", "[[1,0],[2]]", "{}"] {
+            assert!(serde_json::from_str::<List>(json).is_err(), "{json}");
+        }
+        assert!(serde_json::from_str::<List>("[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn stats_serialize_as_a_struct() {
+        let list = SkipList::from_sorted_iter(
+            (1..=16_u32).map(|i| (i, ())),
+            DefaultComparator::default(),
+            BlockArena::new(),
+        );
+        let stats = list.stats();
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["level_counts"], serde_json::json!([16, 4, 1]));
+        assert_eq!(json["mean_height"], 1.3125);
+        assert_eq!(json["mem_usage"], stats.mem_usage);
+        assert_eq!(
+            json.as_object().unwrap().len(),
+            if cfg!(feature = "counters") { 9 } else { 7 }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "counters")]
+    fn snapshot_keeps_its_field_names() {
+        let list = SkipList::<_, _>::default();
+        list.insert(1, 1);
+        list.get(&1);
+        let json = serde_json::to_value(list.metrics_snapshot()).unwrap();
+        let names: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "arena_reserved_bytes",
+                "arena_used_bytes",
+                "cas_retries",
+                "comparisons",
+                "entries",
+                "gets",
+                "inserts",
+                "seeks"
+            ]
+        );
+        assert_eq!(
+            (json["inserts"].as_u64(), json["gets"].as_u64()),
+            (Some(1), Some(1))
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! `SkipSet`, a `SkipList` of keys alone.
+
+use std::{
+    cmp::Ordering::*,
+    fmt,
+    ops::{Bound, RangeBounds},
+};
+
+use crate::{
+    arena::{BlockArena, MemAllocator},
+    comparator::{Comparator, DefaultComparator},
+    skip_list::{NodeError, SkipList},
+};
+
+/// An ordered set that threads insert into and read at once, like the `SkipList` it is
+/// made of. Its value is `()`, which takes no room in a node: a key costs what it would
+/// in a list without values. Keys are never removed.
+pub struct SkipSet<K, C = DefaultComparator<K>, A = BlockArena> {
+    list: SkipList<K, (), C, A>,
+}
+
+impl<K, C, A> SkipSet<K, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    /// Panics like `SkipList::new`.
+    pub fn new(c: C, a: A) -> Self {
+        SkipSet {
+            list: SkipList::new(c, a),
+        }
+    }
+
+    /// Whether the key went in rather than being in the set already. Panics when the node
+    /// cannot be allocated; see `try_insert`.
+    pub fn insert(&self, key: K) -> bool {
+        self.try_insert(key)
+            .unwrap_or_else(|e| panic!("failed to insert into the skip set: {e}"))
+    }
+
+    pub fn try_insert(&self, key: K) -> Result<bool, NodeError> {
+        match self.list.try_insert(key, ()) {
+            Ok(()) => Ok(true),
+            Err(NodeError::KeyExists) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.list.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn mem_usage(&self) -> usize {
+        self.list.mem_usage()
+    }
+
+    /// Every key in order, including the ones inserted ahead of the walk while it goes.
+    pub fn iter(&self) -> impl Iterator<Item = &K> {
+        self.list.entries().map(|(key, _)| key)
+    }
+
+    /// `iter` over the keys within `range`, bounded by the comparator.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl Iterator<Item = &K> {
+        let c = self.list.comparator();
+        self.list
+            .entries_after(range.start_bound())
+            .map(|(key, _)| key)
+            .take_while(move |key| match range.end_bound() {
+                Bound::Included(end) => c.compare(key, end) != Greater,
+                Bound::Excluded(end) => c.compare(key, end) == Less,
+                Bound::Unbounded => true,
+            })
+    }
+}
+
+impl<K, C, A> Default for SkipSet<K, C, A>
+where
+    C: Default + Comparator<Item = K>,
+    A: Default + MemAllocator,
+{
+    fn default() -> Self {
+        Self::new(C::default(), A::default())
+    }
+}
+
+impl<K, C, A> fmt::Debug for SkipSet<K, C, A>
+where
+    K: fmt::Debug,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{mem::size_of, thread};
+
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::SkipSet;
+    use crate::{
+        arena::DefaultAllocator,
+        comparator::{BytewiseComparator, DefaultComparator},
+        skip_list::{MAX_HEIGHT, Node, SkipList},
+    };
+
+    #[test]
+    fn byte_strings_across_threads() {
+        const THREADS: usize = 4;
+        const COUNT: usize = if cfg!(miri) { 50 } else { 5_000 };
+
+        let set = SkipSet::new(BytewiseComparator, DefaultAllocator::default());
+        let inserted: usize = thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    let set = &set;
+                    s.spawn(move || {
+                        (0..COUNT)
+                            .filter(|i| set.insert(format!("{i:05}").into_bytes()))
+                            .count()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(inserted, COUNT);
+        assert_eq!(set.len(), COUNT);
+        assert!(set.contains(&b"00042".to_vec()));
+        assert!(!set.contains(&b"0042".to_vec()));
+        assert!(
+            set.iter().eq((0..COUNT)
+                .map(|i| format!("{i:05}").into_bytes())
+                .collect::<Vec<_>>()
+                .iter())
+        );
+        let range: Vec<_> = set.range(b"00010".to_vec()..=b"00012".to_vec()).collect();
+        assert_eq!(range, [b"00010", b"00011", b"00012"]);
+        assert_eq!(set.range(b"00010".to_vec()..b"00010".to_vec()).count(), 0);
+
+        let small = SkipSet::<u32>::default();
+        small.insert(2);
+        small.insert(1);
+        assert!(!small.insert(1));
+        assert_eq!(format!("{small:?}"), "{1, 2}");
+    }
+
+    #[test]
+    fn keys_cost_no_value() {
+        const COUNT: u64 = if cfg!(miri) { 100 } else { 10_000 };
+
+        // a node with no value field at all
+        #[repr(C)]
+        struct KeyOnly {
+            seq: u64,
+            log: usize,
+            key: u64,
+            height: u8,
+            tower: [usize; MAX_HEIGHT],
+        }
+        assert_eq!(size_of::<Node<u64, ()>>(), size_of::<KeyOnly>());
+        assert_eq!(
+            size_of::<Node<u64, u64>>(),
+            size_of::<KeyOnly>() + size_of::<u64>()
+        );
+
+        // the same towers for every list, so the difference is the values
+        fn list<V>() -> SkipList<u64, V, DefaultComparator<u64>, DefaultAllocator> {
+            SkipList::new(DefaultComparator::default(), DefaultAllocator::default())
+                .with_rng(StdRng::seed_from_u64(1))
+        }
+        let set = SkipSet { list: list() };
+        let unit = list();
+        let valued = list();
+        for i in 0..COUNT {
+            set.insert(i);
+            unit.insert(i, ());
+            valued.insert(i, i);
+        }
+        assert_eq!(set.mem_usage(), unit.mem_usage());
+        // and the head's, which has room for one too
+        assert_eq!(
+            valued.mem_usage() - set.mem_usage(),
+            (COUNT as usize + 1) * size_of::<u64>()
+        );
+    }
+}
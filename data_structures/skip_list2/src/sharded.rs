@@ -0,0 +1,254 @@
+use std::{
+    hash::{BuildHasher, Hash, RandomState},
+    sync::Arc,
+};
+
+use crate::{
+    arena::MemAllocator,
+    comparator::Comparator,
+    skip_list::{NodeError, SkipList, SkipListIter},
+};
+
+type Router<K> = Box<dyn Fn(&K) -> usize + Send + Sync>;
+
+/// Independent `SkipList`s with every key routed to one of them, so that writers on
+/// different shards share neither tower CASes nor an arena.
+///
+/// With `with_hash` each shard is sorted but the shards interleave, so `iter` yields the
+/// shards one after another and only in per-shard order. With `with_ranges` the shards
+/// cover consecutive key ranges and `iter` is sorted across all of them.
+pub struct ShardedSkipList<K, V, C, A> {
+    shards: Vec<Arc<SkipList<K, V, C, A>>>,
+    route: Router<K>,
+    ordered: bool,
+}
+
+impl<K, V, C, A> ShardedSkipList<K, V, C, A>
+where
+    C: Comparator<Item = K> + Clone,
+    A: MemAllocator,
+{
+    /// Routes by the hash of the key. Every shard gets a clone of `c` and an allocator of
+    /// its own from `new_allocator`.
+    pub fn with_hash(shards: usize, c: C, new_allocator: impl FnMut() -> A) -> Self
+    where
+        K: Hash,
+    {
+        let hasher = RandomState::new();
+        let route = move |key: &K| (hasher.hash_one(key) % shards as u64) as usize;
+        Self::build(shards, c, new_allocator, Box::new(route), false)
+    }
+
+    /// Routes through `splitter`, which returns the shard of a key and has to be monotonic
+    /// in key order: a key never goes to a lower shard than one that sorts before it.
+    pub fn with_ranges(
+        shards: usize,
+        c: C,
+        new_allocator: impl FnMut() -> A,
+        splitter: impl Fn(&K) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        Self::build(shards, c, new_allocator, Box::new(splitter), true)
+    }
+
+    fn build(
+        shards: usize,
+        c: C,
+        mut new_allocator: impl FnMut() -> A,
+        route: Router<K>,
+        ordered: bool,
+    ) -> Self {
+        assert!(shards > 0, "a sharded list needs at least one shard");
+        let shards = (0..shards)
+            .map(|_| Arc::new(SkipList::new(c.clone(), new_allocator())))
+            .collect();
+        Self {
+            shards,
+            route,
+            ordered,
+        }
+    }
+
+    fn shard(&self, key: &K) -> &Arc<SkipList<K, V, C, A>> {
+        let index = (self.route)(key);
+        assert!(
+            index < self.shards.len(),
+            "splitter returned shard {index} of {}",
+            self.shards.len()
+        );
+        &self.shards[index]
+    }
+
+    /// Panics when the node cannot be allocated; see `try_insert`.
+    pub fn insert(&self, key: K, value: V) {
+        self.shard(&key).insert(key, value)
+    }
+
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), NodeError> {
+        self.shard(&key).try_insert(key, value)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.shard(key).get(key)
+    }
+
+    /// Summed over the shards.
+    pub fn mem_usage(&self) -> usize {
+        self.shards.iter().map(|shard| shard.mem_usage()).sum()
+    }
+
+    pub fn shards(&self) -> &[Arc<SkipList<K, V, C, A>>] {
+        &self.shards
+    }
+
+    /// Whether `iter` is sorted across shards, i.e. the list was built `with_ranges`.
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+
+    pub fn iter(&self) -> ShardedIter<'_, K, V, C, A> {
+        ShardedIter {
+            list: self,
+            shard: 0,
+            iter: self.shards[0].iter(),
+        }
+    }
+}
+
+/// Walks the shards in order, see `ShardedSkipList` for when that is sorted. Range shards
+/// never overlap, so no merging is needed: each shard picks up where the last one ended.
+pub struct ShardedIter<'a, K, V, C, A> {
+    list: &'a ShardedSkipList<K, V, C, A>,
+    shard: usize,
+    iter: SkipListIter<K, V, C, A>,
+}
+
+impl<K, V, C, A> ShardedIter<'_, K, V, C, A>
+where
+    C: Comparator<Item = K> + Clone,
+    A: MemAllocator,
+{
+    pub fn is_valid(&self) -> bool {
+        self.iter.is_valid()
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        self.iter.key()
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.iter.value()
+    }
+
+    pub fn next(&mut self) {
+        self.iter.next();
+        self.skip_empty();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.enter(0);
+        self.iter.seek_to_first();
+        self.skip_empty();
+    }
+
+    /// With hash shards this only finds positions within the key's own shard.
+    pub fn seek(&mut self, key: &K) {
+        self.enter((self.list.route)(key));
+        self.iter.seek(key);
+        self.skip_empty();
+    }
+
+    fn enter(&mut self, shard: usize) {
+        self.shard = shard;
+        self.iter = self.list.shards[shard].iter();
+    }
+
+    // moves on to the first entry of the next non-empty shard
+    fn skip_empty(&mut self) {
+        while !self.iter.is_valid() && self.shard + 1 < self.list.shards.len() {
+            self.enter(self.shard + 1);
+            self.iter.seek_to_first();
+        }
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{arena::BlockArena, comparator::DefaultComparator};
+
+    use super::ShardedSkipList;
+
+    #[test]
+    fn hash_shards() {
+        let list = ShardedSkipList::with_hash(4, DefaultComparator::default(), BlockArena::new);
+        for i in 0..1000 {
+            list.insert(i, i * 2);
+        }
+        for i in 0..1000 {
+            assert_eq!(list.get(&i), Some(&(i * 2)));
+        }
+        assert_eq!(list.get(&1000), None);
+        assert!(!list.is_ordered());
+        assert_eq!(
+            list.mem_usage(),
+            list.shards().iter().map(|s| s.mem_usage()).sum::<usize>()
+        );
+
+        // sorted within each shard only
+        for shard in list.shards() {
+            let mut iter = shard.iter();
+            iter.seek_to_first();
+            let mut prev = None;
+            while let Some(&key) = iter.key() {
+                assert!(prev < Some(key));
+                prev = Some(key);
+                iter.next();
+            }
+        }
+        let mut seen = HashSet::new();
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        while let Some(&key) = iter.key() {
+            assert!(seen.insert(key));
+            iter.next();
+        }
+        assert_eq!(seen.len(), 1000);
+    }
+
+    #[test]
+    fn range_shards() {
+        // shard 1 stays empty
+        let list = ShardedSkipList::with_ranges(
+            4,
+            DefaultComparator::default(),
+            BlockArena::new,
+            |&key: &u32| match key {
+                0..100 => 0,
+                100..200 => 2,
+                _ => 3,
+            },
+        );
+        for i in (0..300).rev() {
+            list.insert(i, i);
+        }
+        assert!(list.is_ordered());
+        assert_eq!(list.get(&150), Some(&150));
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for i in 0..300 {
+            assert_eq!(iter.key(), Some(&i));
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        iter.seek(&99);
+        assert_eq!(iter.key(), Some(&99));
+        iter.next();
+        assert_eq!(iter.key(), Some(&100));
+        iter.seek(&299);
+        iter.next();
+        assert!(!iter.is_valid());
+    }
+}
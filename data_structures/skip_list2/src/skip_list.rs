@@ -1,68 +1,603 @@
 use std::{
-    alloc::Layout,
+    alloc::{Layout, LayoutError},
+    borrow::Borrow,
+    cell::Cell,
     cmp::Ordering::*,
-    mem,
+    collections::BTreeMap,
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
     ops::Bound,
-    ptr::{self, NonNull, addr_of_mut, null_mut},
-    sync::{
-        Arc,
-        atomic::{AtomicPtr, AtomicUsize, Ordering::*},
-    },
+    ptr::{self, NonNull, addr_of, addr_of_mut, null_mut},
+    sync::{Arc, atomic::Ordering::*},
+};
+
+use rand::{Rng, RngCore, SeedableRng};
+
+use crate::{
+    arena::{AllocError, BlockArena, MemAllocator},
+    bloom::Bloom,
+    cache_padded::CachePadded,
+    comparator::{Comparator, DefaultComparator},
+    frozen::FrozenSkipList,
+    guard::{EntryRef, ValueGuard},
+    sync::{AtomicPtr, AtomicU64, AtomicUsize, Mutex},
 };
 
-use crate::{arena::MemAllocator, comparator::Comparator};
+// ceiling for `SkipListOptions::max_height`; loom explores every interleaving of every
+// tower slot, so its lists stay tiny
+#[cfg(not(loom))]
+pub(crate) const MAX_HEIGHT: usize = 32;
+#[cfg(loom)]
+pub(crate) const MAX_HEIGHT: usize = 3;
+
+// `seq` of a node no one has stamped yet, see `SkipList::stamp`
+const UNSTAMPED: u64 = u64::MAX;
+
+// nodes `estimate_quantiles` reads per run it splits off: the rank of the `k`th node on a
+// level is off by about `sqrt(k)` nodes' worth of entries, which stays within a few
+// percent of the list with this many
+const QUANTILE_SAMPLES: usize = 32;
+
+// entries the `Debug` output of a list shows before it gives up with `..`
+const DEBUG_ENTRIES: usize = 8;
+
+// how many characters of a key's `Debug` output `dump_structure` and `to_dot` print
+const DUMP_KEY_WIDTH: usize = 12;
 
-const MAX_HEIGHT: usize = 20;
+// edge colors of `to_dot`, by level
+const DOT_COLORS: [&str; 8] = [
+    "black",
+    "blue",
+    "red",
+    "darkgreen",
+    "orange",
+    "purple",
+    "brown",
+    "gray40",
+];
 
+// `key` and `value` are initialized on every node but the head. Only the first `height`
+// tower slots are allocated; `height` sits in front of the tower, usually in padding.
+// `seq` orders the node against snapshots, and `log` points at the node stamped right
+// after it, see `SkipList::stamp`; they go first so small keys and values pack behind.
 #[repr(C)]
 pub struct Node<K, V> {
-    key: K,
-    value: V,
+    seq: AtomicU64,
+    log: AtomicPtr<Self>,
+    key: MaybeUninit<K>,
+    value: MaybeUninit<V>,
+    height: u8,
     tower: [AtomicPtr<Self>; MAX_HEIGHT],
 }
 
+// Nodes are only ever touched through `*mut Node`: a `&Node` would claim the whole
+// `MAX_HEIGHT` tower, while the allocation stops after the node's height.
+//
+// Publication contract, for every node linked while the list is shared:
+//
+// 1. Key, value, height and tower slots are written with plain stores before the node is
+//    linked anywhere.
+// 2. The node becomes reachable through a `Release` CAS on its predecessor's level 0 slot
+//    (`try_insert`), and on each higher level through another `Release` CAS that comes
+//    after the one below succeeded.
+// 3. Every load of a tower slot whose result may be dereferenced is `Acquire`
+//    (`get_next`), so it synchronizes with the CAS that stored the pointer and sees
+//    everything from 1.
+// 4. A node's own slot on `level` is set (`set_next`) only before that level's CAS, so
+//    no reader can observe the store except through that CAS. A reader that found the
+//    node on a level may read its slots on that level and below, never above: a node on
+//    level 0 says nothing about whether it is linked higher up yet.
+//
+// Debug builds check the height half of 3 in `get_next`, which also gives Miri's race
+// detector a plain read to catch a weakened ordering with; `loom_tests` checks the rest.
 impl<K, V> Node<K, V> {
-    fn get_next(&self, level: usize) -> *mut Self {
-        self.tower[level].load(SeqCst)
+    /// # Safety
+    ///
+    /// `this` is a live node, not the head.
+    unsafe fn key<'a>(this: *mut Self) -> &'a K {
+        unsafe { (*addr_of!((*this).key)).assume_init_ref() }
+    }
+
+    /// # Safety
+    ///
+    /// `this` is a live node, not the head.
+    unsafe fn value<'a>(this: *mut Self) -> &'a V {
+        unsafe { (*addr_of!((*this).value)).assume_init_ref() }
+    }
+
+    /// # Safety
+    ///
+    /// `this` is a live node.
+    unsafe fn seq<'a>(this: *mut Self) -> &'a AtomicU64 {
+        unsafe { &*addr_of!((*this).seq) }
+    }
+
+    /// # Safety
+    ///
+    /// `this` is a live node.
+    unsafe fn log<'a>(this: *mut Self) -> &'a AtomicPtr<Self> {
+        unsafe { &*addr_of!((*this).log) }
+    }
+
+    /// # Safety
+    ///
+    /// `this` is a live node.
+    unsafe fn height(this: *mut Self) -> usize {
+        unsafe { *addr_of!((*this).height) as usize }
     }
 
-    fn set_next(&self, level: usize, node: *mut Self) {
-        self.tower[level].store(node, SeqCst);
+    /// # Safety
+    ///
+    /// `this` is a live node with a tower higher than `level`.
+    unsafe fn tower<'a>(this: *mut Self, level: usize) -> &'a AtomicPtr<Self> {
+        unsafe {
+            debug_assert!(level < Self::height(this));
+            &*(addr_of!((*this).tower) as *const AtomicPtr<Self>).add(level)
+        }
+    }
+
+    // pairs with the `Release` linking CAS in `try_insert`, so whatever the node behind
+    // the pointer was initialized with is visible
+    unsafe fn get_next(this: *mut Self, level: usize) -> *mut Self {
+        unsafe {
+            let next = Self::tower(this, level).load(Acquire);
+            debug_assert!(
+                next.is_null() || Self::height(next) > level,
+                "reached a node on level {level} whose tower is not visible"
+            );
+            next
+        }
+    }
+
+    // only on nodes not yet linked on `level`, with the list borrowed mutably, or from a
+    // `LocalSkipList`: the CAS that links the node is what publishes the store, so it need
+    // not be `Release`, and a list no other thread can reach needs no publishing
+    unsafe fn set_next(this: *mut Self, level: usize, node: *mut Self) {
+        unsafe { Self::tower(this, level).store(node, Relaxed) };
     }
 
-    fn get_layout(height: usize) -> Layout {
-        assert!(height > 0);
+    fn get_layout(height: usize) -> Result<Layout, LayoutError> {
+        assert!(height > 0 && height <= MAX_HEIGHT);
         let size =
             mem::size_of::<Self>() - mem::size_of::<AtomicPtr<Self>>() * (MAX_HEIGHT - height);
-        let align = mem::align_of::<Self>();
-        Layout::from_size_align(size, align)
-            .expect(format!("Layout error, size: {size}, align: {align}").as_str())
+        Layout::from_size_align(size, mem::align_of::<Self>())
     }
 
-    fn new_in(key: K, value: V, height: usize, allocator: &impl MemAllocator) -> *mut Self {
-        let layout = Self::get_layout(height);
-        unsafe {
-            let p = allocator.allocate(layout) as *mut Self;
-            assert!(!p.is_null() && p.is_aligned());
+    // `get_layout` with `trailer` bytes of the entry's own behind the tower, and the offset
+    // they start at; see `SkipList::try_insert_with`
+    fn get_layout_with(height: usize, trailer: usize) -> Result<(Layout, usize), LayoutError> {
+        Self::get_layout(height)?.extend(Layout::array::<u8>(trailer)?)
+    }
+
+    fn new_in(
+        key: K,
+        value: V,
+        height: usize,
+        allocator: &impl MemAllocator,
+    ) -> Result<*mut Self, NodeError> {
+        Self::new_in_with(height, 0, allocator, |_| (key, value))
+    }
 
-            let node = &mut *p;
-            ptr::write(addr_of_mut!(node.key), key);
-            ptr::write(addr_of_mut!(node.value), value);
-            ptr::write_bytes(node.tower.as_mut_ptr(), 0, height);
-            p
+    // `init` gets the node's `trailer` bytes, uninitialized, and returns the key and value
+    fn new_in_with(
+        height: usize,
+        trailer: usize,
+        allocator: &impl MemAllocator,
+        init: impl FnOnce(*mut u8) -> (K, V),
+    ) -> Result<*mut Self, NodeError> {
+        let (layout, offset) = Self::get_layout_with(height, trailer)?;
+        let p = Self::alloc_in(height, layout, allocator)?;
+        unsafe {
+            let (key, value) = init(p.cast::<u8>().add(offset));
+            addr_of_mut!((*p).key).write(MaybeUninit::new(key));
+            addr_of_mut!((*p).value).write(MaybeUninit::new(value));
         }
+        Ok(p)
+    }
+
+    // the head gets the list's full height, but neither key nor value; it starts the log
+    // of `SkipList::stamp` with sequence number 0
+    fn new_head(height: usize, allocator: &impl MemAllocator) -> Result<*mut Self, NodeError> {
+        let head = Self::alloc_in(height, Self::get_layout(height)?, allocator)?;
+        unsafe { Self::seq(head).store(0, Relaxed) };
+        Ok(head)
     }
 
-    fn new_head(allocator: &impl MemAllocator) -> *mut Self {
-        unsafe { Self::new_in(mem::zeroed(), mem::zeroed(), MAX_HEIGHT, allocator) }
+    fn alloc_in(
+        height: usize,
+        layout: Layout,
+        allocator: &impl MemAllocator,
+    ) -> Result<*mut Self, NodeError> {
+        unsafe {
+            let p = allocator.allocate(layout)?.as_ptr() as *mut Self;
+            assert!(p.is_aligned());
+
+            // The memory may be uninitialized, and only `height` tower slots exist. Write
+            // through raw pointers, and fill every slot a reader can reach before the node
+            // is linked: a node is only linked on levels below its height.
+            addr_of_mut!((*p).seq).write(AtomicU64::new(UNSTAMPED));
+            addr_of_mut!((*p).log).write(AtomicPtr::new(null_mut()));
+            addr_of_mut!((*p).height).write(height as u8);
+            let tower = addr_of_mut!((*p).tower) as *mut AtomicPtr<Self>;
+            for level in 0..height {
+                // a memset for `std` atomics, but loom's have to be constructed
+                tower.add(level).write(AtomicPtr::new(null_mut()));
+            }
+            Ok(p)
+        }
     }
 }
 
-pub struct SkipList<K, V, C, A> {
-    height: AtomicUsize,
+/// The height counter, bumped by inserts that grow the list, sits on its own cache line so
+/// that readers loading `head` and `options` do not miss on every bump; so do the write
+/// buffer and length counters and the tail of the log of stamped nodes. Together with the
+/// padding inside `BlockArena` this makes `SkipList<u64, u64, _, BlockArena>` 1024 bytes
+/// on x86_64.
+///
+/// Nodes are never unlinked while the list is shared: only `pop_first` and `drain` remove
+/// entries, and both take `&mut self`. A key or value reference therefore lives as long as
+/// the borrow of the list, readers take no guards, and there is no deferred reclamation,
+/// epoch based or otherwise, to stall behind a slow reader.
+pub struct SkipList<K, V, C = DefaultComparator<K>, A = BlockArena> {
+    height: CachePadded<AtomicUsize>,
+    // node bytes charged against `options.write_buffer_size`, 0 without a budget
+    write_buffer_usage: CachePadded<AtomicUsize>,
+    // entries linked on level 0, bumped with `Release` right after an insert's level 0 CAS
+    len: CachePadded<AtomicUsize>,
+    // the last node stamped, or one a little before it, see `stamp`
+    log_tail: CachePadded<AtomicPtr<Node<K, V>>>,
+    // at or above every sequence number handed out before the last removal, so that
+    // numbers never repeat when the newest entry goes; only written through `&mut self`
+    seq_floor: u64,
+    // the start of the log by sequence number, for `pop_first` to find the node stamped
+    // before the one it takes off; filled in only when it has to, through `&mut self`
+    log_index: BTreeMap<u64, *mut Node<K, V>>,
     head: NonNull<Node<K, V>>,
+    options: SkipListOptions,
+    // where heights come from when set, see `with_rng`; otherwise the thread's `HEIGHT_RNG`
+    rng: Option<Mutex<Box<dyn RngCore + Send>>>,
+    // the bytes a node carries behind its tower, see `try_insert_with`
+    trailer: fn(&K, &V) -> usize,
+    // consulted by lookups before they search, see `with_bloom`
+    bloom: Option<Box<Bloom<K>>>,
+    // kept up by inserts and removals, see `with_incremental_hash`
+    digest: Option<Box<Digest<K, V>>>,
+    // told of every insert, see `register_observer`
+    observers: Observers<K, V>,
     c: C,
     a: A,
+    #[cfg(feature = "counters")]
+    counters: CachePadded<Counters>,
+    // the full `validate`, run by `drop` in debug test builds, which cannot ask for a
+    // comparator itself
+    #[cfg(all(test, debug_assertions, not(any(loom, shuttle))))]
+    validate_on_drop: fn(&Self) -> Result<(), InvariantViolation>,
+}
+
+/// Told of every entry inserted into a list, see `SkipList::register_observer`.
+pub trait InsertObserver<K, V>: Send + Sync {
+    fn on_insert(&self, key: &K, value: &V);
+}
+
+impl<K, V, F: Fn(&K, &V) + Send + Sync> InsertObserver<K, V> for F {
+    fn on_insert(&self, key: &K, value: &V) {
+        self(key, value)
+    }
+}
+
+// The observers of `SkipList::register_observer`, newest first. Observers are never
+// removed while the list is shared, so inserts walk the chain without a lock and
+// registering is a push onto its head.
+struct Observers<K, V> {
+    head: AtomicPtr<ObserverLink<K, V>>,
+}
+
+struct ObserverLink<K, V> {
+    observer: Arc<dyn InsertObserver<K, V>>,
+    next: *mut ObserverLink<K, V>,
+}
+
+impl<K, V> Default for Observers<K, V> {
+    fn default() -> Self {
+        Observers {
+            head: AtomicPtr::new(null_mut()),
+        }
+    }
+}
+
+impl<K, V> Observers<K, V> {
+    fn push(&self, observer: Arc<dyn InsertObserver<K, V>>) {
+        let link = Box::into_raw(Box::new(ObserverLink {
+            observer,
+            next: self.head.load(Relaxed),
+        }));
+        // `Release` publishes the link to the `Acquire` load in `notify`
+        while let Err(head) =
+            self.head
+                .compare_exchange_weak(unsafe { (*link).next }, link, Release, Relaxed)
+        {
+            unsafe { (*link).next = head };
+        }
+    }
+
+    fn notify(&self, key: &K, value: &V) {
+        let mut cur = self.head.load(Acquire);
+        while !cur.is_null() {
+            // links live until the list is dropped, and are not written once published
+            let link = unsafe { &*cur };
+            link.observer.on_insert(key, value);
+            cur = link.next;
+        }
+    }
+}
+
+impl<K, V> Drop for Observers<K, V> {
+    fn drop(&mut self) {
+        let mut cur = self.head.load(Relaxed);
+        while !cur.is_null() {
+            let link = unsafe { Box::from_raw(cur) };
+            cur = link.next;
+        }
+    }
+}
+
+// the order-independent hash of `SkipList::with_incremental_hash`
+struct Digest<K, V> {
+    sum: AtomicU64,
+    hash: fn(&K, &V) -> u64,
+}
+
+impl<K, V> Digest<K, V> {
+    fn add(&self, key: &K, value: &V) {
+        self.sum.fetch_add((self.hash)(key, value), Relaxed);
+    }
+
+    fn remove(&self, key: &K, value: &V) {
+        self.sum.fetch_sub((self.hash)(key, value), Relaxed);
+    }
+}
+
+/// Per-list tuning, passed to `SkipList::with_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkipListOptions {
+    /// A node reaches each next level with probability `1 / branching`. Smaller factors
+    /// mean taller towers and shorter searches. At least 2, 4 by default.
+    pub branching: u32,
+    /// Height of the tallest tower, between 1 and 32, 20 by default. Searches stay
+    /// logarithmic up to about `branching ^ max_height` entries.
+    pub max_height: usize,
+    /// Once the list's nodes take this many bytes, inserts fail with
+    /// `WriteStall::MemtableFull`; see `SkipList::with_write_buffer_size`. Unlimited by
+    /// default.
+    pub write_buffer_size: Option<usize>,
+    /// Between retries of a lost tower CAS an insert spins 1, 2, 4, ... up to
+    /// `2^backoff_spin_limit` times, then yields its thread instead. 0 retries right away;
+    /// 6 by default.
+    pub backoff_spin_limit: u32,
+    /// Caps a new node's height at `ceil(log_branching(len)) + 2`, so that a small list
+    /// does not carry towers only a much bigger one would use, and searches do not start
+    /// levels above where the data is. The cap only grows while the list is shared and
+    /// never passes `max_height`. On by default.
+    pub adaptive_height: bool,
+    /// What inserting a key already in the list does. Rejected by default.
+    pub duplicates: DuplicatePolicy,
+}
+
+/// What an insert does with a key that compares equal to an entry, see
+/// `SkipListOptions::duplicates`. Either way the entry already there stays: nodes are not
+/// replaced while the list is shared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// `try_insert` fails with `NodeError::KeyExists`, and `insert` panics.
+    #[default]
+    Reject,
+    /// The insert succeeds without changing anything, dropping the new key and value.
+    KeepExisting,
+}
+
+impl Default for SkipListOptions {
+    fn default() -> Self {
+        Self {
+            branching: 4,
+            max_height: 20.min(MAX_HEIGHT),
+            write_buffer_size: None,
+            backoff_spin_limit: 6,
+            adaptive_height: true,
+            duplicates: DuplicatePolicy::Reject,
+        }
+    }
+}
+
+/// What a list's searches and inserts have cost so far, from `SkipList::metrics`. Only
+/// with the `counters` feature.
+#[cfg(feature = "counters")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SkipListMetrics {
+    /// Tower CASes lost to a concurrent insert into the same gap.
+    pub cas_failures: usize,
+    /// Lost CASes after which the splice point moved, because the winner's node sorts
+    /// before the key. The others only had to pick up the new successor.
+    pub re_searches: usize,
+    /// Comparator calls made by searches, including the ones inside inserts.
+    pub comparisons: usize,
+    /// Top-down searches: seeks, `get`, and one per insert.
+    pub searches: usize,
+    /// Nodes stepped past by those searches; divided by `searches` it is the mean search
+    /// depth, not counting the levels walked down.
+    pub search_steps: usize,
+    /// Inserts started, the ones that failed included.
+    pub inserts: usize,
+    /// Point lookups: `get`, `contains_key`, `get_guard` and `get_key_value`.
+    pub gets: usize,
+    /// Iterator seeks, and the first entry of every range.
+    pub seeks: usize,
+}
+
+/// The counters and gauges a metrics pipeline scrapes off a list, from
+/// `SkipList::metrics_snapshot`. The field names are the stable names of the series; with
+/// the `metrics` feature, `export::ListMetrics` publishes them through the `metrics`
+/// facade. Only with the `counters` feature.
+#[cfg(feature = "counters")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// `SkipListMetrics::inserts`.
+    pub inserts: u64,
+    /// `SkipListMetrics::gets`.
+    pub gets: u64,
+    /// `SkipListMetrics::seeks`.
+    pub seeks: u64,
+    /// Tower CASes lost and tried again, `SkipListMetrics::cas_failures`.
+    pub cas_retries: u64,
+    /// `SkipListMetrics::comparisons`.
+    pub comparisons: u64,
+    /// A gauge: entries in the list, as `approx_len` counts them.
+    pub entries: u64,
+    /// A gauge: bytes the allocator reserved, `SkipList::mem_usage`.
+    pub arena_reserved_bytes: u64,
+    /// A gauge: bytes the allocator handed out, `SkipList::useful_mem_usage`.
+    pub arena_used_bytes: u64,
+}
+
+/// The shape of a list, from `SkipList::stats`, or estimated by `SkipList::stats_sampled`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkipListStats {
+    /// Levels in use, the length of `level_counts`.
+    pub height: usize,
+    /// Entries, as `approx_len` counts them.
+    pub len: usize,
+    /// Nodes linked on each level, level 0 first. A node on a level is on every level
+    /// below, so with branching `b` every level holds about `1/b` of the one below it.
+    pub level_counts: Vec<usize>,
+    /// Mean tower height of the nodes on level 0.
+    pub mean_height: f64,
+    /// Nodes whose heights the counts come from, all of them for `stats`.
+    pub sampled_nodes: usize,
+    /// `SkipList::mem_usage`.
+    pub mem_usage: usize,
+    /// `SkipList::useful_mem_usage`.
+    pub useful_mem_usage: usize,
+    /// `SkipListMetrics::comparisons`.
+    #[cfg(feature = "counters")]
+    pub comparisons: usize,
+    /// `SkipListMetrics::cas_failures`.
+    #[cfg(feature = "counters")]
+    pub cas_failures: usize,
+}
+
+// Relaxed counters behind `SkipList::metrics`. Searches tally into a local `Tally` and add
+// it here once, so the hot loops stay free of shared writes. Without the `counters` feature
+// both are empty and every method is a no-op.
+#[derive(Default)]
+struct Counters {
+    #[cfg(feature = "counters")]
+    cas_failures: std::sync::atomic::AtomicUsize,
+    #[cfg(feature = "counters")]
+    re_searches: std::sync::atomic::AtomicUsize,
+    #[cfg(feature = "counters")]
+    comparisons: std::sync::atomic::AtomicUsize,
+    #[cfg(feature = "counters")]
+    searches: std::sync::atomic::AtomicUsize,
+    #[cfg(feature = "counters")]
+    search_steps: std::sync::atomic::AtomicUsize,
+    #[cfg(feature = "counters")]
+    inserts: std::sync::atomic::AtomicUsize,
+    #[cfg(feature = "counters")]
+    gets: std::sync::atomic::AtomicUsize,
+    #[cfg(feature = "counters")]
+    seeks: std::sync::atomic::AtomicUsize,
+}
+
+impl Counters {
+    #[inline(always)]
+    fn lost_cas(&self, moved: bool) {
+        #[cfg(feature = "counters")]
+        {
+            self.cas_failures.fetch_add(1, Relaxed);
+            if moved {
+                self.re_searches.fetch_add(1, Relaxed);
+            }
+        }
+        let _ = moved;
+    }
+
+    #[inline(always)]
+    fn inserted(&self) {
+        #[cfg(feature = "counters")]
+        self.inserts.fetch_add(1, Relaxed);
+    }
+
+    #[inline(always)]
+    fn got(&self) {
+        #[cfg(feature = "counters")]
+        self.gets.fetch_add(1, Relaxed);
+    }
+
+    #[inline(always)]
+    fn sought(&self) {
+        #[cfg(feature = "counters")]
+        self.seeks.fetch_add(1, Relaxed);
+    }
+
+    #[inline(always)]
+    fn add(&self, tally: Tally) {
+        #[cfg(feature = "counters")]
+        {
+            self.comparisons.fetch_add(tally.comparisons, Relaxed);
+            self.searches.fetch_add(1, Relaxed);
+            self.search_steps.fetch_add(tally.steps, Relaxed);
+        }
+        let _ = tally;
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    #[cfg(feature = "counters")]
+    comparisons: usize,
+    #[cfg(feature = "counters")]
+    steps: usize,
+}
+
+impl Tally {
+    #[inline(always)]
+    fn compared(&mut self) {
+        #[cfg(feature = "counters")]
+        {
+            self.comparisons += 1;
+        }
+    }
+
+    #[inline(always)]
+    fn stepped(&mut self) {
+        #[cfg(feature = "counters")]
+        {
+            self.steps += 1;
+        }
+    }
+}
+
+// SAFETY: the raw pointers are what keeps `SkipList` from being `Send` and `Sync` on its
+// own, and they only ever point into memory the list owns through `arena`, so the list is
+// owned like a struct of its nodes:
+// - a key or value is written once, by the insert that allocates its node, before the node
+//   is published with a release store; readers find it through acquire loads, and nothing
+//   but `&mut self` methods and `drop` touches it again;
+// - shared access hands out `&K` and `&V` (directly or through `EntryRef`), which is why
+//   `Sync` needs `K: Sync` and `V: Sync`, and an insert through `&self` moves its key and
+//   value in from whichever thread holds the reference, which is why it needs `Send`;
+// - `compare` and `alloc` are called through `&C` and `&A` from every thread at once, and
+//   the list drops both wherever it is dropped.
+// `Send` asks for the same bounds as `Sync`: a list is built to be shared, and a list that
+// can move but not be shared is one nobody has needed.
+unsafe impl<K: Send + Sync, V: Send + Sync, C: Send + Sync, A: Send + Sync> Send
+    for SkipList<K, V, C, A>
+{
+}
+unsafe impl<K: Send + Sync, V: Send + Sync, C: Send + Sync, A: Send + Sync> Sync
+    for SkipList<K, V, C, A>
+{
 }
 
 impl<K, V, C, A> SkipList<K, V, C, A>
@@ -71,21 +606,432 @@ where
     A: MemAllocator,
 {
     pub fn new(c: C, a: A) -> Self {
+        Self::try_new(c, a).expect("failed to allocate the skip list head")
+    }
+
+    pub fn try_new(c: C, a: A) -> Result<Self, NodeError> {
+        Self::try_with_options(c, a, SkipListOptions::default())
+    }
+
+    pub fn with_options(c: C, a: A, options: SkipListOptions) -> Self {
+        Self::try_with_options(c, a, options).expect("failed to allocate the skip list head")
+    }
+
+    pub fn try_with_options(c: C, a: A, options: SkipListOptions) -> Result<Self, NodeError> {
+        assert!(
+            options.branching >= 2,
+            "branching factor must be at least 2"
+        );
+        assert!(
+            (1..=MAX_HEIGHT).contains(&options.max_height),
+            "max height must be between 1 and {MAX_HEIGHT}"
+        );
         let height = 1;
-        let head = Node::new_head(&a);
-        SkipList {
-            height: AtomicUsize::new(height),
+        let head = Node::new_head(options.max_height, &a)?;
+        Ok(SkipList {
+            height: CachePadded::new(AtomicUsize::new(height)),
+            write_buffer_usage: CachePadded::new(AtomicUsize::new(0)),
+            len: CachePadded::new(AtomicUsize::new(0)),
+            log_tail: CachePadded::new(AtomicPtr::new(head)),
+            seq_floor: 0,
+            log_index: BTreeMap::new(),
             head: NonNull::new(head).unwrap(),
+            options,
+            rng: None,
+            trailer: |_, _| 0,
+            bloom: None,
+            digest: None,
+            observers: Observers::default(),
             c,
             a,
+            #[cfg(feature = "counters")]
+            counters: CachePadded::default(),
+            #[cfg(all(test, debug_assertions, not(any(loom, shuttle))))]
+            validate_on_drop: |list| list.validate_counted(true),
+        })
+    }
+
+    pub(crate) fn comparator(&self) -> &C {
+        &self.c
+    }
+
+    pub(crate) fn comparator_mut(&mut self) -> &mut C {
+        &mut self.c
+    }
+
+    pub fn options(&self) -> SkipListOptions {
+        self.options
+    }
+
+    /// Entries in the list. An insert counts right after it links its node on level 0,
+    /// the point where readers can find it, and before it returns. So `len` never goes
+    /// down while the list is shared, counts every insert that has returned, and never
+    /// counts one that is not linked yet: an iteration that starts after `len` returned
+    /// `n` finds at least `n` entries. Inserts in flight may already be found but not yet
+    /// counted.
+    pub fn len(&self) -> usize {
+        // pairs with the `Release` bump in `try_insert`, which every later bump continues
+        self.len.load(Acquire)
+    }
+
+    /// Like `len`, without ordering the iterations that follow after the inserts it
+    /// counted: the value holds, but entries behind it may not be visible yet. Enough for
+    /// sizing decisions and statistics.
+    pub fn approx_len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+
+    /// Never true once an insert has returned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Caps the list at `bytes` of nodes. Past that, inserts fail with
+    /// `WriteStall::MemtableFull` instead of growing the list, the signal to switch to a
+    /// fresh memtable.
+    ///
+    /// A node counts with its full layout: key, value and tower, but not memory the key
+    /// or value own. Each insert charges its node before checking the budget, so even
+    /// under concurrent inserts the list overshoots by less than one node.
+    pub fn with_write_buffer_size(mut self, bytes: usize) -> Self {
+        self.options.write_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Draws node heights from `rng` instead of the generator each thread keeps for all
+    /// lists. Seeded, it makes a list built by one thread come out with the same towers on
+    /// every run, so tests can count nodes per level and benchmarks compare like with
+    /// like. Concurrent inserts take turns on it, so their heights still depend on the
+    /// order they get there.
+    pub fn with_rng(mut self, rng: impl Rng + Send + 'static) -> Self {
+        self.rng = Some(Mutex::new(Box::new(rng)));
+        self
+    }
+
+    /// Keeps a bloom filter of `bits_per_key` bits per key for `expected_entries` keys,
+    /// so that `get` and `contains_key` of a key that was never inserted mostly return
+    /// without searching. Inserts set the key's bits before linking its node, so a key
+    /// any reader can find is never filtered out. Beyond `expected_entries` the filter
+    /// keeps working, with more false positives; at 10 bits per key about 1%.
+    ///
+    /// Keys already in the list are added.
+    pub fn with_bloom(self, bits_per_key: usize, expected_entries: usize) -> Self
+    where
+        K: Hash,
+    {
+        self.with_bloom_hasher(bits_per_key, expected_entries, |key| {
+            // fixed keys, so that a filter means the same in every process
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    /// `with_bloom` with the keys hashed by `hasher`, e.g. over the bytes of the key
+    /// directly. Keys that compare equal must hash the same.
+    pub fn with_bloom_hasher(
+        mut self,
+        bits_per_key: usize,
+        expected_entries: usize,
+        hasher: fn(&K) -> u64,
+    ) -> Self {
+        let bloom = Bloom::new(bits_per_key, expected_entries, hasher);
+        for (key, _) in self.entries() {
+            bloom.insert(key);
+        }
+        self.bloom = Some(Box::new(bloom));
+        self
+    }
+
+    // false when the bloom filter has never seen `key`
+    fn may_contain(&self, key: &K) -> bool {
+        self.bloom
+            .as_ref()
+            .is_none_or(|bloom| bloom.may_contain(key))
+    }
+
+    /// Hashes every entry in key order, and how many there were, through a fresh `H`: two
+    /// lists with the same entries hash the same however they were built, as long as their
+    /// keys are ordered alike. Takes a walk over the list; see `with_incremental_hash` for
+    /// a hash that is always at hand.
+    pub fn content_hash<H: Hasher + Default>(&self) -> u64
+    where
+        K: Hash,
+        V: Hash,
+    {
+        let mut hasher = H::default();
+        let mut len = 0;
+        for (key, value) in self.entries() {
+            key.hash(&mut hasher);
+            value.hash(&mut hasher);
+            len += 1;
+        }
+        hasher.write_usize(len);
+        hasher.finish()
+    }
+
+    /// Whether both lists hold the same number of entries with keys equal by `==` in the
+    /// same order, and values equal by `eq`; the comparator orders them but does not decide
+    /// equality. Walks both lists side by side up to the first difference, so O(n), and
+    /// with writers under way it compares whatever each walk happens to see.
+    pub fn content_eq_by<V2, B>(
+        &self,
+        other: &SkipList<K, V2, C, B>,
+        mut eq: impl FnMut(&V, &V2) -> bool,
+    ) -> bool
+    where
+        K: PartialEq,
+        B: MemAllocator,
+    {
+        if self.len() != other.len() {
+            return false;
+        }
+        let mut theirs = other.entries();
+        self.entries().all(|(key, value)| {
+            theirs
+                .next()
+                .is_some_and(|(their_key, their_value)| key == their_key && eq(value, their_value))
+        }) && theirs.next().is_none()
+    }
+
+    /// Keeps a hash of the content up to date as entries come and go: the wrapping sum of
+    /// a hash of each entry, which does not depend on the order they arrive in, read with
+    /// `incremental_hash`. Lists with the same entries have the same sum, and a
+    /// differing entry changes it by the difference of two unrelated hashes.
+    ///
+    /// Entries already in the list are added.
+    pub fn with_incremental_hash(self) -> Self
+    where
+        K: Hash,
+        V: Hash,
+    {
+        self.with_incremental_hasher(|key, value| {
+            // fixed keys, so that the sums of two processes can be compared
+            let mut hasher = DefaultHasher::new();
+            (key, value).hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    /// `with_incremental_hash` with the entries hashed by `hasher`.
+    pub fn with_incremental_hasher(mut self, hasher: fn(&K, &V) -> u64) -> Self {
+        let digest = Digest {
+            sum: AtomicU64::new(0),
+            hash: hasher,
+        };
+        for (key, value) in self.entries() {
+            digest.add(key, value);
+        }
+        self.digest = Some(Box::new(digest));
+        self
+    }
+
+    /// The sum kept by `with_incremental_hash`, `None` without one. It counts every insert
+    /// that has returned, and maybe some in flight.
+    pub fn incremental_hash(&self) -> Option<u64> {
+        self.digest.as_ref().map(|digest| digest.sum.load(Relaxed))
+    }
+
+    /// Has `observer` told of every insert from now on, to keep a structure alongside the
+    /// list such as a sketch of its keys. `on_insert` runs on the inserting thread once the
+    /// entry is linked on every level, exactly once per insert that adds an entry however
+    /// many CASes it lost, and before `insert` returns; inserts that add none never call
+    /// it, not even duplicates kept by `DuplicatePolicy::KeepExisting`. Inserts
+    /// already in flight may or may not tell a new observer. Observers are called newest
+    /// first, with no lock of the list held, so one may read the list, but must not insert
+    /// into it: that insert would call it again from inside itself. A panicking observer
+    /// panics the insert, with the entry already in the list and later observers not told.
+    pub fn register_observer(&self, observer: Arc<dyn InsertObserver<K, V>>) {
+        self.observers.push(observer);
+    }
+
+    /// Node bytes charged against the write buffer; always 0 without a budget.
+    pub fn write_buffer_usage(&self) -> usize {
+        self.write_buffer_usage.load(Relaxed)
+    }
+
+    // Charges a node of `size` bytes to the write buffer and returns what it charged, or
+    // refuses once the buffer is full. Whoever adds while the total is still below the
+    // budget gets in, so only the insert that crosses it overshoots.
+    fn charge(&self, size: usize) -> Result<usize, NodeError> {
+        let Some(budget) = self.options.write_buffer_size else {
+            return Ok(0);
+        };
+        if self.write_buffer_usage.fetch_add(size, Relaxed) >= budget {
+            self.write_buffer_usage.fetch_sub(size, Relaxed);
+            return Err(WriteStall::MemtableFull.into());
+        }
+        Ok(size)
+    }
+
+    /// A snapshot of the counters; they are read one by one, so under concurrent inserts
+    /// they may not add up exactly.
+    #[cfg(feature = "counters")]
+    pub fn metrics(&self) -> SkipListMetrics {
+        let c = &self.counters;
+        SkipListMetrics {
+            cas_failures: c.cas_failures.load(Relaxed),
+            re_searches: c.re_searches.load(Relaxed),
+            comparisons: c.comparisons.load(Relaxed),
+            searches: c.searches.load(Relaxed),
+            search_steps: c.search_steps.load(Relaxed),
+            inserts: c.inserts.load(Relaxed),
+            gets: c.gets.load(Relaxed),
+            seeks: c.seeks.load(Relaxed),
+        }
+    }
+
+    /// `metrics` along with the list's gauges, under the names of `MetricsSnapshot`.
+    #[cfg(feature = "counters")]
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let metrics = self.metrics();
+        MetricsSnapshot {
+            inserts: metrics.inserts as u64,
+            gets: metrics.gets as u64,
+            seeks: metrics.seeks as u64,
+            cas_retries: metrics.cas_failures as u64,
+            comparisons: metrics.comparisons as u64,
+            entries: self.approx_len() as u64,
+            arena_reserved_bytes: self.mem_usage() as u64,
+            arena_used_bytes: self.useful_mem_usage() as u64,
+        }
+    }
+
+    /// Counts the nodes on every level, a walk over every link in the list. Under
+    /// concurrent inserts each level is counted at a different moment.
+    pub fn stats(&self) -> SkipListStats {
+        let level_counts: Vec<usize> = (0..self.height())
+            .map(|level| self.count_level(level, usize::MAX).unwrap())
+            .collect();
+        let nodes = level_counts.first().copied().unwrap_or(0);
+        self.stats_of(level_counts, nodes)
+    }
+
+    /// `stats` from about `samples` nodes, however long the list. The levels with at most
+    /// `samples` nodes are counted exactly; below those, the counts are scaled up from the
+    /// heights of runs of level-0 nodes spread over the whole list.
+    pub fn stats_sampled(&self, samples: usize) -> SkipListStats {
+        let head = self.head.as_ptr();
+        let height = self.height();
+        let mut level_counts = vec![0; height];
+        // the levels from `exact` up are counted
+        let mut exact = height;
+        while exact > 0 {
+            match self.count_level(exact - 1, samples) {
+                Some(count) => level_counts[exact - 1] = count,
+                None => break,
+            }
+            exact -= 1;
+        }
+        if exact == 0 {
+            let nodes = level_counts.first().copied().unwrap_or(0);
+            return self.stats_of(level_counts, nodes);
+        }
+
+        // The nodes below `exact` are sampled in runs, one after the head and one after
+        // each node on `exact`, each ending at the next node that reaches it. Heights are
+        // drawn independently, so the nodes behind a tall one are as fair a sample of the
+        // short ones as any.
+        let mut anchors = vec![head];
+        if exact < height {
+            let mut cur = unsafe { Node::get_next(head, exact) };
+            while !cur.is_null() {
+                anchors.push(cur);
+                cur = unsafe { Node::get_next(cur, exact) };
+            }
+        }
+        let tall = anchors.len() - 1;
+        let run = (samples / anchors.len()).max(1);
+        // sampled short nodes taller than each level
+        let mut taller = vec![0_usize; exact];
+        let mut sampled = 0;
+        for &anchor in &anchors {
+            let mut cur = unsafe { Node::get_next(anchor, 0) };
+            for _ in 0..run {
+                if cur.is_null() {
+                    break;
+                }
+                let node_height = unsafe { Node::height(cur) };
+                if node_height > exact {
+                    break;
+                }
+                for count in &mut taller[..node_height] {
+                    *count += 1;
+                }
+                sampled += 1;
+                cur = unsafe { Node::get_next(cur, 0) };
+            }
+        }
+        let short = self.approx_len().saturating_sub(tall) as f64;
+        for (count, taller) in level_counts.iter_mut().zip(&taller) {
+            *count = tall + (short * *taller as f64 / sampled.max(1) as f64).round() as usize;
+        }
+        self.stats_of(level_counts, tall + sampled)
+    }
+
+    // Nodes linked on `level`, or `None` once there are more than `limit`.
+    fn count_level(&self, level: usize, limit: usize) -> Option<usize> {
+        let mut count = 0;
+        let mut cur = unsafe { Node::get_next(self.head.as_ptr(), level) };
+        while !cur.is_null() {
+            if count == limit {
+                return None;
+            }
+            count += 1;
+            cur = unsafe { Node::get_next(cur, level) };
+        }
+        Some(count)
+    }
+
+    fn stats_of(&self, level_counts: Vec<usize>, sampled_nodes: usize) -> SkipListStats {
+        let nodes = level_counts.first().copied().unwrap_or(0);
+        let links: usize = level_counts.iter().sum();
+        SkipListStats {
+            height: level_counts.len(),
+            len: self.approx_len(),
+            mean_height: if nodes == 0 {
+                0.0
+            } else {
+                links as f64 / nodes as f64
+            },
+            level_counts,
+            sampled_nodes,
+            mem_usage: self.mem_usage(),
+            useful_mem_usage: self.useful_mem_usage(),
+            #[cfg(feature = "counters")]
+            comparisons: self.counters.comparisons.load(Relaxed),
+            #[cfg(feature = "counters")]
+            cas_failures: self.counters.cas_failures.load(Relaxed),
         }
     }
 
+    #[inline(always)]
+    fn counters(&self) -> &Counters {
+        #[cfg(feature = "counters")]
+        return &self.counters;
+        #[cfg(not(feature = "counters"))]
+        return &Counters {};
+    }
+
     fn height(&self) -> usize {
-        self.height.load(SeqCst)
+        // only a hint of where to start searching: a level above it that is already
+        // linked is just skipped, one below it that is not yet linked is a null pointer
+        self.height.load(Relaxed)
     }
 
     fn find_near(&self, key: Bound<&K>, reverse: bool) -> *mut Node<K, V> {
+        let mut tally = Tally::default();
+        let node = self.find_near_counted(key, reverse, &mut tally);
+        self.counters().add(tally);
+        node
+    }
+
+    fn find_near_counted(
+        &self,
+        key: Bound<&K>,
+        reverse: bool,
+        tally: &mut Tally,
+    ) -> *mut Node<K, V> {
         unsafe {
             let head = self.head.as_ptr();
             let mut cur = head;
@@ -106,7 +1052,7 @@ where
                 Bound::Unbounded => {
                     // find head
                     if reverse {
-                        return (*head).get_next(0);
+                        return Node::get_next(head, 0);
                     }
 
                     // find last
@@ -115,12 +1061,13 @@ where
             };
 
             loop {
-                let next_ptr = (*cur).get_next(level);
+                let next_ptr = Node::get_next(cur, level);
                 if next_ptr.is_null() {
                     // 如果还在高层，那么就下一层
                     down_level!();
                     // 如果没有后续了，如果是往前或者往后，那么直接结束
-                    if cur == head || !reverse {
+                    // (find last 时 cur 就是最后一个)
+                    if cur == head || (!reverse && key.is_some()) {
                         return null_mut();
                     }
                     // 最接近的是这个
@@ -135,8 +1082,9 @@ where
                     continue;
                 };
 
-                let next = &*next_ptr;
-                match self.c.compare(key, &next.key) {
+                prefetch(Node::get_next(next_ptr, level));
+                tally.compared();
+                match self.c.compare(key, Node::key(next_ptr)) {
                     Less => {
                         down_level!();
                         if !reverse {
@@ -153,7 +1101,7 @@ where
                             return next_ptr;
                         }
                         if !reverse {
-                            return next.get_next(0);
+                            return Node::get_next(next_ptr, 0);
                         }
                         down_level!();
                         if cur == head {
@@ -163,6 +1111,7 @@ where
                     }
 
                     Greater => {
+                        tally.stepped();
                         cur = next_ptr;
                         continue;
                     }
@@ -171,6 +1120,58 @@ where
         }
     }
 
+    // The first node at or after `key`, like `find_near(Included(key), false)`. The search
+    // starts from `finger`, the predecessors left by the last call, when the key is past
+    // them: it climbs while the finger's successor still sorts before the key, so the cost
+    // grows with the distance from the last seek rather than with the list. Afterwards
+    // `finger` holds the new predecessors, each at most as far as the one below it.
+    fn seek_from(&self, key: &K, finger: &mut Finger<K, V>) -> *mut Node<K, V> {
+        let head = self.head.as_ptr();
+        let height = self.height();
+        let mut tally = Tally::default();
+        unsafe {
+            let before = |node: *mut Node<K, V>, tally: &mut Tally| {
+                if node == head {
+                    return true;
+                }
+                tally.compared();
+                self.c.compare(Node::key(node), key) == Less
+            };
+
+            let (mut cur, mut level) = if before(finger[0], &mut tally) {
+                let mut level = 0;
+                while level + 1 < height {
+                    let next = Node::get_next(finger[level], level);
+                    if next.is_null() || !before(next, &mut tally) {
+                        break;
+                    }
+                    level += 1;
+                }
+                (finger[level], level)
+            } else {
+                (head, height - 1)
+            };
+
+            loop {
+                let next = Node::get_next(cur, level);
+                if !next.is_null() {
+                    prefetch(Node::get_next(next, level));
+                    if before(next, &mut tally) {
+                        tally.stepped();
+                        cur = next;
+                        continue;
+                    }
+                }
+                finger[level] = cur;
+                if level == 0 {
+                    self.counters().add(tally);
+                    return next;
+                }
+                level -= 1;
+            }
+        }
+    }
+
     fn find_last(&self) -> *mut Node<K, V> {
         self.find_near(Bound::Unbounded, false)
     }
@@ -179,235 +1180,5597 @@ where
         self.find_near(Bound::Unbounded, true)
     }
 
-    pub fn insert(&self, key: K, value: V) {
-        let mut prev_height = self.height();
-        let mut prev = [null_mut(); MAX_HEIGHT + 1];
-        let mut next = [null_mut(); MAX_HEIGHT + 1];
-
-        prev[prev_height] = self.head.as_ptr();
-        for level in (0..prev_height).rev() {
-            (prev[level], next[level]) = self.find_node_prev_next(&key, prev[level + 1], level);
-            assert_ne!(prev[level], next[level]);
-        }
-
-        let height = random_height();
-        let new_node_ptr = Node::new_in(key, value, height, &self.a);
-        while height > prev_height {
-            match self
-                .height
-                .compare_exchange(prev_height, height, SeqCst, SeqCst)
-            {
-                Ok(_) => break,
-                Err(cur_h) => prev_height = cur_h,
-            }
-        }
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_entry(key).map(|(_, value)| value)
+    }
 
-        unsafe {
-            let new_node = &*new_node_ptr;
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_entry(key).is_some()
+    }
 
-            for level in 0..height {
-                loop {
-                    if prev[level].is_null() {
-                        // level >= prev_height
-                        (prev[level], next[level]) =
-                            self.find_node_prev_next(&new_node.key, self.head.as_ptr(), level);
-                    }
+    /// `get`, as a guard that keeps the value alive instead of a bare reference.
+    pub fn get_guard(&self, key: &K) -> Option<ValueGuard<'_, V>> {
+        self.get(key).map(ValueGuard::borrowed)
+    }
 
-                    new_node.set_next(level, next[level]);
+    /// The list's own key along with the value, see `get_guard`.
+    pub fn get_key_value(&self, key: &K) -> Option<EntryRef<'_, K, V>> {
+        self.get_entry(key)
+            .map(|(key, value)| EntryRef::borrowed(key, value))
+    }
 
-                    match (*prev[level]).tower[level].compare_exchange(
-                        next[level],
-                        new_node_ptr,
-                        SeqCst,
-                        SeqCst,
-                    ) {
-                        Ok(_) => break,
-                        Err(_) => {
-                            // re calculate prev[level] and next[level]
-                            (prev[level], next[level]) =
-                                self.find_node_prev_next(&new_node.key, prev[level], level);
-                        }
-                    }
-                }
-            }
-        }
+    // `entries` as guards, from the first entry within `start`
+    pub(crate) fn entry_refs_after<'a>(
+        &'a self,
+        start: Bound<&K>,
+    ) -> impl Iterator<Item = EntryRef<'a, K, V>> + use<'a, K, V, C, A> {
+        self.entries_after(start)
+            .map(|(key, value)| EntryRef::borrowed(key, value))
     }
 
-    fn find_node_prev_next(
-        &self,
-        key: &K,
-        start: *mut Node<K, V>,
-        level: usize,
-    ) -> (*mut Node<K, V>, *mut Node<K, V>) {
-        let mut cur = start;
-        unsafe {
-            loop {
-                let next = (*cur).get_next(level);
-                if next.is_null() {
-                    return (cur, null_mut());
-                }
+    // the first entry at or after `key`
+    pub(crate) fn lower_bound(&self, key: &K) -> Option<(&K, &V)> {
+        let node = self.find_near(Bound::Included(key), false);
+        (!node.is_null()).then(|| unsafe { (Node::key(node), Node::value(node)) })
+    }
 
-                match self.c.compare(&(*next).key, key) {
-                    Less => cur = next,
-                    Equal => return (next, next),
-                    Greater => return (cur, next),
-                }
-            }
+    // `get` with the list's own copy of the key
+    pub(crate) fn get_entry(&self, key: &K) -> Option<(&K, &V)> {
+        self.counters().got();
+        if !self.may_contain(key) {
+            return None;
         }
+        let mut tally = Tally::default();
+        let node = self.find_near_counted(Bound::Included(key), false, &mut tally);
+        let found = !node.is_null() && {
+            tally.compared();
+            unsafe { self.c.compare(Node::key(node), key) == Equal }
+        };
+        self.counters().add(tally);
+        found.then(|| unsafe { (Node::key(node), Node::value(node)) })
     }
 
-    pub fn mem_usage(&self) -> usize {
-        self.a.mem_usage()
+    /// Panics when the key is refused as already in the list, the node cannot be
+    /// allocated or the write buffer is full; see `try_insert`.
+    pub fn insert(&self, key: K, value: V) {
+        if let Err(e) = self.try_insert(key, value) {
+            panic!("failed to insert into the skip list: {e}");
+        }
     }
 
-    pub fn iter(self: &Arc<Self>) -> SkipListIter<K, V, C, A> {
-        SkipListIter::new(self.clone())
+    /// The node is allocated before anything is linked, so on `Err` the list is left as
+    /// it was and `key`/`value` are dropped. Fails with `WriteStall::MemtableFull` once
+    /// the write buffer set by `with_write_buffer_size` is used up.
+    ///
+    /// Keys are unique: inserting one that compares equal to an entry fails with
+    /// `NodeError::KeyExists`, or with `DuplicatePolicy::KeepExisting` succeeds and leaves
+    /// the entry as it was. Of several inserts racing for the same key exactly one
+    /// wins, the first to link on level 0; the others find its node when they search again
+    /// after their lost CAS, and back out before linking anything.
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), NodeError> {
+        self.insert_node::<true>(0, 0, |_| (key, value), None)
     }
-}
 
-impl<K, V, C, A> Drop for SkipList<K, V, C, A> {
-    fn drop(&mut self) {
+    /// Inserts every pair like `insert`, for a list behind an `Arc` as much as for `extend`.
+    /// Keys already in the list are skipped whatever the `DuplicatePolicy`, as there is no
+    /// way to report them. While the keys come in ascending order, each insert starts its
+    /// search where the one before linked its node instead of at the head, so a sorted
+    /// batch walks the list about once rather than once per entry.
+    pub fn extend_shared(&self, entries: impl IntoIterator<Item = (K, V)>) {
+        if let Err(e) = self.try_extend_shared(entries) {
+            panic!("failed to insert into the skip list: {e}");
+        }
+    }
+
+    /// `extend_shared`, stopping at the first insert that fails for another reason than
+    /// its key. The entries before it stay in the list, and the rest are dropped.
+    pub fn try_extend_shared(
+        &self,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), Error> {
+        let mut finger = [self.head.as_ptr(); MAX_HEIGHT];
+        for (key, value) in entries {
+            let last = finger[0];
+            if last != self.head.as_ptr()
+                && self.c.compare(unsafe { Node::key(last) }, &key) != Less
+            {
+                finger = [self.head.as_ptr(); MAX_HEIGHT];
+            }
+            match self.insert_node::<true>(0, 0, |_| (key, value), Some(&mut finger)) {
+                Ok(()) | Err(NodeError::KeyExists) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// `try_insert` of an entry that keeps `trailer` bytes of its own in the node, right
+    /// behind the tower; see `BytesSkipList`. `init` gets them uninitialized and returns
+    /// the key and value, which may point into them: the node never moves. The list must
+    /// have been set up by `with_trailer` to find the same number of bytes again. Such
+    /// entries must not leave the list: `pop_first` and `drain` free the node first.
+    /// `outside` more bytes, which the entry holds elsewhere, are charged to the write
+    /// buffer with the node.
+    pub(crate) fn try_insert_with(
+        &self,
+        trailer: usize,
+        outside: usize,
+        init: impl FnOnce(*mut u8) -> (K, V),
+    ) -> Result<(), NodeError> {
+        self.insert_node::<true>(trailer, outside, init, None)
+    }
+
+    // `trailer` tells how many bytes `try_insert_with` gave an entry's node, so that it
+    // can be charged and handed back with its whole layout
+    pub(crate) fn with_trailer(mut self, trailer: fn(&K, &V) -> usize) -> Self {
+        self.trailer = trailer;
+        self
+    }
+
+    // the layout `node` was allocated with
+    //
+    // # Safety
+    //
+    // `node` is a live node, not the head.
+    unsafe fn node_layout(&self, node: *mut Node<K, V>) -> Layout {
+        unsafe {
+            let trailer = (self.trailer)(Node::key(node), Node::value(node));
+            // the same layout already worked when the node was allocated
+            Node::<K, V>::get_layout_with(Node::height(node), trailer)
+                .unwrap()
+                .0
+        }
+    }
+
+    /// `try_insert` for a list no other thread can reach, linking with plain stores
+    /// instead of CASes; see `LocalSkipList`.
+    ///
+    /// # Safety
+    ///
+    /// No other thread reads or writes the list until this returns, and whatever hands
+    /// the list to another thread afterwards synchronizes with this one.
+    pub(crate) unsafe fn try_insert_local(&self, key: K, value: V) -> Result<(), NodeError> {
+        self.insert_node::<false>(0, 0, |_| (key, value), None)
+    }
+
+    // `try_insert`, or with `SHARED` false `try_insert_local`, which finds the splice the
+    // same way but has no one to race: each level is linked with a store, and the
+    // counters are bumped by loading and storing them. With a `finger` whose nodes all
+    // sort before the key, each level starts from it where it is ahead of the level above,
+    // and on success the finger moves to the new node.
+    #[inline(always)]
+    fn insert_node<const SHARED: bool>(
+        &self,
+        trailer: usize,
+        outside: usize,
+        init: impl FnOnce(*mut u8) -> (K, V),
+        finger: Option<&mut Finger<K, V>>,
+    ) -> Result<(), NodeError> {
+        self.counters().inserted();
+        let height = self.new_height();
+        let size = Node::<K, V>::get_layout_with(height, trailer)?.0.size();
+        let charged = self.charge(size + outside)?;
+        let new_node_ptr = Node::new_in_with(height, trailer, &self.a, init).inspect_err(|_| {
+            self.write_buffer_usage.fetch_sub(charged, Relaxed);
+        })?;
+        let key = unsafe { Node::key(new_node_ptr) };
+        if let Some(bloom) = &self.bloom {
+            // before the node can be found, see `with_bloom`
+            bloom.insert(key);
+        }
+
+        // One top-down pass over every level the node goes on, each level starting from
+        // the predecessor found on the level above. Levels above the current height are
+        // covered too, and are searched from the head only at the very top. The successor
+        // found on the level above is already known to sort after the key, so a level
+        // that reaches it again stops there without comparing.
+        // Only `0..=top` of `splices` is ever written, top down, so most inserts touch a
+        // few slots of the array instead of clearing all of it.
+        let top = height.max(self.height());
+        let head = self.head.as_ptr();
+        let mut splices = [const { MaybeUninit::<Splice<K, V>>::uninit() }; MAX_HEIGHT + 1];
+        splices[top].write(Splice {
+            prev: head,
+            next: null_mut(),
+        });
+        let mut tally = Tally::default();
+        for level in (0..top).rev() {
+            let above = unsafe { splices[level + 1].assume_init_ref() };
+            // Had the level above nothing between the finger and the key, the finger on this
+            // level is at least as far; otherwise the level above got past the finger's
+            // node, and so past the finger here.
+            let start = match &finger {
+                Some(finger) if above.prev == *finger.get(level + 1).unwrap_or(&head) => {
+                    finger[level]
+                }
+                _ => above.prev,
+            };
+            let (prev, next) = self.find_node_prev_next(key, start, above.next, level, &mut tally);
+            if prev == next {
+                self.counters().add(tally);
+                unsafe { self.discard(new_node_ptr, charged) };
+                return self.duplicate();
+            }
+            splices[level].write(Splice { prev, next });
+        }
+
+        // readers may see the new height before anything is linked up there, they find a
+        // null head pointer and go down a level
+        if SHARED {
+            self.height.fetch_max(height, Relaxed);
+        } else if height > self.height() {
+            self.height.store(height, Relaxed);
+        }
+
+        if !SHARED {
+            unsafe {
+                for (level, splice) in splices[..height].iter().enumerate() {
+                    let &Splice { prev, next } = splice.assume_init_ref();
+                    Node::set_next(new_node_ptr, level, next);
+                    Node::set_next(prev, level, new_node_ptr);
+                }
+                self.append_local(new_node_ptr);
+                self.len.store(self.len.load(Relaxed) + 1, Relaxed);
+                if let Some(digest) = &self.digest {
+                    digest.add(key, Node::value(new_node_ptr));
+                }
+                self.observers.notify(key, Node::value(new_node_ptr));
+            }
+            self.counters().add(tally);
+            if let Some(finger) = finger {
+                unsafe { advance_finger(finger, new_node_ptr, height, &splices[..top]) };
+            }
+            return Ok(());
+        }
+
+        // Publication, see the contract above `impl Node`: the node is fully written, and
+        // each level's own next pointer is set right before the `Release` CAS that links
+        // the node on that level, bottom up. A failed CAS only tells us to search again,
+        // and the search loads with `Acquire` itself.
+        unsafe {
+            for (level, splice) in splices[..height].iter_mut().enumerate() {
+                // written by the descent, as `height <= top`
+                let Splice { prev, next } = splice.assume_init_mut();
+                let mut backoff = Backoff::new(self.options.backoff_spin_limit);
+                loop {
+                    Node::set_next(new_node_ptr, level, *next);
+
+                    match Node::tower(*prev, level).compare_exchange(
+                        *next,
+                        new_node_ptr,
+                        Release,
+                        Relaxed,
+                    ) {
+                        Ok(_) => {
+                            if level == 0 {
+                                self.stamp(new_node_ptr);
+                                self.len.fetch_add(1, Release);
+                                if let Some(digest) = &self.digest {
+                                    digest.add(key, Node::value(new_node_ptr));
+                                }
+                            }
+                            break;
+                        }
+                        Err(_) => {
+                            // Someone linked a node right behind `prev`. Nothing is unlinked
+                            // while the list is shared, so `prev` still sorts before the
+                            // key: resume from there, after letting the other writers in the
+                            // same gap get ahead. The old `next` is still after the key, so
+                            // the search ends when it gets back to it.
+                            backoff.snooze();
+                            let lost_to = *prev;
+                            (*prev, *next) =
+                                self.find_node_prev_next(key, lost_to, *next, level, &mut tally);
+                            self.counters().lost_cas(*prev != lost_to);
+                            if *prev == *next {
+                                // An equal key won the race. That can only show up on
+                                // level 0: once our node is linked there, any other
+                                // insert of the key loses to it on level 0 in turn.
+                                assert_eq!(level, 0, "key linked twice");
+                                self.counters().add(tally);
+                                self.discard(new_node_ptr, charged);
+                                return self.duplicate();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.counters().add(tally);
+        if let Some(finger) = finger {
+            unsafe { advance_finger(finger, new_node_ptr, height, &splices[..top]) };
+        }
+        // once, by the insert that linked the node, whose CAS on level 0 succeeded once
+        self.observers
+            .notify(key, unsafe { Node::value(new_node_ptr) });
+        Ok(())
+    }
+
+    // what an insert that found its key in the list returns, see `DuplicatePolicy`
+    fn duplicate(&self) -> Result<(), NodeError> {
+        match self.options.duplicates {
+            DuplicatePolicy::Reject => Err(NodeError::KeyExists),
+            DuplicatePolicy::KeepExisting => Ok(()),
+        }
+    }
+
+    // The node's sequence number, handing it the next one if it has none yet. The insert
+    // stamps its node right after linking it on level 0, but a snapshot that finds the
+    // node first stamps it instead; either way the number is fixed from then on.
+    //
+    // Stamping appends the node to the log, a chain of every node in the order stamped
+    // that starts at the head and runs through `Node::log`, and the number is the one of
+    // the node before it plus one. Published like a tower level: the node is fully
+    // written before the `Release` CAS that links it, and `Acquire` loads follow the
+    // chain. The append goes like a Michael-Scott queue's: `log_tail` is the last node or
+    // lags behind it, and whoever finds a node past the tail hands it its number before
+    // moving the tail on, so the tail's number is always set and every node gets the
+    // number of its place in the chain, whichever thread stamps it. A node goes on the
+    // chain once: a thread links it only at the end of the chain, after seeing it
+    // unstamped with a tail loaded before, and the tail passes a node only once it has a
+    // number. A stamp handed out after `last_seq` returned is above what it returned, and
+    // an insert that returned before `last_seq` was called is at or below it.
+    fn stamp(&self, node: *mut Node<K, V>) -> u64 {
+        let slot = unsafe { Node::seq(node) };
+        loop {
+            let tail = self.log_tail.load(Acquire);
+            let seq = slot.load(Acquire);
+            if seq != UNSTAMPED {
+                return seq;
+            }
+            let next = unsafe { Node::log(tail) };
+            let after = match next.load(Acquire) {
+                after if !after.is_null() => after,
+                _ => match next.compare_exchange(null_mut(), node, Release, Acquire) {
+                    Ok(_) => node,
+                    Err(after) => after,
+                },
+            };
+            self.advance_log(tail, after);
+        }
+    }
+
+    // numbers `next`, which follows `tail` in the log, and moves the tail past it
+    fn advance_log(&self, tail: *mut Node<K, V>, next: *mut Node<K, V>) {
+        unsafe { self.log_seq(tail, next) };
+        let _ = self.log_tail.compare_exchange(tail, next, Release, Relaxed);
+    }
+
+    // The sequence number of `next`, handing it the one after `prev`'s if it has none yet.
+    //
+    // # Safety
+    //
+    // `next` follows `prev` in the log, and `prev` has its number.
+    unsafe fn log_seq(&self, prev: *mut Node<K, V>, next: *mut Node<K, V>) -> u64 {
+        let slot = unsafe { Node::seq(next) };
+        let seq = slot.load(Acquire);
+        if seq != UNSTAMPED {
+            return seq;
+        }
+        let after = unsafe { Node::seq(prev).load(Acquire) }.max(self.seq_floor) + 1;
+        match slot.compare_exchange(UNSTAMPED, after, AcqRel, Acquire) {
+            Ok(_) => after,
+            Err(seq) => seq,
+        }
+    }
+
+    // The number of the last node in the log, above which every later stamp goes.
+    fn last_seq(&self) -> u64 {
+        let mut tail = self.log_tail.load(Acquire);
+        loop {
+            let next = unsafe { Node::log(tail).load(Acquire) };
+            if next.is_null() {
+                return unsafe { Node::seq(tail).load(Acquire) }.max(self.seq_floor);
+            }
+            self.advance_log(tail, next);
+            tail = next;
+        }
+    }
+
+    // `stamp` for `try_insert_local` and the builders, with no one to race
+    fn append_local(&self, node: *mut Node<K, V>) {
+        unsafe {
+            let tail = self.log_tail.load(Relaxed);
+            let seq = Node::seq(tail).load(Relaxed).max(self.seq_floor) + 1;
+            Node::seq(node).store(seq, Relaxed);
+            Node::log(tail).store(node, Relaxed);
+            self.log_tail.store(node, Relaxed);
+        }
+    }
+
+    /// Every entry stamped after `seq`, in the order of their sequence numbers, which is
+    /// the order their inserts took effect in; see `ChangeIter`. Finding `seq` walks the
+    /// log from its start, so a reader that follows the list keeps one iterator going
+    /// instead of asking again.
+    pub fn changes_since(&self, seq: u64) -> ChangeIter<'_, K, V, C, A> {
+        let mut changes = ChangeIter {
+            list: self,
+            last: self.head.as_ptr(),
+        };
+        loop {
+            let next = unsafe { Node::log(changes.last).load(Acquire) };
+            if next.is_null() || unsafe { self.log_seq(changes.last, next) } > seq {
+                return changes;
+            }
+            changes.last = next;
+        }
+    }
+
+    /// A view of the list as it is now, for reads that stay consistent while inserts go on.
+    /// Taking one copies nothing; it keeps the list, and with it the arena, alive.
+    pub fn snapshot(self: &Arc<Self>) -> Snapshot<K, V, C, A> {
+        Snapshot {
+            list: self.clone(),
+            seq: self.last_seq(),
+        }
+    }
+
+    // Drops a node `try_insert` allocated but never linked, and refunds its charge.
+    //
+    // # Safety
+    //
+    // No other thread can reach `node`.
+    unsafe fn discard(&self, node: *mut Node<K, V>, charged: usize) {
+        unsafe {
+            let layout = self.node_layout(node);
+            drop(addr_of_mut!((*node).key).read().assume_init());
+            drop(addr_of_mut!((*node).value).read().assume_init());
+            self.a.deallocate(node as *mut u8, layout);
+        }
+        self.write_buffer_usage.fetch_sub(charged, Relaxed);
+    }
+
+    // The tallest tower a new node may get, see `SkipListOptions::adaptive_height`. The
+    // length only grows while the list is shared, so neither does the cap.
+    fn height_cap(&self) -> usize {
+        let max = self.options.max_height;
+        if !self.options.adaptive_height {
+            return max;
+        }
+        // the new node is the `len + 1`th entry
+        (log_ceil(self.approx_len() + 1, self.options.branching) + 2).min(max)
+    }
+
+    // The last node on `level` before `key` and the one after it, or the node with the key
+    // twice. `after` is a node already compared and known to sort after the key (or null),
+    // which ends the search without another comparison.
+    fn find_node_prev_next(
+        &self,
+        key: &K,
+        start: *mut Node<K, V>,
+        after: *mut Node<K, V>,
+        level: usize,
+        tally: &mut Tally,
+    ) -> (*mut Node<K, V>, *mut Node<K, V>) {
+        let mut cur = start;
+        unsafe {
+            loop {
+                let next = Node::get_next(cur, level);
+                if next.is_null() {
+                    return (cur, null_mut());
+                }
+                if next == after {
+                    return (cur, next);
+                }
+
+                prefetch(Node::get_next(next, level));
+                tally.compared();
+                match self.c.compare(Node::key(next), key) {
+                    Less => {
+                        tally.stepped();
+                        cur = next;
+                    }
+                    Equal => return (next, next),
+                    Greater => return (cur, next),
+                }
+            }
+        }
+    }
+
+    // the height of the next node, capped by `height_cap`
+    fn new_height(&self) -> usize {
+        let (branching, max) = (self.options.branching, self.height_cap());
+        match &self.rng {
+            Some(rng) => {
+                let mut rng = rng.lock().unwrap();
+                random_height(branching, max, || rng.next_u64())
+            }
+            None => random_height(branching, max, next_random),
+        }
+    }
+
+    pub fn mem_usage(&self) -> usize {
+        self.a.mem_usage()
+    }
+
+    /// Bytes the allocator handed to this list, as opposed to the bytes it reserved
+    /// (`mem_usage`).
+    pub fn useful_mem_usage(&self) -> usize {
+        self.a.useful_mem_usage()
+    }
+
+    /// Unlinks the smallest entry and hands its memory back to the allocator. Takes it off
+    /// the log of `changes_since` too: right away when it is the oldest entry there, and
+    /// otherwise through an index of the log by sequence number, which the first such pop
+    /// builds with a walk along the log and later ones extend with what was stamped since.
+    /// Popping every entry thus costs a logarithm each, whatever order they came in, and
+    /// the index takes up to a map entry for every entry in the list until `drain`.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let head = self.head.as_ptr();
+        let first = unsafe { Node::get_next(head, 0) };
+        if first.is_null() {
+            return None;
+        }
+        unsafe {
+            let seq = Node::seq(first).load(Relaxed);
+            self.seq_floor = self.seq_floor.max(seq);
+            let prev = if Node::log(head).load(Relaxed) == first {
+                head
+            } else {
+                self.index_log();
+                let mut before = self.log_index.range(..seq);
+                before.next_back().map_or(head, |(_, &node)| node)
+            };
+            // the index holds the start of the log, so the oldest entry if any
+            self.log_index.remove(&seq);
+            Node::log(prev).store(Node::log(first).load(Relaxed), Relaxed);
+            if self.log_tail.load(Relaxed) == first {
+                self.log_tail.store(prev, Relaxed);
+            }
+            Some(self.remove_first(first))
+        }
+    }
+
+    // Adds the nodes stamped after the last one in `log_index` to it, or the whole log when
+    // it is empty.
+    fn index_log(&mut self) {
+        let mut prev = match self.log_index.last_key_value() {
+            Some((_, &node)) => node,
+            None => self.head.as_ptr(),
+        };
+        loop {
+            let next = unsafe { Node::log(prev).load(Relaxed) };
+            if next.is_null() {
+                return;
+            }
+            let seq = unsafe { self.log_seq(prev, next) };
+            self.log_index.insert(seq, next);
+            prev = next;
+        }
+    }
+
+    // Unlinks `first` from every level and frees it, leaving the log alone.
+    //
+    // # Safety
+    //
+    // `first` is the first node, and off the log.
+    unsafe fn remove_first(&mut self, first: *mut Node<K, V>) -> (K, V) {
         unsafe {
             let head = self.head.as_ptr();
-            let mut cur = (*head).get_next(0);
-            while cur.is_null() {
-                let next = (*cur).get_next(0);
-                ptr::drop_in_place(cur);
-                cur = next;
+            // the first node is linked from the head on every level it has
+            let height = Node::height(first);
+            for level in 0..height {
+                debug_assert_eq!(Node::get_next(head, level), first);
+                Node::set_next(head, level, Node::get_next(first, level));
+            }
+
+            let layout = self.node_layout(first);
+            let key = addr_of_mut!((*first).key).read().assume_init();
+            let value = addr_of_mut!((*first).value).read().assume_init();
+            self.a.deallocate(first as *mut u8, layout);
+            if self.options.write_buffer_size.is_some() {
+                self.write_buffer_usage.fetch_sub(layout.size(), Relaxed);
+            }
+            self.len.fetch_sub(1, Relaxed);
+            if let Some(digest) = &self.digest {
+                digest.remove(&key, &value);
+            }
+            (key, value)
+        }
+    }
+
+    /// Removes every entry in key order, handing each node's memory back as it goes.
+    /// Entries the iterator is not driven over stay in the list, and go back on the log
+    /// of `changes_since` when the iterator is dropped.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        // The log is emptied up front rather than kept up entry by entry, which would be a
+        // walk along it for every entry. Leaking the iterator then leaves the rest of the
+        // entries off the log, not freed nodes on it.
+        self.seq_floor = self.last_seq();
+        self.log_index = BTreeMap::new();
+        unsafe { Node::log(self.head.as_ptr()).store(null_mut(), Relaxed) };
+        self.log_tail.store(self.head.as_ptr(), Relaxed);
+        Drain { list: self }
+    }
+
+    /// Every entry in key order, moved out of the list by `drain`.
+    pub fn into_vec(mut self) -> Vec<(K, V)> {
+        let mut entries = Vec::with_capacity(self.len());
+        entries.extend(self.drain());
+        entries
+    }
+
+    /// `into_vec` for a list that stays as it is, with clones of the entries.
+    pub fn to_vec(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.entries()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Every entry moved into a `BTreeMap`, which orders its keys by `Ord` instead of the
+    /// comparator. They had better agree: debug builds panic when they don't, and release
+    /// builds hand back the entries in `Ord` order.
+    pub fn into_btree_map(self) -> BTreeMap<K, V>
+    where
+        K: Ord,
+    {
+        let entries = self.into_vec();
+        debug_assert!(
+            entries.is_sorted_by(|(a, _), (b, _)| a < b),
+            "the comparator orders keys unlike `Ord`"
+        );
+        // in order already, which the sort ahead of the map's bulk build gets through in a
+        // single pass
+        entries.into_iter().collect()
+    }
+
+    pub fn iter(self: &Arc<Self>) -> SkipListIter<K, V, C, A> {
+        SkipListIter::new(self.clone())
+    }
+
+    /// Turns the list into a `FrozenSkipList` for the rest of its life, once it takes no
+    /// more inserts. Gathers the entries into an array, one pointer pair each.
+    pub fn freeze(self: Arc<Self>) -> FrozenSkipList<K, V, C, A> {
+        FrozenSkipList::new(self)
+    }
+
+    /// Copies the live entries into a fresh list on `allocator`, laid out contiguously in
+    /// key order with deterministic heights.
+    #[allow(clippy::type_complexity)]
+    pub fn compact_into<A2>(
+        &self,
+        allocator: A2,
+    ) -> Result<(SkipList<K, V, C, A2>, CompactionReport), NodeError>
+    where
+        K: Clone,
+        V: Clone,
+        C: Clone,
+        A2: MemAllocator,
+    {
+        let entries = self.entries().map(|(k, v)| (k.clone(), v.clone()));
+        let list = SkipList::build_sorted(self.c.clone(), allocator, self.options, entries)?;
+        let report = CompactionReport {
+            mem_usage_before: self.mem_usage(),
+            mem_usage_after: list.mem_usage(),
+        };
+        Ok((list, report))
+    }
+
+    /// Builds a list from entries already in ascending order by `c`, as when reading back
+    /// a flushed table: no searches and no random heights. The list comes out laid out like
+    /// `compact_into`'s, every 4th node two levels high, every 16th three and so on.
+    ///
+    /// Panics like `new`. Entries out of order or with equal keys make a broken list; debug
+    /// builds panic on them instead.
+    pub fn from_sorted_iter(entries: impl IntoIterator<Item = (K, V)>, c: C, a: A) -> Self {
+        Self::try_from_sorted_iter(entries, c, a).expect("failed to build the skip list")
+    }
+
+    pub fn try_from_sorted_iter(
+        entries: impl IntoIterator<Item = (K, V)>,
+        c: C,
+        a: A,
+    ) -> Result<Self, NodeError> {
+        Self::build_sorted(c, a, SkipListOptions::default(), entries)
+    }
+
+    // Builds a list from entries already sorted by `c`, see `SortedBuilder`.
+    fn build_sorted(
+        c: C,
+        a: A,
+        options: SkipListOptions,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Self, NodeError> {
+        let mut builder = SortedBuilder::new(Self::try_with_options(c, a, options)?);
+        for (key, value) in entries {
+            debug_assert!(
+                builder
+                    .last_key()
+                    .is_none_or(|last| builder.comparator().compare(last, &key) == Less),
+                "entries are not in ascending order"
+            );
+            builder.push(key, value)?;
+        }
+        Ok(builder.finish())
+    }
+
+    /// Builds a list from `entries` in any order, sorting them and creating the nodes on
+    /// all of rayon's threads. The list comes out the same as `compact_into` would lay it
+    /// out, and iterates in the order one-by-one inserts would give. Fails with
+    /// `NodeError::KeyExists` when two keys compare equal.
+    ///
+    /// The nodes come from `a` concurrently. With the heights fixed by the position in
+    /// sorted order, the successor of every node on every level is known up front, so
+    /// the nodes link themselves without a pass over each level.
+    #[cfg(feature = "rayon")]
+    pub fn par_build(entries: Vec<(K, V)>, c: C, a: A) -> Result<Self, NodeError>
+    where
+        K: Send + Sync,
+        V: Send + Sync,
+        C: Sync,
+        A: Sync,
+    {
+        use rayon::prelude::*;
+
+        // a node pointer to pass between rayon's threads; no two touch the same node
+        #[derive(Clone, Copy)]
+        struct Shared<K, V>(*mut Node<K, V>);
+        unsafe impl<K, V> Send for Shared<K, V> {}
+        unsafe impl<K, V> Sync for Shared<K, V> {}
+
+        let mut entries = entries;
+        entries.par_sort_unstable_by(|(a, _), (b, _)| c.compare(a, b));
+        if entries
+            .par_windows(2)
+            .any(|pair| c.compare(&pair[0].0, &pair[1].0) == Equal)
+        {
+            return Err(NodeError::KeyExists);
+        }
+
+        let options = SkipListOptions::default();
+        let list = Self::try_with_options(c, a, options)?;
+        let count = entries.len();
+        let nodes: Vec<_> = entries
+            .into_par_iter()
+            .enumerate()
+            .map(|(i, (key, value))| {
+                Node::new_in(key, value, sorted_height(i + 1, &options), &list.a).map(Shared)
+            })
+            .collect();
+        if nodes.iter().any(Result::is_err) {
+            let err = nodes.iter().find_map(|node| node.as_ref().err()).cloned();
+            for node in nodes.into_iter().flatten() {
+                unsafe { list.discard(node.0, 0) };
+            }
+            return Err(err.unwrap());
+        }
+        let nodes: Vec<_> = nodes.into_iter().flatten().collect();
+
+        // the `n`th node (counting from 1) reaches `level` when the branching factor to
+        // the `level` divides `n`, so its successor there is the next such multiple
+        let branching = options.branching as usize;
+        let next = |n: usize, level: usize| -> *mut Node<K, V> {
+            branching
+                .checked_pow(level as u32)
+                .and_then(|step| (n / step + 1).checked_mul(step))
+                .filter(|&m| m <= count)
+                .map_or(null_mut(), |m| nodes[m - 1].0)
+        };
+        nodes.par_iter().enumerate().for_each(|(i, node)| unsafe {
+            let n = i + 1;
+            for level in 0..Node::height(node.0) {
+                Node::set_next(node.0, level, next(n, level));
+            }
+            // the log runs in key order, as the `n`th node is stamped `n`
+            Node::seq(node.0).store(n as u64, Relaxed);
+            Node::log(node.0).store(nodes.get(n).map_or(null_mut(), |next| next.0), Relaxed);
+        });
+
+        let head = list.head.as_ptr();
+        let mut max_height = 1;
+        for level in 0..options.max_height {
+            let first = next(0, level);
+            if first.is_null() {
+                break;
+            }
+            unsafe { Node::set_next(head, level, first) };
+            max_height = level + 1;
+        }
+        list.height.store(max_height, Relaxed);
+        list.len.store(count, Relaxed);
+        if let (Some(first), Some(last)) = (nodes.first(), nodes.last()) {
+            unsafe { Node::log(head).store(first.0, Relaxed) };
+            list.log_tail.store(last.0, Relaxed);
+        }
+        Ok(list)
+    }
+
+    /// Checks the structure: level 0 is strictly ordered by the comparator (keys are
+    /// unique under every `DuplicatePolicy`), every node on a level is tall enough to be
+    /// there, each level is a subsequence of the one below, the head links nothing at or
+    /// above `height`, and level 0 holds at least the `len` entries. Inserts link
+    /// bottom-up and raise the height first, so this may run while they are in flight.
+    /// `violation_key` finds the node a violation is about.
+    ///
+    /// Debug builds of this crate's tests run it on every list they drop, which also
+    /// checks that `len` is exact.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.validate_counted(false)
+    }
+
+    // `validate`; with `exact`, for a list no insert is in flight in, `len` has to be the
+    // number of nodes on level 0 rather than at most it
+    fn validate_counted(&self, exact: bool) -> Result<(), InvariantViolation> {
+        let head = self.head.as_ptr();
+        // before the walk, so that every insert it counts is linked by then
+        let len = self.len.load(Acquire);
+        unsafe {
+            let mut prev: *mut Node<K, V> = null_mut();
+            let mut cur = Node::get_next(head, 0);
+            let mut index = 0;
+            while !cur.is_null() {
+                if !prev.is_null() && self.c.compare(Node::key(prev), Node::key(cur)) != Less {
+                    return Err(InvariantViolation::OutOfOrder { index });
+                }
+                prev = cur;
+                cur = Node::get_next(cur, 0);
+                index += 1;
+            }
+            let linked = index;
+
+            // loads `cur` without `get_next`, whose debug assertion would panic on the
+            // towers this is meant to report
+            for level in 1..self.options.max_height {
+                let mut below = Node::get_next(head, level - 1);
+                let mut cur = Node::tower(head, level).load(Acquire);
+                let mut index = 0;
+                while !cur.is_null() {
+                    if Node::height(cur) <= level {
+                        return Err(InvariantViolation::TooShort { level, index });
+                    }
+                    while below != cur {
+                        if below.is_null() {
+                            return Err(InvariantViolation::NotBelow { level, index });
+                        }
+                        below = Node::get_next(below, level - 1);
+                    }
+                    cur = Node::tower(cur, level).load(Acquire);
+                    index += 1;
+                }
+            }
+
+            // An insert raises the height before it links anything up there, so a link
+            // loaded first is covered by the height loaded after it
+            let top = (0..self.options.max_height)
+                .rev()
+                .find(|&level| !Node::tower(head, level).load(Acquire).is_null());
+            let height = self.height();
+            if let Some(level) = top.filter(|&level| level >= height) {
+                return Err(InvariantViolation::AboveHeight { level, height });
+            }
+            if linked < len || (exact && linked != len) {
+                return Err(InvariantViolation::LenMismatch { len, linked });
+            }
+        }
+        Ok(())
+    }
+
+    /// The key of the node `violation` is about, of one `validate` returned for the list
+    /// as it still is; `None` for the violations of no node in particular.
+    pub fn violation_key(&self, violation: &InvariantViolation) -> Option<&K> {
+        let (level, index) = match *violation {
+            InvariantViolation::OutOfOrder { index } => (0, index),
+            InvariantViolation::TooShort { level, index }
+            | InvariantViolation::NotBelow { level, index } => (level, index),
+            InvariantViolation::LenMismatch { .. } | InvariantViolation::AboveHeight { .. } => {
+                return None;
+            }
+        };
+        if level >= self.options.max_height {
+            return None;
+        }
+        unsafe {
+            let mut cur = Node::tower(self.head.as_ptr(), level).load(Acquire);
+            for _ in 0..index {
+                // a node too short for the level has no link there to follow
+                if cur.is_null() || Node::height(cur) <= level {
+                    return None;
+                }
+                cur = Node::tower(cur, level).load(Acquire);
+            }
+            (!cur.is_null()).then(|| Node::key(cur))
+        }
+    }
+
+    /// Up to `q - 1` keys splitting the list into `q` runs of roughly equal length, the
+    /// `i`th run holding the keys from split key `i - 1` (or the first key) up to split key
+    /// `i`. Read off the highest level that has at least `QUANTILE_SAMPLES` nodes per run,
+    /// each of which stands for about `branching ^ level` entries, so the cost is
+    /// `O(q log n)` rather than a walk over every entry. A list with fewer than `q` entries
+    /// gets fewer split keys, and one with none, or `q < 2`, gets none.
+    pub fn estimate_quantiles(&self, q: usize) -> Vec<&K> {
+        if q < 2 {
+            return Vec::new();
+        }
+        let head = self.head.as_ptr();
+        let wanted = q.saturating_mul(QUANTILE_SAMPLES);
+        let mut sample = Vec::new();
+        for level in (0..self.height()).rev() {
+            sample.clear();
+            let mut cur = unsafe { Node::get_next(head, level) };
+            while !cur.is_null() {
+                sample.push(cur);
+                cur = unsafe { Node::get_next(cur, level) };
+            }
+            if sample.len() >= wanted {
+                break;
+            }
+        }
+
+        let mut splits: Vec<&K> = Vec::with_capacity(q - 1);
+        let mut last = 0;
+        for i in 1..q {
+            let at = i * sample.len() / q;
+            if at > last {
+                splits.push(unsafe { Node::key(sample[at]) });
+                last = at;
+            }
+        }
+        splits
+    }
+
+    // level 0 walk in key order
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries_from(unsafe { Node::get_next(self.head.as_ptr(), 0) })
+    }
+
+    // `entries` from the first entry at or after `key`
+    pub(crate) fn entries_at<'a>(
+        &'a self,
+        key: &K,
+    ) -> impl Iterator<Item = (&'a K, &'a V)> + use<'a, K, V, C, A> {
+        self.entries_after(Bound::Included(key))
+    }
+
+    // `entries` from the first entry within `start`
+    pub(crate) fn entries_after<'a>(
+        &'a self,
+        start: Bound<&K>,
+    ) -> impl Iterator<Item = (&'a K, &'a V)> + use<'a, K, V, C, A> {
+        self.counters().sought();
+        let first = match start {
+            Bound::Unbounded => self.find_first(),
+            start => self.find_near(start, false),
+        };
+        self.entries_from(first)
+    }
+
+    fn entries_from(&self, mut cur: *mut Node<K, V>) -> impl Iterator<Item = (&K, &V)> {
+        std::iter::from_fn(move || {
+            if cur.is_null() {
+                return None;
+            }
+            unsafe {
+                let node = cur;
+                cur = Node::get_next(node, 0);
+                Some((Node::key(node), Node::value(node)))
+            }
+        })
+    }
+}
+
+/// The list `new` makes of a default comparator and allocator. Naming the key and value
+/// types, even as `SkipList::<_, _>::default()`, picks `DefaultComparator` and `BlockArena`
+/// for the rest.
+impl<K, V, C, A> Default for SkipList<K, V, C, A>
+where
+    C: Default + Comparator<Item = K>,
+    A: Default + MemAllocator,
+{
+    fn default() -> Self {
+        Self::new(C::default(), A::default())
+    }
+}
+
+impl<K, V, C, A> SkipList<K, V, C, A>
+where
+    C: Default + Comparator<Item = K>,
+    A: Default + MemAllocator,
+{
+    /// `default` behind an `Arc`, ready for `iter` and `snapshot`.
+    pub fn default_arc() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+/// Sorts the entries by the comparator and bulk builds the list like `from_sorted_iter`,
+/// so the towers come out the same for the same keys. Of entries with equal keys the last
+/// one wins.
+///
+/// Panics like `new`.
+impl<K, V, C, A> FromIterator<(K, V)> for SkipList<K, V, C, A>
+where
+    C: Default + Comparator<Item = K>,
+    A: Default + MemAllocator,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let c = C::default();
+        let mut entries: Vec<(K, V)> = iter.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| c.compare(a, b));
+        // `dedup_by` keeps the first of a run, so move each later entry into its place
+        entries.dedup_by(|later, kept| {
+            let equal = c.compare(&later.0, &kept.0) == Equal;
+            if equal {
+                mem::swap(later, kept);
+            }
+            equal
+        });
+        Self::from_sorted_iter(entries, c, A::default())
+    }
+}
+
+/// `content_eq_by` with `==` on the values. Lists in different allocators compare with
+/// `content_eq_by(other, PartialEq::eq)`.
+impl<K, V, C, A> PartialEq for SkipList<K, V, C, A>
+where
+    K: PartialEq,
+    V: PartialEq,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.content_eq_by(other, V::eq)
+    }
+}
+
+impl<K, V, C, A> Eq for SkipList<K, V, C, A>
+where
+    K: Eq,
+    V: Eq,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+}
+
+/// `extend_shared`, which a list behind an `Arc` calls directly.
+impl<K, V, C, A> Extend<(K, V)> for SkipList<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.extend_shared(iter)
+    }
+}
+
+/// A copy of every entry in its own allocator, bulk built like `from_sorted_iter` with the
+/// same comparator and options. Its towers are the regular ones of a bulk build, whatever
+/// the original's were, and what was added after construction, such as a bloom filter,
+/// hashing, observers or a generator, is not carried over.
+///
+/// Panics like `new`; see `try_clone`.
+impl<K, V, C, A> Clone for SkipList<K, V, C, A>
+where
+    K: Clone,
+    V: Clone,
+    C: Clone + Comparator<Item = K>,
+    A: Default + MemAllocator,
+{
+    fn clone(&self) -> Self {
+        self.try_clone().expect("failed to build the skip list")
+    }
+}
+
+impl<K, V, C, A> SkipList<K, V, C, A>
+where
+    K: Clone,
+    V: Clone,
+    C: Clone + Comparator<Item = K>,
+    A: Default + MemAllocator,
+{
+    /// `clone`, returning the error instead of panicking when the copy cannot be built.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        let entries = self
+            .entries()
+            .map(|(key, value)| (key.clone(), value.clone()));
+        Ok(Self::build_sorted(
+            self.c.clone(),
+            A::default(),
+            self.options,
+            entries,
+        )?)
+    }
+}
+
+/// `extend_shared` with clones of the pairs, as from another list's entries.
+impl<'a, K, V, C, A> Extend<(&'a K, &'a V)> for SkipList<K, V, C, A>
+where
+    K: Clone + 'a,
+    V: Clone + 'a,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend_shared(
+            iter.into_iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        )
+    }
+}
+
+impl<K, V, C, A> SkipList<K, V, C, A>
+where
+    A: Borrow<BlockArena>,
+{
+    /// See `BlockArena::on_watermark`. With a shared arena the threshold is on the arena's
+    /// total usage, not this list's share.
+    pub fn on_watermark(&self, bytes: usize, callback: impl Fn(usize) + Send + Sync + 'static) {
+        self.a.borrow().on_watermark(bytes, callback)
+    }
+}
+
+/// Memory used by a list before and after `SkipList::compact_into`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionReport {
+    pub mem_usage_before: usize,
+    pub mem_usage_after: usize,
+}
+
+/// Why a node could not be created or linked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeError {
+    /// `K`, `V` and the tower do not fit in a valid `Layout`.
+    Layout(LayoutError),
+    Alloc(AllocError),
+    Stall(WriteStall),
+    /// An equal key is already in the list, or an insert racing for the same key linked
+    /// first. The list is left as it was.
+    KeyExists,
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::Layout(e) => write!(f, "invalid node layout: {e}"),
+            NodeError::Alloc(e) => e.fmt(f),
+            NodeError::Stall(e) => e.fmt(f),
+            NodeError::KeyExists => f.write_str("key already exists"),
+        }
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+impl From<LayoutError> for NodeError {
+    fn from(e: LayoutError) -> Self {
+        NodeError::Layout(e)
+    }
+}
+
+impl From<AllocError> for NodeError {
+    fn from(e: AllocError) -> Self {
+        NodeError::Alloc(e)
+    }
+}
+
+impl From<WriteStall> for NodeError {
+    fn from(e: WriteStall) -> Self {
+        NodeError::Stall(e)
+    }
+}
+
+/// A structural problem found by `SkipList::validate`. `index` counts the nodes before
+/// the bad one on its level; `SkipList::violation_key` finds its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// A node on level 0 does not sort after the one before it.
+    OutOfOrder { index: usize },
+    /// A node is linked on a level at or above its height.
+    TooShort { level: usize, index: usize },
+    /// A node is missing from the level below `level`.
+    NotBelow { level: usize, index: usize },
+    /// `len` counts more entries than level 0 links, or, with no insert in flight, any
+    /// other number.
+    LenMismatch { len: usize, linked: usize },
+    /// The head links a node on `level`, at or above the list's `height`.
+    AboveHeight { level: usize, height: usize },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::OutOfOrder { index } => {
+                write!(f, "node {index} on level 0 is out of order")
+            }
+            InvariantViolation::TooShort { level, index } => {
+                write!(f, "node {index} on level {level} is too short for it")
+            }
+            InvariantViolation::NotBelow { level, index } => {
+                write!(
+                    f,
+                    "node {index} on level {level} is missing from the level below"
+                )
+            }
+            InvariantViolation::LenMismatch { len, linked } => {
+                write!(f, "the length is {len}, but level 0 links {linked} nodes")
+            }
+            InvariantViolation::AboveHeight { level, height } => {
+                write!(
+                    f,
+                    "the head links level {level}, above the height of {height}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+/// Why an insert was refused although memory was available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStall {
+    /// The list reached its `write_buffer_size`.
+    MemtableFull,
+}
+
+impl fmt::Display for WriteStall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteStall::MemtableFull => f.write_str("memtable is full"),
+        }
+    }
+}
+
+impl std::error::Error for WriteStall {}
+
+/// Every way the list's fallible operations fail, for callers that would rather handle one
+/// type: the errors of single operations, `NodeError`, `WriteStall` and
+/// `InvariantViolation`, convert into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    AllocFailed(AllocError),
+    /// `K`, `V` and the tower do not fit in a valid `Layout`.
+    LayoutInvalid(LayoutError),
+    /// An equal key is already in the list.
+    KeyExists,
+    /// The list reached its `write_buffer_size`.
+    MemtableFull,
+    /// Two lists meant to be ordered alike have comparators of different names.
+    ComparatorMismatch {
+        a: String,
+        b: String,
+    },
+    /// The list's structure is broken, see `SkipList::validate`.
+    Corruption(InvariantViolation),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AllocFailed(e) => e.fmt(f),
+            Error::LayoutInvalid(e) => write!(f, "invalid node layout: {e}"),
+            Error::KeyExists => f.write_str("key already exists"),
+            Error::MemtableFull => WriteStall::MemtableFull.fmt(f),
+            Error::ComparatorMismatch { a, b } => {
+                write!(f, "the lists are ordered differently: {a} against {b}")
+            }
+            Error::Corruption(e) => write!(f, "corrupted skip list: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::AllocFailed(e) => Some(e),
+            Error::LayoutInvalid(e) => Some(e),
+            Error::Corruption(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<NodeError> for Error {
+    fn from(e: NodeError) -> Self {
+        match e {
+            NodeError::Layout(e) => Error::LayoutInvalid(e),
+            NodeError::Alloc(e) => Error::AllocFailed(e),
+            NodeError::Stall(e) => e.into(),
+            NodeError::KeyExists => Error::KeyExists,
+        }
+    }
+}
+
+impl From<WriteStall> for Error {
+    fn from(e: WriteStall) -> Self {
+        match e {
+            WriteStall::MemtableFull => Error::MemtableFull,
+        }
+    }
+}
+
+impl From<AllocError> for Error {
+    fn from(e: AllocError) -> Self {
+        Error::AllocFailed(e)
+    }
+}
+
+impl From<LayoutError> for Error {
+    fn from(e: LayoutError) -> Self {
+        Error::LayoutInvalid(e)
+    }
+}
+
+impl From<InvariantViolation> for Error {
+    fn from(e: InvariantViolation) -> Self {
+        Error::Corruption(e)
+    }
+}
+
+// Runs the key and value destructors of every linked node. The nodes' memory belongs to the
+// allocator.
+impl<K, V, C, A> Drop for SkipList<K, V, C, A> {
+    fn drop(&mut self) {
+        #[cfg(all(test, debug_assertions, not(any(loom, shuttle))))]
+        if !std::thread::panicking()
+            && let Err(e) = (self.validate_on_drop)(self)
+        {
+            panic!("dropped a broken skip list: {e}");
+        }
+        unsafe {
+            let head = self.head.as_ptr();
+            let mut cur = Node::get_next(head, 0);
+            while !cur.is_null() {
+                let next = Node::get_next(cur, 0);
+                ptr::drop_in_place(addr_of_mut!((*cur).key).cast::<K>());
+                ptr::drop_in_place(addr_of_mut!((*cur).value).cast::<V>());
+                cur = next;
+            }
+        }
+    }
+}
+
+/// A summary rather than a dump: the length, height, memory and key range, then the first
+/// few entries. `{:#?}` adds how many nodes each level holds, which walks the whole list.
+impl<K, V, C, A> fmt::Debug for SkipList<K, V, C, A>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last = self.find_last();
+        let levels = f.alternate();
+        let mut list = f.debug_struct("SkipList");
+        list.field("len", &self.len())
+            .field("height", &self.height())
+            .field("mem_usage", &self.mem_usage())
+            .field("first", &self.entries().next().map(|(key, _)| key))
+            .field(
+                "last",
+                &(!last.is_null()).then(|| unsafe { Node::key(last) }),
+            )
+            .field("entries", &DebugEntries(self));
+        if levels {
+            let head = self.head.as_ptr();
+            let counts: Vec<usize> = (0..self.height())
+                .map(|level| {
+                    let mut count = 0;
+                    let mut cur = unsafe { Node::get_next(head, level) };
+                    while !cur.is_null() {
+                        count += 1;
+                        cur = unsafe { Node::get_next(cur, level) };
+                    }
+                    count
+                })
+                .collect();
+            list.field("levels", &counts);
+        }
+        list.finish()
+    }
+}
+
+// the first `DEBUG_ENTRIES` entries of a list, as a map
+struct DebugEntries<'a, K, V, C, A>(&'a SkipList<K, V, C, A>);
+
+impl<K, V, C, A> fmt::Debug for DebugEntries<'_, K, V, C, A>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries = self.0.entries();
+        let mut map = f.debug_map();
+        map.entries(entries.by_ref().take(DEBUG_ENTRIES));
+        if entries.next().is_some() {
+            map.finish_non_exhaustive()
+        } else {
+            map.finish()
+        }
+    }
+}
+
+impl<K, V, C, A> SkipList<K, V, C, A>
+where
+    K: fmt::Debug,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    /// Draws the towers of the first `max_entries` entries the way textbooks do: one row
+    /// per level, top first, with each key in the column of its level-0 entry. The rows
+    /// follow the links on their level, so a node tall enough for a level but not linked
+    /// on it shows up as `?`. A footer counts the nodes on every level of the whole list.
+    ///
+    /// ```text
+    ///  1 head ----------> 4 -> ...
+    ///  0 head -> 1 -> 2 -> 3 -> 4 -> ...
+    /// nodes: 0:16 1:4
+    /// ```
+    ///
+    /// Keys are printed with `Debug`, cut to 12 characters. What is allocated grows with
+    /// `max_entries`, not with the list.
+    pub fn dump_structure(&self, max_entries: usize) -> String {
+        let first = unsafe { Node::get_next(self.head.as_ptr(), 0) };
+        self.dump_from(first, max_entries)
+    }
+
+    /// `dump_structure` for a window of `max_entries` entries around `key`, half of them
+    /// before it, or all of them when `key` is past the last. A row that does not start
+    /// from the head starts with `...`.
+    pub fn dump_structure_around(&self, key: &K, max_entries: usize) -> String {
+        let mut start = self.find_near(Bound::Included(key), false);
+        // past the last key the whole window comes before it
+        let before = if start.is_null() {
+            max_entries
+        } else {
+            max_entries / 2
+        };
+        for _ in 0..before {
+            let prev = if start.is_null() {
+                self.find_last()
+            } else {
+                self.find_near(Bound::Excluded(unsafe { Node::key(start) }), true)
+            };
+            if prev.is_null() {
+                break;
+            }
+            start = prev;
+        }
+        self.dump_from(start, max_entries)
+    }
+
+    /// The list as a Graphviz digraph of its first `max_nodes` nodes: one record per node
+    /// with its key and height, the head included, and one edge per link, colored by
+    /// level. Links leaving the window end at a `...` node.
+    ///
+    /// Keys are printed with `Debug`, cut to 12 characters like `dump_structure`'s.
+    pub fn to_dot(&self, max_nodes: usize) -> String {
+        let first = unsafe { Node::get_next(self.head.as_ptr(), 0) };
+        self.dot_from(first, max_nodes)
+    }
+
+    /// `to_dot` for the `max_nodes` nodes from the first key not below `key`. Links
+    /// coming into the window from before it start at a `...` node.
+    pub fn to_dot_from(&self, key: &K, max_nodes: usize) -> String {
+        self.dot_from(self.find_near(Bound::Included(key), false), max_nodes)
+    }
+
+    fn dot_from(&self, start: *mut Node<K, V>, max_nodes: usize) -> String {
+        use std::fmt::Write;
+
+        let head = self.head.as_ptr();
+        let height = self.height();
+        let (window, labels, preds) = self.dump_window(start, max_nodes, height);
+        let mut out = String::from("digraph skip_list {\n");
+        out.push_str("    rankdir=LR;\n");
+        out.push_str("    node [shape=record, fontname=\"monospace\"];\n");
+        let _ = writeln!(out, "    head [label=\"head|h {height}\"];");
+        for (i, (&node, label)) in window.iter().zip(&labels).enumerate() {
+            let label = dot_escape(label);
+            let node_height = unsafe { Node::height(node) };
+            let _ = writeln!(out, "    n{i} [label=\"{label}|h {node_height}\"];");
+        }
+        if preds[..height].iter().any(|&pred| pred != head) {
+            out.push_str("    before [shape=plaintext, label=\"...\"];\n");
+        }
+        let mut leaves = false;
+        let mut edges = String::new();
+        for level in 0..height {
+            let color = DOT_COLORS[level % DOT_COLORS.len()];
+            let pred = preds[level];
+            let mut from = if pred == head {
+                "head".to_string()
+            } else {
+                "before".to_string()
+            };
+            let mut next = unsafe { Node::get_next(pred, level) };
+            for (i, &node) in window.iter().enumerate() {
+                if next == node {
+                    let _ = writeln!(
+                        edges,
+                        "    {from} -> n{i} [label=\"{level}\", color={color}, fontcolor={color}];"
+                    );
+                    from = format!("n{i}");
+                    next = unsafe { Node::get_next(node, level) };
+                }
+            }
+            if !next.is_null() {
+                leaves = true;
+                let _ = writeln!(
+                    edges,
+                    "    {from} -> after [label=\"{level}\", color={color}, fontcolor={color}];"
+                );
+            }
+        }
+        if leaves {
+            out.push_str("    after [shape=plaintext, label=\"...\"];\n");
+        }
+        out.push_str(&edges);
+        out.push_str("}\n");
+        out
+    }
+
+    // The first `max_entries` nodes from `start` on level 0, their labels, and the last
+    // node before `start` on every level of `height`, the last of all when it is null.
+    #[allow(clippy::type_complexity)]
+    fn dump_window(
+        &self,
+        start: *mut Node<K, V>,
+        max_entries: usize,
+        height: usize,
+    ) -> (Vec<*mut Node<K, V>>, Vec<String>, Finger<K, V>) {
+        let head = self.head.as_ptr();
+        let mut window = Vec::with_capacity(max_entries.min(self.len()));
+        let mut cur = start;
+        while !cur.is_null() && window.len() < max_entries {
+            window.push(cur);
+            cur = unsafe { Node::get_next(cur, 0) };
+        }
+        let labels: Vec<String> = window
+            .iter()
+            .map(|&node| {
+                let label = format!("{:?}", unsafe { Node::key(node) });
+                if label.chars().count() > DUMP_KEY_WIDTH {
+                    let mut cut: String = label.chars().take(DUMP_KEY_WIDTH - 1).collect();
+                    cut.push('~');
+                    cut
+                } else {
+                    label
+                }
+            })
+            .collect();
+
+        let first = (!start.is_null()).then(|| unsafe { Node::key(start) });
+        let mut preds = [head; MAX_HEIGHT];
+        let mut cur = head;
+        for level in (0..height).rev() {
+            loop {
+                let next = unsafe { Node::get_next(cur, level) };
+                if next.is_null()
+                    || first.is_some_and(|first| {
+                        self.c.compare(unsafe { Node::key(next) }, first) != Less
+                    })
+                {
+                    break;
+                }
+                cur = next;
+            }
+            preds[level] = cur;
+        }
+        (window, labels, preds)
+    }
+
+    fn dump_from(&self, start: *mut Node<K, V>, max_entries: usize) -> String {
+        use std::fmt::Write;
+
+        let head = self.head.as_ptr();
+        let height = self.height();
+        let (window, labels, preds) = self.dump_window(start, max_entries, height);
+        let mut out = String::new();
+        for level in (0..height).rev() {
+            let pred = preds[level];
+            let from = if pred == head { "head" } else { "..." };
+            let _ = write!(out, "{level:>2} {from:<5}");
+            let mut next = unsafe { Node::get_next(pred, level) };
+            for (&node, label) in window.iter().zip(&labels) {
+                let width = label.chars().count();
+                if next == node {
+                    let _ = write!(out, "-> {label} ");
+                    next = unsafe { Node::get_next(node, level) };
+                } else if unsafe { Node::height(node) } > level {
+                    let _ = write!(out, "-- {} ", "?".repeat(width));
+                } else {
+                    out.push_str(&"-".repeat(width + 4));
+                }
+            }
+            out.push_str(if next.is_null() {
+                "-> nil\n"
+            } else {
+                "-> ...\n"
+            });
+        }
+
+        out.push_str("nodes:");
+        for level in 0..height {
+            let count = self.count_level(level, usize::MAX).unwrap();
+            let _ = write!(out, " {level}:{count}");
+        }
+        out.push('\n');
+        out
+    }
+}
+
+// Backslash-escapes what a Graphviz record label would read as quoting or structure.
+fn dot_escape(label: &str) -> String {
+    let mut out = String::with_capacity(label.len());
+    for c in label.chars() {
+        if matches!(c, '\\' | '"' | '{' | '}' | '|' | '<' | '>') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// Hints the cache to load the node after the one being compared. A no-op without the
+// `prefetch` feature and on targets other than x86_64 and aarch64.
+#[inline(always)]
+fn prefetch<K, V>(node: *const Node<K, V>) {
+    #[cfg(all(feature = "prefetch", target_arch = "x86_64", not(miri)))]
+    unsafe {
+        use std::arch::x86_64::{_MM_HINT_T0, _mm_prefetch};
+        _mm_prefetch::<_MM_HINT_T0>(node as *const i8);
+    }
+    #[cfg(all(feature = "prefetch", target_arch = "aarch64", not(miri)))]
+    unsafe {
+        std::arch::asm!("prfm pldl1keep, [{}]", in(reg) node, options(nostack, readonly, preserves_flags));
+    }
+    let _ = node;
+}
+
+#[cfg(not(any(loom, shuttle)))]
+thread_local! {
+    // xorshift64* state, seeded on first use
+    static HEIGHT_RNG: Cell<u64> = Cell::new(height_seed() | 1);
+}
+
+#[cfg(not(any(loom, shuttle, target_arch = "wasm32", feature = "no-os-rand")))]
+fn height_seed() -> u64 {
+    rand::random()
+}
+
+// wasm32-unknown-unknown has no OS entropy without JS glue
+#[cfg(all(
+    not(any(loom, shuttle)),
+    any(target_arch = "wasm32", feature = "no-os-rand")
+))]
+fn height_seed() -> u64 {
+    SplitMix64::from_address_entropy().next_u64()
+}
+#[cfg(loom)]
+loom::thread_local! {
+    // loom replays each execution many times, which only works if the heights repeat
+    static HEIGHT_RNG: Cell<u64> = Cell::new(1);
+}
+#[cfg(shuttle)]
+shuttle::thread_local! {
+    // likewise for replaying a failing shuttle schedule
+    static HEIGHT_RNG: Cell<u64> = Cell::new(1);
+}
+
+/// Reseeds the generator behind node heights on the current thread, so tests can get
+/// reproducible lists.
+pub fn seed_height_rng(seed: u64) {
+    // xorshift gets stuck on an all-zero state
+    HEIGHT_RNG.with(|rng| rng.set(seed | 1));
+}
+
+/// SplitMix64, a generator for `with_rng` that needs nothing from the OS. It is also what
+/// seeds heights on threads that cannot ask the OS, on `wasm32` or with the `no-os-rand`
+/// feature. Fine for tower heights; nothing to draw keys or secrets from.
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+// a stride shared by every `from_address_entropy`, so that no two start alike
+static SPLIT_MIX_STREAMS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeded from a process-wide counter and the addresses of a stack slot and of a
+    /// static: distinct within a process, and across runs as far as address space layout
+    /// randomization goes. That is what `Default` does.
+    pub fn from_address_entropy() -> Self {
+        let stream = SPLIT_MIX_STREAMS.fetch_add(0x9e37_79b9_7f4a_7c15, Relaxed);
+        let slot = 0_u8;
+        let stack = (&raw const slot).addr() as u64;
+        let image = (&raw const SPLIT_MIX_STREAMS).addr() as u64;
+        let mut mixer = Self::new(stream ^ stack.rotate_left(32) ^ image);
+        Self::new(mixer.next_u64())
+    }
+}
+
+impl Default for SplitMix64 {
+    fn default() -> Self {
+        Self::from_address_entropy()
+    }
+}
+
+impl RngCore for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        rand::rand_core::impls::fill_bytes_via_next(self, dst);
+    }
+}
+
+impl SeedableRng for SplitMix64 {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: [u8; 8]) -> Self {
+        Self::new(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        Self::new(seed)
+    }
+}
+
+fn next_random() -> u64 {
+    HEIGHT_RNG.with(|rng| {
+        let mut x = rng.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        rng.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+// [1, max], geometric with p = 1 / branching. For a power of two it takes one draw,
+// where every log2(branching) trailing zero bits add a level; a 64 bit draw covers
+// `MAX_HEIGHT` levels even for branching 2. Other factors take one draw per level.
+fn random_height(branching: u32, max: usize, mut draw: impl FnMut() -> u64) -> usize {
+    if branching.is_power_of_two() {
+        let levels = draw().trailing_zeros() / branching.trailing_zeros();
+        return (1 + levels as usize).min(max);
+    }
+    let mut h = 1;
+    while h < max && draw().is_multiple_of(branching as u64) {
+        h += 1;
+    }
+    h
+}
+
+// Links entries already sorted by `c` into a fresh list, without searching: the n-th node
+// (counting from 1) is one level higher for every time the branching factor divides n,
+// and each level is linked by appending to its tail. The order is the caller's to check.
+pub(crate) struct SortedBuilder<K, V, C, A> {
+    list: SkipList<K, V, C, A>,
+    tails: [*mut Node<K, V>; MAX_HEIGHT],
+    max_height: usize,
+}
+
+impl<K, V, C, A> SortedBuilder<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    // `list` is empty
+    pub(crate) fn new(list: SkipList<K, V, C, A>) -> Self {
+        Self {
+            tails: [list.head.as_ptr(); MAX_HEIGHT],
+            list,
+            max_height: 1,
+        }
+    }
+
+    pub(crate) fn comparator(&self) -> &C {
+        &self.list.c
+    }
+
+    pub(crate) fn last_key(&self) -> Option<&K> {
+        let tail = self.tails[0];
+        (tail != self.list.head.as_ptr()).then(|| unsafe { Node::key(tail) })
+    }
+
+    pub(crate) fn push(&mut self, key: K, value: V) -> Result<(), NodeError> {
+        let list = &self.list;
+        let n = list.len.load(Relaxed) + 1;
+        let height = sorted_height(n, &list.options);
+        let node = Node::new_in(key, value, height, &list.a)?;
+        if list.options.write_buffer_size.is_some() {
+            // a compacted list starts out with what it holds, but is never refused
+            let size = Node::<K, V>::get_layout(height)?.size();
+            list.write_buffer_usage.fetch_add(size, Relaxed);
+        }
+        for (level, tail) in self.tails.iter_mut().enumerate().take(height) {
+            unsafe { Node::set_next(*tail, level, node) };
+            *tail = node;
+        }
+        self.max_height = self.max_height.max(height);
+        list.append_local(node);
+        list.len.store(n, Relaxed);
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> SkipList<K, V, C, A> {
+        self.list.height.store(self.max_height, Relaxed);
+        self.list
+    }
+}
+
+// The height of the `n`th node of a list built from sorted entries, see `SortedBuilder`.
+fn sorted_height(n: usize, options: &SkipListOptions) -> usize {
+    let branching = options.branching as usize;
+    let mut height = 1;
+    let mut rest = n;
+    while height < options.max_height && rest.is_multiple_of(branching) {
+        rest /= branching;
+        height += 1;
+    }
+    height
+}
+
+// Where an insert links its node on one level: right after `prev`, in front of `next`.
+struct Splice<K, V> {
+    prev: *mut Node<K, V>,
+    next: *mut Node<K, V>,
+}
+
+// The smallest `k` with `base^k >= n`, for `n >= 1`. Like `random_height`, a power of two
+// base takes a few bit operations instead of a loop.
+fn log_ceil(n: usize, base: u32) -> usize {
+    if base.is_power_of_two() {
+        let bits = usize::BITS - (n - 1).leading_zeros();
+        return bits.div_ceil(base.trailing_zeros()) as usize;
+    }
+    let (mut k, mut reach) = (0, 1_usize);
+    while reach < n {
+        reach = reach.saturating_mul(base as usize);
+        k += 1;
+    }
+    k
+}
+
+// Exponential backoff between tower CAS retries, after crossbeam's `Backoff`. Only built
+// on the path to a CAS, and does nothing until one is lost.
+struct Backoff {
+    step: u32,
+    spin_limit: u32,
+}
+
+impl Backoff {
+    fn new(spin_limit: u32) -> Self {
+        Self {
+            step: 0,
+            spin_limit,
+        }
+    }
+
+    fn snooze(&mut self) {
+        if self.spin_limit == 0 {
+            return;
+        }
+        if self.step <= self.spin_limit {
+            for _ in 0..1_u32 << self.step {
+                std::hint::spin_loop();
+            }
+            self.step += 1;
+        } else {
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// Where two lists differ, from `diff`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Diff<'a, K> {
+    OnlyInA(&'a K),
+    OnlyInB(&'a K),
+    /// Both have the key, with unequal values; the key is `a`'s.
+    ValueDiffers(&'a K),
+}
+
+/// The keys where `a` and `b` differ, in key order, from one walk over both lists side by
+/// side: O(n + m) comparisons, and no allocation. Entries inserted during the walk may or
+/// may not be seen. Panics when the comparators have different names, see
+/// `Comparator::name` and `try_diff`.
+pub fn diff<'a, K, V, C, A, B>(
+    a: &'a SkipList<K, V, C, A>,
+    b: &'a SkipList<K, V, C, B>,
+) -> DiffIter<'a, K, V, C, A, B>
+where
+    V: PartialEq,
+    C: Comparator<Item = K>,
+{
+    try_diff(a, b).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// `diff`, failing with `Error::ComparatorMismatch` instead of panicking.
+pub fn try_diff<'a, K, V, C, A, B>(
+    a: &'a SkipList<K, V, C, A>,
+    b: &'a SkipList<K, V, C, B>,
+) -> Result<DiffIter<'a, K, V, C, A, B>, Error>
+where
+    V: PartialEq,
+    C: Comparator<Item = K>,
+{
+    if a.c.name() != b.c.name() {
+        return Err(Error::ComparatorMismatch {
+            a: a.c.name().to_owned(),
+            b: b.c.name().to_owned(),
+        });
+    }
+    unsafe {
+        Ok(DiffIter {
+            a,
+            b: PhantomData,
+            a_cur: Node::get_next(a.head.as_ptr(), 0),
+            b_cur: Node::get_next(b.head.as_ptr(), 0),
+        })
+    }
+}
+
+/// The iterator of `diff`.
+pub struct DiffIter<'a, K, V, C, A, B> {
+    a: &'a SkipList<K, V, C, A>,
+    // for the lifetime of `b_cur`; `a`'s comparator does for both
+    b: PhantomData<&'a SkipList<K, V, C, B>>,
+    a_cur: *mut Node<K, V>,
+    b_cur: *mut Node<K, V>,
+}
+
+impl<'a, K, V, C, A, B> Iterator for DiffIter<'a, K, V, C, A, B>
+where
+    V: PartialEq,
+    C: Comparator<Item = K>,
+{
+    type Item = Diff<'a, K>;
+
+    fn next(&mut self) -> Option<Diff<'a, K>> {
+        // nodes stay put while the lists are borrowed
+        unsafe {
+            loop {
+                let (a, b) = (self.a_cur, self.b_cur);
+                let order = match (a.is_null(), b.is_null()) {
+                    (true, true) => return None,
+                    (false, true) => Less,
+                    (true, false) => Greater,
+                    (false, false) => self.a.c.compare(Node::key(a), Node::key(b)),
+                };
+                match order {
+                    Less => {
+                        self.a_cur = Node::get_next(a, 0);
+                        return Some(Diff::OnlyInA(Node::key(a)));
+                    }
+                    Greater => {
+                        self.b_cur = Node::get_next(b, 0);
+                        return Some(Diff::OnlyInB(Node::key(b)));
+                    }
+                    Equal => {
+                        self.a_cur = Node::get_next(a, 0);
+                        self.b_cur = Node::get_next(b, 0);
+                        if Node::value(a) != Node::value(b) {
+                            return Some(Diff::ValueDiffers(Node::key(a)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// per-level predecessors of the last finger seek, the head where it did not reach
+type Finger<K, V> = [*mut Node<K, V>; MAX_HEIGHT];
+
+// Moves an insert's finger onto `node`, of `height`, just linked by `splices`, one for every
+// level below the top: the node on the levels it went on, the one before it above. The
+// finger then holds predecessors of every key after the node's, see `extend_shared`.
+//
+// # Safety
+//
+// `splices` are all written.
+unsafe fn advance_finger<K, V>(
+    finger: &mut Finger<K, V>,
+    node: *mut Node<K, V>,
+    height: usize,
+    splices: &[MaybeUninit<Splice<K, V>>],
+) {
+    for (level, splice) in splices.iter().enumerate() {
+        finger[level] = if level < height {
+            node
+        } else {
+            unsafe { splice.assume_init_ref() }.prev
+        };
+    }
+}
+
+pub struct SkipListIter<K, V, C, A> {
+    list: Arc<SkipList<K, V, C, A>>,
+    cur: *mut Node<K, V>,
+    finger: Option<Box<Finger<K, V>>>,
+}
+
+impl<K, V, C, A> SkipListIter<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn new(list: Arc<SkipList<K, V, C, A>>) -> Self {
+        SkipListIter {
+            list,
+            cur: null_mut(),
+            finger: None,
+        }
+    }
+
+    /// An iterator whose `seek` remembers where it ended up, so that a following seek to a
+    /// key a little further on only walks the distance in between. Seeking backwards
+    /// starts from the head as usual.
+    pub fn with_finger(list: Arc<SkipList<K, V, C, A>>) -> Self {
+        let head = list.head.as_ptr();
+        SkipListIter {
+            list,
+            cur: null_mut(),
+            finger: Some(Box::new([head; MAX_HEIGHT])),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.cur.is_null()
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        if self.is_valid() {
+            unsafe { Some(Node::key(self.cur)) }
+        } else {
+            None
+        }
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        if self.is_valid() {
+            unsafe { Some(Node::value(self.cur)) }
+        } else {
+            None
+        }
+    }
+
+    /// The entry the cursor is at, as an `EntryRef` that holds on to the list and so
+    /// stays readable after the cursor moves on or is dropped.
+    pub fn entry<'g>(&self) -> Option<EntryRef<'g, K, V>>
+    where
+        K: 'g,
+        V: 'g,
+        C: 'g,
+        A: 'g,
+    {
+        let (key, value) = (self.key()?, self.value()?);
+        Some(unsafe { EntryRef::kept(key, value, self.list.clone()) })
+    }
+
+    pub fn next(&mut self) {
+        assert!(self.is_valid());
+        self.cur = unsafe { Node::get_next(self.cur, 0) };
+    }
+
+    pub fn prev(&mut self) {
+        assert!(self.is_valid());
+        self.cur = self
+            .list
+            .find_near(Bound::Excluded(self.key().unwrap()), true);
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.list.counters().sought();
+        self.cur = self.list.find_first();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.list.counters().sought();
+        self.cur = self.list.find_last();
+    }
+
+    pub fn seek(&mut self, key: &K) {
+        self.list.counters().sought();
+        self.cur = match &mut self.finger {
+            Some(finger) => self.list.seek_from(key, finger),
+            None => self.list.find_near(Bound::Included(key), false),
+        };
+    }
+
+    /// Moves to the last entry at or before `key`.
+    pub fn seek_for_prev(&mut self, key: &K) {
+        self.list.counters().sought();
+        self.cur = self.list.find_near(Bound::Included(key), true);
+    }
+}
+
+impl<K, V, C, A> fmt::Debug for SkipListIter<K, V, C, A>
+where
+    K: fmt::Debug,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SkipListIter")
+            .field("valid", &self.is_valid())
+            .field("key", &self.key())
+            .finish()
+    }
+}
+
+/// A point-in-time view of a list, from `SkipList::snapshot`. It sees every entry whose
+/// insert returned before it was taken, maybe some that were in flight then, and none
+/// inserted afterwards. Whatever it sees once it keeps seeing: two reads of the same
+/// snapshot always agree. Snapshots can overlap freely; each one is an `Arc` and a number.
+pub struct Snapshot<K, V, C, A> {
+    list: Arc<SkipList<K, V, C, A>>,
+    seq: u64,
+}
+
+impl<K, V, C, A> Clone for Snapshot<K, V, C, A> {
+    fn clone(&self) -> Self {
+        Snapshot {
+            list: self.list.clone(),
+            seq: self.seq,
+        }
+    }
+}
+
+impl<K, V, C, A> Snapshot<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    /// Entries stamped up to this number are in the snapshot. Numbers count up one by one
+    /// with the inserts, see `SkipList::changes_since`.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    fn contains(&self, node: *mut Node<K, V>) -> bool {
+        self.list.stamp(node) <= self.seq
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let node = self.list.find_near(Bound::Included(key), false);
+        let found = !node.is_null()
+            && unsafe { self.list.c.compare(Node::key(node), key) == Equal }
+            && self.contains(node);
+        found.then(|| unsafe { Node::value(node) })
+    }
+
+    pub fn iter(&self) -> SnapshotIter<K, V, C, A> {
+        SnapshotIter {
+            inner: self.list.iter(),
+            snapshot: self.clone(),
+        }
+    }
+}
+
+/// `SkipListIter` over a `Snapshot`: it steps over the entries the snapshot does not see.
+pub struct SnapshotIter<K, V, C, A> {
+    inner: SkipListIter<K, V, C, A>,
+    snapshot: Snapshot<K, V, C, A>,
+}
+
+impl<K, V, C, A> SnapshotIter<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        self.inner.key()
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.inner.value()
+    }
+
+    pub fn next(&mut self) {
+        self.inner.next();
+        self.skip_forward();
+    }
+
+    pub fn prev(&mut self) {
+        self.inner.prev();
+        self.skip_backward();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+        self.skip_forward();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last();
+        self.skip_backward();
+    }
+
+    pub fn seek(&mut self, key: &K) {
+        self.inner.seek(key);
+        self.skip_forward();
+    }
+
+    pub fn seek_for_prev(&mut self, key: &K) {
+        self.inner.seek_for_prev(key);
+        self.skip_backward();
+    }
+
+    fn skip_forward(&mut self) {
+        while self.inner.is_valid() && !self.snapshot.contains(self.inner.cur) {
+            self.inner.next();
+        }
+    }
+
+    fn skip_backward(&mut self) {
+        while self.inner.is_valid() && !self.snapshot.contains(self.inner.cur) {
+            self.inner.prev();
+        }
+    }
+}
+
+/// The entries of a list with their sequence numbers, in the order they were stamped, from
+/// `SkipList::changes_since`. Numbers go up one by one except where `pop_first` or `drain`
+/// took an entry out. `next` returns `None` once it has caught up with the inserts, and
+/// goes on with whatever was stamped since when called again, so a reader can follow the
+/// list without gaps or repeats.
+pub struct ChangeIter<'a, K, V, C, A> {
+    list: &'a SkipList<K, V, C, A>,
+    // the last node handed out, or the head
+    last: *mut Node<K, V>,
+}
+
+impl<'a, K, V, C, A> Iterator for ChangeIter<'a, K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    type Item = (u64, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = unsafe { Node::log(self.last).load(Acquire) };
+        if next.is_null() {
+            return None;
+        }
+        let seq = unsafe { self.list.log_seq(self.last, next) };
+        self.last = next;
+        Some((seq, unsafe { Node::key(next) }, unsafe {
+            Node::value(next)
+        }))
+    }
+}
+
+// `SkipList::drain`, which takes the log down when it starts
+struct Drain<'a, K, V, C, A> {
+    list: &'a mut SkipList<K, V, C, A>,
+}
+
+impl<K, V, C, A> Iterator for Drain<'_, K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let first = unsafe { Node::get_next(self.list.head.as_ptr(), 0) };
+        (!first.is_null()).then(|| unsafe { self.list.remove_first(first) })
+    }
+}
+
+impl<K, V, C, A> Drop for Drain<'_, K, V, C, A> {
+    // puts what is left back on the log, in the order it was stamped
+    fn drop(&mut self) {
+        let list = &mut *self.list;
+        let mut nodes = Vec::new();
+        let mut cur = unsafe { Node::get_next(list.head.as_ptr(), 0) };
+        while !cur.is_null() {
+            nodes.push(cur);
+            cur = unsafe { Node::get_next(cur, 0) };
+        }
+        nodes.sort_unstable_by_key(|&node| unsafe { Node::seq(node).load(Relaxed) });
+        let mut tail = list.head.as_ptr();
+        for node in nodes {
+            unsafe { Node::log(tail).store(node, Relaxed) };
+            tail = node;
+        }
+        unsafe { Node::log(tail).store(null_mut(), Relaxed) };
+        list.log_tail.store(tail, Relaxed);
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::{
+        cell::Cell,
+        hash::DefaultHasher,
+        mem::MaybeUninit,
+        ptr::addr_of_mut,
+        sync::{
+            Arc, Mutex,
+            atomic::{
+                AtomicUsize,
+                Ordering::{Acquire, Relaxed, Release, SeqCst},
+            },
+        },
+        thread,
+    };
+
+    use rand::{RngCore, SeedableRng, rngs::StdRng};
+
+    use crate::{
+        arena::{
+            AccountingHandle, BlockArena, DefaultAllocator, FaultInjector, MemAllocator,
+            TrackingAllocator,
+        },
+        comparator::{Comparator, DefaultComparator},
+    };
+
+    use super::{
+        Error, InsertObserver, InvariantViolation, MAX_HEIGHT, Node, NodeError, SkipList,
+        SkipListIter, SkipListOptions, Snapshot, SplitMix64, WriteStall, next_random,
+        random_height, seed_height_rng,
+    };
+
+    #[test]
+    fn insert_some() {
+        const TEST_COUNT: usize = if cfg!(miri) { 1_000 } else { 1_000_000 };
+
+        let list = SkipList::<_, _>::default_arc();
+
+        for i in 0..TEST_COUNT {
+            list.insert(i, i + 1);
+        }
+        assert_eq!(list.get(&5), Some(&6));
+        assert_eq!(list.get(&TEST_COUNT), None);
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for i in 0..TEST_COUNT {
+            assert_eq!(iter.key().unwrap(), &i);
+            assert_eq!(iter.value().unwrap(), &(i + 1));
+            iter.next();
+        }
+    }
+
+    #[test]
+    fn iterator() {
+        const TEST_COUNT: usize = if cfg!(miri) { 1_000 } else { 1_000_000 };
+
+        let list = SkipList::<_, _>::default_arc();
+
+        for i in 0..TEST_COUNT {
+            list.insert(i, i);
+        }
+
+        let mut iter = list.iter();
+        iter.seek_to_last();
+        iter.seek_to_first();
+        for i in 0..TEST_COUNT {
+            assert_eq!(iter.key().unwrap(), &i);
+            assert_eq!(iter.value().unwrap(), &i);
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        for i in 0..TEST_COUNT {
+            iter.seek(&i);
+            assert_eq!(iter.key().unwrap(), &i);
+            assert_eq!(iter.value().unwrap(), &i);
+        }
+    }
+
+    #[test]
+    fn seek_for_prev() {
+        let list = SkipList::<_, _>::default_arc();
+        for i in 1..100 {
+            list.insert(i * 2, i);
+        }
+
+        let mut iter = list.iter();
+        for key in 2..250 {
+            iter.seek_for_prev(&key);
+            assert_eq!(iter.key(), Some(&(key / 2 * 2).min(198)), "{key}");
+        }
+        iter.seek_for_prev(&1);
+        assert!(!iter.is_valid());
+    }
+
+    #[test]
+    fn shared_arena_accounting() {
+        let arena = Arc::new(BlockArena::default());
+
+        let a = Arc::new(SkipList::new(
+            DefaultComparator::default(),
+            AccountingHandle::new(arena.clone()),
+        ));
+        let b = SkipList::new(
+            DefaultComparator::default(),
+            AccountingHandle::new(arena.clone()),
+        );
+
+        for i in 0..1000 {
+            a.insert(i, i);
+        }
+        for i in 0..10 {
+            b.insert(i, i);
+        }
+
+        assert!(a.mem_usage() > b.mem_usage());
+        assert!(a.mem_usage() + b.mem_usage() <= arena.memory_usage());
+
+        drop(b);
+
+        let mut iter = a.iter();
+        iter.seek_to_first();
+        for i in 0..1000 {
+            assert_eq!(iter.key().unwrap(), &i);
+            assert_eq!(iter.value().unwrap(), &i);
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+    }
+
+    #[test]
+    fn pop_first_returns_memory() {
+        let mut list = SkipList::new(DefaultComparator::default(), DefaultAllocator::default());
+        let empty = list.mem_usage();
+
+        for i in (0..100).rev() {
+            list.insert(i, i * 10);
+        }
+        let full = list.mem_usage();
+        assert!(full > empty);
+
+        for i in 0..50 {
+            assert_eq!(list.pop_first(), Some((i, i * 10)));
+        }
+        assert!(list.mem_usage() < full);
+
+        let half = list.mem_usage();
+        for i in 50..100 {
+            assert_eq!(list.pop_first(), Some((i, i * 10)));
+        }
+        assert!(list.mem_usage() < half);
+        assert_eq!(list.mem_usage(), empty);
+        assert_eq!(list.pop_first(), None);
+    }
+
+    #[test]
+    fn pop_first_after_descending_inserts() {
+        // every pop takes the newest entry, which is a walk along the whole log without the
+        // index; this would not finish
+        const COUNT: u64 = if cfg!(miri) { 1_000 } else { 200_000 };
+        let mut list = SkipList::<_, _>::default();
+        for key in (0..COUNT).rev() {
+            list.insert(key, key);
+        }
+
+        for key in 0..COUNT / 2 {
+            assert_eq!(list.pop_first(), Some((key, key)));
+        }
+        assert_eq!(list.last_seq(), COUNT);
+        assert!(
+            list.changes_since(0)
+                .map(|(seq, &key, _)| (seq, key))
+                .eq((1..=COUNT / 2).map(|seq| (seq, COUNT - seq)))
+        );
+
+        // inserts after the index was built, newest and oldest key alike
+        list.insert(COUNT, COUNT);
+        list.insert(0, 0);
+        assert_eq!(list.pop_first(), Some((0, 0)));
+        for key in COUNT / 2..=COUNT {
+            assert_eq!(list.pop_first(), Some((key, key)));
+        }
+        assert_eq!(list.changes_since(0).count(), 0);
+        list.insert(0, 0);
+        assert!(list.changes_since(0).map(|(seq, ..)| seq).eq([COUNT + 3]));
+    }
+
+    #[test]
+    fn pop_first_and_drain_free_every_node() {
+        let mut list = SkipList::new(
+            DefaultComparator::default(),
+            TrackingAllocator::new(FaultInjector::new(DefaultAllocator::default())),
+        );
+        // just the head
+        list.a.assert_no_leaks_except(1);
+
+        for i in (0..100).rev() {
+            list.insert(i, i);
+        }
+        list.a.allocator().fail_after(0);
+        assert!(list.try_insert(100, 100).is_err());
+        list.a.assert_no_leaks_except(101);
+        assert_eq!(list.len(), 100);
+
+        for i in 0..10 {
+            assert_eq!(list.pop_first(), Some((i, i)));
+        }
+        list.a.assert_no_leaks_except(91);
+        assert_eq!(list.len(), 90);
+
+        let drained: Vec<_> = list.drain().take(40).collect();
+        assert_eq!(drained, (10..50).map(|i| (i, i)).collect::<Vec<_>>());
+        list.a.assert_no_leaks_except(51);
+
+        assert_eq!(list.drain().count(), 50);
+        list.a.assert_no_leaks_except(1);
+        assert_eq!(list.pop_first(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn compact_into_fresh_arena() {
+        const COUNT: usize = if cfg!(miri) { 1_000 } else { 10_000 };
+        const POPPED: usize = COUNT / 10 * 9;
+
+        let mut list = SkipList::<_, _>::default();
+        for i in 0..COUNT {
+            list.insert(i, i * 2);
+        }
+        // the arena keeps the popped nodes' memory
+        for _ in 0..POPPED {
+            list.pop_first();
+        }
+
+        let (compacted, report) = list.compact_into(BlockArena::default()).unwrap();
+        assert_eq!(compacted.len(), COUNT - POPPED);
+        assert_eq!(report.mem_usage_before, list.mem_usage());
+        assert_eq!(report.mem_usage_after, compacted.mem_usage());
+        assert!(report.mem_usage_after * 5 < report.mem_usage_before);
+
+        let compacted = Arc::new(compacted);
+        let mut iter = compacted.iter();
+        iter.seek_to_first();
+        for i in POPPED..COUNT {
+            assert_eq!(iter.key(), Some(&i));
+            assert_eq!(iter.value(), Some(&(i * 2)));
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        for i in (POPPED..COUNT).step_by(7) {
+            iter.seek(&i);
+            assert_eq!(iter.key(), Some(&i));
+        }
+        iter.seek_to_last();
+        assert_eq!(iter.key(), Some(&(COUNT - 1)));
+    }
+
+    #[test]
+    fn bloom_filter() {
+        const COUNT: u64 = if cfg!(miri) { 1_000 } else { 10_000 };
+
+        let list = SkipList::<_, _>::default().with_bloom(10, COUNT as usize);
+        for i in 0..COUNT {
+            list.insert(i * 2, i);
+        }
+        for i in 0..COUNT {
+            assert!(list.contains_key(&(i * 2)));
+            assert_eq!(list.get(&(i * 2 + 1)), None);
+        }
+
+        let misses = COUNT * 10;
+        let false_positives = (0..misses)
+            .filter(|i| list.may_contain(&(COUNT * 2 + i)))
+            .count();
+        let rate = false_positives as f64 / misses as f64;
+        assert!(rate < 0.02, "{rate}");
+
+        // a filter set up on a filled list knows its keys
+        let built = SkipList::from_sorted_iter(
+            (0..100_u64).map(|i| (i, i)),
+            DefaultComparator::default(),
+            BlockArena::default(),
+        )
+        .with_bloom_hasher(10, 100, |&key| key.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        assert!((0..100).all(|i| built.contains_key(&i)));
+        assert!(!built.contains_key(&100));
+    }
+
+    #[test]
+    fn observers_under_concurrent_inserts() {
+        const THREADS: usize = 4;
+        const KEYS: usize = if cfg!(miri) { 100 } else { 10_000 };
+
+        // a count-min-like tally kept beside the list, and a count of calls per key
+        struct Tally {
+            calls: AtomicUsize,
+            seen: Vec<AtomicUsize>,
+        }
+        impl InsertObserver<usize, usize> for Tally {
+            fn on_insert(&self, &key: &usize, &value: &usize) {
+                assert_eq!(key, value);
+                self.calls.fetch_add(1, Relaxed);
+                self.seen[key].fetch_add(1, Relaxed);
+            }
+        }
+
+        let list = SkipList::<_, _>::default();
+        list.insert(0, 0);
+        let tally = Arc::new(Tally {
+            calls: AtomicUsize::new(0),
+            seen: (0..=KEYS).map(|_| AtomicUsize::new(0)).collect(),
+        });
+        list.register_observer(tally.clone());
+        let wins = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for t in 0..THREADS {
+                let (list, wins) = (&list, &wins);
+                s.spawn(move || {
+                    // every thread inserts every key, from different ends, to race on
+                    // both the splices and the keys themselves
+                    for i in 0..KEYS {
+                        let key = if t % 2 == 0 { i } else { KEYS - 1 - i };
+                        if list.try_insert(key, key).is_ok() {
+                            wins.fetch_add(1, Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        // a closure observes too, registered late
+        let late = Arc::new(AtomicUsize::new(0));
+        let counted = late.clone();
+        list.register_observer(Arc::new(move |_: &usize, _: &usize| {
+            counted.fetch_add(1, Relaxed);
+        }));
+        list.insert(KEYS, KEYS);
+        assert_eq!(late.load(Relaxed), 1);
+
+        assert_eq!(wins.load(Relaxed), KEYS - 1);
+        assert_eq!(tally.calls.load(Relaxed), KEYS);
+        assert_eq!(tally.seen[0].load(Relaxed), 0);
+        assert!(tally.seen[1..].iter().all(|n| n.load(Relaxed) == 1));
+        assert_eq!(list.len(), KEYS + 1);
+    }
+
+    #[test]
+    fn change_log_under_concurrent_inserts() {
+        const WRITERS: usize = 4;
+        const PER_WRITER: usize = if cfg!(miri) { 50 } else { 5_000 };
+        const COUNT: usize = WRITERS * PER_WRITER;
+
+        let list = SkipList::<_, _>::default_arc();
+        let seen = thread::scope(|s| {
+            for w in 0..WRITERS {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..PER_WRITER {
+                        let key = i * WRITERS + w;
+                        list.insert(key, key * 10);
+                    }
+                });
+            }
+            // snapshots stamp nodes too, racing the writers for some
+            s.spawn(|| {
+                while list.len() < COUNT {
+                    let snapshot = list.snapshot();
+                    assert!(snapshot.iter().key().is_none_or(|&key| key < COUNT));
+                }
+            });
+            // one iterator tails the log until it has seen every insert
+            let mut changes = list.changes_since(0);
+            let mut seen = Vec::with_capacity(COUNT);
+            while seen.len() < COUNT {
+                match changes.next() {
+                    Some((seq, &key, &value)) => {
+                        assert_eq!(seq, seen.len() as u64 + 1, "a gap or a repeat");
+                        assert_eq!(value, key * 10);
+                        seen.push(key);
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+            assert!(changes.next().is_none());
+            seen
+        });
+        let mut keys = seen.clone();
+        keys.sort_unstable();
+        assert!(keys.iter().copied().eq(0..COUNT));
+        // each writer's keys in the order it inserted them
+        for w in 0..WRITERS {
+            let mine: Vec<_> = seen.iter().filter(|&&key| key % WRITERS == w).collect();
+            assert!(mine.is_sorted());
+        }
+        assert_eq!(list.snapshot().seq(), COUNT as u64);
+        let since = |seq| {
+            list.changes_since(seq)
+                .map(|(seq, &key, _)| (seq, key))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            since(COUNT as u64 - 2),
+            [
+                (COUNT as u64 - 1, seen[COUNT - 2]),
+                (COUNT as u64, seen[COUNT - 1])
+            ]
+        );
+        assert!(since(COUNT as u64).is_empty());
+    }
+
+    #[test]
+    fn change_log_after_removals() {
+        fn log<C, A>(list: &SkipList<u32, u32, C, A>) -> Vec<(u64, u32)>
+        where
+            C: Comparator<Item = u32>,
+            A: MemAllocator,
+        {
+            list.changes_since(0)
+                .map(|(seq, &key, _)| (seq, key))
+                .collect()
+        }
+
+        let mut list = SkipList::<_, _>::default();
+        for key in [5, 1, 4, 2, 3] {
+            list.insert(key, key);
+        }
+        assert_eq!(log(&list), [(1, 5), (2, 1), (3, 4), (4, 2), (5, 3)]);
+        assert_eq!(list.pop_first(), Some((1, 1)));
+        assert_eq!(list.pop_first(), Some((2, 2)));
+        assert_eq!(log(&list), [(1, 5), (3, 4), (5, 3)]);
+        // the newest entry goes, but its number is not handed out again
+        assert_eq!(list.pop_first(), Some((3, 3)));
+        list.insert(0, 0);
+        assert_eq!(log(&list), [(1, 5), (3, 4), (6, 0)]);
+        assert_eq!(list.changes_since(3).count(), 1);
+
+        // what a drain leaves goes back on the log in order, even when the drain leaks
+        assert_eq!(list.drain().next(), Some((0, 0)));
+        assert_eq!(log(&list), [(1, 5), (3, 4)]);
+        list.insert(9, 9);
+        std::mem::forget(list.drain());
+        assert!(log(&list).is_empty());
+        assert_eq!(list.len(), 3);
+        list.insert(7, 7);
+        assert_eq!(log(&list), [(8, 7)]);
+        list.drain().for_each(drop);
+        assert!(log(&list).is_empty());
+        list.insert(1, 1);
+        assert_eq!(log(&list), [(9, 1)]);
+        assert_eq!(Arc::new(list).snapshot().seq(), 9);
+    }
+
+    #[test]
+    fn bloom_filter_under_concurrent_inserts() {
+        const PER_THREAD: usize = if cfg!(miri) { 100 } else { 10_000 };
+
+        // sized for a tenth of the keys, to crowd the bits
+        let list = SkipList::<_, _>::default().with_bloom(10, PER_THREAD / 5);
+        let published = [AtomicUsize::new(0), AtomicUsize::new(0)];
+        thread::scope(|s| {
+            for (t, published) in published.iter().enumerate() {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        list.insert(i * 2 + t, ());
+                        published.store(i + 1, Release);
+                    }
+                });
+            }
+            // every key a writer had inserted before it was published is found
+            s.spawn(|| {
+                loop {
+                    let done = published.each_ref().map(|p| p.load(Acquire));
+                    for (t, &done) in done.iter().enumerate() {
+                        if let Some(last) = done.checked_sub(1) {
+                            assert!(list.contains_key(&(last * 2 + t)));
+                        }
+                    }
+                    if done == [PER_THREAD; 2] {
+                        break;
+                    }
+                    thread::yield_now();
+                }
+            });
+        });
+        assert!((0..PER_THREAD * 2).all(|key| list.contains_key(&key)));
+    }
+
+    #[test]
+    fn content_hashes() {
+        let keys: Vec<u32> = (0..1000).map(|i| i * 7919 % 1000).collect();
+        let forward = SkipList::<_, _>::default().with_incremental_hash();
+        let shuffled = SkipList::<_, _>::default();
+        for &key in &keys {
+            forward.insert(key, key.to_string());
+        }
+        for key in (0..1000).rev() {
+            shuffled.insert(key, key.to_string());
+        }
+        // added by the knob for entries already there
+        let shuffled = shuffled.with_incremental_hash();
+        assert_eq!(
+            forward.content_hash::<DefaultHasher>(),
+            shuffled.content_hash::<DefaultHasher>()
+        );
+        assert_eq!(forward.incremental_hash(), shuffled.incremental_hash());
+
+        // one value off
+        let mut differs = SkipList::<_, _>::default().with_incremental_hash();
+        for key in 0..1000_u32 {
+            let value = if key == 500 {
+                "x".to_string()
+            } else {
+                key.to_string()
+            };
+            differs.insert(key, value);
+        }
+        assert_ne!(
+            differs.content_hash::<DefaultHasher>(),
+            forward.content_hash::<DefaultHasher>()
+        );
+        assert_ne!(differs.incremental_hash(), forward.incremental_hash());
+        assert_eq!(
+            SkipList::<u32, (), _, _>::new(DefaultComparator::default(), BlockArena::default())
+                .incremental_hash(),
+            None
+        );
+
+        // removals take their entries out again
+        let popped: Vec<_> = differs.drain().take(10).collect();
+        let mut rest = SkipList::<_, _>::default().with_incremental_hash();
+        for key in 10..1000_u32 {
+            let value = if key == 500 {
+                "x".to_string()
+            } else {
+                key.to_string()
+            };
+            rest.insert(key, value);
+        }
+        assert_eq!(popped.len(), 10);
+        assert_eq!(differs.incremental_hash(), rest.incremental_hash());
+        rest.drain().for_each(drop);
+        assert_eq!(rest.incremental_hash(), Some(0));
+    }
+
+    #[test]
+    fn incremental_hash_under_concurrent_inserts() {
+        const PER_THREAD: u32 = if cfg!(miri) { 100 } else { 5_000 };
+
+        let list = SkipList::<_, _>::default().with_incremental_hash();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        list.insert(i * 4 + t, i);
+                    }
+                });
+            }
+        });
+        let serial = SkipList::from_sorted_iter(
+            (0..PER_THREAD * 4).map(|key| (key, key / 4)),
+            DefaultComparator::default(),
+            BlockArena::default(),
+        )
+        .with_incremental_hash();
+        assert_eq!(list.incremental_hash(), serial.incremental_hash());
+        assert_eq!(
+            list.content_hash::<DefaultHasher>(),
+            serial.content_hash::<DefaultHasher>()
+        );
+    }
+
+    #[test]
+    fn diff_of_perturbed_copies() {
+        use rand::Rng;
+
+        use super::{Diff, diff};
+
+        let mut rng = StdRng::seed_from_u64(174);
+        for round in 0..20 {
+            let a = SkipList::<_, _>::default();
+            let b = SkipList::new(DefaultComparator::default(), DefaultAllocator::default());
+            let mut expected = vec![];
+            for key in 0..rng.random_range(0..500_u32) {
+                let value = rng.random_range(0..1000_u32);
+                // mostly copied, some dropped from either side or changed
+                match rng.random_range(0..10) {
+                    0 => {
+                        a.insert(key, value);
+                        expected.push(("a", key));
+                    }
+                    1 => {
+                        b.insert(key, value);
+                        expected.push(("b", key));
+                    }
+                    2 => {
+                        a.insert(key, value);
+                        b.insert(key, value + 1);
+                        expected.push(("value", key));
+                    }
+                    3 => {}
+                    _ => {
+                        a.insert(key, value);
+                        b.insert(key, value);
+                    }
+                }
+            }
+            let found: Vec<_> = diff(&a, &b)
+                .map(|d| match d {
+                    Diff::OnlyInA(&key) => ("a", key),
+                    Diff::OnlyInB(&key) => ("b", key),
+                    Diff::ValueDiffers(&key) => ("value", key),
+                })
+                .collect();
+            assert_eq!(found, expected, "{round}");
+        }
+
+        let list = SkipList::<_, _>::default();
+        list.insert(1, ());
+        assert_eq!(diff(&list, &list).count(), 0);
+    }
+
+    #[test]
+    #[should_panic = "the lists are ordered differently"]
+    fn diff_checks_comparator_names() {
+        struct Named(&'static str);
+
+        impl Comparator for Named {
+            type Item = u32;
+
+            fn compare(&self, a: &u32, b: &u32) -> std::cmp::Ordering {
+                a.cmp(b)
+            }
+
+            fn name(&self) -> &str {
+                self.0
+            }
+        }
+
+        let a = SkipList::<_, (), _, _>::new(Named("a"), BlockArena::default());
+        let b = SkipList::new(Named("b"), BlockArena::default());
+        super::diff(&a, &b).count();
+    }
+
+    #[test]
+    fn from_sorted_iter_links_every_level() {
+        const COUNT: usize = if cfg!(miri) { 1_000 } else { 100_000 };
+
+        let list = Arc::new(SkipList::from_sorted_iter(
+            (0..COUNT).map(|i| (i * 2, i)),
+            DefaultComparator::default(),
+            BlockArena::default(),
+        ));
+        assert_eq!(list.len(), COUNT);
+        assert_eq!(list.validate(), Ok(()));
+        // one level for every factor of 4 in COUNT's range
+        let levels = (COUNT as f64).log(4.0) as usize + 1;
+        assert_eq!(list.height(), levels);
+        for i in (0..COUNT).step_by(13) {
+            assert_eq!(list.get(&(i * 2)), Some(&i));
+            assert_eq!(list.get(&(i * 2 + 1)), None);
+        }
+
+        // and takes inserts in between like any other list
+        for i in (0..COUNT).step_by(3) {
+            list.insert(i * 2 + 1, 0);
+        }
+        assert_eq!(list.validate(), Ok(()));
+        let mut iter = list.iter();
+        iter.seek(&7);
+        assert_eq!(iter.key(), Some(&7));
+        iter.next();
+        assert_eq!(iter.key(), Some(&8));
+
+        let empty = SkipList::<usize, usize, _, _>::from_sorted_iter(
+            [],
+            DefaultComparator::default(),
+            BlockArena::default(),
+        );
+        assert!(empty.is_empty());
+        assert_eq!(empty.validate(), Ok(()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "entries are not in ascending order"]
+    fn from_sorted_iter_checks_order() {
+        SkipList::from_sorted_iter(
+            [(1, ()), (3, ()), (2, ())],
+            DefaultComparator::default(),
+            BlockArena::default(),
+        );
+    }
+
+    #[test]
+    fn collect_sorts_and_keeps_the_last_duplicate() {
+        use rand::seq::SliceRandom;
+
+        const COUNT: usize = if cfg!(miri) { 300 } else { 30_000 };
+
+        // every key three times, the rounds in order but shuffled within each
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut pairs = vec![];
+        for round in 0..3 {
+            let mut keys: Vec<usize> = (0..COUNT).collect();
+            keys.shuffle(&mut rng);
+            pairs.extend(keys.into_iter().map(|key| (key, (key, round))));
+        }
+        let list: SkipList<_, _> = pairs.into_iter().collect();
+        assert_eq!(list.len(), COUNT);
+        assert_eq!(list.validate(), Ok(()));
+        assert!(
+            list.entries()
+                .map(|(&key, &value)| (key, value))
+                .eq((0..COUNT).map(|key| (key, (key, 2))))
+        );
+        // laid out like any bulk build of the same keys
+        let sorted = SkipList::<_, _>::from_sorted_iter(
+            (0..COUNT).map(|key| (key, ())),
+            Default::default(),
+            Default::default(),
+        );
+        assert_eq!(list.dump_structure(64), sorted.dump_structure(64));
+
+        let empty: SkipList<u32, u32> = std::iter::empty().collect();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn extend_follows_sorted_runs() {
+        use rand::seq::SliceRandom;
+
+        const COUNT: usize = if cfg!(miri) { 300 } else { 20_000 };
+
+        // the evens in order, onto an empty list: each insert starts from the one before
+        let mut list = SkipList::new(CountingComparator(AtomicUsize::new(0)), BlockArena::new());
+        list.extend((0..COUNT).map(|i| (i * 2, i)));
+        assert_eq!(list.validate(), Ok(()));
+        let appended = list.c.0.swap(0, Relaxed);
+        assert!(appended < COUNT * 3, "{appended} comparisons");
+
+        // then the odds in between, through the `Arc`, in order and shuffled
+        let odds = |list: &SkipList<usize, usize, CountingComparator, BlockArena>, keys| {
+            list.c.0.store(0, Relaxed);
+            list.extend_shared(keys);
+            list.c.0.load(Relaxed)
+        };
+        let list = Arc::new(list);
+        let sorted = odds(
+            &list,
+            (0..COUNT).map(|i| (i * 2 + 1, i)).collect::<Vec<_>>(),
+        );
+        let mut pairs: Vec<_> = (0..COUNT).map(|i| (i * 2 + 1, i)).collect();
+        pairs.shuffle(&mut StdRng::seed_from_u64(3));
+        let fresh = SkipList::new(CountingComparator(AtomicUsize::new(0)), BlockArena::new());
+        fresh.extend_shared((0..COUNT).map(|i| (i * 2, i)));
+        let shuffled = odds(&fresh, pairs);
+        assert!(sorted * 2 < shuffled, "{sorted} against {shuffled}");
+        for list in [&*list, &fresh] {
+            assert_eq!(list.validate(), Ok(()));
+            assert!(
+                list.entries()
+                    .map(|(&key, &value)| (key, value))
+                    .eq((0..COUNT * 2).map(|key| (key, key / 2)))
+            );
+        }
+
+        // unsorted with duplicates: the first of each key stays, the rest are skipped
+        let mut pairs: Vec<_> = (0..3)
+            .flat_map(|round| (0..COUNT).map(move |key| (key, round)))
+            .collect();
+        pairs.shuffle(&mut StdRng::seed_from_u64(4));
+        let first: Vec<_> = (0..COUNT)
+            .map(|key| *pairs.iter().find(|(k, _)| *k == key).unwrap())
+            .collect();
+        let mut list = SkipList::<_, _>::default();
+        list.extend(pairs.iter().copied());
+        assert_eq!(list.len(), COUNT);
+        assert_eq!(list.validate(), Ok(()));
+        assert!(list.entries().map(|(&key, &value)| (key, value)).eq(first));
+
+        // and borrowed pairs are cloned in
+        let mut copy = SkipList::<_, _>::default();
+        copy.extend(list.entries());
+        copy.extend(list.entries().take(10));
+        assert!(copy.entries().eq(list.entries()));
+
+        // sorted runs racing into the same gaps, each thread on every 4th key
+        let list = SkipList::<_, _>::default_arc();
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let list = &list;
+                s.spawn(move || list.extend_shared((t..COUNT).step_by(4).map(|key| (key, t))));
+            }
+        });
+        assert_eq!(list.validate(), Ok(()));
+        assert!(list.entries().map(|(&key, _)| key).eq(0..COUNT));
+    }
+
+    #[test]
+    fn clone_copies_into_its_own_arena() {
+        const COUNT: usize = if cfg!(miri) { 200 } else { 10_000 };
+
+        let list = SkipList::<_, _>::default();
+        for i in (0..COUNT).rev() {
+            list.insert(format!("key{i:05}"), vec![i as u8; i % 7]);
+        }
+        let mut copy = list.clone();
+        assert_eq!(copy.len(), COUNT);
+        assert_eq!(copy.options(), list.options());
+        assert_eq!(copy.validate(), Ok(()));
+        assert!(copy.entries().eq(list.entries()));
+        // nothing in one points into the other, neither the nodes nor the heap
+        for ((key, value), (copied_key, copied_value)) in list.entries().zip(copy.entries()) {
+            assert!(!std::ptr::eq(key, copied_key));
+            assert_ne!(key.as_ptr(), copied_key.as_ptr());
+            assert!(value.is_empty() || value.as_ptr() != copied_value.as_ptr());
+        }
+
+        // changing the copy leaves the original alone, and it outlives it
+        assert_eq!(
+            copy.pop_first().map(|(key, _)| key),
+            Some("key00000".to_string())
+        );
+        copy.insert("new".to_string(), vec![]);
+        assert_eq!(list.len(), COUNT);
+        assert_eq!(list.get(&"key00000".to_string()), Some(&vec![]));
+        assert!(!list.contains_key(&"new".to_string()));
+        drop(list);
+        assert_eq!(copy.len(), COUNT);
+        assert_eq!(copy.get(&"key00008".to_string()), Some(&vec![8; 1]));
+    }
+
+    #[test]
+    fn lists_compare_by_content() {
+        let inserted = SkipList::<_, _>::default();
+        for i in [5, 1, 4, 2, 3] {
+            inserted.insert(i, i * 10);
+        }
+        let collected: SkipList<_, _> = (1..=5).map(|i| (i, i * 10)).collect();
+        assert_eq!(inserted, collected);
+        assert_eq!(inserted, inserted.clone());
+        let elsewhere = SkipList::new(DefaultComparator::default(), DefaultAllocator::default());
+        elsewhere.extend_shared((1..=5).map(|i| (i, i * 10)));
+        assert!(inserted.content_eq_by(&elsewhere, PartialEq::eq));
+
+        let shorter: SkipList<_, _> = (1..=4).map(|i| (i, i * 10)).collect();
+        assert_ne!(inserted, shorter);
+        let other_key: SkipList<_, _> = (2..=6).map(|i| (i, i * 10)).collect();
+        assert_ne!(inserted, other_key);
+        let other_value: SkipList<_, _> = (1..=5)
+            .map(|i| (i, if i == 4 { 0 } else { i * 10 }))
+            .collect();
+        assert_ne!(inserted, other_value);
+        assert_eq!(SkipList::<u32, u32>::default(), SkipList::default());
+
+        // stops at the first difference, and can compare values of another type
+        let mut calls = 0;
+        let near = |a: &u32, b: &f64| {
+            calls += 1;
+            (*a as f64 - b).abs() < 1.5
+        };
+        let floats: SkipList<_, _> = [(1, 10.0), (2, 21.0), (3, 30.5), (4, 45.0), (5, 50.0)]
+            .into_iter()
+            .collect();
+        assert!(!inserted.content_eq_by(&floats, near));
+        assert_eq!(calls, 4);
+        assert!(inserted.content_eq_by(&floats, |a, b| (*a as f64 - b).abs() < 5.5));
+    }
+
+    #[test]
+    fn converts_into_std_collections() {
+        let list = SkipList::<_, _>::default();
+        for i in [3, 1, 2] {
+            list.insert(format!("key{i}"), vec![i; i]);
+        }
+        let expected = vec![
+            ("key1".to_string(), vec![1]),
+            ("key2".to_string(), vec![2, 2]),
+            ("key3".to_string(), vec![3, 3, 3]),
+        ];
+        assert_eq!(list.to_vec(), expected);
+
+        // moved out: the heap buffers are the ones the list held
+        let buffers: Vec<_> = list
+            .entries()
+            .map(|(key, value)| (key.as_ptr(), value.as_ptr()))
+            .collect();
+        let entries = list.clone().into_vec();
+        let moved = list.into_vec();
+        assert_eq!(moved, expected);
+        assert!(
+            moved
+                .iter()
+                .map(|(key, value)| (key.as_ptr(), value.as_ptr()))
+                .eq(buffers)
+        );
+
+        let list: SkipList<_, _> = entries.into_iter().collect();
+        let map = list.into_btree_map();
+        assert_eq!(map.into_iter().collect::<Vec<_>>(), expected);
+        assert!(SkipList::<u32, u32>::default().into_vec().is_empty());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "the comparator orders keys unlike `Ord`"]
+    fn into_btree_map_checks_the_order() {
+        struct Reverse;
+
+        impl Comparator for Reverse {
+            type Item = u32;
+
+            fn compare(&self, a: &u32, b: &u32) -> std::cmp::Ordering {
+                b.cmp(a)
+            }
+        }
+
+        let list = SkipList::new(Reverse, BlockArena::new());
+        list.insert(1, ());
+        list.insert(2, ());
+        list.into_btree_map();
+    }
+
+    #[test]
+    fn errors_name_what_failed() {
+        use std::error::Error as _;
+
+        // a node allocation that fails stops the batch, the entries before it stay
+        let list = SkipList::new(
+            DefaultComparator::default(),
+            FaultInjector::new(DefaultAllocator::default()),
+        );
+        list.a.fail_after(3);
+        let err = list.try_extend_shared((0..10).map(|i| (i, i))).unwrap_err();
+        assert!(matches!(err, Error::AllocFailed(_)));
+        assert!(err.source().is_some());
+        assert_eq!(list.len(), 3);
+
+        let list = SkipList::<_, _>::default().with_write_buffer_size(4096);
+        let err = list.try_extend_shared((0..1_000).map(|i| (i, i)));
+        assert_eq!(err, Err(Error::MemtableFull));
+        assert_eq!(err.unwrap_err().to_string(), "memtable is full");
+
+        let list = SkipList::<_, _>::default();
+        list.insert(0, 0);
+        assert_eq!(
+            list.try_insert(0, 0).map_err(Error::from),
+            Err(Error::KeyExists)
+        );
+        let layout = std::alloc::Layout::from_size_align(8, 3).unwrap_err();
+        assert_eq!(
+            Error::from(NodeError::Layout(layout.clone())),
+            Error::LayoutInvalid(layout)
+        );
+
+        struct Named(&'static str);
+
+        impl Comparator for Named {
+            type Item = u32;
+
+            fn compare(&self, a: &u32, b: &u32) -> std::cmp::Ordering {
+                a.cmp(b)
+            }
+
+            fn name(&self) -> &str {
+                self.0
+            }
+        }
+
+        let a = SkipList::<_, (), _, _>::new(Named("a"), BlockArena::default());
+        let b = SkipList::new(Named("b"), BlockArena::default());
+        let err = super::try_diff(&a, &b).err().unwrap();
+        assert_eq!(
+            err,
+            Error::ComparatorMismatch {
+                a: "a".to_string(),
+                b: "b".to_string()
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "the lists are ordered differently: a against b"
+        );
+
+        let list = SkipList::<_, _>::default().with_rng(StdRng::seed_from_u64(7));
+        list.extend_shared((0..100).map(|i| (i, i)));
+        unsafe {
+            // the first tall node, off level 0 only
+            let mut prev = list.head.as_ptr();
+            let mut cur = Node::get_next(prev, 0);
+            while Node::height(cur) < 2 {
+                prev = cur;
+                cur = Node::get_next(cur, 0);
+            }
+            Node::set_next(prev, 0, Node::get_next(cur, 0));
+            let err = list.validate().map_err(Error::from).unwrap_err();
+            assert!(matches!(
+                err,
+                Error::Corruption(InvariantViolation::NotBelow { level: 1, .. })
+            ));
+            assert!(err.to_string().starts_with("corrupted skip list: node"));
+            Node::set_next(prev, 0, cur);
+        }
+    }
+
+    // `<T as NotSend<_>>::check` names one impl when `T` is not `Send`, and is ambiguous,
+    // a compile error, when it is; likewise for `Sync`
+    trait NotSend<M> {
+        fn check() {}
+    }
+
+    impl<T: ?Sized> NotSend<()> for T {}
+
+    impl<T: ?Sized + Send> NotSend<u8> for T {}
+
+    trait NotSync<M> {
+        fn check() {}
+    }
+
+    impl<T: ?Sized> NotSync<()> for T {}
+
+    impl<T: ?Sized + Sync> NotSync<u8> for T {}
+
+    #[test]
+    fn shares_only_what_its_entries_allow() {
+        use std::rc::Rc;
+
+        fn shared<T: Send + Sync>() {}
+
+        // orders `Rc`s without being one, so that only the key or value keeps the list home
+        struct RcOrder;
+
+        impl Comparator for RcOrder {
+            type Item = Rc<u8>;
+
+            fn compare(&self, a: &Rc<u8>, b: &Rc<u8>) -> std::cmp::Ordering {
+                a.cmp(b)
+            }
+        }
+
+        shared::<BlockArena>();
+        shared::<SkipList<String, u64>>();
+        shared::<SkipList<String, u64, DefaultComparator<String>, Arc<BlockArena>>>();
+        shared::<SkipList<u64, Vec<u8>, DefaultComparator<u64>, DefaultAllocator>>();
+
+        <SkipList<Rc<u8>, u64, RcOrder> as NotSend<_>>::check();
+        <SkipList<Rc<u8>, u64, RcOrder> as NotSync<_>>::check();
+        <SkipList<u64, Rc<u8>> as NotSend<_>>::check();
+        <SkipList<u64, Rc<u8>> as NotSync<_>>::check();
+        // `Cell` can move but not be shared, and the list asks both of its entries
+        <SkipList<u64, Cell<u64>> as NotSend<_>>::check();
+        <SkipList<u64, Cell<u64>> as NotSync<_>>::check();
+    }
+
+    #[test]
+    fn guards_keep_entries_alive() {
+        let list = SkipList::<_, _>::default_arc();
+        for i in 0..10 {
+            list.insert(i.to_string(), vec![i; 3]);
+        }
+        assert_eq!(
+            list.get_guard(&"3".to_string()).as_deref(),
+            Some(&vec![3; 3])
+        );
+        assert!(list.get_guard(&"10".to_string()).is_none());
+        let entry = list.get_key_value(&"4".to_string()).unwrap();
+        assert!(std::ptr::eq(entry.key(), list.entries().nth(4).unwrap().0));
+        assert_eq!(format!("{entry:?}"), r#"EntryRef("4", [4, 4, 4])"#);
+        assert_eq!(format!("{:?}", entry.into_value()), "[4, 4, 4]");
+
+        // the cursor's entries hold on to the list after it and every handle are gone
+        let mut iter = list.iter();
+        iter.seek(&"7".to_string());
+        let seventh = iter.entry().unwrap();
+        iter.next();
+        let eighth = iter.entry().unwrap().into_value();
+        drop((iter, list));
+        assert_eq!(
+            (seventh.key().as_str(), seventh.value()),
+            ("7", &vec![7; 3])
+        );
+        assert_eq!(*eighth, [8; 3]);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    #[cfg_attr(
+        miri,
+        ignore = "rayon's pool threads outlive the test, and crossbeam-epoch fails stacked borrows"
+    )]
+    fn par_build_matches_sequential() {
+        use rand::seq::SliceRandom;
+
+        const COUNT: usize = if cfg!(miri) { 200 } else { 100_000 };
+
+        // keys on `level`, in order
+        fn level_keys<V, A: MemAllocator>(
+            list: &SkipList<usize, V, DefaultComparator<usize>, A>,
+            level: usize,
+        ) -> Vec<usize> {
+            let mut keys = vec![];
+            unsafe {
+                let mut cur = Node::get_next(list.head.as_ptr(), level);
+                while !cur.is_null() {
+                    keys.push(*Node::key(cur));
+                    cur = Node::get_next(cur, level);
+                }
+            }
+            keys
+        }
+
+        let mut entries: Vec<_> = (0..COUNT).map(|i| (i * 3, i)).collect();
+        entries.shuffle(&mut StdRng::seed_from_u64(1));
+        let inserted = SkipList::<_, _>::default();
+        for &(key, value) in &entries {
+            inserted.insert(key, value);
+        }
+        let built = SkipList::par_build(
+            entries.clone(),
+            DefaultComparator::default(),
+            BlockArena::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            built.entries().collect::<Vec<_>>(),
+            inserted.entries().collect::<Vec<_>>()
+        );
+
+        // tower for tower what the sequential bulk build makes of the sorted entries
+        let mut sorted = entries.clone();
+        sorted.sort();
+        let sequential = SkipList::build_sorted(
+            DefaultComparator::default(),
+            BlockArena::new(),
+            SkipListOptions::default(),
+            sorted,
+        )
+        .unwrap();
+        for level in 0..built.options.max_height {
+            assert_eq!(level_keys(&built, level), level_keys(&sequential, level));
+        }
+        assert_eq!(built.height(), sequential.height());
+        assert_eq!(built.len(), COUNT);
+        assert_eq!(built.validate(), Ok(()));
+        assert_eq!(Arc::new(built).snapshot().seq(), COUNT as u64);
+
+        entries.push((3, 0));
+        assert!(matches!(
+            SkipList::par_build(entries, DefaultComparator::default(), BlockArena::new()),
+            Err(NodeError::KeyExists)
+        ));
+        let empty = SkipList::<usize, usize, _, _>::par_build(
+            vec![],
+            DefaultComparator::default(),
+            BlockArena::new(),
+        )
+        .unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.get(&0), None);
+    }
+
+    #[test]
+    fn watermark_from_list() {
+        let list = SkipList::new(DefaultComparator::default(), Arc::new(BlockArena::new()));
+        let hit = Arc::new(AtomicUsize::new(0));
+        let h = hit.clone();
+        list.on_watermark(64 * 1024, move |usage| {
+            h.store(usage, SeqCst);
+        });
+
+        let mut i = 0;
+        while hit.load(SeqCst) == 0 {
+            list.insert(i, i);
+            i += 1;
+        }
+        assert!(hit.load(SeqCst) >= 64 * 1024);
+        assert!(list.mem_usage() >= 64 * 1024);
+    }
+
+    #[test]
+    fn uninit_blocks() {
+        const COUNT: usize = if cfg!(miri) { 1_000 } else { 10_000 };
+
+        let list = Arc::new(SkipList::new(
+            DefaultComparator::default(),
+            BlockArena::with_uninit_blocks(),
+        ));
+        for i in (0..COUNT).rev() {
+            list.insert(i, i);
+        }
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for i in 0..COUNT {
+            assert_eq!(iter.key(), Some(&i));
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+    }
+
+    #[test]
+    fn borrowed_arena() {
+        let arena = BlockArena::new();
+        {
+            let list = SkipList::new(DefaultComparator::default(), &arena);
+            for i in 0..100 {
+                list.insert(i, i);
+            }
+            assert_eq!(list.mem_usage(), arena.memory_usage());
+        }
+        assert!(arena.memory_usage() > 0);
+    }
+
+    #[test]
+    fn failed_insert_leaves_list_intact() {
+        let list = Arc::new(SkipList::new(
+            DefaultComparator::default(),
+            FaultInjector::new(BlockArena::new()),
+        ));
+        for i in 0..100 {
+            list.insert(i * 2, i);
+        }
+
+        let usage = list.mem_usage();
+        list.a.fail_after(0);
+        assert!(list.try_insert(51, 0).is_err());
+        assert_eq!(list.mem_usage(), usage);
+
+        list.a.never_fail();
+        list.try_insert(51, 0).unwrap();
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        let mut keys = Vec::new();
+        while iter.is_valid() {
+            keys.push(*iter.key().unwrap());
+            iter.next();
+        }
+        let mut expected: Vec<_> = (0..100).map(|i| i * 2).collect();
+        expected.insert(26, 51);
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn node_layouts() {
+        let full = Node::<u64, u64>::get_layout(MAX_HEIGHT).unwrap();
+        assert_eq!(full.size(), size_of::<Node<u64, u64>>());
+        assert_eq!(full.align(), align_of::<Node<u64, u64>>());
+
+        // zero sized key and value: the sequence, the log pointer and the height, padded
+        // to the tower's alignment
+        let tower = Node::<(), ()>::get_layout(1).unwrap();
+        assert_eq!(tower.size(), size_of::<u64>() + size_of::<usize>() * 3);
+        let full = Node::<(), ()>::get_layout(MAX_HEIGHT).unwrap();
+        assert_eq!(
+            full.size(),
+            size_of::<u64>() + size_of::<usize>() * (MAX_HEIGHT + 2)
+        );
+
+        // the height fits in the padding behind a key and value that leave some
+        let small = Node::<u32, u16>::get_layout(3).unwrap();
+        assert_eq!(small.size(), size_of::<u64>() + size_of::<usize>() * 5);
+    }
+
+    #[test]
+    fn boxed_dyn_allocator() {
+        let allocators: Vec<Box<dyn MemAllocator + Send + Sync>> = vec![
+            Box::new(BlockArena::new()),
+            Box::new(DefaultAllocator::default()),
+        ];
+        for a in allocators {
+            let list = Arc::new(SkipList::new(DefaultComparator::default(), a));
+            for i in (0..1000).rev() {
+                list.insert(i, i + 1);
+            }
+            assert_eq!(list.mem_usage(), list.a.mem_usage());
+            assert!(list.useful_mem_usage() > 0);
+
+            let mut iter = list.iter();
+            iter.seek_to_first();
+            for i in 0..1000 {
+                assert_eq!(iter.key(), Some(&i));
+                assert_eq!(iter.value(), Some(&(i + 1)));
+                iter.next();
+            }
+            assert!(!iter.is_valid());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "too slow under Miri; loom_tests cover the interleavings"
+    )]
+    fn concurrent_insert_and_read() {
+        const WRITERS: usize = 4;
+        const PER_WRITER: usize = 20_000;
+
+        let list = SkipList::<_, _>::default_arc();
+        let done = Arc::new(AtomicUsize::new(0));
+
+        std::thread::scope(|s| {
+            for w in 0..WRITERS {
+                let list = &list;
+                let done = &done;
+                s.spawn(move || {
+                    // interleaved keys so writers keep racing for the same predecessors
+                    for i in 0..PER_WRITER {
+                        let key = i * WRITERS + w;
+                        list.insert(key, [key; 4]);
+                    }
+                    done.fetch_add(1, SeqCst);
+                });
+            }
+
+            for _ in 0..2 {
+                s.spawn(|| {
+                    while done.load(SeqCst) < WRITERS {
+                        let mut iter = list.iter();
+                        iter.seek_to_first();
+                        let mut last = None;
+                        while iter.is_valid() {
+                            let key = *iter.key().unwrap();
+                            assert_eq!(iter.value(), Some(&[key; 4]));
+                            assert!(last < Some(key));
+                            last = Some(key);
+                            iter.next();
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        for key in 0..WRITERS * PER_WRITER {
+            assert_eq!(iter.key(), Some(&key));
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+    }
+
+    thread_local! {
+        static DROPS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Tracked(usize);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            DROPS.set(DROPS.get() + 1);
+        }
+    }
+
+    #[test]
+    fn drop_runs_each_destructor_once() {
+        let tracker = TrackingAllocator::new(BlockArena::new());
+        {
+            let mut list = SkipList::new(DefaultComparator::default(), &tracker);
+            for i in 0..100 {
+                list.insert(i, Tracked(i));
+            }
+            for i in 0..10 {
+                let (key, value) = list.pop_first().unwrap();
+                assert_eq!((key, value.0), (i, i));
+            }
+            assert_eq!(DROPS.get(), 10);
+        }
+        assert_eq!(DROPS.get(), 100);
+        // the remaining nodes and the head are left to the allocator
+        tracker.assert_no_leaks_except(91);
+
+        drop(SkipList::<usize, Tracked, _, _>::new(
+            DefaultComparator::default(),
+            BlockArena::new(),
+        ));
+        assert_eq!(DROPS.get(), 100);
+    }
+
+    #[test]
+    fn string_entries() {
+        let mut list = SkipList::<_, _>::default();
+        for i in (0..50).rev() {
+            list.insert(format!("key{i:02}"), i.to_string());
+        }
+        assert_eq!(
+            list.pop_first(),
+            Some(("key00".to_string(), "0".to_string()))
+        );
+
+        let list = Arc::new(list);
+        let mut iter = list.iter();
+        iter.seek(&"key10".to_string());
+        assert_eq!(iter.key().map(String::as_str), Some("key10"));
+        assert_eq!(iter.value().map(String::as_str), Some("10"));
+    }
+
+    #[test]
+    fn seeded_lists_repeat() {
+        const COUNT: u64 = if cfg!(miri) { 300 } else { 10_000 };
+
+        // every node's key and height, in order
+        fn towers(seed: u64) -> Vec<(u64, usize)> {
+            let list = SkipList::<_, _>::default().with_rng(StdRng::seed_from_u64(seed));
+            for i in 0..COUNT {
+                list.insert(i.wrapping_mul(7_919) % COUNT, i);
+            }
+            let mut towers = vec![];
+            unsafe {
+                let mut cur = Node::get_next(list.head.as_ptr(), 0);
+                while !cur.is_null() {
+                    towers.push((*Node::key(cur), Node::height(cur)));
+                    cur = Node::get_next(cur, 0);
+                }
+            }
+            towers
+        }
+
+        // other lists drawing from this thread's generator in between change nothing
+        let first = towers(9);
+        seed_height_rng(1);
+        random_height(4, 20, next_random);
+        assert_eq!(towers(9), first);
+        assert_ne!(towers(10), first);
+    }
+
+    #[test]
+    fn heights_without_os_entropy() {
+        let mut reference = SplitMix64::new(0);
+        assert_eq!(
+            [(); 3].map(|_| reference.next_u64()),
+            [
+                0xe220_a839_7b1d_cdaf,
+                0x6e78_9e6a_a1b9_65f4,
+                0x06c4_5d18_8009_454f
+            ]
+        );
+        assert_ne!(
+            SplitMix64::default().next_u64(),
+            SplitMix64::default().next_u64()
+        );
+
+        let count = if cfg!(miri) { 300 } else { 10_000 };
+        let list = SkipList::<_, _>::default().with_rng(SplitMix64::default());
+        for i in 0..count {
+            list.insert(i, ());
+        }
+        list.validate().unwrap();
+        assert!(list.height() > 1);
+
+        // a fresh thread seeds its generator on first use, from the OS or, with
+        // `no-os-rand`, the way wasm32 does
+        thread::spawn(move || {
+            let list = SkipList::<_, _>::default();
+            for i in 0..count {
+                list.insert(i, ());
+            }
+            list.validate().unwrap();
+            assert!(list.height() > 1);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn quantile_estimates() {
+        // most of the list is skipped, but under Miri it still has to be built
+        const N: u32 = if cfg!(miri) { 3000 } else { 100_000 };
+
+        let list = SkipList::<_, _>::default().with_rng(StdRng::seed_from_u64(11));
+        assert!(list.estimate_quantiles(4).is_empty());
+        for i in 0..N {
+            list.insert((u64::from(i) * 7919 % u64::from(N)) as u32, ());
+        }
+        for q in [2, 4, 10] {
+            let splits = list.estimate_quantiles(q);
+            assert_eq!(splits.len(), q - 1);
+            assert!(splits.is_sorted());
+            for (i, &&key) in splits.iter().enumerate() {
+                let exact = (i as u32 + 1) * N / q as u32;
+                assert!(
+                    exact.abs_diff(key) < N / 10,
+                    "{q}-quantile {i}: {key} vs {exact}"
+                );
+            }
+        }
+        assert!(list.estimate_quantiles(1).is_empty());
+        assert!(list.estimate_quantiles(0).is_empty());
+
+        let tiny = SkipList::<_, _>::default();
+        for i in 0..3 {
+            tiny.insert(i, ());
+        }
+        assert_eq!(tiny.estimate_quantiles(2), [&1]);
+        assert_eq!(tiny.estimate_quantiles(3), [&1, &2]);
+        assert_eq!(tiny.estimate_quantiles(10), [&1, &2]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "statistics over safe code, too slow under Miri")]
+    fn height_distribution() {
+        const SAMPLES: usize = 400_000;
+
+        seed_height_rng(42);
+        let first: Vec<_> = (0..100)
+            .map(|_| random_height(4, 20, next_random))
+            .collect();
+        seed_height_rng(42);
+        assert_eq!(
+            (0..100)
+                .map(|_| random_height(4, 20, next_random))
+                .collect::<Vec<_>>(),
+            first
+        );
+
+        for branching in [2, 3, 4, 8] {
+            let mut at_least = [0_usize; MAX_HEIGHT + 1];
+            for _ in 0..SAMPLES {
+                let h = random_height(branching, MAX_HEIGHT, next_random);
+                assert!((1..=MAX_HEIGHT).contains(&h));
+                for n in at_least.iter_mut().take(h + 1) {
+                    *n += 1;
+                }
+            }
+            // P(height >= h) = (1 / branching)^(h - 1)
+            for (h, &got) in at_least.iter().enumerate().take(5).skip(2) {
+                let expected = SAMPLES as f64 / (branching as f64).powi(h as i32 - 1);
+                let got = got as f64;
+                assert!(
+                    (got - expected).abs() < expected * 0.1,
+                    "branching {branching}, height {h}: {got} vs {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "too slow under Miri; the unsafe paths are covered by the other tests"
+    )]
+    fn custom_branching() {
+        for branching in [2, 3, 16] {
+            let options = SkipListOptions {
+                branching,
+                ..Default::default()
+            };
+            let list =
+                SkipList::with_options(DefaultComparator::default(), BlockArena::new(), options);
+            for i in (0..5_000).rev() {
+                list.insert(i, i);
+            }
+            let (compacted, _) = list.compact_into(BlockArena::new()).unwrap();
+            assert_eq!(compacted.options(), options);
+
+            for list in [Arc::new(list), Arc::new(compacted)] {
+                let mut iter = list.iter();
+                iter.seek(&1234);
+                assert_eq!(iter.key(), Some(&1234));
+                iter.seek_to_first();
+                for i in 0..5_000 {
+                    assert_eq!(iter.key(), Some(&i));
+                    iter.next();
+                }
+                assert!(!iter.is_valid());
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "too slow under Miri; the unsafe paths are covered by the other tests"
+    )]
+    fn configured_max_height() {
+        for max_height in [1, 4, 32] {
+            let options = SkipListOptions {
+                branching: 2,
+                max_height,
+                ..Default::default()
+            };
+            let mut list =
+                SkipList::with_options(DefaultComparator::default(), BlockArena::new(), options);
+            for i in (0..2_000).rev().chain(2_000..4_000) {
+                list.insert(i, i);
+            }
+            assert!(list.height() <= max_height);
+            assert_eq!(unsafe { Node::height(list.head.as_ptr()) }, max_height);
+
+            assert_eq!(list.pop_first(), Some((0, 0)));
+            let (compacted, _) = list.compact_into(BlockArena::new()).unwrap();
+            assert!(compacted.height() <= max_height);
+
+            for list in [Arc::new(list), Arc::new(compacted)] {
+                let mut iter = list.iter();
+                iter.seek_to_first();
+                for i in 1..4_000 {
+                    assert_eq!(iter.key(), Some(&i));
+                    iter.next();
+                }
+                assert!(!iter.is_valid());
+
+                iter.seek_to_last();
+                assert_eq!(iter.key(), Some(&3_999));
+                iter.prev();
+                assert_eq!(iter.key(), Some(&3_998));
+                iter.seek(&2_500);
+                assert_eq!(iter.value(), Some(&2_500));
+            }
+        }
+    }
+
+    struct CountingComparator(AtomicUsize);
+
+    // lets other threads in between a search and its CAS, even on a single core
+    struct YieldingComparator;
+
+    impl Comparator for YieldingComparator {
+        type Item = usize;
+
+        fn compare(&self, a: &usize, b: &usize) -> std::cmp::Ordering {
+            std::thread::yield_now();
+            a.cmp(b)
+        }
+    }
+
+    impl Comparator for CountingComparator {
+        type Item = usize;
+
+        fn compare(&self, a: &usize, b: &usize) -> std::cmp::Ordering {
+            self.0.fetch_add(1, Relaxed);
+            a.cmp(b)
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "too slow under Miri; loom_tests cover the interleavings"
+    )]
+    fn contended_insert_comparisons() {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 10_000;
+
+        let list = SkipList::new(CountingComparator(AtomicUsize::new(0)), BlockArena::new());
+        std::thread::scope(|s| {
+            for t in 0..THREADS {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        list.insert(i * THREADS + t, i);
+                    }
+                });
+            }
+        });
+
+        let per_insert = list.c.0.load(Relaxed) as f64 / (THREADS * PER_THREAD) as f64;
+        assert!(per_insert < 40.0, "{per_insert} comparisons per insert");
+    }
+
+    #[test]
+    fn insert_compares_each_node_once() {
+        const COUNT: usize = if cfg!(miri) { 300 } else { 10_000 };
+
+        struct RecordingComparator(Mutex<Vec<usize>>);
+
+        impl Comparator for RecordingComparator {
+            type Item = usize;
+
+            // inserts compare a node's key (`a`) against the new key
+            fn compare(&self, a: &usize, b: &usize) -> std::cmp::Ordering {
+                self.0.lock().unwrap().push(*a);
+                a.cmp(b)
+            }
+        }
+
+        let list = SkipList::new(
+            RecordingComparator(Mutex::new(Vec::new())),
+            BlockArena::new(),
+        )
+        .with_rng(StdRng::seed_from_u64(3));
+        for i in 0..COUNT {
+            let key = i.wrapping_mul(7_919) % COUNT;
+            list.insert(key, i);
+
+            let mut compared = std::mem::take(&mut *list.c.0.lock().unwrap());
+            let calls = compared.len();
+            compared.sort_unstable();
+            compared.dedup();
+            assert_eq!(
+                compared.len(),
+                calls,
+                "inserting {key} compared a node twice"
+            );
+        }
+    }
+
+    #[test]
+    fn debug_is_a_summary() {
+        const COUNT: u32 = if cfg!(miri) { 200 } else { 100_000 };
+
+        let list = SkipList::<_, _>::default_arc();
+        assert!(format!("{list:?}").contains("len: 0, height: 1,"));
+        assert!(format!("{list:?}").ends_with("first: None, last: None, entries: {} }"));
+        for i in [3, 1, 2] {
+            list.insert(i, i * 10);
+        }
+        let small = format!("{list:?}");
+        assert!(small.contains("first: Some(1), last: Some(3), entries: {1: 10, 2: 20, 3: 30} }"));
+
+        for i in 4..COUNT {
+            list.insert(i, i * 10);
+        }
+        let big = format!("{list:?}");
+        assert!(big.contains(&format!("len: {},", COUNT - 1)), "{big}");
+        assert!(
+            big.ends_with(
+                "entries: {1: 10, 2: 20, 3: 30, 4: 40, 5: 50, 6: 60, 7: 70, 8: 80, ..} }"
+            )
+        );
+        assert!(!big.contains("levels"));
+        // one count per level, starting with every entry on level 0
+        let pretty = format!("{list:#?}");
+        let levels = pretty.split("levels: [").nth(1).unwrap();
+        let counts: Vec<usize> = levels
+            .split(']')
+            .next()
+            .unwrap()
+            .split(',')
+            .filter_map(|count| count.trim().parse().ok())
+            .collect();
+        assert_eq!(counts.len(), list.height());
+        assert_eq!(counts[0], COUNT as usize - 1);
+        assert!(counts.is_sorted_by(|a, b| a >= b));
+
+        let mut iter = list.iter();
+        assert_eq!(
+            format!("{iter:?}"),
+            "SkipListIter { valid: false, key: None }"
+        );
+        iter.seek(&2);
+        assert_eq!(
+            format!("{iter:?}"),
+            "SkipListIter { valid: true, key: Some(2) }"
+        );
+    }
+
+    #[test]
+    fn dump_draws_the_towers() {
+        let list = SkipList::from_sorted_iter(
+            (1..=16_u32).map(|i| (i, ())),
+            DefaultComparator::default(),
+            BlockArena::new(),
+        );
+        assert_eq!(
+            list.dump_structure(5),
+            " 2 head --------------------------> ...\n\
+             \x201 head ----------------> 4 ------> ...\n\
+             \x200 head -> 1 -> 2 -> 3 -> 4 -> 5 -> ...\n\
+             nodes: 0:16 1:4 2:1\n"
+        );
+        assert_eq!(
+            list.dump_structure_around(&10, 4),
+            " 2 head -----------------------> ...\n\
+             \x201 ...  -> 8 ------------------> ...\n\
+             \x200 ...  -> 8 -> 9 -> 10 -> 11 -> ...\n\
+             nodes: 0:16 1:4 2:1\n"
+        );
+        assert!(
+            list.dump_structure_around(&100, 2)
+                .starts_with(" 2 head -------> 16 -> nil\n")
+        );
+        assert_eq!(
+            SkipList::<u32, ()>::default().dump_structure(5),
+            " 0 head -> nil\nnodes: 0:0\n"
+        );
+
+        let long = SkipList::<_, _>::default();
+        long.insert("a very long key", ());
+        assert!(
+            long.dump_structure(1)
+                .contains("head -> \"a very lon~ -> nil")
+        );
+
+        unsafe {
+            // a node that is tall enough for level 1 but not linked on it
+            let head = list.head.as_ptr();
+            let four = Node::get_next(head, 1);
+            Node::set_next(head, 1, Node::get_next(four, 1));
+            let dump = list.dump_structure(5);
+            assert!(
+                dump.contains("\n 1 head ----------------- ? ------> ...\n"),
+                "{dump}"
+            );
+            Node::set_next(head, 1, four);
+        }
+    }
+
+    #[test]
+    fn dot_matches_the_golden_file() {
+        let list = SkipList::<_, _>::default().with_rng(StdRng::seed_from_u64(5));
+        for i in [5, 3, 8, 1, 7, 2, 6, 4_u32] {
+            list.insert(i, ());
+        }
+        let dot = list.to_dot(6);
+        // `BLESS=1 cargo test` rewrites the file after a deliberate change
+        if std::env::var_os("BLESS").is_some() {
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/to_dot.dot");
+            std::fs::write(path, &dot).unwrap();
+        }
+        assert_eq!(dot, include_str!("../testdata/to_dot.dot"));
+
+        let window = list.to_dot_from(&4, 2);
+        assert!(window.contains("n0 [label=\"4|h "), "{window}");
+        assert!(window.contains("n1 [label=\"5|h "), "{window}");
+        assert!(window.contains("    before -> n0 [label=\"0\""), "{window}");
+        assert!(window.contains("    n1 -> after [label=\"0\""), "{window}");
+        assert!(!list.to_dot_from(&9, 2).contains("->"));
+
+        let quoted = SkipList::<_, _>::default();
+        quoted.insert("a|b".to_string(), ());
+        assert!(quoted.to_dot(1).contains(r#"n0 [label="\"a\|b\"|h "#));
+    }
+
+    #[test]
+    fn stats_follow_the_branching_factor() {
+        let list = SkipList::from_sorted_iter(
+            (1..=16_u32).map(|i| (i, ())),
+            DefaultComparator::default(),
+            BlockArena::new(),
+        );
+        let stats = list.stats();
+        assert_eq!((stats.height, stats.len, stats.sampled_nodes), (3, 16, 16));
+        assert_eq!(stats.level_counts, [16, 4, 1]);
+        assert_eq!(stats.mean_height, 21.0 / 16.0);
+        assert_eq!(stats.mem_usage, list.mem_usage());
+        // few enough nodes that sampling reads them all
+        assert_eq!(list.stats_sampled(16), stats);
+        let empty = SkipList::<u32, ()>::default().stats();
+        assert_eq!((empty.level_counts, empty.mean_height), (vec![0], 0.0));
+
+        const COUNT: u64 = if cfg!(miri) { 2_000 } else { 100_000 };
+        const SAMPLES: usize = if cfg!(miri) { 500 } else { 1_000 };
+        for branching in [2, 4] {
+            let options = SkipListOptions {
+                branching,
+                ..SkipListOptions::default()
+            };
+            let list =
+                SkipList::with_options(DefaultComparator::default(), BlockArena::new(), options)
+                    .with_rng(StdRng::seed_from_u64(3));
+            for i in 0..COUNT {
+                list.insert(i.wrapping_mul(0x9e37_79b9_7f4a_7c15), ());
+            }
+            let stats = list.stats();
+            assert_eq!(stats.len, COUNT as usize);
+            assert_eq!(stats.level_counts[0], stats.len);
+            let p = 1.0 / branching as f64;
+            for pair in stats.level_counts.windows(2) {
+                if pair[0] >= 500 {
+                    let ratio = pair[1] as f64 / pair[0] as f64;
+                    assert!((ratio - p).abs() < 0.05, "{branching}: {stats:?}");
+                }
+            }
+            let mean = branching as f64 / (branching as f64 - 1.0);
+            // about four standard deviations
+            let slack = 4.0 / (COUNT as f64).sqrt();
+            assert!((stats.mean_height - mean).abs() < slack, "{stats:?}");
+            #[cfg(feature = "counters")]
+            assert!(stats.comparisons > 0);
+
+            let sampled = list.stats_sampled(SAMPLES);
+            assert!(sampled.sampled_nodes < 3 * SAMPLES, "{sampled:?}");
+            assert_eq!(sampled.height, stats.height);
+            assert_eq!(sampled.level_counts[0], stats.len);
+            for (estimate, exact) in sampled.level_counts.iter().zip(&stats.level_counts) {
+                if *exact <= SAMPLES {
+                    assert_eq!(estimate, exact);
+                } else {
+                    assert!(
+                        estimate.abs_diff(*exact) <= exact / 5,
+                        "{sampled:?} {stats:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn duplicate_keys_are_rejected() {
+        let tracked = Arc::new(());
+        let list = SkipList::<_, _>::default().with_write_buffer_size(1 << 20);
+        for i in 0..100 {
+            list.insert(i, tracked.clone());
+        }
+        let usage = list.write_buffer_usage();
+
+        for i in [0, 50, 99] {
+            assert_eq!(
+                list.try_insert(i, tracked.clone()),
+                Err(NodeError::KeyExists)
+            );
+        }
+        // the rejected nodes dropped their values and gave back their charge
+        assert_eq!(Arc::strong_count(&tracked), 101);
+        assert_eq!(list.write_buffer_usage(), usage);
+        assert_eq!(list.len(), 100);
+        assert_eq!(list.validate(), Ok(()));
+    }
+
+    #[test]
+    fn racing_inserts_of_equal_keys() {
+        const THREADS: usize = 8;
+        const KEYS: usize = 10;
+        const ROUNDS: usize = if cfg!(miri) { 2 } else { 200 };
+
+        for round in 0..ROUNDS {
+            let list = SkipList::new(YieldingComparator, BlockArena::new());
+            let wins: Vec<Vec<usize>> = std::thread::scope(|s| {
+                let threads: Vec<_> = (0..THREADS)
+                    .map(|t| {
+                        let list = &list;
+                        s.spawn(move || {
+                            // every thread starts at another key, so they collide everywhere
+                            (0..KEYS)
+                                .map(|i| (i + t + round) % KEYS)
+                                .filter(|&key| match list.try_insert(key, t) {
+                                    Ok(()) => true,
+                                    Err(NodeError::KeyExists) => false,
+                                    Err(e) => panic!("{e}"),
+                                })
+                                .collect()
+                        })
+                    })
+                    .collect();
+                threads.into_iter().map(|t| t.join().unwrap()).collect()
+            });
+
+            let mut won: Vec<_> = wins.iter().flatten().copied().collect();
+            won.sort_unstable();
+            assert_eq!(won, (0..KEYS).collect::<Vec<_>>(), "round {round}");
+            assert_eq!(
+                list.entries().map(|(&k, _)| k).collect::<Vec<_>>(),
+                (0..KEYS).collect::<Vec<_>>()
+            );
+            // each key holds the value of the thread that won it
+            for (t, keys) in wins.iter().enumerate() {
+                for key in keys {
+                    assert_eq!(list.get(key), Some(&t));
+                }
+            }
+            assert_eq!(list.len(), KEYS);
+            assert_eq!(list.validate(), Ok(()));
+        }
+    }
+
+    fn snapshot_keys<C: Comparator<Item = usize>>(
+        snapshot: &Snapshot<usize, usize, C, BlockArena>,
+    ) -> Vec<usize> {
+        let mut keys = vec![];
+        let mut iter = snapshot.iter();
+        iter.seek_to_first();
+        while iter.is_valid() {
+            keys.push(*iter.key().unwrap());
+            iter.next();
+        }
+        keys
+    }
+
+    #[test]
+    fn snapshot_is_stable_under_inserts() {
+        const WRITERS: usize = 4;
+        const PER_WRITER: usize = if cfg!(miri) { 25 } else { 5_000 };
+
+        let list = Arc::new(SkipList::new(YieldingComparator, BlockArena::new()));
+        for key in (0..100).map(|i| i * 2) {
+            list.insert(key, key);
+        }
+        let before = list.snapshot();
+        assert_eq!(before.seq(), 100);
+
+        let (during, seen) = std::thread::scope(|s| {
+            for w in 0..WRITERS {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..PER_WRITER {
+                        let key = 201 + 2 * (i * WRITERS + w);
+                        list.insert(key, key);
+                    }
+                });
+            }
+            while list.len() < 100 + WRITERS * PER_WRITER / 2 {
+                std::thread::yield_now();
+            }
+            let during = list.snapshot();
+            let seen = snapshot_keys(&during);
+            // the writers keep going, the snapshot does not change
+            for _ in 0..3 {
+                assert_eq!(snapshot_keys(&during), seen);
+            }
+            (during, seen)
+        });
+
+        // everything inserted before the snapshot and nothing after the writers stopped
+        assert!(seen.len() >= 100 + WRITERS * PER_WRITER / 2);
+        assert_eq!(snapshot_keys(&during), seen);
+        assert_eq!(
+            snapshot_keys(&before),
+            (0..100).map(|i| i * 2).collect::<Vec<_>>()
+        );
+        for (key, _) in list.entries() {
+            let visible = seen.binary_search(key).is_ok();
+            assert_eq!(during.get(key).is_some(), visible, "key {key}");
+            assert_eq!(before.get(key).is_some(), key % 2 == 0);
+        }
+        // a writer and a snapshot stamping the same node agree on one number
+        assert_eq!(list.snapshot().seq(), list.len() as u64);
+        assert_eq!(snapshot_keys(&list.snapshot()).len(), list.len());
+    }
+
+    #[test]
+    fn overlapping_snapshots() {
+        let list = SkipList::<_, _>::default_arc();
+        let mut snapshots = vec![list.snapshot()];
+        for round in 0..4 {
+            // each round lands between the keys of the ones before
+            for i in 0..10 {
+                let key = i * 16 + (16 >> round) % 16;
+                list.insert(key, round);
+            }
+            snapshots.push(list.snapshot());
+        }
+
+        let keys: Vec<_> = snapshots.iter().map(snapshot_keys).collect();
+        for (i, keys) in keys.iter().enumerate() {
+            assert_eq!(keys.len(), i * 10);
+            assert!(keys.is_sorted());
+        }
+        for pair in keys.windows(2) {
+            assert!(pair[0].iter().all(|key| pair[1].contains(key)));
+        }
+
+        // backwards and from a seek the filter holds as well
+        let mut iter = snapshots[1].iter();
+        iter.seek_to_last();
+        assert_eq!(iter.key(), Some(&144));
+        iter.prev();
+        assert_eq!(iter.key(), Some(&128));
+        iter.seek(&1);
+        assert_eq!(iter.key(), Some(&16));
+        assert_eq!(snapshots[2].get(&8), Some(&1));
+        assert_eq!(snapshots[1].get(&8), None);
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "too slow under Miri; loom_tests cover the interleavings"
+    )]
+    fn seek_while_height_grows() {
+        let options = SkipListOptions {
+            branching: 2,
+            max_height: MAX_HEIGHT,
+            ..Default::default()
+        };
+        for _ in 0..50 {
+            let list = Arc::new(SkipList::with_options(
+                DefaultComparator::default(),
+                BlockArena::new(),
+                options,
+            ));
+            let done = AtomicUsize::new(0);
+            std::thread::scope(|s| {
+                for t in 0..2 {
+                    let list = &list;
+                    let done = &done;
+                    s.spawn(move || {
+                        // even keys only
+                        for i in 0..2_000 {
+                            list.insert((i * 2 + t) * 2, i);
+                        }
+                        done.fetch_add(1, SeqCst);
+                    });
+                }
+                for _ in 0..2 {
+                    s.spawn(|| {
+                        let mut iter = list.iter();
+                        let mut probe = 1;
+                        while done.load(SeqCst) < 2 {
+                            iter.seek(&probe);
+                            if let Some(&key) = iter.key() {
+                                assert!(key > probe && key % 2 == 0);
+                            }
+                            probe = ((probe + 6_007) % 8_000) | 1;
+                        }
+                    });
+                }
+            });
+            assert!(list.height() > 1);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "counters")]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn metrics_move_under_contention() {
+        let list = SkipList::new(CountingComparator(AtomicUsize::new(0)), BlockArena::new());
+        for i in 0..1_000 {
+            list.insert(i, i);
+        }
+        list.get(&500);
+        let alone = list.metrics();
+        assert_eq!(alone.cas_failures, 0);
+        assert_eq!(alone.searches, 1_001);
+        assert_eq!(alone.comparisons, list.c.0.load(Relaxed));
+        assert!(alone.search_steps > 0);
+
+        // yielding in the comparator lets the other writers fill a gap between a search
+        // and its CAS
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 2_000;
+        let list = SkipList::new(YieldingComparator, BlockArena::new());
+        std::thread::scope(|s| {
+            for t in 0..THREADS {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        list.insert(i * THREADS + t, i);
+                    }
+                });
+            }
+        });
+        let contended = list.metrics();
+        assert_eq!(contended.searches, THREADS * PER_THREAD);
+        assert!(contended.cas_failures > 0);
+        assert!(contended.re_searches <= contended.cas_failures);
+        assert!(contended.comparisons > alone.comparisons);
+        let snapshot = list.metrics_snapshot();
+        assert_eq!(snapshot.cas_retries, contended.cas_failures as u64);
+        assert_eq!(snapshot.inserts, (THREADS * PER_THREAD) as u64);
+    }
+
+    #[test]
+    #[cfg(feature = "counters")]
+    fn snapshot_counts_every_operation() {
+        let list = SkipList::<_, _>::default_arc();
+        let start = list.metrics_snapshot();
+        assert_eq!(
+            (start.inserts, start.gets, start.seeks, start.entries),
+            (0, 0, 0, 0)
+        );
+        assert_eq!(start.arena_reserved_bytes, list.mem_usage() as u64);
+
+        list.insert(1, 1);
+        list.insert(2, 2);
+        assert!(list.try_insert(2, 2).is_err());
+        let inserted = list.metrics_snapshot();
+        assert_eq!((inserted.inserts, inserted.gets, inserted.seeks), (3, 0, 0));
+        assert_eq!(inserted.entries, 2);
+        assert!(inserted.comparisons > start.comparisons);
+        assert!(inserted.arena_used_bytes > start.arena_used_bytes);
+
+        list.get(&1);
+        list.contains_key(&3);
+        list.get_guard(&2);
+        list.get_key_value(&2);
+        let got = list.metrics_snapshot();
+        assert_eq!((got.inserts, got.gets, got.seeks), (3, 4, 0));
+        assert!(got.comparisons > inserted.comparisons);
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        iter.seek_to_last();
+        iter.seek(&2);
+        iter.seek_for_prev(&2);
+        list.entries_at(&1).count();
+        let sought = list.metrics_snapshot();
+        assert_eq!((sought.inserts, sought.gets, sought.seeks), (3, 4, 5));
+        assert_eq!(sought.cas_retries, 0);
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "too slow under Miri; loom_tests cover the interleavings"
+    )]
+    fn len_during_concurrent_inserts() {
+        const WRITERS: usize = 4;
+        const PER_WRITER: usize = 500;
+        const TOTAL: usize = WRITERS * PER_WRITER;
+
+        let list = SkipList::<_, _>::default();
+        let done = AtomicUsize::new(0);
+        std::thread::scope(|s| {
+            for w in 0..WRITERS {
+                let (list, done) = (&list, &done);
+                s.spawn(move || {
+                    for i in 0..PER_WRITER {
+                        list.insert(i * WRITERS + w, i);
+                        assert!(list.len() > i, "a returned insert is not counted");
+                    }
+                    done.fetch_add(1, SeqCst);
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    while done.load(SeqCst) < WRITERS {
+                        let len = list.len();
+                        assert!(last <= len && len <= TOTAL, "{last} then {len}");
+                        assert!(list.entries().count() >= len, "counted an unlinked entry");
+                        last = len;
+                    }
+                });
+            }
+        });
+        assert_eq!(list.len(), TOTAL);
+        assert_eq!(list.approx_len(), TOTAL);
+    }
+
+    #[test]
+    fn adaptive_height_tracks_len() {
+        const CHECKPOINTS: [usize; 3] = [16, 256, if cfg!(miri) { 1_024 } else { 16_384 }];
+
+        fn tallest<C, A>(list: &SkipList<usize, usize, C, A>) -> usize {
+            let mut tallest = 0;
+            unsafe {
+                let mut cur = Node::get_next(list.head.as_ptr(), 0);
+                while !cur.is_null() {
+                    tallest = tallest.max(Node::height(cur));
+                    cur = Node::get_next(cur, 0);
+                }
+            }
+            tallest
+        }
+
+        let list = SkipList::<_, _>::default().with_rng(StdRng::seed_from_u64(5));
+        let mut seen = Vec::new();
+        let mut inserted = 0;
+        for n in CHECKPOINTS {
+            while inserted < n {
+                list.insert(inserted, inserted);
+                inserted += 1;
             }
+            assert_eq!(list.len(), n);
+            // the cap for the last insert is the highest any node got
+            let tallest = tallest(&list);
+            assert!(tallest <= super::log_ceil(n, 4) + 2, "{tallest} for {n}");
+            assert_eq!(list.height(), tallest);
+            seen.push(tallest);
         }
+        assert!(seen.is_sorted() && seen[0] < seen[2], "{seen:?}");
+
+        assert_eq!(super::log_ceil(1, 4), 0);
+        assert_eq!(super::log_ceil(17, 4), 3);
+        assert_eq!(super::log_ceil(27, 3), 3);
+        assert_eq!(super::log_ceil(28, 3), 4);
     }
-}
 
-// [1, MAX_HEIGHT]
-fn random_height() -> usize {
-    const UPGRADE: usize = 4;
-    let mut h = 1;
-    while h < MAX_HEIGHT && (rand::random::<u32>() as usize % UPGRADE) == 0 {
-        h += 1;
+    // what the nodes reachable on level 0 take, the way the write buffer counts them
+    fn node_bytes<C, A>(list: &SkipList<usize, usize, C, A>) -> usize {
+        let mut bytes = 0;
+        unsafe {
+            let mut cur = Node::get_next(list.head.as_ptr(), 0);
+            while !cur.is_null() {
+                bytes += Node::<usize, usize>::get_layout(Node::height(cur))
+                    .unwrap()
+                    .size();
+                cur = Node::get_next(cur, 0);
+            }
+        }
+        bytes
     }
-    h
-}
 
-pub struct SkipListIter<K, V, C, A> {
-    list: Arc<SkipList<K, V, C, A>>,
-    cur: *mut Node<K, V>,
-}
+    #[test]
+    fn write_buffer_fills_up() {
+        const BUDGET: usize = 16 * 1024;
 
-impl<K, V, C, A> SkipListIter<K, V, C, A>
-where
-    C: Comparator<Item = K>,
-    A: MemAllocator,
-{
-    pub fn new(list: Arc<SkipList<K, V, C, A>>) -> Self {
-        SkipListIter {
-            list,
-            cur: null_mut(),
+        let mut list = SkipList::<_, _>::default().with_write_buffer_size(BUDGET);
+        let mut inserted = 0;
+        let err = loop {
+            match list.try_insert(inserted, inserted) {
+                Ok(()) => inserted += 1,
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(err, NodeError::Stall(WriteStall::MemtableFull));
+        assert!(list.write_buffer_usage() >= BUDGET);
+        assert_eq!(list.write_buffer_usage(), node_bytes(&list));
+        assert_eq!(list.entries().count(), inserted);
+
+        // popping makes room again, though the node that crossed the budget may be taller
+        // than the first one popped
+        while list.write_buffer_usage() >= BUDGET {
+            list.pop_first();
         }
+        assert_eq!(list.write_buffer_usage(), node_bytes(&list));
+        list.insert(inserted, inserted);
+
+        let (compacted, _) = list.compact_into(BlockArena::new()).unwrap();
+        assert_eq!(compacted.write_buffer_usage(), node_bytes(&compacted));
+
+        // no budget, nothing is counted
+        let list = SkipList::<_, _>::default();
+        list.insert(1, 1);
+        assert_eq!(list.write_buffer_usage(), 0);
     }
 
-    pub fn is_valid(&self) -> bool {
-        !self.cur.is_null()
+    #[test]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn write_buffer_overshoot_under_contention() {
+        const BUDGET: usize = 256 * 1024;
+        const THREADS: usize = 4;
+
+        let list = SkipList::<_, _>::default().with_write_buffer_size(BUDGET);
+        std::thread::scope(|s| {
+            for t in 0..THREADS {
+                let list = &list;
+                s.spawn(move || {
+                    for i in 0.. {
+                        match list.try_insert(i * THREADS + t, i) {
+                            Ok(()) => {}
+                            Err(NodeError::Stall(WriteStall::MemtableFull)) => break,
+                            Err(e) => panic!("{e}"),
+                        }
+                    }
+                });
+            }
+        });
+
+        let tallest = Node::<usize, usize>::get_layout(list.options().max_height)
+            .unwrap()
+            .size();
+        let usage = list.write_buffer_usage();
+        assert!(usage >= BUDGET);
+        assert!(
+            usage < BUDGET + tallest,
+            "{usage} overshoots by more than a node"
+        );
+        assert_eq!(usage, node_bytes(&list));
     }
 
-    pub fn key(&self) -> Option<&K> {
-        if self.is_valid() {
-            unsafe { Some(&(*self.cur).key) }
-        } else {
-            None
+    #[test]
+    #[cfg_attr(miri, ignore = "too slow under Miri")]
+    fn backoff_settings() {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 2_000;
+
+        for backoff_spin_limit in [0, 1, 6] {
+            let options = SkipListOptions {
+                backoff_spin_limit,
+                ..Default::default()
+            };
+            let list = Arc::new(SkipList::with_options(
+                DefaultComparator::default(),
+                BlockArena::new(),
+                options,
+            ));
+            std::thread::scope(|s| {
+                for t in 0..THREADS {
+                    let list = &list;
+                    s.spawn(move || {
+                        for i in 0..PER_THREAD {
+                            list.insert(i * THREADS + t, i);
+                        }
+                    });
+                }
+            });
+
+            let mut iter = list.iter();
+            iter.seek_to_first();
+            for key in 0..THREADS * PER_THREAD {
+                assert_eq!(iter.key(), Some(&key));
+                iter.next();
+            }
+            assert!(!iter.is_valid());
         }
     }
 
-    pub fn value(&self) -> Option<&V> {
-        if self.is_valid() {
-            unsafe { Some(&(*self.cur).value) }
-        } else {
-            None
+    #[test]
+    fn validate_finds_corruption() {
+        let list = SkipList::<_, _>::default().with_rng(StdRng::seed_from_u64(7));
+        for i in 0..200 {
+            list.insert(i, i);
+        }
+        assert_eq!(list.validate(), Ok(()));
+
+        unsafe {
+            // unlink the first tall node from level 0 only
+            let mut prev = list.head.as_ptr();
+            let mut cur = Node::get_next(prev, 0);
+            while Node::height(cur) < 2 {
+                prev = cur;
+                cur = Node::get_next(cur, 0);
+            }
+            Node::set_next(prev, 0, Node::get_next(cur, 0));
+            assert_eq!(
+                list.validate(),
+                Err(InvariantViolation::NotBelow { level: 1, index: 0 })
+            );
+            Node::set_next(prev, 0, cur);
+            assert_eq!(list.validate(), Ok(()));
+
+            // link a short node on level 1
+            let head = list.head.as_ptr();
+            let mut short = Node::get_next(head, 0);
+            while Node::height(short) > 1 {
+                short = Node::get_next(short, 0);
+            }
+            let first = Node::get_next(head, 1);
+            Node::set_next(head, 1, short);
+            assert_eq!(
+                list.validate(),
+                Err(InvariantViolation::TooShort { level: 1, index: 0 })
+            );
+            Node::set_next(head, 1, first);
+
+            let second = Node::get_next(Node::get_next(list.head.as_ptr(), 0), 0);
+            addr_of_mut!((*second).key).write(MaybeUninit::new(0));
+            let err = list.validate().unwrap_err();
+            assert_eq!(err, InvariantViolation::OutOfOrder { index: 1 });
+            assert_eq!(list.violation_key(&err), Some(&0));
+            addr_of_mut!((*second).key).write(MaybeUninit::new(1));
+        }
+        assert_eq!(list.validate(), Ok(()));
+
+        // the keys the other violations point at
+        let tall = |level: usize| unsafe { Node::key(Node::get_next(list.head.as_ptr(), level)) };
+        let not_below = InvariantViolation::NotBelow { level: 1, index: 0 };
+        assert_eq!(list.violation_key(&not_below), Some(tall(1)));
+        let too_short = InvariantViolation::TooShort { level: 1, index: 1 };
+        assert!(list.violation_key(&too_short) > Some(tall(1)));
+        let past_the_end = InvariantViolation::TooShort {
+            level: 1,
+            index: 200,
+        };
+        assert_eq!(list.violation_key(&past_the_end), None);
+
+        // the head linked above the height
+        let height = list.height();
+        list.height.store(1, Relaxed);
+        let err = list.validate().unwrap_err();
+        assert_eq!(
+            err,
+            InvariantViolation::AboveHeight {
+                level: height - 1,
+                height: 1
+            }
+        );
+        assert_eq!(list.violation_key(&err), None);
+        list.height.store(height, Relaxed);
+
+        // a length that level 0 does not cover is wrong even with inserts in flight, one
+        // short of it only once they are done
+        list.len.store(201, Relaxed);
+        let err = list.validate().unwrap_err();
+        assert_eq!(
+            err,
+            InvariantViolation::LenMismatch {
+                len: 201,
+                linked: 200
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "the length is 201, but level 0 links 200 nodes"
+        );
+        list.len.store(199, Relaxed);
+        assert_eq!(list.validate(), Ok(()));
+        assert!(list.validate_counted(true).is_err());
+        list.len.store(200, Relaxed);
+        assert_eq!(list.validate_counted(true), Ok(()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dropped a broken skip list: the length is 0")]
+    fn tests_drop_only_valid_lists() {
+        let list = SkipList::<_, _>::default();
+        list.insert(1, ());
+        list.len.store(0, Relaxed);
+    }
+
+    #[test]
+    fn finger_seeks() {
+        // big enough that the finger saves more than half the comparisons with room to spare
+        // (about 2.4 times fewer under Miri, 3.7 times otherwise); the heights are seeded so
+        // the counts come out the same on every run
+        const COUNT: usize = if cfg!(miri) { 2_000 } else { 20_000 };
+
+        let list = Arc::new(
+            SkipList::new(CountingComparator(AtomicUsize::new(0)), BlockArena::new())
+                .with_rng(SplitMix64::new(7)),
+        );
+        for i in 0..COUNT {
+            list.insert(i * 2, i);
         }
+
+        // mostly forward with small jitter, plus a few jumps back and past the end
+        let mut trace: Vec<_> = (0..COUNT).map(|i| i * 2 + 3 - i % 5).collect();
+        trace.extend([7, COUNT * 2 + 10, 0, 1]);
+
+        let comparisons = |finger: bool| {
+            list.c.0.store(0, Relaxed);
+            let mut iter = if finger {
+                SkipListIter::with_finger(list.clone())
+            } else {
+                list.iter()
+            };
+            let keys: Vec<_> = trace
+                .iter()
+                .map(|key| {
+                    iter.seek(key);
+                    iter.key().copied()
+                })
+                .collect();
+            (keys, list.c.0.load(Relaxed))
+        };
+        let (plain, plain_comparisons) = comparisons(false);
+        let (fingered, finger_comparisons) = comparisons(true);
+        assert_eq!(plain, fingered);
+        assert!(finger_comparisons * 2 < plain_comparisons);
     }
+}
 
-    pub fn next(&mut self) {
-        assert!(self.is_valid());
-        self.cur = unsafe { (*self.cur).get_next(0) };
+// Random operation sequences run against the list and a `BTreeMap`; proptest shrinks a
+// failing sequence down to the few operations that matter. The key spaces are tiny so
+// duplicates, neighbouring keys and bounds that hit an entry come up all the time.
+#[cfg(all(test, not(any(loom, shuttle, miri))))]
+mod proptests {
+    use std::{
+        collections::{BTreeMap, btree_map::Entry},
+        fmt::Debug,
+        ops::{Bound, RangeBounds},
+        sync::Arc,
+    };
+
+    use proptest::{collection::vec, prelude::*};
+
+    use crate::{
+        arena::BlockArena,
+        comparator::{BytewiseComparator, Comparator, DefaultComparator},
+    };
+
+    use super::{NodeError, SkipList, SkipListIter};
+
+    #[derive(Debug, Clone)]
+    enum Op<K> {
+        Insert(K),
+        Get(K),
+        Seek(K),
+        SeekForPrev(K),
+        SeekToFirst,
+        SeekToLast,
+        Next,
+        Prev,
+        Range(Bound<K>, Bound<K>),
+        PopFirst,
     }
 
-    pub fn prev(&mut self) {
-        assert!(self.is_valid());
-        self.cur = self
-            .list
-            .find_near(Bound::Excluded(self.key().unwrap()), true);
+    fn ops<K: Clone + Debug>(
+        key: impl Strategy<Value = K> + Clone,
+    ) -> impl Strategy<Value = Vec<Op<K>>> {
+        let bound = prop_oneof![
+            key.clone().prop_map(Bound::Included),
+            key.clone().prop_map(Bound::Excluded),
+            Just(Bound::Unbounded),
+        ];
+        let op = prop_oneof![
+            6 => key.clone().prop_map(Op::Insert),
+            2 => key.clone().prop_map(Op::Get),
+            2 => key.clone().prop_map(Op::Seek),
+            2 => key.prop_map(Op::SeekForPrev),
+            1 => Just(Op::SeekToFirst),
+            1 => Just(Op::SeekToLast),
+            3 => Just(Op::Next),
+            3 => Just(Op::Prev),
+            2 => (bound.clone(), bound).prop_map(|(start, end)| Op::Range(start, end)),
+            1 => Just(Op::PopFirst),
+        ];
+        vec(op, 0..64)
     }
 
-    pub fn seek_to_first(&mut self) {
-        self.cur = self.list.find_first();
+    fn int_keys() -> impl Strategy<Value = u32> + Clone {
+        prop_oneof![
+            8 => 0..16u32,
+            1 => Just(u32::MAX),
+            1 => Just(u32::MAX - 1),
+        ]
     }
 
-    pub fn seek_to_last(&mut self) {
-        self.cur = self.list.find_last();
+    // the empty key, prefixes of each other and keys one byte apart
+    fn byte_keys() -> impl Strategy<Value = Vec<u8>> + Clone {
+        vec(b'a'..=b'c', 0..3)
     }
 
-    pub fn seek(&mut self, key: &K) {
-        self.cur = self.list.find_near(Bound::Included(key), false);
+    fn run<K, C>(c: C, fingered: bool, ops: Vec<Op<K>>) -> Result<(), TestCaseError>
+    where
+        K: Ord + Clone + Debug,
+        C: Comparator<Item = K>,
+    {
+        let new_iter = |list: &Arc<SkipList<K, u32, C, BlockArena>>| {
+            if fingered {
+                SkipListIter::with_finger(list.clone())
+            } else {
+                list.iter()
+            }
+        };
+        let mut list = Arc::new(SkipList::new(c, BlockArena::new()));
+        let mut model = BTreeMap::new();
+        let mut iter = new_iter(&list);
+        // the key the model's cursor is on
+        let mut cursor: Option<K> = None;
+
+        for (value, op) in (0u32..).zip(ops) {
+            match op {
+                Op::Insert(key) => {
+                    let result = list.try_insert(key.clone(), value);
+                    match model.entry(key) {
+                        Entry::Occupied(_) => prop_assert_eq!(result, Err(NodeError::KeyExists)),
+                        Entry::Vacant(entry) => {
+                            prop_assert_eq!(result, Ok(()));
+                            entry.insert(value);
+                        }
+                    }
+                }
+                Op::Get(key) => prop_assert_eq!(list.get(&key), model.get(&key)),
+                Op::Seek(key) => {
+                    iter.seek(&key);
+                    cursor = model.range(key..).next().map(|(k, _)| k.clone());
+                }
+                Op::SeekForPrev(key) => {
+                    iter.seek_for_prev(&key);
+                    cursor = model.range(..=key).next_back().map(|(k, _)| k.clone());
+                }
+                Op::SeekToFirst => {
+                    iter.seek_to_first();
+                    cursor = model.keys().next().cloned();
+                }
+                Op::SeekToLast => {
+                    iter.seek_to_last();
+                    cursor = model.keys().next_back().cloned();
+                }
+                Op::Next | Op::Prev if cursor.is_none() => {}
+                Op::Next => {
+                    iter.next();
+                    let from = (Bound::Excluded(cursor.unwrap()), Bound::Unbounded);
+                    cursor = model.range(from).next().map(|(k, _)| k.clone());
+                }
+                Op::Prev => {
+                    iter.prev();
+                    let to = (Bound::Unbounded, Bound::Excluded(cursor.unwrap()));
+                    cursor = model.range(to).next_back().map(|(k, _)| k.clone());
+                }
+                Op::Range(start, end) => {
+                    // filtered rather than `model.range`, which panics on inverted bounds
+                    let range = (start.clone(), end.clone());
+                    let want: Vec<_> = model
+                        .iter()
+                        .filter(|(k, _)| range.contains(k))
+                        .map(|(k, v)| (k.clone(), *v))
+                        .collect();
+
+                    let mut scan = new_iter(&list);
+                    match &start {
+                        Bound::Included(key) => scan.seek(key),
+                        Bound::Excluded(key) => {
+                            scan.seek(key);
+                            if scan.key() == Some(key) {
+                                scan.next();
+                            }
+                        }
+                        Bound::Unbounded => scan.seek_to_first(),
+                    }
+                    let mut found = Vec::new();
+                    while let (Some(k), Some(v)) = (scan.key(), scan.value()) {
+                        if !range.contains(k) {
+                            break;
+                        }
+                        found.push((k.clone(), *v));
+                        scan.next();
+                    }
+                    prop_assert_eq!(&found, &want);
+
+                    // and the same entries walking back from the end
+                    match &end {
+                        Bound::Included(key) => scan.seek_for_prev(key),
+                        Bound::Excluded(key) => {
+                            scan.seek_for_prev(key);
+                            if scan.key() == Some(key) {
+                                scan.prev();
+                            }
+                        }
+                        Bound::Unbounded => scan.seek_to_last(),
+                    }
+                    found.clear();
+                    while let (Some(k), Some(v)) = (scan.key(), scan.value()) {
+                        if !range.contains(k) {
+                            break;
+                        }
+                        found.push((k.clone(), *v));
+                        scan.prev();
+                    }
+                    found.reverse();
+                    prop_assert_eq!(found, want);
+                }
+                Op::PopFirst => {
+                    // popping needs the list to itself, so the cursor goes
+                    drop(iter);
+                    let popped = Arc::get_mut(&mut list).unwrap().pop_first();
+                    prop_assert_eq!(popped, model.pop_first());
+                    iter = new_iter(&list);
+                    cursor = None;
+                }
+            }
+            prop_assert_eq!(iter.key(), cursor.as_ref());
+            if let Some(key) = &cursor {
+                prop_assert_eq!(iter.value(), model.get(key));
+            }
+        }
+
+        prop_assert_eq!(list.len(), model.len());
+        prop_assert_eq!(list.validate(), Ok(()));
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn int_keys_match_btree_map(fingered: bool, ops in ops(int_keys())) {
+            run(DefaultComparator::default(), fingered, ops)?;
+        }
+
+        #[test]
+        fn byte_keys_match_btree_map(fingered: bool, ops in ops(byte_keys())) {
+            run(BytewiseComparator, fingered, ops)?;
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::sync::Arc;
+// Run with `RUSTFLAGS="--cfg loom" cargo test --release loom_tests`. Readers get threads
+// of their own too: a reader on the model's main thread only ever ran before a writer
+// whose first step is a load, so loom never tried the other order.
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use std::{ops::Bound, sync::Arc};
+
+    use loom::{cell::UnsafeCell, thread};
 
     use crate::{arena::BlockArena, comparator::DefaultComparator};
 
-    use super::SkipList;
+    use super::{
+        DuplicatePolicy, MAX_HEIGHT, Node, SkipList, SkipListOptions, next_random, random_height,
+        seed_height_rng,
+    };
 
-    #[test]
-    fn insert_some() {
-        const TEST_COUNT: usize = 1_000_000;
+    type List = SkipList<u64, u64, DefaultComparator<u64>, BlockArena>;
 
-        let list = Arc::new(SkipList::new(
-            DefaultComparator::default(),
-            BlockArena::default(),
-        ));
+    const OPTIONS: SkipListOptions = SkipListOptions {
+        branching: 2,
+        max_height: MAX_HEIGHT,
+        write_buffer_size: None,
+        backoff_spin_limit: 0,
+        // the tests pick their heights with `next_height`
+        adaptive_height: false,
+        duplicates: DuplicatePolicy::Reject,
+    };
 
-        for i in 0..TEST_COUNT {
-            list.insert(i, i + 1);
+    fn new_list<V>() -> SkipList<u64, V, DefaultComparator<u64>, BlockArena> {
+        SkipList::with_options(DefaultComparator::default(), BlockArena::new(), OPTIONS)
+    }
+
+    // A value loom tracks: reading it on another thread without a happens-before edge to
+    // the write in `new` fails the model, which a plain `u64` would not.
+    struct Tracked(UnsafeCell<u64>);
+
+    unsafe impl Sync for Tracked {}
+
+    impl Tracked {
+        fn new(value: u64) -> Self {
+            let cell = UnsafeCell::new(0);
+            cell.with_mut(|p| unsafe { *p = value });
+            Self(cell)
         }
 
-        let mut iter = list.iter();
-        iter.seek_to_first();
-        for i in 0..TEST_COUNT {
-            assert_eq!(iter.key().unwrap(), &i);
-            assert_eq!(iter.value().unwrap(), &(i + 1));
-            iter.next();
+        fn get(&self) -> u64 {
+            self.0.with(|p| unsafe { *p })
+        }
+    }
+
+    // seeds the current thread so that its next insert gets a node of `height`
+    fn next_height(height: usize) {
+        let seed = (0..)
+            .find(|&seed| {
+                seed_height_rng(seed);
+                random_height(OPTIONS.branching, OPTIONS.max_height, next_random) == height
+            })
+            .unwrap();
+        seed_height_rng(seed);
+    }
+
+    // every level is sorted and only holds nodes at least that tall, and level 0 holds
+    // exactly `keys`
+    fn check(list: &List, keys: &[u64]) {
+        unsafe {
+            let head = list.head.as_ptr();
+            for level in 0..MAX_HEIGHT {
+                let mut prev = None;
+                let mut cur = Node::get_next(head, level);
+                let mut found = Vec::new();
+                while !cur.is_null() {
+                    assert!(Node::height(cur) > level);
+                    let key = *Node::key(cur);
+                    assert!(prev < Some(key), "level {level} out of order");
+                    prev = Some(key);
+                    found.push(key);
+                    cur = Node::get_next(cur, level);
+                }
+                if level == 0 {
+                    assert_eq!(found, keys);
+                }
+            }
         }
     }
 
+    fn key_at<V>(node: *mut Node<u64, V>) -> Option<u64> {
+        (!node.is_null()).then(|| unsafe { *Node::key(node) })
+    }
+
     #[test]
-    fn iterator() {
-        const TEST_COUNT: usize = 1_000_000;
+    fn adjacent_inserts() {
+        loom::model(|| {
+            let list = Arc::new(new_list());
+            let threads: Vec<_> = [(1, 2), (2, 3)]
+                .into_iter()
+                .map(|(key, height)| {
+                    let list = list.clone();
+                    thread::spawn(move || {
+                        next_height(height);
+                        list.insert(key, key);
+                    })
+                })
+                .collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+            check(&list, &[1, 2]);
+        });
+    }
 
-        let list = Arc::new(SkipList::new(
-            DefaultComparator::default(),
-            BlockArena::default(),
-        ));
+    #[test]
+    fn insert_races_find_near() {
+        loom::model(|| {
+            let list = Arc::new(new_list());
+            list.insert(1, 1);
+            list.insert(3, 3);
 
-        for i in 0..TEST_COUNT {
-            list.insert(i, i);
-        }
+            let writer = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    next_height(MAX_HEIGHT);
+                    list.insert(2, 2);
+                })
+            };
+            // either before or after the insert, never a node that sorts wrong
+            let reader = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    let found = key_at(list.find_near(Bound::Included(&2), false));
+                    assert!(matches!(found, Some(2 | 3)), "found {found:?}");
+                    let found = key_at(list.find_near(Bound::Excluded(&3), true));
+                    assert!(matches!(found, Some(1 | 2)), "found {found:?}");
+                })
+            };
+
+            reader.join().unwrap();
+            writer.join().unwrap();
+            check(&list, &[1, 2, 3]);
+            assert_eq!(key_at(list.find_near(Bound::Included(&2), false)), Some(2));
+        });
+    }
+
+    // the publication contract above `impl Node`: whichever level a reader finds the node
+    // on, its value is visible
+    #[test]
+    fn readers_see_initialized_nodes() {
+        loom::model(|| {
+            let list = Arc::new(new_list());
+            list.insert(1, Tracked::new(10));
+
+            let writer = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    next_height(MAX_HEIGHT);
+                    list.insert(2, Tracked::new(20));
+                })
+            };
+            let reader = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    // from the top, so the node may be found on any level it is linked on
+                    if let Some(value) = list.get(&2) {
+                        assert_eq!(value.get(), 20);
+                    }
+                    // stepping along level 0 from its predecessor
+                    let node = list.find_near(Bound::Excluded(&1), false);
+                    if let Some(key) = key_at(node) {
+                        assert_eq!(key, 2);
+                        assert_eq!(unsafe { Node::value(node) }.get(), 20);
+                    }
+                })
+            };
+
+            reader.join().unwrap();
+            writer.join().unwrap();
+            assert_eq!(list.get(&2).map(Tracked::get), Some(20));
+        });
+    }
+
+    // exactly one of two inserts of the same key links, whatever the towers
+    #[test]
+    fn equal_keys_race() {
+        loom::model(|| {
+            let list = Arc::new(new_list());
+            let threads: Vec<_> = [(1, 1), (2, MAX_HEIGHT)]
+                .into_iter()
+                .map(|(value, height)| {
+                    let list = list.clone();
+                    thread::spawn(move || {
+                        next_height(height);
+                        list.try_insert(7, value).is_ok()
+                    })
+                })
+                .collect();
+            let won: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+            assert_eq!(won.iter().filter(|&&won| won).count(), 1, "{won:?}");
+            check(&list, &[7]);
+            let winner = if won[0] { 1 } else { 2 };
+            assert_eq!(list.get(&7), Some(&winner));
+        });
+    }
+
+    // a reader that sees the count also sees the entries behind it
+    #[test]
+    fn len_counts_only_linked_entries() {
+        loom::model(|| {
+            let list = Arc::new(new_list::<u64>());
+            list.insert(1, 1);
+
+            let writer = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    next_height(1);
+                    list.insert(2, 2);
+                    assert_eq!(list.len(), 2);
+                })
+            };
+            let reader = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    let len = list.len();
+                    assert!(list.entries().count() >= len);
+                })
+            };
+
+            reader.join().unwrap();
+            writer.join().unwrap();
+        });
+    }
 
+    // a snapshot racing the insert decides once whether it sees the entry, and one taken
+    // after the insert returned always does
+    #[test]
+    fn snapshot_races_insert() {
+        loom::model(|| {
+            let list = Arc::new(new_list::<u64>());
+
+            let writer = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    next_height(1);
+                    list.insert(2, 2);
+                    assert_eq!(list.snapshot().get(&2), Some(&2));
+                })
+            };
+            let reader = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    let snapshot = list.snapshot();
+                    let seen = snapshot.get(&2).is_some();
+                    assert_eq!(snapshot.get(&2).is_some(), seen);
+                })
+            };
+
+            reader.join().unwrap();
+            writer.join().unwrap();
+        });
+    }
+
+    // two inserts stamp their nodes onto the log while a reader follows it: the reader
+    // sees initialized entries numbered one by one, and afterwards the log has both
+    #[test]
+    fn change_log_races_inserts() {
+        loom::model(|| {
+            let list = Arc::new(new_list::<u64>());
+            let writers: Vec<_> = [1, 2]
+                .into_iter()
+                .map(|key| {
+                    let list = list.clone();
+                    thread::spawn(move || {
+                        next_height(1);
+                        list.insert(key, key * 10);
+                    })
+                })
+                .collect();
+            let mut seqs = Vec::new();
+            for (seq, &key, &value) in list.changes_since(0) {
+                assert_eq!(value, key * 10);
+                seqs.push(seq);
+            }
+            assert!(seqs.iter().copied().eq(1..=seqs.len() as u64));
+
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            let log: Vec<_> = list
+                .changes_since(0)
+                .map(|(seq, &key, _)| (seq, key))
+                .collect();
+            assert_eq!(log.len(), 2);
+            assert_eq!((log[0].0, log[1].0), (1, 2));
+            assert_ne!(log[0].1, log[1].1);
+        });
+    }
+
+    #[test]
+    fn concurrent_height_growth() {
+        loom::model(|| {
+            let list = Arc::new(new_list());
+            let threads: Vec<_> = [(1, MAX_HEIGHT), (5, MAX_HEIGHT - 1)]
+                .into_iter()
+                .map(|(key, height)| {
+                    let list = list.clone();
+                    thread::spawn(move || {
+                        next_height(height);
+                        list.insert(key, key);
+                    })
+                })
+                .collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+            assert_eq!(list.height(), MAX_HEIGHT);
+            check(&list, &[1, 5]);
+        });
+    }
+}
+
+// Run with `RUSTFLAGS="--cfg shuttle" cargo test --release shuttle_tests`. Each scenario runs
+// under a few thousand random schedules, which reaches cases far too big for loom. A
+// failure prints the schedule that caused it; pass that string to `shuttle::replay` in
+// place of `check_random` to run exactly that schedule again. Heights are deterministic
+// under shuttle, so the replay builds the same towers.
+#[cfg(all(test, shuttle))]
+mod shuttle_tests {
+    use std::{collections::BTreeSet, sync::Arc};
+
+    use shuttle::thread;
+
+    use crate::{arena::BlockArena, comparator::DefaultComparator};
+
+    use super::{
+        DuplicatePolicy, MAX_HEIGHT, NodeError, SkipList, SkipListOptions, next_random,
+        random_height, seed_height_rng,
+    };
+
+    const ITERATIONS: usize = 2_000;
+
+    type List = SkipList<u64, u64, DefaultComparator<u64>, BlockArena>;
+
+    const OPTIONS: SkipListOptions = SkipListOptions {
+        branching: 2,
+        max_height: MAX_HEIGHT,
+        write_buffer_size: None,
+        backoff_spin_limit: 0,
+        adaptive_height: false,
+        duplicates: DuplicatePolicy::Reject,
+    };
+
+    fn new_list() -> List {
+        SkipList::with_options(DefaultComparator::default(), BlockArena::new(), OPTIONS)
+    }
+
+    // seeds the current thread so that its next insert gets a node of `height`
+    fn next_height(height: usize) {
+        let seed = (0..)
+            .find(|&seed| {
+                seed_height_rng(seed);
+                random_height(OPTIONS.branching, OPTIONS.max_height, next_random) == height
+            })
+            .unwrap();
+        seed_height_rng(seed);
+    }
+
+    fn keys(list: &Arc<List>) -> Vec<u64> {
+        let mut keys = vec![];
         let mut iter = list.iter();
-        iter.seek_to_last();
         iter.seek_to_first();
-        for i in 0..TEST_COUNT {
-            assert_eq!(iter.key().unwrap(), &i);
-            assert_eq!(iter.value().unwrap(), &i);
+        while let Some(&key) = iter.key() {
+            keys.push(key);
             iter.next();
         }
-        assert!(!iter.is_valid());
+        keys
+    }
 
-        for i in 0..TEST_COUNT {
-            iter.seek(&i);
-            assert_eq!(iter.key().unwrap(), &i);
-            assert_eq!(iter.value().unwrap(), &i);
-        }
+    // every thread inserts the same keys, starting at a different one
+    #[test]
+    fn same_key_races() {
+        const THREADS: u64 = 3;
+        const KEYS: u64 = 4;
+
+        shuttle::check_random(
+            || {
+                let list = Arc::new(new_list());
+                let threads: Vec<_> = (0..THREADS)
+                    .map(|t| {
+                        let list = list.clone();
+                        thread::spawn(move || {
+                            (0..KEYS)
+                                .map(|i| (i + t) % KEYS)
+                                .filter(|&key| {
+                                    next_height(1 + (key + t) as usize % 3);
+                                    match list.try_insert(key, t) {
+                                        Ok(()) => true,
+                                        Err(NodeError::KeyExists) => false,
+                                        Err(e) => panic!("{e}"),
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    })
+                    .collect();
+                let wins: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+                let mut won: Vec<_> = wins.iter().flatten().copied().collect();
+                won.sort_unstable();
+                assert_eq!(won, (0..KEYS).collect::<Vec<_>>());
+                for (t, keys) in wins.iter().enumerate() {
+                    for key in keys {
+                        assert_eq!(list.get(key), Some(&(t as u64)));
+                    }
+                }
+                assert_eq!(list.len(), KEYS as usize);
+                assert_eq!(list.validate(), Ok(()));
+            },
+            ITERATIONS,
+        );
+    }
+
+    // writers raise the list with tall towers while readers look up what was there before
+    #[test]
+    fn height_growth_under_readers() {
+        const PRELOAD: u64 = 8;
+
+        shuttle::check_random(
+            || {
+                let list = Arc::new(new_list());
+                for key in 0..PRELOAD {
+                    next_height(1);
+                    list.insert(key * 10, key);
+                }
+
+                let writers: Vec<_> = (0..2_u64)
+                    .map(|w| {
+                        let list = list.clone();
+                        thread::spawn(move || {
+                            for i in 0..3 {
+                                next_height(4 + 4 * w as usize + i);
+                                list.insert(w * 10 + i as u64 + 1, 0);
+                            }
+                        })
+                    })
+                    .collect();
+                let readers: Vec<_> = (0..2_u64)
+                    .map(|r| {
+                        let list = list.clone();
+                        thread::spawn(move || {
+                            let mut iter = list.iter();
+                            let mut height = 0;
+                            for key in (0..PRELOAD).map(|k| (k + r * 3) % PRELOAD) {
+                                assert_eq!(list.get(&(key * 10)), Some(&key));
+                                iter.seek(&(key * 10));
+                                assert_eq!(iter.key(), Some(&(key * 10)));
+                                let now = list.height();
+                                assert!(now >= height, "height went down");
+                                height = now;
+                            }
+                        })
+                    })
+                    .collect();
+                for t in writers.into_iter().chain(readers) {
+                    t.join().unwrap();
+                }
+
+                assert_eq!(list.height(), 10);
+                assert_eq!(list.len(), PRELOAD as usize + 6);
+                assert_eq!(list.validate(), Ok(()));
+            },
+            ITERATIONS,
+        );
+    }
+
+    // an iterator walks the list while writers keep filling in the gaps
+    #[test]
+    fn iterate_during_inserts() {
+        const WRITERS: u64 = 2;
+        const PER_WRITER: u64 = 6;
+
+        shuttle::check_random(
+            || {
+                let list = Arc::new(new_list());
+                for key in 0..10 {
+                    list.insert(key * 4, key);
+                }
+                let inserted: BTreeSet<_> = (0..WRITERS)
+                    .flat_map(|w| (0..PER_WRITER).map(move |i| i * 4 + 1 + w * 2))
+                    .collect();
+
+                let writers: Vec<_> = (0..WRITERS)
+                    .map(|w| {
+                        let list = list.clone();
+                        thread::spawn(move || {
+                            for i in 0..PER_WRITER {
+                                next_height(1 + (i as usize + w as usize) % 4);
+                                list.insert(i * 4 + 1 + w * 2, i);
+                            }
+                        })
+                    })
+                    .collect();
+                let reader = {
+                    let (list, inserted) = (list.clone(), inserted.clone());
+                    thread::spawn(move || {
+                        let first = keys(&list);
+                        let second = keys(&list);
+                        for pass in [&first, &second] {
+                            assert!(pass.is_sorted_by(|a, b| a < b), "{pass:?}");
+                            for key in pass {
+                                assert!(key % 4 == 0 || inserted.contains(key), "{key}");
+                            }
+                            assert_eq!(pass.iter().filter(|&k| k % 4 == 0).count(), 10);
+                        }
+                        // nothing is ever unlinked
+                        assert!(first.iter().all(|key| second.contains(key)));
+                    })
+                };
+                for t in writers {
+                    t.join().unwrap();
+                }
+                reader.join().unwrap();
+
+                let expected: Vec<_> = (0..10)
+                    .map(|key| key * 4)
+                    .chain(inserted)
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect();
+                assert_eq!(keys(&list), expected);
+                assert_eq!(list.validate(), Ok(()));
+            },
+            ITERATIONS,
+        );
     }
 }
@@ -0,0 +1,672 @@
+//! A minimal sorted table, what a memtable is flushed to:
+//!
+//! ```text
+//! data block* | index block | footer | u32 le(footer_len) | u32 le(footer crc) | u64 le(MAGIC)
+//! ```
+//!
+//! A block is its records, the u32 le offsets of its restart points and their count, then
+//! the CRC32C of all of that. A record is
+//!
+//! ```text
+//! varint32(shared) | varint32(unshared) | varint32(value_len) | key[shared..] | value
+//! ```
+//!
+//! where `shared` is how many bytes the key has in common with the one before it, and 0
+//! at a restart point. The index block has a record per data block, keyed by a key
+//! between that block's last key and the next block's first, with the block's offset and
+//! length as varint64s for the value. The footer holds the entry count, the smallest and
+//! largest key, both length-prefixed, and the index block's offset and length.
+//!
+//! Keys are in bytewise order.
+
+use std::io;
+
+use crate::{
+    arena::MemAllocator,
+    comparator::Comparator,
+    encoding::{
+        DecodeError, get_length_prefixed, get_varint32, get_varint64, put_varint32, put_varint64,
+    },
+    skip_list::SkipList,
+};
+
+/// Ends every table.
+pub const MAGIC: u64 = 0x736b_6970_6c69_7374;
+
+// footer length, footer crc, magic
+const TAIL_LEN: usize = 16;
+
+/// How `SstWriter` lays out its blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SstOptions {
+    /// A data block is cut once its records take this many bytes.
+    pub block_size: usize,
+    /// Records between restart points. 1 makes every record a restart point, writing
+    /// every key whole.
+    pub restart_interval: usize,
+}
+
+impl Default for SstOptions {
+    fn default() -> Self {
+        Self {
+            block_size: 4096,
+            restart_interval: 16,
+        }
+    }
+}
+
+/// What went into a table, from `SstWriter::finish`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushStats {
+    pub entries: usize,
+    pub data_blocks: usize,
+    /// The length of the table.
+    pub bytes: u64,
+}
+
+/// Writes a table to `W`, one record at a time in bytewise order.
+pub struct SstWriter<W> {
+    inner: W,
+    options: SstOptions,
+    offset: u64,
+    block: BlockBuilder,
+    index: BlockBuilder,
+    // the block waiting for its index record until the next key says how far it reaches
+    pending: Option<(u64, u64)>,
+    smallest: Vec<u8>,
+    last_key: Vec<u8>,
+    entries: usize,
+    data_blocks: usize,
+    finished: bool,
+}
+
+impl<W: io::Write> SstWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_options(inner, SstOptions::default())
+    }
+
+    /// Panics when `options.restart_interval` is 0.
+    pub fn with_options(inner: W, options: SstOptions) -> Self {
+        assert!(
+            options.restart_interval > 0,
+            "restart interval must be positive"
+        );
+        Self {
+            inner,
+            options,
+            offset: 0,
+            block: BlockBuilder::new(options.restart_interval),
+            index: BlockBuilder::new(1),
+            pending: None,
+            smallest: vec![],
+            last_key: vec![],
+            entries: 0,
+            data_blocks: 0,
+            finished: false,
+        }
+    }
+
+    /// Fails with `InvalidInput` when `key` is not above the last key added, or the table
+    /// is finished.
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        if self.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the table is finished",
+            ));
+        }
+        if self.entries > 0 && key <= &self.last_key[..] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "keys must be added in ascending bytewise order",
+            ));
+        }
+        if let Some(handle) = self.pending.take() {
+            let separator = find_shortest_separator(&self.last_key, key);
+            self.add_index(&separator, handle);
+        }
+        if self.entries == 0 {
+            self.smallest = key.to_vec();
+        }
+
+        self.block.add(key, value);
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.entries += 1;
+        if self.block.len() >= self.options.block_size {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the index and the footer. Adds fail from here on.
+    pub fn finish(&mut self) -> io::Result<FlushStats> {
+        if self.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the table is finished",
+            ));
+        }
+        self.finished = true;
+        if !self.block.is_empty() {
+            self.flush_block()?;
+        }
+        if let Some(handle) = self.pending.take() {
+            // nothing follows the last block, so its last key bounds it
+            let last_key = std::mem::take(&mut self.last_key);
+            self.add_index(&last_key, handle);
+            self.last_key = last_key;
+        }
+        let index = self.index.finish();
+        let index_handle = self.write_block(&index)?;
+
+        let mut footer = vec![];
+        put_varint64(&mut footer, self.entries as u64);
+        put_length_prefixed(&mut footer, &self.smallest);
+        put_length_prefixed(&mut footer, &self.last_key);
+        put_varint64(&mut footer, index_handle.0);
+        put_varint64(&mut footer, index_handle.1);
+        let footer_len = footer.len() as u32;
+        let footer_crc = crc32c(&footer);
+        footer.extend_from_slice(&footer_len.to_le_bytes());
+        footer.extend_from_slice(&footer_crc.to_le_bytes());
+        footer.extend_from_slice(&MAGIC.to_le_bytes());
+        self.write_raw(&footer)?;
+        self.inner.flush()?;
+
+        Ok(FlushStats {
+            entries: self.entries,
+            data_blocks: self.data_blocks,
+            bytes: self.offset,
+        })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn flush_block(&mut self) -> io::Result<()> {
+        let block = self.block.finish();
+        self.pending = Some(self.write_block(&block)?);
+        self.data_blocks += 1;
+        Ok(())
+    }
+
+    fn add_index(&mut self, key: &[u8], (offset, len): (u64, u64)) {
+        let mut handle = vec![];
+        put_varint64(&mut handle, offset);
+        put_varint64(&mut handle, len);
+        self.index.add(key, &handle);
+    }
+
+    // the block and its checksum; the handle covers the block alone
+    fn write_block(&mut self, block: &[u8]) -> io::Result<(u64, u64)> {
+        let handle = (self.offset, block.len() as u64);
+        self.write_raw(block)?;
+        self.write_raw(&crc32c(block).to_le_bytes())?;
+        Ok(handle)
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Flushing a memtable: its entries go to the table in the list's order, which has to be
+/// the bytewise order of the keys' bytes, e.g. that of `BytewiseComparator`.
+impl<K, V, C, A> SkipList<K, V, C, A>
+where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    /// Writes every entry to `writer` and finishes the table. Entries inserted while the
+    /// flush runs may or may not make it in.
+    ///
+    /// Fails with `InvalidInput` when the list's order is not bytewise, or on the first
+    /// error from the writer.
+    pub fn flush_to<W: io::Write>(&self, writer: &mut SstWriter<W>) -> io::Result<FlushStats> {
+        for (key, value) in self.entries() {
+            writer.add(key.as_ref(), value.as_ref())?;
+        }
+        writer.finish()
+    }
+}
+
+/// A key `k` with `start <= k < limit` in bytewise order that is as short as it can be,
+/// for an index record that only has to tell two blocks apart. `start` itself when there
+/// is nothing to cut, e.g. when it is a prefix of `limit`.
+pub fn find_shortest_separator(start: &[u8], limit: &[u8]) -> Vec<u8> {
+    let shared = common_prefix(start, limit);
+    if shared < start.len().min(limit.len()) {
+        let byte = start[shared];
+        if byte < 0xff && byte + 1 < limit[shared] {
+            let mut separator = start[..=shared].to_vec();
+            separator[shared] += 1;
+            return separator;
+        }
+    }
+    start.to_vec()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn put_length_prefixed(dst: &mut Vec<u8>, bytes: &[u8]) {
+    let len = u32::try_from(bytes.len()).expect("keys are shorter than 4 GiB");
+    put_varint32(dst, len);
+    dst.extend_from_slice(bytes);
+}
+
+struct BlockBuilder {
+    buf: Vec<u8>,
+    restarts: Vec<u32>,
+    restart_interval: usize,
+    // records since the last restart point
+    counter: usize,
+    last_key: Vec<u8>,
+}
+
+impl BlockBuilder {
+    fn new(restart_interval: usize) -> Self {
+        Self {
+            buf: vec![],
+            restarts: vec![],
+            restart_interval,
+            counter: 0,
+            last_key: vec![],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn add(&mut self, key: &[u8], value: &[u8]) {
+        let shared = if self.counter.is_multiple_of(self.restart_interval) {
+            let offset = u32::try_from(self.buf.len()).expect("blocks are shorter than 4 GiB");
+            self.restarts.push(offset);
+            0
+        } else {
+            common_prefix(&self.last_key, key)
+        };
+        let unshared = &key[shared..];
+        let value_len = u32::try_from(value.len()).expect("values are shorter than 4 GiB");
+        put_varint32(&mut self.buf, shared as u32);
+        put_varint32(&mut self.buf, unshared.len() as u32);
+        put_varint32(&mut self.buf, value_len);
+        self.buf.extend_from_slice(unshared);
+        self.buf.extend_from_slice(value);
+
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.counter += 1;
+    }
+
+    // the finished block; the builder starts over
+    fn finish(&mut self) -> Vec<u8> {
+        let mut block = std::mem::take(&mut self.buf);
+        for restart in &self.restarts {
+            block.extend_from_slice(&restart.to_le_bytes());
+        }
+        block.extend_from_slice(&(self.restarts.len() as u32).to_le_bytes());
+        self.restarts.clear();
+        self.counter = 0;
+        self.last_key.clear();
+        block
+    }
+}
+
+/// Reads back what `SstWriter` wrote, checking every checksum on the way.
+pub struct SstReader<'a> {
+    table: &'a [u8],
+    entries: u64,
+    smallest: &'a [u8],
+    largest: &'a [u8],
+    // separator and block, in order
+    index: Vec<(Vec<u8>, &'a [u8])>,
+}
+
+impl<'a> SstReader<'a> {
+    /// Fails with `DecodeError::Corrupted` when a checksum or the magic number does not
+    /// match.
+    pub fn open(table: &'a [u8]) -> Result<Self, DecodeError> {
+        let tail_start = table
+            .len()
+            .checked_sub(TAIL_LEN)
+            .ok_or(DecodeError::Truncated)?;
+        let tail = &table[tail_start..];
+        let footer_len = u32::from_le_bytes(tail[..4].try_into().unwrap()) as usize;
+        let footer_crc = u32::from_le_bytes(tail[4..8].try_into().unwrap());
+        if u64::from_le_bytes(tail[8..].try_into().unwrap()) != MAGIC {
+            return Err(DecodeError::Corrupted);
+        }
+        let footer_start = tail_start
+            .checked_sub(footer_len)
+            .ok_or(DecodeError::Truncated)?;
+        let footer = &table[footer_start..tail_start];
+        if crc32c(footer) != footer_crc {
+            return Err(DecodeError::Corrupted);
+        }
+
+        let (entries, len) = get_varint64(footer)?;
+        let (smallest, rest) = get_length_prefixed(&footer[len..])?;
+        let (largest, rest) = get_length_prefixed(rest)?;
+        let (index_offset, len) = get_varint64(rest)?;
+        let (index_len, end) = get_varint64(&rest[len..])?;
+        if len + end != rest.len() {
+            return Err(DecodeError::Corrupted);
+        }
+
+        let index_block = read_block(&table[..footer_start], index_offset, index_len)?;
+        let mut index = vec![];
+        for record in records(index_block)? {
+            let (key, handle) = record?;
+            let (offset, len) = get_varint64(handle)?;
+            let (block_len, end) = get_varint64(&handle[len..])?;
+            if len + end != handle.len() {
+                return Err(DecodeError::Corrupted);
+            }
+            index.push((key, read_block(&table[..footer_start], offset, block_len)?));
+        }
+
+        Ok(Self {
+            table,
+            entries,
+            smallest,
+            largest,
+            index,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// The table's first and last key, unless it is empty.
+    pub fn key_range(&self) -> Option<(&'a [u8], &'a [u8])> {
+        (self.entries > 0).then_some((self.smallest, self.largest))
+    }
+
+    pub fn data_blocks(&self) -> usize {
+        self.index.len()
+    }
+
+    /// The length of the table.
+    pub fn bytes(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Looks in the one block whose range holds `key`.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DecodeError> {
+        let block = self
+            .index
+            .partition_point(|(separator, _)| &separator[..] < key);
+        let Some((_, block)) = self.index.get(block) else {
+            return Ok(None);
+        };
+        for record in records(block)? {
+            let (k, value) = record?;
+            if k == key {
+                return Ok(Some(value.to_vec()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Every entry, in order.
+    #[allow(clippy::type_complexity)]
+    pub fn entries(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, DecodeError> {
+        let mut entries = vec![];
+        for (_, block) in &self.index {
+            for record in records(block)? {
+                let (key, value) = record?;
+                entries.push((key, value.to_vec()));
+            }
+        }
+        if entries.len() as u64 != self.entries {
+            return Err(DecodeError::Corrupted);
+        }
+        Ok(entries)
+    }
+}
+
+// the block at `offset`, once its checksum matches
+fn read_block(table: &[u8], offset: u64, len: u64) -> Result<&[u8], DecodeError> {
+    let start = usize::try_from(offset).map_err(|_| DecodeError::Truncated)?;
+    let len = usize::try_from(len).map_err(|_| DecodeError::Truncated)?;
+    let end = start
+        .checked_add(len)
+        .filter(|end| end + 4 <= table.len())
+        .ok_or(DecodeError::Truncated)?;
+    let block = &table[start..end];
+    let crc = u32::from_le_bytes(table[end..end + 4].try_into().unwrap());
+    if crc32c(block) != crc {
+        return Err(DecodeError::Corrupted);
+    }
+    Ok(block)
+}
+
+// the records of a block, keys put back together
+#[allow(clippy::type_complexity)]
+fn records(
+    block: &[u8],
+) -> Result<impl Iterator<Item = Result<(Vec<u8>, &[u8]), DecodeError>>, DecodeError> {
+    let count_start = block.len().checked_sub(4).ok_or(DecodeError::Truncated)?;
+    let restarts = u32::from_le_bytes(block[count_start..].try_into().unwrap()) as usize;
+    let records_end = restarts
+        .checked_mul(4)
+        .and_then(|len| count_start.checked_sub(len))
+        .ok_or(DecodeError::Corrupted)?;
+    let mut rest = &block[..records_end];
+    let mut key = vec![];
+    Ok(std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut record = || {
+            let (shared, len) = get_varint32(rest)?;
+            let (unshared, len2) = get_varint32(&rest[len..])?;
+            let (value_len, len3) = get_varint32(&rest[len + len2..])?;
+            let body = &rest[len + len2 + len3..];
+            let (unshared, value_len) = (unshared as usize, value_len as usize);
+            if shared as usize > key.len() {
+                return Err(DecodeError::Corrupted);
+            }
+            if body.len() < unshared + value_len {
+                return Err(DecodeError::Truncated);
+            }
+            key.truncate(shared as usize);
+            key.extend_from_slice(&body[..unshared]);
+            let value = &body[unshared..unshared + value_len];
+            rest = &body[unshared + value_len..];
+            Ok((key.clone(), value))
+        };
+        let record = record();
+        if record.is_err() {
+            // one error ends the block
+            rest = &[];
+        }
+        Some(record)
+    }))
+}
+
+/// CRC-32C (Castagnoli), as LevelDB checksums its blocks.
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                crc >> 1 ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::io;
+
+    use crate::{
+        arena::BlockArena, comparator::BytewiseComparator, encoding::DecodeError,
+        skip_list::SkipList,
+    };
+
+    use super::{SstOptions, SstReader, SstWriter, crc32c, find_shortest_separator};
+
+    #[test]
+    fn flush_round_trips() {
+        let list = SkipList::new(BytewiseComparator, BlockArena::new());
+        for i in (0..2_000_u32).rev() {
+            let key = format!("user{:06}", i * 7).into_bytes();
+            list.insert(key, i.to_le_bytes().repeat(i as usize % 5));
+        }
+        let want: Vec<_> = list
+            .entries()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
+        let mut sizes = vec![];
+        for restart_interval in [1, 16] {
+            let options = SstOptions {
+                block_size: 1024,
+                restart_interval,
+            };
+            let mut writer = SstWriter::with_options(vec![], options);
+            let stats = list.flush_to(&mut writer).unwrap();
+            let table = writer.into_inner();
+            assert_eq!(stats.entries, 2_000);
+            assert!(stats.data_blocks > 10, "{}", stats.data_blocks);
+            assert_eq!(stats.bytes, table.len() as u64);
+
+            let reader = SstReader::open(&table).unwrap();
+            assert_eq!(reader.len(), 2_000);
+            assert_eq!(reader.data_blocks(), stats.data_blocks);
+            assert_eq!(
+                reader.key_range(),
+                Some((&b"user000000"[..], &b"user013993"[..]))
+            );
+            assert_eq!(reader.entries().unwrap(), want);
+            for (key, value) in &want {
+                assert_eq!(reader.get(key).unwrap().as_ref(), Some(value));
+            }
+            for missing in [&b""[..], b"user", b"user000001", b"user013994", b"z"] {
+                assert_eq!(reader.get(missing).unwrap(), None);
+            }
+            sizes.push(table.len());
+        }
+        // the shared prefixes are written once per restart point
+        assert!(sizes[1] < sizes[0], "{sizes:?}");
+    }
+
+    #[test]
+    fn empty_table() {
+        let list = SkipList::<_, Vec<u8>, _, _>::new(BytewiseComparator, BlockArena::new());
+        let mut writer = SstWriter::new(vec![]);
+        let stats = list.flush_to(&mut writer).unwrap();
+        assert_eq!((stats.entries, stats.data_blocks), (0, 0));
+        let table = writer.into_inner();
+        let reader = SstReader::open(&table).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(reader.key_range(), None);
+        assert_eq!(reader.get(b"key").unwrap(), None);
+    }
+
+    #[test]
+    fn writer_rejects_bad_input() {
+        let mut writer = SstWriter::new(vec![]);
+        writer.add(b"b", b"").unwrap();
+        for key in [&b"a"[..], b"b"] {
+            let e = writer.add(key, b"").unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        }
+        writer.finish().unwrap();
+        let e = writer.add(b"c", b"").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            writer.finish().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn corrupted_tables() {
+        let mut writer = SstWriter::with_options(
+            vec![],
+            SstOptions {
+                block_size: 64,
+                ..SstOptions::default()
+            },
+        );
+        for i in 0..100_u32 {
+            writer.add(&i.to_be_bytes(), &[i as u8; 10]).unwrap();
+        }
+        writer.finish().unwrap();
+        let table = writer.into_inner();
+        assert!(SstReader::open(&table).is_ok());
+
+        // a bit flipped anywhere fails a checksum or the magic number
+        for at in (0..table.len()).step_by(7) {
+            let mut flipped = table.clone();
+            flipped[at] ^= 0x10;
+            assert!(SstReader::open(&flipped).is_err(), "{at}");
+        }
+        assert_eq!(
+            SstReader::open(&table[..table.len() - 1]).err(),
+            Some(DecodeError::Corrupted)
+        );
+        assert_eq!(
+            SstReader::open(&table[..8]).err(),
+            Some(DecodeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn separators() {
+        for (start, limit, want) in [
+            (&b"abcdef"[..], &b"abzz"[..], &b"abd"[..]),
+            (b"abc", b"abd", b"abc"),
+            (b"ab", b"abc", b"ab"),
+            (b"a\xff", b"b", b"a\xff"),
+            (b"", b"a", b""),
+            (b"helloworld", b"hellozoo", b"hellox"),
+        ] {
+            let separator = find_shortest_separator(start, limit);
+            assert_eq!(separator, want);
+            assert!(start <= &separator[..] && &separator[..] < limit);
+        }
+    }
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(b""), 0);
+    }
+}
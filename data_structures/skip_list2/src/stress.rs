@@ -0,0 +1,206 @@
+pub mod workload;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+    },
+    thread,
+    time::Duration,
+};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    arena::BlockArena,
+    comparator::DefaultComparator,
+    skip_list::{SkipList, SkipListOptions},
+};
+
+use self::workload::{share, value_of};
+
+type List = SkipList<u64, u64, DefaultComparator<u64>, BlockArena>;
+
+/// Knobs for `run`.
+#[derive(Debug, Clone, Copy)]
+pub struct StressConfig {
+    pub writers: usize,
+    pub readers: usize,
+    pub duration: Duration,
+    /// Writer `w` shuffles its share of the keys with `seed + w`, the readers use the
+    /// seeds after the writers'.
+    pub seed: u64,
+    /// Keys come from `0..key_space`, split between the writers with `workload::share` so
+    /// that no key is inserted twice. A writer stops early once its share is used up.
+    pub key_space: u64,
+    pub options: SkipListOptions,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self {
+            writers: 4,
+            readers: 4,
+            duration: Duration::from_secs(2),
+            seed: 0,
+            key_space: 1 << 20,
+            options: SkipListOptions::default(),
+        }
+    }
+}
+
+/// What a `run` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StressReport {
+    pub inserted: usize,
+    pub seeks: usize,
+    /// Entries the readers stepped over after their seeks.
+    pub steps: usize,
+}
+
+/// Lets writers insert and readers seek and iterate on one list for `config.duration`,
+/// then checks that it passes `SkipList::validate` and that every key a writer inserted
+/// is found with its value. Panics on the first problem, from whichever thread saw it.
+pub fn run(config: StressConfig) -> StressReport {
+    assert!(config.writers > 0, "a stress run needs a writer");
+    assert!(
+        config.key_space >= config.writers as u64,
+        "every writer needs a key of its own"
+    );
+
+    let list = Arc::new(SkipList::with_options(
+        DefaultComparator::default(),
+        BlockArena::new(),
+        config.options,
+    ));
+    let stop = AtomicBool::new(false);
+    let seeks = AtomicUsize::new(0);
+    let steps = AtomicUsize::new(0);
+
+    let inserted: Vec<Vec<u64>> = thread::scope(|s| {
+        let writers: Vec<_> = (0..config.writers)
+            .map(|w| {
+                let (list, stop) = (&list, &stop);
+                s.spawn(move || write(list, &config, w, stop))
+            })
+            .collect();
+        for r in 0..config.readers {
+            let (list, stop, seeks, steps) = (&list, &stop, &seeks, &steps);
+            s.spawn(move || {
+                let seed = config.seed + (config.writers + r) as u64;
+                let (s, n) = read(list, &config, seed, stop);
+                seeks.fetch_add(s, Relaxed);
+                steps.fetch_add(n, Relaxed);
+            });
+        }
+
+        thread::sleep(config.duration);
+        stop.store(true, Relaxed);
+        writers
+            .into_iter()
+            .map(|w| w.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+
+    if let Err(e) = list.validate() {
+        let key = list.violation_key(&e);
+        panic!("invalid list after the stress run: {e}, at key {key:?}");
+    }
+    let mut total = 0;
+    for &key in inserted.iter().flatten() {
+        assert_eq!(list.get(&key), Some(&value_of(key)), "lost key {key}");
+        total += 1;
+    }
+    let mut iter = list.iter();
+    iter.seek_to_first();
+    let mut linked = 0;
+    while iter.is_valid() {
+        linked += 1;
+        iter.next();
+    }
+    assert_eq!(linked, total, "entries on level 0 and keys inserted differ");
+
+    StressReport {
+        inserted: total,
+        seeks: seeks.into_inner(),
+        steps: steps.into_inner(),
+    }
+}
+
+// inserts the keys of its own share in random order until stopped, and returns them
+fn write(list: &List, config: &StressConfig, w: usize, stop: &AtomicBool) -> Vec<u64> {
+    let mut keys = share(config.key_space, config.writers, w, config.seed + w as u64);
+    let mut inserted = 0;
+    for &key in &keys {
+        if stop.load(Relaxed) {
+            break;
+        }
+        list.insert(key, value_of(key));
+        inserted += 1;
+    }
+    keys.truncate(inserted);
+    keys
+}
+
+// seeks to random keys and walks a few entries from each, checking what it sees; returns
+// the seeks and steps taken
+fn read(list: &Arc<List>, config: &StressConfig, seed: u64, stop: &AtomicBool) -> (usize, usize) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut iter = list.iter();
+    let (mut seeks, mut steps) = (0, 0);
+    while !stop.load(Relaxed) {
+        let target = rng.random_range(0..config.key_space);
+        iter.seek(&target);
+        seeks += 1;
+
+        let mut prev = None;
+        for _ in 0..16 {
+            let (Some(&key), Some(&value)) = (iter.key(), iter.value()) else {
+                break;
+            };
+            assert!(key >= target, "seek to {target} landed on {key}");
+            assert!(prev < Some(key), "stepped from {prev:?} to {key}");
+            assert_eq!(value, value_of(key), "wrong value for {key}");
+            prev = Some(key);
+            iter.next();
+            steps += 1;
+        }
+    }
+    (seeks, steps)
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::time::Duration;
+
+    use crate::skip_list::SkipListOptions;
+
+    use super::{StressConfig, run};
+
+    #[test]
+    #[ignore = "stress run, see `cargo test -- --ignored`"]
+    fn stress_default() {
+        let report = run(StressConfig::default());
+        assert!(report.inserted > 0);
+        assert!(report.seeks > 0);
+    }
+
+    #[test]
+    #[ignore = "stress run, see `cargo test -- --ignored`"]
+    fn stress_dense_tall_towers() {
+        // few keys and tall towers, so the writers keep splicing into the same gaps
+        let report = run(StressConfig {
+            writers: 8,
+            readers: 2,
+            duration: Duration::from_secs(2),
+            seed: 1,
+            key_space: 1 << 14,
+            options: SkipListOptions {
+                branching: 2,
+                max_height: 32,
+                ..Default::default()
+            },
+        });
+        assert!(report.inserted > 0);
+    }
+}
@@ -0,0 +1,57 @@
+//! Keys and values shared by the stress harness and `benches/compare.rs`.
+//!
+//! The bench includes this file with `#[path]` so that `cargo bench` works without the
+//! `stress` feature, which is why nothing in here may use `crate::`.
+
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+/// A key (or value) type the workloads can be run with.
+pub trait Key: Ord + Clone + Send + Sync + 'static {
+    /// Short name for bench ids.
+    const NAME: &'static str;
+
+    /// The `i`th key; keys sort in the order of their indices.
+    fn from_index(i: u64) -> Self;
+}
+
+impl Key for u64 {
+    const NAME: &'static str = "8b";
+
+    fn from_index(i: u64) -> Self {
+        i
+    }
+}
+
+/// 64 byte strings sharing a 56 byte prefix, so comparisons have to read most of the key.
+impl Key for [u8; 64] {
+    const NAME: &'static str = "64b";
+
+    fn from_index(i: u64) -> Self {
+        let mut key = [b'k'; 64];
+        key[56..].copy_from_slice(&i.to_be_bytes());
+        key
+    }
+}
+
+/// The value stored under the key with index `i`, so readers can check what they find.
+pub fn value_of(i: u64) -> u64 {
+    i.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// `0..n` in an order fixed by `seed`.
+pub fn shuffled(n: u64, seed: u64) -> Vec<u64> {
+    let mut indices: Vec<_> = (0..n).collect();
+    indices.shuffle(&mut StdRng::seed_from_u64(seed));
+    indices
+}
+
+/// Writer `w`'s share of `0..key_space` when split between `writers`, i.e. every
+/// `writers`th index starting at `w`, in an order fixed by `seed`. Shares of different
+/// writers are disjoint and interleave, so the writers keep splicing next to each other.
+pub fn share(key_space: u64, writers: usize, w: usize, seed: u64) -> Vec<u64> {
+    let (writers, w) = (writers as u64, w as u64);
+    let len = (key_space - w).div_ceil(writers);
+    let mut indices: Vec<_> = (0..len).map(|i| i * writers + w).collect();
+    indices.shuffle(&mut StdRng::seed_from_u64(seed));
+    indices
+}
@@ -0,0 +1,19 @@
+// The atomics and locks that inserts and searches synchronise through. Building with
+// `RUSTFLAGS="--cfg loom"` swaps them for loom's, so the `loom_tests` in `skip_list` can
+// explore every interleaving, and `--cfg shuttle` for shuttle's, whose `shuttle_tests` try
+// random schedules of bigger cases; anything else keeps using `std::sync` directly.
+#[cfg(loom)]
+pub(crate) use loom::sync::{
+    Mutex,
+    atomic::{AtomicPtr, AtomicU64, AtomicUsize},
+};
+#[cfg(shuttle)]
+pub(crate) use shuttle::sync::{
+    Mutex,
+    atomic::{AtomicPtr, AtomicU64, AtomicUsize},
+};
+#[cfg(not(any(loom, shuttle)))]
+pub(crate) use std::sync::{
+    Mutex,
+    atomic::{AtomicPtr, AtomicU64, AtomicUsize},
+};
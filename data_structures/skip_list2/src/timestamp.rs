@@ -0,0 +1,358 @@
+//! Versions ordered by the application's timestamps instead of sequence numbers. A
+//! timestamped key is the user key followed by the timestamp as a big-endian `u64`:
+//!
+//! ```text
+//! user_key | u64 be(timestamp)
+//! ```
+//!
+//! `TimestampComparator` orders such keys by user key and then newest timestamp first, so
+//! a seek to `(key, ts)` lands on the newest version at or before `ts`.
+
+use std::{cmp, fmt};
+
+use crate::{
+    arena::MemAllocator,
+    bytes::{BytesIter, BytesSkipList},
+    comparator::Comparator,
+    skip_list::NodeError,
+};
+
+const TIMESTAMP_LEN: usize = 8;
+
+/// Appends the timestamped key of `user_key` at `ts`.
+pub fn append_timestamp(dst: &mut Vec<u8>, user_key: &[u8], ts: u64) {
+    dst.extend_from_slice(user_key);
+    dst.extend_from_slice(&ts.to_be_bytes());
+}
+
+/// The user key and timestamp of a timestamped key. Panics on keys shorter than the
+/// timestamp.
+pub fn split_timestamp(key: &[u8]) -> (&[u8], u64) {
+    let (user_key, ts) = key
+        .split_last_chunk::<TIMESTAMP_LEN>()
+        .expect("timestamped keys end in 8 bytes of timestamp");
+    (user_key, u64::from_be_bytes(*ts))
+}
+
+/// Orders timestamped keys by user key under `C`, then newest first. Panics on keys
+/// shorter than the timestamp.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimestampComparator<C> {
+    user: C,
+}
+
+impl<C> TimestampComparator<C> {
+    pub fn new(user: C) -> Self {
+        Self { user }
+    }
+
+    pub fn user_comparator(&self) -> &C {
+        &self.user
+    }
+}
+
+impl<C: Comparator<Item = [u8]>> Comparator for TimestampComparator<C> {
+    type Item = [u8];
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+        let (a, a_ts) = split_timestamp(a);
+        let (b, b_ts) = split_timestamp(b);
+        self.user.compare(a, b).then_with(|| b_ts.cmp(&a_ts))
+    }
+}
+
+/// What `TimestampSkipList::insert` does with a version older than the newest one of its
+/// key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampOrder {
+    /// Fails with `TimestampError::OutOfOrder`.
+    #[default]
+    Reject,
+    /// Inserts it below the newer ones, where it changes reads at its timestamp up to the
+    /// next one.
+    Allow,
+}
+
+/// Why a timestamped version could not be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimestampError {
+    /// The key already has a version at `newest`, later than the one written.
+    OutOfOrder {
+        newest: u64,
+    },
+    Node(NodeError),
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampError::OutOfOrder { newest } => {
+                write!(f, "the key already has a newer version, at {newest}")
+            }
+            TimestampError::Node(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TimestampError {}
+
+impl From<NodeError> for TimestampError {
+    fn from(e: NodeError) -> Self {
+        TimestampError::Node(e)
+    }
+}
+
+/// A `BytesSkipList` of versions keyed by user key and application timestamp: reads ask
+/// for the newest version at or before a timestamp. Writes of one key are expected to come
+/// with timestamps that do not go down, see `TimestampOrder`.
+pub struct TimestampSkipList<C, A> {
+    list: BytesSkipList<TimestampComparator<C>, A>,
+    order: TimestampOrder,
+}
+
+impl<C, A> TimestampSkipList<C, A>
+where
+    C: Comparator<Item = [u8]>,
+    A: MemAllocator,
+{
+    /// A list with user keys ordered by `user`.
+    pub fn new(user: C, a: A) -> Self {
+        Self::from_list(BytesSkipList::new(TimestampComparator::new(user), a))
+    }
+
+    pub fn try_new(user: C, a: A) -> Result<Self, NodeError> {
+        BytesSkipList::try_new(TimestampComparator::new(user), a).map(Self::from_list)
+    }
+
+    fn from_list(list: BytesSkipList<TimestampComparator<C>, A>) -> Self {
+        Self {
+            list,
+            order: TimestampOrder::default(),
+        }
+    }
+
+    pub fn with_order(mut self, order: TimestampOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Versions in the list.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Panics when the write fails; see `try_insert`.
+    pub fn insert(&self, user_key: &[u8], ts: u64, value: &[u8]) {
+        if let Err(e) = self.try_insert(user_key, ts, value) {
+            panic!("failed to insert into the skip list: {e}");
+        }
+    }
+
+    /// Writes the version of `user_key` at `ts`. Fails with `TimestampError::OutOfOrder`
+    /// when the key has a later version and the order is `Reject`, and with
+    /// `NodeError::KeyExists` when it has one at `ts`. The order is checked against the
+    /// versions linked when the write starts: of two racing writes of a key, the older
+    /// may still land below the newer.
+    pub fn try_insert(&self, user_key: &[u8], ts: u64, value: &[u8]) -> Result<(), TimestampError> {
+        if self.order == TimestampOrder::Reject
+            && let Some(newest) = self.newest_timestamp(user_key)
+            && newest > ts
+        {
+            return Err(TimestampError::OutOfOrder { newest });
+        }
+        let mut key = Vec::with_capacity(user_key.len() + TIMESTAMP_LEN);
+        append_timestamp(&mut key, user_key, ts);
+        Ok(self.list.try_insert(&key, value)?)
+    }
+
+    /// The newest version of `user_key` at or before `ts`, with its timestamp.
+    pub fn get_at_timestamp(&self, user_key: &[u8], ts: u64) -> Option<(u64, &[u8])> {
+        let mut probe = Vec::with_capacity(user_key.len() + TIMESTAMP_LEN);
+        append_timestamp(&mut probe, user_key, ts);
+        let (key, value) = self.list.lower_bound(&probe)?;
+        let (found, ts) = split_timestamp(key);
+        let c = self.list.comparator().user_comparator();
+        (c.compare(found, user_key) == cmp::Ordering::Equal).then_some((ts, value))
+    }
+
+    /// The newest version of `user_key`, with its timestamp.
+    pub fn get(&self, user_key: &[u8]) -> Option<(u64, &[u8])> {
+        self.get_at_timestamp(user_key, u64::MAX)
+    }
+
+    pub fn newest_timestamp(&self, user_key: &[u8]) -> Option<u64> {
+        self.get(user_key).map(|(ts, _)| ts)
+    }
+
+    /// The user keys with a version at or before `ts`, each with the newest such version.
+    pub fn iter_at_timestamp(&self, ts: u64) -> TimestampIter<C, A> {
+        TimestampIter {
+            inner: self.list.iter(),
+            ts,
+            probe: vec![],
+        }
+    }
+}
+
+/// A forward cursor over the user keys of a `TimestampSkipList` as of a timestamp, from
+/// `iter_at_timestamp`. Seeks step over the versions of a key newer than the timestamp and
+/// over its older ones, instead of walking them.
+pub struct TimestampIter<C, A> {
+    inner: BytesIter<TimestampComparator<C>, A>,
+    ts: u64,
+    // the timestamped key of the last seek
+    probe: Vec<u8>,
+}
+
+impl<C, A> TimestampIter<C, A>
+where
+    C: Comparator<Item = [u8]>,
+    A: MemAllocator,
+{
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    pub fn key(&self) -> Option<&[u8]> {
+        self.inner.key().map(|key| split_timestamp(key).0)
+    }
+
+    /// The timestamp of the version `value` comes from.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.inner.key().map(|key| split_timestamp(key).1)
+    }
+
+    pub fn value(&self) -> Option<&[u8]> {
+        self.inner.value()
+    }
+
+    pub fn next(&mut self) {
+        assert!(self.is_valid());
+        // past the oldest version the key can have
+        let (user_key, _) = split_timestamp(self.inner.key().unwrap());
+        self.probe.clear();
+        append_timestamp(&mut self.probe, user_key, 0);
+        self.inner.seek(&self.probe);
+        if self.inner.key() == Some(self.probe.as_slice()) {
+            self.inner.next();
+        }
+        self.find_visible();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+        self.find_visible();
+    }
+
+    /// Moves to the first user key at or after `user_key`.
+    pub fn seek(&mut self, user_key: &[u8]) {
+        self.probe.clear();
+        append_timestamp(&mut self.probe, user_key, self.ts);
+        self.inner.seek(&self.probe);
+        self.find_visible();
+    }
+
+    // onto the newest version at or before `ts` of this or a later user key
+    fn find_visible(&mut self) {
+        while let Some(key) = self.inner.key() {
+            let (user_key, ts) = split_timestamp(key);
+            if ts <= self.ts {
+                return;
+            }
+            // a version at `ts` sorts right behind the newer ones
+            self.probe.clear();
+            append_timestamp(&mut self.probe, user_key, self.ts);
+            self.inner.seek(&self.probe);
+        }
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use crate::{arena::BlockArena, comparator::DefaultComparator, skip_list::NodeError};
+
+    use super::{TimestampError, TimestampOrder, TimestampSkipList};
+
+    fn list() -> TimestampSkipList<DefaultComparator<[u8]>, BlockArena> {
+        TimestampSkipList::new(DefaultComparator::default(), BlockArena::new())
+    }
+
+    #[test]
+    fn reads_at_timestamps() {
+        let list = list();
+        list.insert(b"a", 10, b"a10");
+        list.insert(b"a", 20, b"a20");
+        list.insert(b"b", 15, b"b15");
+        // a user key that is a prefix of another's
+        list.insert(b"", 5, b"empty");
+        list.insert(b"a", 30, b"a30");
+
+        assert_eq!(list.get_at_timestamp(b"a", 9), None);
+        // exactly at a version, and between two
+        assert_eq!(list.get_at_timestamp(b"a", 10), Some((10, &b"a10"[..])));
+        assert_eq!(list.get_at_timestamp(b"a", 19), Some((10, &b"a10"[..])));
+        assert_eq!(list.get_at_timestamp(b"a", 20), Some((20, &b"a20"[..])));
+        assert_eq!(list.get(b"a"), Some((30, &b"a30"[..])));
+        assert_eq!(list.get_at_timestamp(b"b", 14), None);
+        assert_eq!(list.get(b"c"), None);
+
+        let scan = |ts| {
+            let mut iter = list.iter_at_timestamp(ts);
+            iter.seek_to_first();
+            let mut seen = vec![];
+            while let (Some(key), Some(ts), Some(value)) =
+                (iter.key(), iter.timestamp(), iter.value())
+            {
+                seen.push((key.to_vec(), ts, value.to_vec()));
+                iter.next();
+            }
+            seen
+        };
+        assert!(scan(4).is_empty());
+        assert_eq!(
+            scan(15),
+            [
+                (b"".to_vec(), 5, b"empty".to_vec()),
+                (b"a".to_vec(), 10, b"a10".to_vec()),
+                (b"b".to_vec(), 15, b"b15".to_vec())
+            ]
+        );
+        assert_eq!(scan(25)[1], (b"a".to_vec(), 20, b"a20".to_vec()));
+
+        let mut iter = list.iter_at_timestamp(12);
+        iter.seek(b"a");
+        assert_eq!(iter.value(), Some(&b"a10"[..]));
+        iter.next();
+        // b is newer than 12
+        assert!(!iter.is_valid());
+        iter.seek(b"");
+        assert_eq!(iter.key(), Some(&b""[..]));
+    }
+
+    #[test]
+    fn timestamps_of_a_key_go_up() {
+        let list = list();
+        list.insert(b"k", 10, b"v10");
+        assert_eq!(
+            list.try_insert(b"k", 5, b"v5"),
+            Err(TimestampError::OutOfOrder { newest: 10 })
+        );
+        assert_eq!(
+            list.try_insert(b"k", 10, b"again"),
+            Err(TimestampError::Node(NodeError::KeyExists))
+        );
+        // other keys have their own order
+        list.insert(b"j", 5, b"j5");
+        list.insert(b"k", 10_000, b"later");
+        assert_eq!(list.len(), 3);
+
+        let list = list.with_order(TimestampOrder::Allow);
+        list.insert(b"k", 5, b"v5");
+        assert_eq!(list.get_at_timestamp(b"k", 7), Some((5, &b"v5"[..])));
+        assert_eq!(list.get_at_timestamp(b"k", 11), Some((10, &b"v10"[..])));
+    }
+}
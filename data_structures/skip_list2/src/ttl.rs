@@ -0,0 +1,379 @@
+//! Entries that expire: a `TtlSkipList` stamps each entry with the time, in milliseconds
+//! on its clock, from which reads treat it as absent. Nothing is unlinked on expiry; the
+//! entries stay where they are, taking their memory and their keys, until the list is
+//! rebuilt with `compact_into`.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    arena::MemAllocator,
+    comparator::Comparator,
+    skip_list::{CompactionReport, NodeError, SkipList, SkipListIter, SortedBuilder},
+};
+
+/// The expiry of entries that never expire.
+pub const NEVER: u64 = u64::MAX;
+
+// expiries are counted in buckets of this many milliseconds, see `expired_count_estimate`
+const BUCKET_MILLIS: u64 = 1000;
+
+struct Expiring<V> {
+    value: V,
+    expires_at: u64,
+}
+
+impl<V> Expiring<V> {
+    fn live_at(&self, now: u64) -> Option<&V> {
+        (now < self.expires_at).then_some(&self.value)
+    }
+}
+
+fn count_expiry(expiries: &mut BTreeMap<u64, usize>, expires_at: u64) {
+    if expires_at != NEVER {
+        *expiries
+            .entry(expires_at.div_ceil(BUCKET_MILLIS))
+            .or_default() += 1;
+    }
+}
+
+type Clock = Arc<dyn Fn() -> u64 + Send + Sync>;
+
+// milliseconds since the Unix epoch
+fn system_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// A `SkipList` whose entries expire. Each is written with the time it expires at, given
+/// or `now` plus the list's default TTL, and from then on `get` and iterators skip it.
+/// A key stays taken after its entry expires: writing it again fails like a duplicate.
+pub struct TtlSkipList<K, V, C, A> {
+    list: Arc<SkipList<K, Expiring<V>, C, A>>,
+    clock: Clock,
+    default_ttl: Option<u64>,
+    // entries by expiry bucket, rounded up
+    expiries: Mutex<BTreeMap<u64, usize>>,
+}
+
+impl<K, V, C, A> TtlSkipList<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    /// A list on the system clock whose entries never expire unless given an expiry.
+    pub fn new(c: C, a: A) -> Self {
+        Self::from_list(SkipList::new(c, a))
+    }
+
+    pub fn try_new(c: C, a: A) -> Result<Self, NodeError> {
+        SkipList::try_new(c, a).map(Self::from_list)
+    }
+
+    fn from_list(list: SkipList<K, Expiring<V>, C, A>) -> Self {
+        Self {
+            list: Arc::new(list),
+            clock: Arc::new(system_clock),
+            default_ttl: None,
+            expiries: Mutex::default(),
+        }
+    }
+
+    /// Reads the time from `clock` instead, in milliseconds from whenever it likes.
+    pub fn with_clock(mut self, clock: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Has `insert` stamp entries to expire `millis` after they are written.
+    pub fn with_default_ttl(mut self, millis: u64) -> Self {
+        self.default_ttl = Some(millis);
+        self
+    }
+
+    /// The time on the list's clock.
+    pub fn now(&self) -> u64 {
+        (self.clock)()
+    }
+
+    /// Entries in the list, expired ones included.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// How many entries have expired, at least: the ones that expired a second or more
+    /// ago are all counted, the ones since maybe not.
+    pub fn expired_count_estimate(&self) -> usize {
+        let now = self.now();
+        let expiries = self.expiries();
+        expiries.range(..=now / BUCKET_MILLIS).map(|(_, n)| n).sum()
+    }
+
+    fn expiries(&self) -> MutexGuard<'_, BTreeMap<u64, usize>> {
+        self.expiries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.list.get(key)?.live_at(self.now())
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Writes an entry that expires after the default TTL, or never without one. Panics
+    /// like `SkipList::insert`.
+    pub fn insert(&self, key: K, value: V) {
+        self.try_insert(key, value)
+            .expect("failed to insert into the skip list")
+    }
+
+    pub fn try_insert(&self, key: K, value: V) -> Result<(), NodeError> {
+        let expires_at = self
+            .default_ttl
+            .map_or(NEVER, |ttl| self.now().saturating_add(ttl));
+        self.try_insert_expiring_at(key, value, expires_at)
+    }
+
+    /// Writes an entry that expires at `expires_at` on the list's clock, or never at
+    /// `NEVER`. Panics like `SkipList::insert`.
+    pub fn insert_expiring_at(&self, key: K, value: V, expires_at: u64) {
+        self.try_insert_expiring_at(key, value, expires_at)
+            .expect("failed to insert into the skip list")
+    }
+
+    pub fn try_insert_expiring_at(
+        &self,
+        key: K,
+        value: V,
+        expires_at: u64,
+    ) -> Result<(), NodeError> {
+        let entry = Expiring { value, expires_at };
+        self.list.try_insert(key, entry)?;
+        count_expiry(&mut self.expiries(), expires_at);
+        Ok(())
+    }
+
+    /// The live entries in key order, as of each move: the cursor skips entries expired
+    /// when it moves, and the one it is on stays readable until it moves again.
+    pub fn iter(&self) -> TtlIter<K, V, C, A> {
+        TtlIter {
+            inner: self.list.iter(),
+            clock: self.clock.clone(),
+        }
+    }
+
+    pub fn mem_usage(&self) -> usize {
+        self.list.mem_usage()
+    }
+
+    /// Copies the entries still live into a fresh list on `allocator`, with the same clock
+    /// and default TTL, like `SkipList::compact_into`.
+    #[allow(clippy::type_complexity)]
+    pub fn compact_into<A2>(
+        &self,
+        allocator: A2,
+    ) -> Result<(TtlSkipList<K, V, C, A2>, CompactionReport), NodeError>
+    where
+        K: Clone,
+        V: Clone,
+        C: Clone,
+        A2: MemAllocator,
+    {
+        let now = self.now();
+        let c = self.list.comparator().clone();
+        let mut builder = SortedBuilder::new(SkipList::try_with_options(
+            c,
+            allocator,
+            self.list.options(),
+        )?);
+        let mut expiries = BTreeMap::<u64, usize>::new();
+        for (key, entry) in self.list.entries() {
+            let Some(value) = entry.live_at(now) else {
+                continue;
+            };
+            let expires_at = entry.expires_at;
+            builder.push(
+                key.clone(),
+                Expiring {
+                    value: value.clone(),
+                    expires_at,
+                },
+            )?;
+            count_expiry(&mut expiries, expires_at);
+        }
+        let list = TtlSkipList {
+            list: Arc::new(builder.finish()),
+            clock: self.clock.clone(),
+            default_ttl: self.default_ttl,
+            expiries: Mutex::new(expiries),
+        };
+        let report = CompactionReport {
+            mem_usage_before: self.mem_usage(),
+            mem_usage_after: list.mem_usage(),
+        };
+        Ok((list, report))
+    }
+}
+
+/// A cursor over the live entries of a `TtlSkipList`, from `TtlSkipList::iter`.
+pub struct TtlIter<K, V, C, A> {
+    inner: SkipListIter<K, Expiring<V>, C, A>,
+    clock: Clock,
+}
+
+impl<K, V, C, A> TtlIter<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        self.inner.key()
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.inner.value().map(|entry| &entry.value)
+    }
+
+    pub fn next(&mut self) {
+        self.inner.next();
+        self.skip_expired(SkipListIter::next);
+    }
+
+    pub fn prev(&mut self) {
+        self.inner.prev();
+        self.skip_expired(SkipListIter::prev);
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+        self.skip_expired(SkipListIter::next);
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last();
+        self.skip_expired(SkipListIter::prev);
+    }
+
+    /// Moves to the first live entry at or after `key`.
+    pub fn seek(&mut self, key: &K) {
+        self.inner.seek(key);
+        self.skip_expired(SkipListIter::next);
+    }
+
+    fn skip_expired(&mut self, step: fn(&mut SkipListIter<K, Expiring<V>, C, A>)) {
+        let now = (self.clock)();
+        while self
+            .inner
+            .value()
+            .is_some_and(|entry| entry.live_at(now).is_none())
+        {
+            step(&mut self.inner);
+        }
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering::Relaxed},
+    };
+
+    use crate::{
+        arena::{BlockArena, DefaultAllocator},
+        comparator::DefaultComparator,
+        skip_list::NodeError,
+    };
+
+    use super::{NEVER, TtlSkipList};
+
+    fn clock() -> (Arc<AtomicU64>, impl Fn() -> u64 + Send + Sync + 'static) {
+        let now = Arc::new(AtomicU64::new(10_000));
+        let read = now.clone();
+        (now, move || read.load(Relaxed))
+    }
+
+    #[test]
+    fn entries_expire() {
+        let (now, clock) = clock();
+        let list = TtlSkipList::new(DefaultComparator::default(), BlockArena::new())
+            .with_clock(clock)
+            .with_default_ttl(5_000);
+        list.insert(1, "default");
+        list.insert_expiring_at(2, "soon", 11_000);
+        list.insert_expiring_at(3, "never", NEVER);
+        assert_eq!(list.get(&1), Some(&"default"));
+        assert_eq!(list.expired_count_estimate(), 0);
+
+        now.store(11_000, Relaxed);
+        assert_eq!(list.get(&2), None);
+        assert!(list.contains_key(&1));
+        assert_eq!(list.expired_count_estimate(), 1);
+
+        now.store(15_000, Relaxed);
+        assert_eq!(list.get(&1), None);
+        assert_eq!(list.get(&3), Some(&"never"));
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.expired_count_estimate(), 2);
+        // the key stays taken
+        assert_eq!(list.try_insert(2, "again"), Err(NodeError::KeyExists));
+
+        let (compacted, report) = list.compact_into(DefaultAllocator::default()).unwrap();
+        assert_eq!(compacted.len(), 1);
+        assert_eq!(compacted.get(&3), Some(&"never"));
+        assert_eq!(compacted.expired_count_estimate(), 0);
+        assert!(report.mem_usage_after < report.mem_usage_before);
+        compacted.insert(2, "again");
+        now.store(20_000, Relaxed);
+        assert_eq!(compacted.get(&2), None);
+    }
+
+    #[test]
+    fn expiring_between_reads_of_one_iterator() {
+        let (now, clock) = clock();
+        let list =
+            TtlSkipList::new(DefaultComparator::default(), BlockArena::new()).with_clock(clock);
+        for i in 0..10_u64 {
+            list.insert_expiring_at(i, i, 10_001 + i % 3);
+        }
+
+        let mut iter = list.iter();
+        iter.seek_to_first();
+        assert_eq!((iter.key(), iter.value()), (Some(&0), Some(&0)));
+        // 0, 3, 6 and 9 expire under the cursor: it stays on 0, then steps over the rest
+        now.store(10_001, Relaxed);
+        assert_eq!(iter.key(), Some(&0));
+        let mut seen = vec![];
+        iter.next();
+        while let Some(&key) = iter.key() {
+            seen.push(key);
+            iter.next();
+        }
+        assert_eq!(seen, [1, 2, 4, 5, 7, 8]);
+
+        now.store(10_002, Relaxed);
+        iter.seek_to_last();
+        assert_eq!(iter.key(), Some(&8));
+        iter.prev();
+        assert_eq!(iter.key(), Some(&5));
+        iter.seek(&6);
+        assert_eq!(iter.key(), Some(&8));
+
+        now.store(10_003, Relaxed);
+        iter.seek_to_first();
+        assert!(!iter.is_valid());
+    }
+}
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use crate::{
+    arena::MemAllocator,
+    comparator::Comparator,
+    skip_list::{NodeError, SkipList, SkipListIter},
+};
+
+/// What a memtable records for a key: a value, or that the key was deleted. Deletes are
+/// writes like any other, so that they shadow older values further down the tree once the
+/// list is flushed.
+///
+/// A tombstone is a bare discriminant and allocates nothing. For values with a niche, such
+/// as `Vec`, `Box` or references, it takes no room either: `Value<Vec<u8>>` is as big as
+/// `Vec<u8>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Value<V> {
+    Put(V),
+    Delete,
+}
+
+impl<V> Value<V> {
+    pub fn as_put(&self) -> Option<&V> {
+        match self {
+            Value::Put(value) => Some(value),
+            Value::Delete => None,
+        }
+    }
+
+    pub fn is_delete(&self) -> bool {
+        matches!(self, Value::Delete)
+    }
+}
+
+/// What `SkipList::lookup` found for a key.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GetResult<'a, V: ?Sized> {
+    Found(&'a V),
+    /// The list holds a tombstone for the key; older tables must not be asked.
+    Deleted,
+    /// Nothing about the key in this list.
+    NotFound,
+}
+
+// not derived, that would require `V: Clone` and so `V: Sized`
+impl<V: ?Sized> Clone for GetResult<'_, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V: ?Sized> Copy for GetResult<'_, V> {}
+
+impl<'a, V: ?Sized> GetResult<'a, V> {
+    /// The value, if there is one.
+    pub fn found(self) -> Option<&'a V> {
+        match self {
+            GetResult::Found(value) => Some(value),
+            GetResult::Deleted | GetResult::NotFound => None,
+        }
+    }
+}
+
+/// Lists of puts and tombstones. `get`, `iter` and `compact_into` still see both,
+/// tombstones as `Value::Delete`, which is what a flush or merge has to carry along;
+/// `lookup` and `live_iter` are for reads and tell the two apart.
+impl<K, V, C, A> SkipList<K, Value<V>, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn lookup(&self, key: &K) -> GetResult<'_, V> {
+        match self.get(key) {
+            Some(Value::Put(value)) => GetResult::Found(value),
+            Some(Value::Delete) => GetResult::Deleted,
+            None => GetResult::NotFound,
+        }
+    }
+
+    /// Panics like `insert`.
+    pub fn put(&self, key: K, value: V) {
+        self.insert(key, Value::Put(value));
+    }
+
+    pub fn try_put(&self, key: K, value: V) -> Result<(), NodeError> {
+        self.try_insert(key, Value::Put(value))
+    }
+
+    /// Records a tombstone for `key`. Like any insert it fails with `NodeError::KeyExists`
+    /// when the list already holds the key, put or deleted.
+    ///
+    /// Panics like `insert`.
+    pub fn delete(&self, key: K) {
+        self.insert(key, Value::Delete);
+    }
+
+    pub fn try_delete(&self, key: K) -> Result<(), NodeError> {
+        self.try_insert(key, Value::Delete)
+    }
+
+    pub fn live_iter(self: &Arc<Self>) -> LiveIter<K, V, C, A> {
+        LiveIter { inner: self.iter() }
+    }
+}
+
+/// `SkipListIter` over puts only: it steps over tombstones, and yields the values
+/// unwrapped.
+pub struct LiveIter<K, V, C, A> {
+    inner: SkipListIter<K, Value<V>, C, A>,
+}
+
+impl<K, V, C, A> LiveIter<K, V, C, A>
+where
+    C: Comparator<Item = K>,
+    A: MemAllocator,
+{
+    pub fn is_valid(&self) -> bool {
+        self.inner.is_valid()
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        self.inner.key()
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        self.inner.value().and_then(Value::as_put)
+    }
+
+    pub fn next(&mut self) {
+        self.inner.next();
+        self.skip_forward();
+    }
+
+    pub fn prev(&mut self) {
+        self.inner.prev();
+        self.skip_backward();
+    }
+
+    pub fn seek_to_first(&mut self) {
+        self.inner.seek_to_first();
+        self.skip_forward();
+    }
+
+    pub fn seek_to_last(&mut self) {
+        self.inner.seek_to_last();
+        self.skip_backward();
+    }
+
+    pub fn seek(&mut self, key: &K) {
+        self.inner.seek(key);
+        self.skip_forward();
+    }
+
+    pub fn seek_for_prev(&mut self, key: &K) {
+        self.inner.seek_for_prev(key);
+        self.skip_backward();
+    }
+
+    fn skip_forward(&mut self) {
+        while self.inner.value().is_some_and(Value::is_delete) {
+            self.inner.next();
+        }
+    }
+
+    fn skip_backward(&mut self) {
+        while self.inner.value().is_some_and(Value::is_delete) {
+            self.inner.prev();
+        }
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use std::mem::size_of;
+
+    use crate::{
+        arena::BlockArena,
+        skip_list::{NodeError, SkipList},
+    };
+
+    use super::{GetResult, Value};
+
+    #[test]
+    fn tombstones() {
+        let list = SkipList::<_, _>::default_arc();
+        for i in 0..100 {
+            if i % 3 == 0 {
+                list.delete(i);
+            } else {
+                list.put(i, i * 10);
+            }
+        }
+        assert_eq!(list.try_delete(1), Err(NodeError::KeyExists));
+        assert_eq!(list.try_put(3, 0), Err(NodeError::KeyExists));
+
+        assert_eq!(list.lookup(&1), GetResult::Found(&10));
+        assert_eq!(list.lookup(&3), GetResult::Deleted);
+        assert_eq!(list.lookup(&100), GetResult::NotFound);
+        assert_eq!(list.get(&3), Some(&Value::Delete));
+        assert_eq!(list.lookup(&2).found(), Some(&20));
+
+        // reads skip the tombstones
+        let mut live = list.live_iter();
+        live.seek_to_first();
+        let mut keys = vec![];
+        while let (Some(&key), Some(&value)) = (live.key(), live.value()) {
+            assert_eq!(value, key * 10);
+            keys.push(key);
+            live.next();
+        }
+        assert_eq!(keys, (0..100).filter(|i| i % 3 != 0).collect::<Vec<_>>());
+        live.seek(&33);
+        assert_eq!(live.key(), Some(&34));
+        live.seek_for_prev(&33);
+        assert_eq!(live.key(), Some(&32));
+        live.prev();
+        assert_eq!(live.key(), Some(&31));
+        live.seek_to_last();
+        assert_eq!(live.key(), Some(&98));
+        live.seek(&99);
+        assert!(!live.is_valid());
+
+        // a flush carries them along
+        let (compacted, _) = list.compact_into(BlockArena::new()).unwrap();
+        assert_eq!(compacted.len(), 100);
+        assert_eq!(compacted.lookup(&99), GetResult::Deleted);
+        let mut all = list.iter();
+        all.seek_to_first();
+        assert_eq!(all.value(), Some(&Value::Delete));
+    }
+
+    #[test]
+    fn tombstones_take_no_room() {
+        assert_eq!(size_of::<Value<Vec<u8>>>(), size_of::<Vec<u8>>());
+        assert_eq!(size_of::<Value<Box<str>>>(), size_of::<Box<str>>());
+        assert_eq!(size_of::<Value<&u64>>(), size_of::<&u64>());
+    }
+}
@@ -0,0 +1,275 @@
+//! Write-ahead log records, what a memtable is rebuilt from after a crash:
+//!
+//! ```text
+//! u32 le(crc) | u32 le(len) | type | varint64(seq) | varint32(key_len) | key | varint32(value_len) | value
+//! ```
+//!
+//! `len` counts the bytes after it, and `crc` is the CRC32C of `len` and those bytes. The
+//! type is `ValueType`'s tag; a deletion has an empty value.
+
+use std::io::{self, Read};
+
+use crate::{
+    encoding::{
+        DecodeError, MAX_SEQUENCE, ValueType, get_length_prefixed, get_varint64, put_varint32,
+        put_varint64,
+    },
+    sst::crc32c,
+};
+
+const HEADER_LEN: usize = 8;
+
+/// One write, borrowed from the reader's buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogRecord<'a> {
+    pub seq: u64,
+    pub value_type: ValueType,
+    pub key: &'a [u8],
+    pub value: &'a [u8],
+}
+
+/// Appends a record. Panics when `seq` is above `MAX_SEQUENCE`, or the key or value is
+/// 4 GiB or longer.
+pub fn encode_record(dst: &mut Vec<u8>, record: &LogRecord<'_>) {
+    assert!(
+        record.seq <= MAX_SEQUENCE,
+        "sequence number {} takes more than 56 bits",
+        record.seq
+    );
+    let start = dst.len();
+    dst.extend_from_slice(&[0; HEADER_LEN]);
+    dst.push(record.value_type as u8);
+    put_varint64(dst, record.seq);
+    for bytes in [record.key, record.value] {
+        let len = u32::try_from(bytes.len()).expect("keys and values are shorter than 4 GiB");
+        put_varint32(dst, len);
+        dst.extend_from_slice(bytes);
+    }
+
+    let len =
+        u32::try_from(dst.len() - start - HEADER_LEN).expect("records are shorter than 4 GiB");
+    dst[start + 4..start + HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+    let crc = crc32c(&dst[start + 4..]);
+    dst[start..start + 4].copy_from_slice(&crc.to_le_bytes());
+}
+
+// what follows the header
+fn decode_body(body: &[u8]) -> Result<LogRecord<'_>, DecodeError> {
+    let (&tag, rest) = body.split_first().ok_or(DecodeError::Truncated)?;
+    let value_type = ValueType::try_from(tag)?;
+    let (seq, len) = get_varint64(rest)?;
+    let (key, rest) = get_length_prefixed(&rest[len..])?;
+    let (value, rest) = get_length_prefixed(rest)?;
+    if !rest.is_empty() || seq > MAX_SEQUENCE {
+        return Err(DecodeError::Corrupted);
+    }
+    Ok(LogRecord {
+        seq,
+        value_type,
+        key,
+        value,
+    })
+}
+
+/// Appends records to `W`.
+pub struct LogWriter<W> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: io::Write> LogWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, buf: vec![] }
+    }
+
+    /// Panics like `encode_record`.
+    pub fn add(&mut self, record: &LogRecord<'_>) -> io::Result<()> {
+        self.buf.clear();
+        encode_record(&mut self.buf, record);
+        self.inner.write_all(&self.buf)
+    }
+
+    pub fn put(&mut self, seq: u64, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.add(&LogRecord {
+            seq,
+            value_type: ValueType::Value,
+            key,
+            value,
+        })
+    }
+
+    pub fn delete(&mut self, seq: u64, key: &[u8]) -> io::Result<()> {
+        self.add(&LogRecord {
+            seq,
+            value_type: ValueType::Deletion,
+            key,
+            value: b"",
+        })
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads records back from `R`, one at a time.
+pub struct LogReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<R: io::Read> LogReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![],
+            done: false,
+        }
+    }
+
+    /// The next record, `None` at the end of the log. A record whose checksum fails or
+    /// that does not decode comes back as the error, and reading goes on behind it: the
+    /// length it claims is trusted. A log that ends inside a record, as after a crash
+    /// mid-write, ends with `DecodeError::Truncated`.
+    pub fn read_record(&mut self) -> io::Result<Option<Result<LogRecord<'_>, DecodeError>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut header = [0; HEADER_LEN];
+        match read_full(&mut self.inner, &mut header)? {
+            0 => {
+                self.done = true;
+                return Ok(None);
+            }
+            HEADER_LEN => {}
+            _ => return Ok(Some(self.truncated())),
+        }
+        let crc = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+
+        self.buf.clear();
+        self.buf.extend_from_slice(&header[4..]);
+        // grown as the bytes come, not by what a corrupted length claims
+        let read = (&mut self.inner)
+            .take(len as u64)
+            .read_to_end(&mut self.buf)?;
+        if read < len {
+            return Ok(Some(self.truncated()));
+        }
+        if crc32c(&self.buf) != crc {
+            return Ok(Some(Err(DecodeError::Corrupted)));
+        }
+        let record = decode_body(&self.buf[4..]).map_err(|_| DecodeError::Corrupted);
+        Ok(Some(record))
+    }
+
+    fn truncated(&mut self) -> Result<LogRecord<'static>, DecodeError> {
+        self.done = true;
+        Err(DecodeError::Truncated)
+    }
+}
+
+// as much of `buf` as `r` has left, stopping short only at the end
+fn read_full(r: &mut impl io::Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use crate::encoding::{DecodeError, ValueType};
+
+    use super::{HEADER_LEN, LogReader, LogRecord, LogWriter};
+
+    type Owned = (u64, ValueType, Vec<u8>, Vec<u8>);
+
+    fn read_all(log: &[u8]) -> Vec<Result<Owned, DecodeError>> {
+        let mut reader = LogReader::new(log);
+        let mut records = vec![];
+        while let Some(record) = reader.read_record().unwrap() {
+            records.push(record.map(|r| (r.seq, r.value_type, r.key.to_vec(), r.value.to_vec())));
+        }
+        records
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let mut writer = LogWriter::new(vec![]);
+        writer.put(1, b"key", b"value").unwrap();
+        writer.delete(2, b"key").unwrap();
+        writer.put(1 << 40, b"", &[7; 1000]).unwrap();
+        let log = writer.into_inner();
+
+        assert_eq!(
+            read_all(&log),
+            [
+                Ok((1, ValueType::Value, b"key".to_vec(), b"value".to_vec())),
+                Ok((2, ValueType::Deletion, b"key".to_vec(), vec![])),
+                Ok((1 << 40, ValueType::Value, vec![], vec![7; 1000])),
+            ]
+        );
+        assert!(read_all(&[]).is_empty());
+    }
+
+    #[test]
+    fn damaged_logs() {
+        let mut writer = LogWriter::new(vec![]);
+        for seq in 0..3 {
+            writer.put(seq, b"key", b"value").unwrap();
+        }
+        let log = writer.into_inner();
+        let record_len = log.len() / 3;
+
+        // a flipped bit costs its record and no other
+        for at in [0, 5, HEADER_LEN, record_len - 1] {
+            let mut flipped = log.clone();
+            flipped[record_len + at] ^= 1;
+            let records = read_all(&flipped);
+            if !(4..HEADER_LEN).contains(&at) {
+                assert_eq!(records.len(), 3, "{at}");
+                assert_eq!(records[1], Err(DecodeError::Corrupted), "{at}");
+                assert!(records[0].is_ok() && records[2].is_ok());
+            } else {
+                // in the length, which the next record's offset hangs on
+                assert!(records[1].is_err(), "{at}");
+            }
+        }
+
+        // cut off mid-record
+        for end in [log.len() - 1, 2 * record_len + 3] {
+            let records = read_all(&log[..end]);
+            assert_eq!(records.len(), 3);
+            assert_eq!(records[2], Err(DecodeError::Truncated));
+        }
+
+        // a checksum can pass over bytes that are not a record
+        let mut body = vec![];
+        super::encode_record(
+            &mut body,
+            &LogRecord {
+                seq: 1,
+                value_type: ValueType::Value,
+                key: b"k",
+                value: b"v",
+            },
+        );
+        // an unknown type, checksummed again
+        body[HEADER_LEN] = 9;
+        let crc = crate::sst::crc32c(&body[4..]);
+        body[..4].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(read_all(&body), [Err(DecodeError::Corrupted)]);
+    }
+}